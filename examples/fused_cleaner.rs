@@ -0,0 +1,58 @@
+//
+// Timing the fused cleaner
+//
+// `cl_empty_and_bad(bad, l)` produces the same lazy graph as
+// `cl_empty(cl_bad_conf(bad, l))`, but traverses `l` only once and
+// does not build the intermediate graph. Here both are applied
+// to the lazy graph of the MOSI protocol, and the times are printed
+// (the numbers depend on the machine, of course).
+//
+// Run with
+//     cargo run --release --example fused_cleaner
+//
+
+use staged_mrsc_rust::prelude::*;
+
+use std::time::Instant;
+
+counter_system! {
+    MOSI(i, o, s, m);
+    Start(ω, 0, 0, 0);
+    Unsafe(o >= 2 || m >= 2 || (s >= 1 && m >= 1));
+    Rules {
+        i >= 1 => i - 1, m + o, s + 1, 0;
+        o >= 1 => i + o + s + m - 1, 0, 0, 1;
+        i >= 1 => i + o + s + m - 1, 0, 0, 1;
+        s >= 1 => i + o + s + m - 1, 0, 0, 1;
+        s >= 1 => i + 1, o, s - 1, m;
+        m >= 1 => i + 1, o, s, m - 1;
+        o >= 1 => i + 1, o - 1, s, m;
+    }
+}
+
+const RUNS: u32 = 20;
+
+fn main() {
+    let s = CountersScWorld::new(MOSI, 3, 10);
+    let l = lazy_mrsc(&s, MOSI.start());
+    let bad = |c: &NWC| MOSI.is_unsafe(c);
+    println!("lazy graph: {} nodes", lazy_graph_size(&l));
+
+    let t = Instant::now();
+    let mut l1 = empty();
+    for _ in 0..RUNS {
+        l1 = cl_empty(&cl_bad_conf(bad, &l));
+    }
+    let separate = t.elapsed() / RUNS;
+
+    let t = Instant::now();
+    let mut l2 = empty();
+    for _ in 0..RUNS {
+        l2 = cl_empty_and_bad(bad, &l);
+    }
+    let fused = t.elapsed() / RUNS;
+
+    assert_eq!(l1, l2);
+    println!("cl_empty(cl_bad_conf(..)): {:?}", separate);
+    println!("cl_empty_and_bad(..):      {:?}", fused);
+}
@@ -0,0 +1,96 @@
+//
+// An end-to-end example: mutual exclusion in a token ring
+//
+// n processes are arranged in a ring, and a single token is passed
+// around the ring. A process may enter its critical section only when
+// it holds the token. We want to prove that, for any n, no two processes
+// are in their critical sections at the same time.
+//
+// Since the processes are identical, a state of the system can be
+// abstracted to a vector of counters:
+//
+// * `n` - the number of processes not holding the token,
+// * `t` - the number of processes holding the token (outside
+//   the critical section),
+// * `c` - the number of processes in the critical section.
+//
+// Initially, one process holds the token, the number of the other
+// processes being arbitrary (ω).
+//
+// Run with
+//     cargo run --example token_ring
+//
+
+use staged_mrsc_rust::big_step_sc::fold_obligations;
+use staged_mrsc_rust::counters::{
+    bmc_instance, fold_obligations_report, instantiate,
+};
+use staged_mrsc_rust::prelude::*;
+use staged_mrsc_rust::render::graph_to_dot;
+use staged_mrsc_rust::verification::{csv_row, CSV_HEADER};
+
+counter_system! {
+    TokenRing(n, t, c);
+    Start(ω, 1, 0);
+    Unsafe(c >= 2 || t >= 2 || (t >= 1 && c >= 1));
+    Rules {
+        // The token holder enters the critical section.
+        t >= 1 => n, t - 1, c + 1;
+        // The process leaves the critical section and passes
+        // the token to its neighbor.
+        c >= 1 => n, t + 1, c - 1;
+    }
+}
+
+// A faulty version: the neighbor may take the token while
+// the process is still in the critical section.
+
+counter_system! {
+    FaultyTokenRing(n, t, c);
+    Start(ω, 1, 0);
+    Unsafe(c >= 2 || t >= 2 || (t >= 1 && c >= 1));
+    Rules {
+        t >= 1 => n, t - 1, c + 1;
+        c >= 1 => n, t + 1, c - 1;
+        n >= 1 && c >= 1 => n - 1, t + 1, c;
+    }
+}
+
+fn main() {
+    // Step 1: supercompile the system, remove the graphs containing
+    // unsafe configurations and select a graph of minimal size.
+
+    let (r, mg) = verify(TokenRing, 3, 10);
+    println!("{}", CSV_HEADER);
+    println!("{}", csv_row(&r));
+
+    // Step 2: the residual graph is a proof of safety. Each fold (back node)
+    // is justified by a configuration in the path to it.
+
+    let g = mg.expect("the token ring should be proved safe");
+    println!("\n{}", graph_pretty_printer(&g));
+    let s = CountersScWorld::new(TokenRing, 3, 10);
+    println!("\nFold obligations:\n{}", fold_obligations_report(&s, &g));
+    assert!(fold_obligations(&s, &g)
+        .iter()
+        .all(|ob| ob.ancestor.is_some()));
+
+    // Step 3: the proof can be checked on concrete instances.
+
+    for n in 1..5 {
+        println!("n = {}: {:?}", n, instantiate(&TokenRing, &g, n, 1000));
+    }
+
+    // Step 4: a picture of the proof.
+
+    println!("\n{}", graph_to_dot(&g));
+
+    // Step 5: the faulty version cannot be proved safe,
+    // and a counterexample can be found.
+
+    let (r, mg) = verify(FaultyTokenRing, 3, 10);
+    println!("\n{}", csv_row(&r));
+    assert!(mg.is_none());
+    let trace = bmc_instance(FaultyTokenRing, 3, 10);
+    println!("Counterexample for n = 3: {}", trace.unwrap());
+}
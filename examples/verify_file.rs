@@ -0,0 +1,42 @@
+//
+// Verifying counter systems loaded at runtime
+//
+// Each argument is a file containing a counter system written in the
+// language of `counter_system!` (see `counters_dsl`). The systems are
+// verified one by one, and a CSV row is printed for each of them.
+//
+// Run with
+//     cargo run --example verify_file -- protocol1.cs protocol2.cs ...
+//
+
+use staged_mrsc_rust::counters_dsl::DslError;
+use staged_mrsc_rust::prelude::*;
+use staged_mrsc_rust::verification::{csv_row, CSV_HEADER};
+
+use std::env;
+use std::process;
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: verify_file FILE...");
+        process::exit(2);
+    }
+    println!("{}", CSV_HEADER);
+    for path in &paths {
+        match DslSystem::load(path) {
+            Ok(cw) => {
+                let (r, _) = verify(cw, 3, 10);
+                println!("{}", csv_row(&r));
+            }
+            Err(e @ DslError::Syntax(..)) => {
+                eprintln!("{}:{}", path, e);
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
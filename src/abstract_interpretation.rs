@@ -0,0 +1,174 @@
+//
+// A baseline verifier for counter systems
+//
+// An alternative verification backend, against which the results
+// of multi-result supercompilation can be compared.
+//
+// The set of reachable configurations is over-approximated by
+// a fixpoint computation in the abstract domain {0, ..., max_nw - 1, ω}
+// (for each coordinate). Since the rules of a counter system are already
+// defined on ω, the only abstraction needed is the widening that replaces
+// every value `≥ max_nw` with ω. The domain is finite, hence the
+// computation terminates.
+//
+// Each configuration found is developed once. Note that a configuration
+// `c` covered by a configuration `c'` found before (`c ≤ c'`, with ω
+// being the greatest value) has to be developed as well, since a guard
+// (such as `i == 0`) may hold for `c`, but not for `c'`.
+//
+// If no unsafe configuration is found, the system is safe (provided
+// that the set of unsafe configurations is upward-closed). Otherwise,
+// the unsafe configuration may be spurious (due to the abstraction).
+// Note that Karp-Miller acceleration (replacing with ω the coordinates
+// that grow along a path) would be unsound here, since rules may reset
+// or transfer counters.
+//
+
+use crate::counters::{CountersWorld, NW, NWC};
+
+use std::collections::{HashSet, VecDeque};
+use NW::{N, W};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AiOutcome {
+    Safe,
+    Unsafe(NWC),
+    Unknown,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AiResult {
+    pub outcome: AiOutcome,
+    // The number of configurations developed.
+    pub nodes: usize,
+    // The configurations found, in the order of development.
+    pub configurations: Vec<NWC>,
+}
+
+fn widen(c: NWC, max_nw: isize) -> NWC {
+    NWC(c
+        .0
+        .into_iter()
+        .map(|nw| match nw {
+            N(i) if i >= max_nw => W(),
+            _ => nw,
+        })
+        .collect())
+}
+
+// `max_nodes` bounds the number of configurations developed
+// (the outcome is `Unknown` if the bound is exceeded).
+
+pub fn abstract_fixpoint<CW: CountersWorld>(
+    cw: &CW,
+    max_nw: isize,
+    max_nodes: usize,
+) -> AiResult {
+    let mut found: Vec<NWC> = Vec::new();
+    let mut seen: HashSet<NWC> = HashSet::new();
+    let mut queue = VecDeque::new();
+    let mut nodes = 0;
+    for c in cw.starts() {
+        queue.push_back(widen(c, max_nw));
+    }
+    let outcome = loop {
+        let c = match queue.pop_front() {
+            None => break AiOutcome::Safe,
+            Some(c) => c,
+        };
+        if cw.is_unsafe(&c) {
+            break AiOutcome::Unsafe(c);
+        }
+        if seen.contains(&c) {
+            continue;
+        }
+        if nodes >= max_nodes {
+            break AiOutcome::Unknown;
+        }
+        nodes += 1;
+        seen.insert(c.clone());
+        found.push(c.clone());
+        for (enabled, c1) in cw.rules(&c) {
+            if enabled {
+                queue.push_back(widen(c1, max_nw));
+            }
+        }
+    };
+    AiResult {
+        outcome,
+        nodes,
+        configurations: found,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::counter_system;
+    use crate::counters::*;
+
+    counter_system! {
+        Pump(i, j);
+        Start(1, 0);
+        Unsafe(j >= 5);
+        Rules{
+            i >= 1 => i, j + 1;
+        }
+    }
+
+    counter_system! {
+        Swap(i, j);
+        Start(1, 0);
+        Unsafe(i >= 2);
+        Rules{
+            i >= 1 => i - 1, j + 1;
+            j >= 1 => i + 1, j - 1;
+        }
+    }
+
+    // The configuration (0, 0, 0) is covered by (1, 0, 0), but only
+    // the former enables the second rule.
+
+    counter_system! {
+        Reset(a, b, u);
+        Start(1, 0, 0);
+        Unsafe(u >= 1);
+        Rules{
+            a >= 1 => a - 1, b, u;
+            a == 0 => a, b, u + 1;
+        }
+    }
+
+    #[test]
+    fn test_widen() {
+        assert_eq!(
+            widen(NWC(vec![N(2), N(3), W()]), 3),
+            NWC(vec![N(2), W(), W()])
+        );
+    }
+
+    #[test]
+    fn test_abstract_fixpoint() {
+        assert_eq!(
+            abstract_fixpoint(&Pump, 3, 100).outcome,
+            AiOutcome::Unsafe(NWC(vec![N(1), W()]))
+        );
+        assert_eq!(
+            abstract_fixpoint(&Swap, 3, 100),
+            AiResult {
+                outcome: AiOutcome::Safe,
+                nodes: 2,
+                configurations: vec![
+                    NWC(vec![N(1), N(0)]),
+                    NWC(vec![N(0), N(1)])
+                ]
+            }
+        );
+        assert_eq!(abstract_fixpoint(&Swap, 3, 1).outcome, AiOutcome::Unknown);
+        assert_eq!(
+            abstract_fixpoint(&Reset, 3, 100).outcome,
+            AiOutcome::Unsafe(NWC(vec![N(0), N(0), N(1)]))
+        );
+    }
+}
@@ -0,0 +1,879 @@
+//
+// Adapters of worlds of supercompilation
+//
+// The functions below wrap a world `s`, producing a new world that
+// differs from `s` in a single respect. This enables experimenting
+// with variants of a world without rewriting it.
+//
+
+use crate::big_step_sc::ScWorld;
+use crate::misc::{History, HistoryCache};
+
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+// `map_conf(s, to, from)` re-encodes the configurations of `s`.
+// `to` and `from` are supposed to be mutually inverse.
+// The histories passed to `s` are decoded incrementally
+// (see `HistoryCache`).
+
+type ConvFn<A, B> = Box<dyn Fn(&A) -> B>;
+
+pub struct MapConf<S: ScWorld, D> {
+    inner: S,
+    to: ConvFn<S::C, D>,
+    from: ConvFn<D, S::C>,
+    histories: HistoryCache<D, S::C>,
+}
+
+pub fn map_conf<S: ScWorld, D>(
+    s: S,
+    to: impl Fn(&S::C) -> D + 'static,
+    from: impl Fn(&D) -> S::C + 'static,
+) -> MapConf<S, D> {
+    MapConf {
+        inner: s,
+        to: Box::new(to),
+        from: Box::new(from),
+        histories: HistoryCache::new(),
+    }
+}
+
+impl<S: ScWorld, D: Clone> MapConf<S, D> {
+    fn inner_history(&self, h: &History<D>) -> History<S::C> {
+        self.histories.map(h, |c| (self.from)(c))
+    }
+
+    fn to_css(&self, css: Vec<Vec<S::C>>) -> Vec<Vec<D>> {
+        css.iter()
+            .map(|cs| cs.iter().map(|c| (self.to)(c)).collect())
+            .collect()
+    }
+}
+
+impl<S: ScWorld, D: Clone> ScWorld for MapConf<S, D> {
+    type C = D;
+
+    fn is_dangerous(&self, h: &History<D>) -> bool {
+        self.inner.is_dangerous(&self.inner_history(h))
+    }
+
+    fn is_foldable_to(&self, c1: &D, c2: &D) -> bool {
+        self.inner
+            .is_foldable_to(&(self.from)(c1), &(self.from)(c2))
+    }
+
+    fn develop(&self, c: &D) -> Vec<Vec<D>> {
+        self.to_css(self.inner.develop(&(self.from)(c)))
+    }
+
+    fn develop_with_history(&self, h: &History<D>, c: &D) -> Vec<Vec<D>> {
+        let h1 = self.inner_history(h);
+        self.to_css(self.inner.develop_with_history(&h1, &(self.from)(c)))
+    }
+
+    fn develop_iter<'b>(
+        &'b self,
+        h: &History<D>,
+        c: &D,
+    ) -> Box<dyn Iterator<Item = Vec<D>> + 'b> {
+        let h1 = self.inner_history(h);
+        let css = self.inner.develop_iter(&h1, &(self.from)(c));
+        Box::new(css.map(move |cs| cs.iter().map(|c| (self.to)(c)).collect()))
+    }
+
+    fn is_foldable_to_history(&self, c: &D, h: &History<D>) -> bool {
+        let h1 = self.inner_history(h);
+        self.inner.is_foldable_to_history(&(self.from)(c), &h1)
+    }
+}
+
+// `with_logging(s, log)` reports the decisions taken by `s`
+// (folding, whistling and developing) to `log`.
+
+pub struct WithLogging<S> {
+    inner: S,
+    log: Box<dyn Fn(&str)>,
+}
+
+pub fn with_logging<S: ScWorld>(
+    s: S,
+    log: impl Fn(&str) + 'static,
+) -> WithLogging<S> {
+    WithLogging {
+        inner: s,
+        log: Box::new(log),
+    }
+}
+
+impl<S> ScWorld for WithLogging<S>
+where
+    S: ScWorld,
+    S::C: Debug,
+{
+    type C = S::C;
+
+    fn is_dangerous(&self, h: &History<S::C>) -> bool {
+        let r = self.inner.is_dangerous(h);
+        if r {
+            (self.log)(&format!("whistle at depth {}", h.length()));
+        }
+        r
+    }
+
+    fn is_foldable_to(&self, c1: &S::C, c2: &S::C) -> bool {
+        self.inner.is_foldable_to(c1, c2)
+    }
+
+    fn develop(&self, c: &S::C) -> Vec<Vec<S::C>> {
+        let css = self.inner.develop(c);
+        (self.log)(&format!("develop {:?}: {} alternatives", c, css.len()));
+        css
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Vec<Vec<S::C>> {
+        let css = self.inner.develop_with_history(h, c);
+        (self.log)(&format!("develop {:?}: {} alternatives", c, css.len()));
+        css
+    }
+
+    // The alternatives are counted, hence, produced up front.
+
+    fn develop_iter<'b>(
+        &'b self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Box<dyn Iterator<Item = Vec<S::C>> + 'b> {
+        let css: Vec<_> = self.inner.develop_iter(h, c).collect();
+        (self.log)(&format!("develop {:?}: {} alternatives", c, css.len()));
+        Box::new(css.into_iter())
+    }
+
+    fn is_foldable_to_history(&self, c: &S::C, h: &History<S::C>) -> bool {
+        let r = self.inner.is_foldable_to_history(c, h);
+        if r {
+            (self.log)(&format!("fold {:?}", c));
+        }
+        r
+    }
+}
+
+// `restrict_develop(s, p)` keeps only the alternatives `cs`
+// produced by `s` for `c` such that `p(c, cs)`.
+
+type AlternativeFn<C> = Box<dyn Fn(&C, &[C]) -> bool>;
+
+pub struct RestrictDevelop<S: ScWorld> {
+    inner: S,
+    p: AlternativeFn<S::C>,
+}
+
+pub fn restrict_develop<S: ScWorld>(
+    s: S,
+    p: impl Fn(&S::C, &[S::C]) -> bool + 'static,
+) -> RestrictDevelop<S> {
+    RestrictDevelop {
+        inner: s,
+        p: Box::new(p),
+    }
+}
+
+impl<S: ScWorld> ScWorld for RestrictDevelop<S> {
+    type C = S::C;
+
+    fn is_dangerous(&self, h: &History<S::C>) -> bool {
+        self.inner.is_dangerous(h)
+    }
+
+    fn is_foldable_to(&self, c1: &S::C, c2: &S::C) -> bool {
+        self.inner.is_foldable_to(c1, c2)
+    }
+
+    fn develop(&self, c: &S::C) -> Vec<Vec<S::C>> {
+        let mut css = self.inner.develop(c);
+        css.retain(|cs| (self.p)(c, cs));
+        css
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Vec<Vec<S::C>> {
+        let mut css = self.inner.develop_with_history(h, c);
+        css.retain(|cs| (self.p)(c, cs));
+        css
+    }
+
+    fn develop_iter<'b>(
+        &'b self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Box<dyn Iterator<Item = Vec<S::C>> + 'b> {
+        let c = c.clone();
+        let css = self.inner.develop_iter(h, &c);
+        Box::new(css.filter(move |cs| (self.p)(&c, cs)))
+    }
+
+    fn is_foldable_to_history(&self, c: &S::C, h: &History<S::C>) -> bool {
+        self.inner.is_foldable_to_history(c, h)
+    }
+}
+
+// `cap_alternatives(s, k)` keeps only the first `k` alternatives
+// produced by `s`.
+
+pub struct CapAlternatives<S> {
+    inner: S,
+    k: usize,
+}
+
+pub fn cap_alternatives<S: ScWorld>(s: S, k: usize) -> CapAlternatives<S> {
+    CapAlternatives { inner: s, k }
+}
+
+impl<S: ScWorld> ScWorld for CapAlternatives<S> {
+    type C = S::C;
+
+    fn is_dangerous(&self, h: &History<S::C>) -> bool {
+        self.inner.is_dangerous(h)
+    }
+
+    fn is_foldable_to(&self, c1: &S::C, c2: &S::C) -> bool {
+        self.inner.is_foldable_to(c1, c2)
+    }
+
+    fn develop(&self, c: &S::C) -> Vec<Vec<S::C>> {
+        let mut css = self.inner.develop(c);
+        css.truncate(self.k);
+        css
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Vec<Vec<S::C>> {
+        let mut css = self.inner.develop_with_history(h, c);
+        css.truncate(self.k);
+        css
+    }
+
+    fn develop_iter<'b>(
+        &'b self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Box<dyn Iterator<Item = Vec<S::C>> + 'b> {
+        Box::new(self.inner.develop_iter(h, c).take(self.k))
+    }
+
+    fn is_foldable_to_history(&self, c: &S::C, h: &History<S::C>) -> bool {
+        self.inner.is_foldable_to_history(c, h)
+    }
+}
+
+// `resource_whistle(s, time_limit, node_limit)` makes the whistle
+// of `s` also blow when the configurations of the current branch
+// have taken more than `time_limit` to develop, or when there are
+// `node_limit` of them. After that, the branch goes no deeper, so that
+// the search winds up with what it has found on the branch so far.
+//
+// The limits are accounted per branch (the time spent by the other
+// branches does not count), so that, with a node limit only, the graphs
+// do not depend on the driver. The time spent on a branch is kept
+// by the length of its history: the time spent on developing the first
+// `k` configurations of the current branch is `spent[k]`. (Hence,
+// the history passed to `is_dangerous` is supposed to be the full one,
+// as passed by the drivers.) The alternatives are produced on demand,
+// so that the time of producing an alternative is added to the branches
+// going through it.
+
+pub struct ResourceWhistle<S> {
+    inner: S,
+    time_limit: Option<Duration>,
+    node_limit: Option<usize>,
+    spent: RefCell<Vec<Duration>>,
+}
+
+pub fn resource_whistle<S: ScWorld>(
+    s: S,
+    time_limit: Option<Duration>,
+    node_limit: Option<usize>,
+) -> ResourceWhistle<S> {
+    ResourceWhistle {
+        inner: s,
+        time_limit,
+        node_limit,
+        spent: RefCell::new(vec![Duration::ZERO]),
+    }
+}
+
+impl<S> ResourceWhistle<S> {
+    // The time spent on developing the configurations of the branch
+    // whose history has the length `k`.
+
+    fn spent(&self, k: usize) -> Duration {
+        let spent = self.spent.borrow();
+        spent.get(k).copied().unwrap_or(Duration::ZERO)
+    }
+
+    fn is_exhausted(&self, h: &History<impl Sized>) -> bool {
+        let k = h.length();
+        self.node_limit.is_some_and(|n| k >= n)
+            || self.time_limit.is_some_and(|d| self.spent(k) >= d)
+    }
+}
+
+// The alternatives produced by a configuration whose history has
+// the length `k`, each one setting `spent[k + 1]`.
+
+struct TimedAlternatives<'a, I> {
+    spent: &'a RefCell<Vec<Duration>>,
+    k: usize,
+    base: Duration,
+    inner: I,
+}
+
+impl<I: Iterator> Iterator for TimedAlternatives<'_, I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let t = Instant::now();
+        let cs = self.inner.next()?;
+        let mut spent = self.spent.borrow_mut();
+        spent.resize(self.k + 1, Duration::ZERO);
+        spent.push(self.base + t.elapsed());
+        Some(cs)
+    }
+}
+
+impl<S: ScWorld> ScWorld for ResourceWhistle<S> {
+    type C = S::C;
+
+    fn is_dangerous(&self, h: &History<S::C>) -> bool {
+        self.is_exhausted(h) || self.inner.is_dangerous(h)
+    }
+
+    fn is_foldable_to(&self, c1: &S::C, c2: &S::C) -> bool {
+        self.inner.is_foldable_to(c1, c2)
+    }
+
+    fn develop(&self, c: &S::C) -> Vec<Vec<S::C>> {
+        self.inner.develop(c)
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Vec<Vec<S::C>> {
+        self.inner.develop_with_history(h, c)
+    }
+
+    fn develop_iter<'b>(
+        &'b self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Box<dyn Iterator<Item = Vec<S::C>> + 'b> {
+        let k = h.length();
+        let t = Instant::now();
+        let inner = self.inner.develop_iter(h, c);
+        Box::new(TimedAlternatives {
+            spent: &self.spent,
+            k,
+            base: self.spent(k) + t.elapsed(),
+            inner,
+        })
+    }
+
+    fn is_foldable_to_history(&self, c: &S::C, h: &History<S::C>) -> bool {
+        self.inner.is_foldable_to_history(c, h)
+    }
+}
+
+// `window_whistle(s, k)` makes the whistle of `s` only consider
+// the most recent `k` configurations of the history, so that "local"
+// termination criteria can be tried out with any world. (Note that
+// a whistle that only depends on the length of the history never blows
+// for the windows shorter than that length.) The folding is not affected.
+// The window shares the nodes of the history, so that no configurations
+// are copied.
+
+pub struct WindowWhistle<S> {
+    inner: S,
+    k: usize,
+}
+
+pub fn window_whistle<S: ScWorld>(s: S, k: usize) -> WindowWhistle<S> {
+    WindowWhistle { inner: s, k }
+}
+
+impl<S: ScWorld> ScWorld for WindowWhistle<S> {
+    type C = S::C;
+
+    fn is_dangerous(&self, h: &History<S::C>) -> bool {
+        self.inner.is_dangerous(&h.window(self.k))
+    }
+
+    fn is_foldable_to(&self, c1: &S::C, c2: &S::C) -> bool {
+        self.inner.is_foldable_to(c1, c2)
+    }
+
+    fn develop(&self, c: &S::C) -> Vec<Vec<S::C>> {
+        self.inner.develop(c)
+    }
+
+    fn drive(&self, c: &S::C) -> Vec<S::C> {
+        self.inner.drive(c)
+    }
+
+    fn rebuild(&self, c: &S::C) -> Vec<S::C> {
+        self.inner.rebuild(c)
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Vec<Vec<S::C>> {
+        self.inner.develop_with_history(h, c)
+    }
+
+    fn develop_iter<'b>(
+        &'b self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Box<dyn Iterator<Item = Vec<S::C>> + 'b> {
+        self.inner.develop_iter(h, c)
+    }
+
+    fn is_foldable_to_history(&self, c: &S::C, h: &History<S::C>) -> bool {
+        self.inner.is_foldable_to_history(c, h)
+    }
+}
+
+// `product_world(s1, s2, sync)` runs two worlds side by side.
+// A configuration is a pair of configurations, a pair is foldable
+// to another pair if both components are foldable, and the whistle
+// blows if it blows for either of the components.
+//
+// `sync` determines how the components are developed:
+//
+// * `SyncMode::Lockstep`: both components make a step. An alternative
+//   `cs1` of the first world is combined with an alternative `cs2`
+//   of the second world component-wise. This requires `cs1` and `cs2`
+//   to be of the same length, unless one of them is a single
+//   configuration, which is then paired with each configuration
+//   of the other one.
+// * `SyncMode::Left`: only the first component makes a step.
+// * `SyncMode::Interleave`: either the first or the second component
+//   makes a step.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SyncMode {
+    Lockstep,
+    Left,
+    Interleave,
+}
+
+// The histories of the components are projected incrementally
+// (see `HistoryCache`).
+
+pub struct ProductWorld<S1: ScWorld, S2: ScWorld> {
+    s1: S1,
+    s2: S2,
+    sync: SyncMode,
+    histories1: HistoryCache<(S1::C, S2::C), S1::C>,
+    histories2: HistoryCache<(S1::C, S2::C), S2::C>,
+}
+
+pub fn product_world<S1: ScWorld, S2: ScWorld>(
+    s1: S1,
+    s2: S2,
+    sync: SyncMode,
+) -> ProductWorld<S1, S2> {
+    ProductWorld {
+        s1,
+        s2,
+        sync,
+        histories1: HistoryCache::new(),
+        histories2: HistoryCache::new(),
+    }
+}
+
+fn zip_alternatives<C1: Clone, C2: Clone>(
+    cs1: &[C1],
+    cs2: &[C2],
+) -> Option<Vec<(C1, C2)>> {
+    if cs1.len() == cs2.len() {
+        Some(cs1.iter().cloned().zip(cs2.iter().cloned()).collect())
+    } else if cs1.len() == 1 {
+        Some(cs2.iter().map(|c2| (cs1[0].clone(), c2.clone())).collect())
+    } else if cs2.len() == 1 {
+        Some(cs1.iter().map(|c1| (c1.clone(), cs2[0].clone())).collect())
+    } else {
+        None
+    }
+}
+
+impl<S1: ScWorld, S2: ScWorld> ProductWorld<S1, S2> {
+    fn history1(&self, h: &History<(S1::C, S2::C)>) -> History<S1::C> {
+        self.histories1.map(h, |c| c.0.clone())
+    }
+
+    fn history2(&self, h: &History<(S1::C, S2::C)>) -> History<S2::C> {
+        self.histories2.map(h, |c| c.1.clone())
+    }
+
+    fn combine(
+        &self,
+        c: &(S1::C, S2::C),
+        css1: Vec<Vec<S1::C>>,
+        css2: Vec<Vec<S2::C>>,
+    ) -> Vec<Vec<(S1::C, S2::C)>> {
+        let (c1, c2) = c;
+        let mut css = Vec::new();
+        match self.sync {
+            SyncMode::Lockstep => {
+                for cs1 in &css1 {
+                    for cs2 in &css2 {
+                        if let Some(cs) = zip_alternatives(cs1, cs2) {
+                            css.push(cs);
+                        }
+                    }
+                }
+            }
+            SyncMode::Left => {
+                for cs1 in css1 {
+                    css.push(
+                        cs1.into_iter().map(|c| (c, c2.clone())).collect(),
+                    );
+                }
+            }
+            SyncMode::Interleave => {
+                for cs1 in css1 {
+                    css.push(
+                        cs1.into_iter().map(|c| (c, c2.clone())).collect(),
+                    );
+                }
+                for cs2 in css2 {
+                    css.push(
+                        cs2.into_iter().map(|c| (c1.clone(), c)).collect(),
+                    );
+                }
+            }
+        }
+        css
+    }
+}
+
+impl<S1: ScWorld, S2: ScWorld> ScWorld for ProductWorld<S1, S2> {
+    type C = (S1::C, S2::C);
+
+    fn is_dangerous(&self, h: &History<Self::C>) -> bool {
+        self.s1.is_dangerous(&self.history1(h))
+            || self.s2.is_dangerous(&self.history2(h))
+    }
+
+    fn is_foldable_to(&self, c1: &Self::C, c2: &Self::C) -> bool {
+        self.s1.is_foldable_to(&c1.0, &c2.0)
+            && self.s2.is_foldable_to(&c1.1, &c2.1)
+    }
+
+    fn develop(&self, c: &Self::C) -> Vec<Vec<Self::C>> {
+        let css2 = match self.sync {
+            SyncMode::Left => vec![],
+            _ => self.s2.develop(&c.1),
+        };
+        self.combine(c, self.s1.develop(&c.0), css2)
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Vec<Vec<Self::C>> {
+        let css1 = self.s1.develop_with_history(&self.history1(h), &c.0);
+        let css2 = match self.sync {
+            SyncMode::Left => vec![],
+            _ => self.s2.develop_with_history(&self.history2(h), &c.1),
+        };
+        self.combine(c, css1, css2)
+    }
+
+    // The alternatives of the components are combined, hence, they are
+    // produced up front.
+
+    fn develop_iter<'b>(
+        &'b self,
+        h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Box<dyn Iterator<Item = Vec<Self::C>> + 'b> {
+        let css1 = self.s1.develop_iter(&self.history1(h), &c.0);
+        let css2 = match self.sync {
+            SyncMode::Left => vec![],
+            _ => self.s2.develop_iter(&self.history2(h), &c.1).collect(),
+        };
+        Box::new(self.combine(c, css1.collect(), css2).into_iter())
+    }
+}
+
+// `in_arena(s, alloc)` makes the configurations of `s` borrowed
+// references `&'a S::C`. The configurations produced by `s` are moved
+// into an external arena by `alloc` (for example,
+// `|c| arena.alloc(c)`, where `arena` is a `typed_arena::Arena`),
+// so that the nodes of graphs and lazy graphs share the configurations
+// stored in the arena, and cloning a configuration just copies
+// a reference. (The graph functions only require `C: Clone`, hence,
+// they work for `C = &'a T`.)
+//
+// Note that the whistle of `s` is applied to the history with
+// the configurations copied out of the arena (each configuration
+// being copied once, see `HistoryCache`).
+
+pub struct InArena<'a, S: ScWorld> {
+    inner: S,
+    alloc: Box<dyn Fn(S::C) -> &'a S::C + 'a>,
+    histories: HistoryCache<&'a S::C, S::C>,
+}
+
+pub fn in_arena<'a, S: ScWorld>(
+    s: S,
+    alloc: impl Fn(S::C) -> &'a S::C + 'a,
+) -> InArena<'a, S> {
+    InArena {
+        inner: s,
+        alloc: Box::new(alloc),
+        histories: HistoryCache::new(),
+    }
+}
+
+impl<'a, S: ScWorld> InArena<'a, S> {
+    pub fn alloc(&self, c: S::C) -> &'a S::C {
+        (self.alloc)(c)
+    }
+
+    fn copy_history(&self, h: &History<&'a S::C>) -> History<S::C> {
+        self.histories.map(h, |c| (*c).clone())
+    }
+
+    fn alloc_css(&self, css: Vec<Vec<S::C>>) -> Vec<Vec<&'a S::C>> {
+        css.into_iter()
+            .map(|cs| cs.into_iter().map(|c| self.alloc(c)).collect())
+            .collect()
+    }
+}
+
+impl<'a, S: ScWorld> ScWorld for InArena<'a, S> {
+    type C = &'a S::C;
+
+    fn is_dangerous(&self, h: &History<&'a S::C>) -> bool {
+        self.inner.is_dangerous(&self.copy_history(h))
+    }
+
+    fn is_foldable_to(&self, c1: &&'a S::C, c2: &&'a S::C) -> bool {
+        self.inner.is_foldable_to(c1, c2)
+    }
+
+    fn develop(&self, c: &&'a S::C) -> Vec<Vec<&'a S::C>> {
+        self.alloc_css(self.inner.develop(c))
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<&'a S::C>,
+        c: &&'a S::C,
+    ) -> Vec<Vec<&'a S::C>> {
+        let h1 = self.copy_history(h);
+        self.alloc_css(self.inner.develop_with_history(&h1, c))
+    }
+
+    fn develop_iter<'b>(
+        &'b self,
+        h: &History<&'a S::C>,
+        c: &&'a S::C,
+    ) -> Box<dyn Iterator<Item = Vec<&'a S::C>> + 'b> {
+        let h1 = self.copy_history(h);
+        let css = self.inner.develop_iter(&h1, c);
+        Box::new(
+            css.map(move |cs| cs.into_iter().map(|c| self.alloc(c)).collect()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::*;
+    use crate::graph::*;
+    use crate::mock_sc_world::mock_world;
+    use crate::statistics::length_unroll;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_map_conf() {
+        let s =
+            map_conf(mock_world(), |c| c.to_string(), |d| d.parse().unwrap());
+        let gs = unroll(&lazy_mrsc(&s, "0".to_string()));
+        let gs0 = unroll(&lazy_mrsc(&mock_world(), 0));
+        assert_eq!(gs.len(), gs0.len());
+        assert_eq!(
+            gs[1],
+            forth(
+                &"0".to_string(),
+                &[forth(
+                    &"1".to_string(),
+                    &[forth(&"2".to_string(), &[back(&"1".to_string())])]
+                )]
+            )
+        );
+    }
+
+    #[test]
+    fn test_product_world() {
+        let develop = |s: &ProductWorld<_, _>, c| s.develop(&c);
+        let ps = product_world(mock_world(), mock_world(), SyncMode::Lockstep);
+        assert_eq!(
+            develop(&ps, (3, 0)),
+            vec![vec![(0, 1), (2, 1)], vec![(2, 1)], vec![(4, 1)]]
+        );
+        assert!(ps.is_foldable_to(&(1, 2), &(1, 2)));
+        assert!(!ps.is_foldable_to(&(1, 2), &(1, 3)));
+        let pl = product_world(mock_world(), mock_world(), SyncMode::Left);
+        assert_eq!(develop(&pl, (0, 5)), vec![vec![(1, 5)]]);
+        let pi =
+            product_world(mock_world(), mock_world(), SyncMode::Interleave);
+        assert_eq!(develop(&pi, (0, 5)).len(), 4);
+        assert!(!unroll(&lazy_mrsc(&ps, (0, 0))).is_empty());
+    }
+
+    #[test]
+    fn test_resource_whistle() {
+        let s = resource_whistle(mock_world(), None, Some(2));
+        assert_eq!(length_unroll(&lazy_mrsc(&s, 0)), 0);
+        let s = resource_whistle(mock_world(), None, Some(3));
+        let gs = unroll(&lazy_mrsc(&s, 0));
+        assert_eq!(gs, naive_mrsc(&s, 0));
+        assert_eq!(
+            gs,
+            vec![
+                forth(&0, &[forth(&1, &[forth(&2, &[back(&0), back(&1)])])]),
+                forth(&0, &[forth(&1, &[forth(&2, &[back(&1)])])]),
+            ]
+        );
+        let s = resource_whistle(mock_world(), Some(Duration::ZERO), None);
+        assert_eq!(lazy_mrsc(&s, 0), empty());
+        let s =
+            resource_whistle(mock_world(), Some(Duration::from_secs(60)), None);
+        assert_eq!(lazy_mrsc(&s, 0), lazy_mrsc(&mock_world(), 0));
+    }
+
+    #[test]
+    fn test_window_whistle() {
+        use crate::mock_sc_world::MockScWorld;
+
+        // The whistle blows if the oldest configuration is 0 and
+        // there are at least 2 configurations.
+        let world = || {
+            MockScWorld::new(
+                |c: &isize| vec![vec![(c + 1) % 10]],
+                |_| vec![],
+                |h| h.length() >= 2 && h.last() == Some(&0),
+            )
+        };
+        let l = lazy_mrsc(&world(), 0);
+        assert_eq!(length_unroll(&l), 0);
+        assert_eq!(lazy_mrsc(&window_whistle(world(), 5), 0), l);
+        let l1 = lazy_mrsc(&window_whistle(world(), 1), 0);
+        assert_eq!(length_unroll(&l1), 1);
+        assert_eq!(graph_size(&unroll(&l1)[0]), 11);
+    }
+
+    #[test]
+    fn test_with_logging() {
+        let lines = Rc::new(RefCell::new(Vec::<String>::new()));
+        let lines1 = Rc::clone(&lines);
+        let s = with_logging(mock_world(), move |m| {
+            lines1.borrow_mut().push(m.to_string())
+        });
+        lazy_mrsc(&s, 0);
+        let lines = lines.borrow();
+        assert_eq!(lines[0], "develop 0: 1 alternatives");
+        assert!(lines.contains(&"fold 1".to_string()));
+        assert!(lines.contains(&"whistle at depth 4".to_string()));
+    }
+
+    #[test]
+    fn test_restrict_develop_and_cap_alternatives() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let s1 = restrict_develop(mock_world(), |_, cs| cs.len() == 1);
+        let s2 = cap_alternatives(mock_world(), 1);
+        assert!(length_unroll(&lazy_mrsc(&s1, 0)) < length_unroll(&l));
+        assert!(length_unroll(&lazy_mrsc(&s2, 0)) < length_unroll(&l));
+    }
+
+    // A world producing infinitely many alternatives (on demand).
+
+    struct Endless;
+
+    impl ScWorld for Endless {
+        type C = usize;
+
+        fn is_dangerous(&self, h: &History<usize>) -> bool {
+            !h.is_empty()
+        }
+
+        fn is_foldable_to(&self, _c1: &usize, _c2: &usize) -> bool {
+            false
+        }
+
+        fn develop(&self, _c: &usize) -> Vec<Vec<usize>> {
+            panic!("the alternatives cannot be produced up front")
+        }
+
+        fn develop_iter<'a>(
+            &'a self,
+            _h: &History<usize>,
+            c: &usize,
+        ) -> Box<dyn Iterator<Item = Vec<usize>> + 'a> {
+            let c = *c;
+            Box::new((1..).map(move |k| vec![c + k]))
+        }
+    }
+
+    #[test]
+    fn test_develop_iter_forwarded() {
+        let s = resource_whistle(Endless, None, None);
+        let s = window_whistle(restrict_develop(s, |_, cs| cs[0] % 2 == 0), 3);
+        let s = cap_alternatives(s, 2);
+        assert_eq!(
+            lazy_mrsc(&s, 0),
+            build(&0, &[vec![empty()], vec![empty()]])
+        );
+        let arena = typed_arena::Arena::new();
+        let s = in_arena(cap_alternatives(Endless, 1), |c| arena.alloc(c));
+        assert_eq!(lazy_mrsc(&s, &0), build(&&0, &[vec![empty()]]));
+    }
+
+    #[test]
+    fn test_in_arena() {
+        let arena = typed_arena::Arena::new();
+        let s = in_arena(mock_world(), |c| arena.alloc(c));
+        let gs = unroll(&lazy_mrsc(&s, s.alloc(0)));
+        let gs0 = unroll(&lazy_mrsc(&mock_world(), 0));
+        assert_eq!(gs.len(), gs0.len());
+        assert_eq!(
+            gs[1],
+            forth(&&0, &[forth(&&1, &[forth(&&2, &[back(&&1)])])])
+        );
+        assert!(arena.len() > 0);
+    }
+}
@@ -0,0 +1,171 @@
+//
+// Annotated configurations
+//
+// An `Annotated<C, A>` is a configuration of type `C` equipped with
+// some metadata of type `A` (provenance, debugging information, etc.).
+// Equality, hashing and printing only take into account
+// the configuration, so that annotations do not affect folding,
+// cleaning and comparing graphs.
+//
+// Since supercompilation and cleaners just copy configurations,
+// graphs of annotated configurations keep the annotations.
+//
+
+use crate::big_step_sc::ScWorld;
+use crate::misc::{History, HistoryCache};
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+#[derive(Clone, Debug)]
+pub struct Annotated<C, A> {
+    pub conf: C,
+    pub ann: A,
+}
+
+impl<C, A> Annotated<C, A> {
+    pub fn new(conf: C, ann: A) -> Annotated<C, A> {
+        Annotated { conf, ann }
+    }
+}
+
+impl<C: PartialEq, A> PartialEq for Annotated<C, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.conf == other.conf
+    }
+}
+
+impl<C: Eq, A> Eq for Annotated<C, A> {}
+
+impl<C: Hash, A> Hash for Annotated<C, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.conf.hash(state)
+    }
+}
+
+impl<C: fmt::Display, A> fmt::Display for Annotated<C, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.conf.fmt(f)
+    }
+}
+
+// `annotate(s, f)` turns a world `s` into a world of annotated
+// configurations. When a configuration `c` is developed, the annotation
+// of each resulting configuration `c1` is `f(c, c1)`.
+
+type AnnotateFn<C, A> = Box<dyn Fn(&Annotated<C, A>, &C) -> A>;
+
+// The histories passed to `s` are stripped of the annotations
+// incrementally (see `HistoryCache`).
+
+pub struct AnnotatedWorld<S: ScWorld, A> {
+    inner: S,
+    f: AnnotateFn<S::C, A>,
+    histories: HistoryCache<Annotated<S::C, A>, S::C>,
+}
+
+pub fn annotate<S: ScWorld, A>(
+    s: S,
+    f: impl Fn(&Annotated<S::C, A>, &S::C) -> A + 'static,
+) -> AnnotatedWorld<S, A> {
+    AnnotatedWorld {
+        inner: s,
+        f: Box::new(f),
+        histories: HistoryCache::new(),
+    }
+}
+
+impl<S: ScWorld, A: Clone> AnnotatedWorld<S, A> {
+    fn inner_history(&self, h: &History<Annotated<S::C, A>>) -> History<S::C> {
+        self.histories.map(h, |c| c.conf.clone())
+    }
+
+    fn annotate_css(
+        &self,
+        c: &Annotated<S::C, A>,
+        css: Vec<Vec<S::C>>,
+    ) -> Vec<Vec<Annotated<S::C, A>>> {
+        css.into_iter()
+            .map(|cs| {
+                cs.into_iter()
+                    .map(|c1| {
+                        let a = (self.f)(c, &c1);
+                        Annotated::new(c1, a)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl<S: ScWorld, A: Clone> ScWorld for AnnotatedWorld<S, A> {
+    type C = Annotated<S::C, A>;
+
+    fn is_dangerous(&self, h: &History<Self::C>) -> bool {
+        self.inner.is_dangerous(&self.inner_history(h))
+    }
+
+    fn is_foldable_to(&self, c1: &Self::C, c2: &Self::C) -> bool {
+        self.inner.is_foldable_to(&c1.conf, &c2.conf)
+    }
+
+    fn develop(&self, c: &Self::C) -> Vec<Vec<Self::C>> {
+        self.annotate_css(c, self.inner.develop(&c.conf))
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Vec<Vec<Self::C>> {
+        let h1 = self.inner_history(h);
+        self.annotate_css(c, self.inner.develop_with_history(&h1, &c.conf))
+    }
+
+    fn develop_iter<'a>(
+        &'a self,
+        h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Box<dyn Iterator<Item = Vec<Self::C>> + 'a> {
+        let h1 = self.inner_history(h);
+        let css = self.inner.develop_iter(&h1, &c.conf);
+        let c = c.clone();
+        Box::new(css.flat_map(move |cs| self.annotate_css(&c, vec![cs])))
+    }
+
+    fn is_foldable_to_history(
+        &self,
+        c: &Self::C,
+        h: &History<Self::C>,
+    ) -> bool {
+        let h1 = self.inner_history(h);
+        self.inner.is_foldable_to_history(&c.conf, &h1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::lazy_mrsc;
+    use crate::graph::*;
+    use crate::mock_sc_world::mock_world;
+
+    #[test]
+    fn test_annotated_eq() {
+        assert_eq!(Annotated::new(1, "a"), Annotated::new(1, "b"));
+        assert_ne!(Annotated::new(1, "a"), Annotated::new(2, "a"));
+        assert_eq!(Annotated::new(1, "a").to_string(), "1");
+    }
+
+    #[test]
+    fn test_annotations_preserved() {
+        let s =
+            annotate(mock_world(), |c: &Annotated<isize, usize>, _| c.ann + 1);
+        let l = lazy_mrsc(&s, Annotated::new(0, 0));
+        let gs = unroll(&cl_min_size(&l));
+        let depths: Vec<(usize, usize)> =
+            gs[0].preorder().map(|(d, c, _)| (d, c.ann)).collect();
+        assert_eq!(depths, [(0, 0), (1, 1), (2, 2), (3, 3)]);
+    }
+}
@@ -0,0 +1,379 @@
+//
+// Archiving graphs
+//
+// Graphs and lazy graphs can be stored in a line-oriented text format.
+// The first line of an archive is a header specifying the kind
+// of the archived object and the version of the schema, e.g.
+//     staged-mrsc graph 1
+// `decode_*_any_version` accept archives produced by any version
+// of the schema known to this crate, converting them to the current
+// representation. Thus, when the schema changes, `SCHEMA_VERSION` is to
+// be incremented, and a decoder for the old version is to be kept.
+//
+// Schema version 1. A graph is written in preorder, a node per line:
+//     B <c>          Back(c)
+//     F <n> <c>      Forth(c, gs), followed by the n subgraphs gs
+// A lazy graph is written in the same way:
+//     E              Empty()
+//     S <c>          Stop(c)
+//     B <n> <c>      Build(c, lss), followed by the n alternatives,
+//                    each of them being
+//     A <m>          followed by m lazy graphs
+// Configurations are written by means of `Display` and read
+// by means of `FromStr`. Backslashes and newlines are escaped.
+//
+// With the `serde` feature, graphs and lazy graphs are to be serialized
+// wrapped in a `Versioned` envelope, e.g.
+//     {"version":1,"graph":...}
+// so that `Versioned::into_graph` rejects the data written by other
+// versions of the (derived) serde representation, instead of
+// misinterpreting them. `SERDE_VERSION` is to be incremented whenever
+// that representation changes.
+//
+
+use crate::graph::*;
+
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+use std::str::{FromStr, Lines};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+const GRAPH_HEADER: &str = "staged-mrsc graph";
+const LAZY_GRAPH_HEADER: &str = "staged-mrsc lazy-graph";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    MissingHeader,
+    UnsupportedVersion(u32),
+    Malformed(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::MissingHeader => write!(f, "missing header"),
+            DecodeError::UnsupportedVersion(v) => {
+                write!(f, "unsupported schema version {}", v)
+            }
+            DecodeError::Malformed(line) => {
+                write!(f, "malformed line: {}", line)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+#[cfg(feature = "serde")]
+pub const SERDE_VERSION: u32 = 1;
+
+#[cfg(feature = "serde")]
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Versioned<G> {
+    pub version: u32,
+    pub graph: G,
+}
+
+#[cfg(feature = "serde")]
+impl<G> Versioned<G> {
+    pub fn new(graph: G) -> Versioned<G> {
+        Versioned {
+            version: SERDE_VERSION,
+            graph,
+        }
+    }
+
+    pub fn into_graph(self) -> Result<G, DecodeError> {
+        if self.version == SERDE_VERSION {
+            Ok(self.graph)
+        } else {
+            Err(DecodeError::UnsupportedVersion(self.version))
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut r = String::new();
+    let mut cs = s.chars();
+    while let Some(ch) = cs.next() {
+        if ch == '\\' {
+            match cs.next() {
+                Some('n') => r.push('\n'),
+                Some(ch1) => r.push(ch1),
+                None => r.push('\\'),
+            }
+        } else {
+            r.push(ch);
+        }
+    }
+    r
+}
+
+//
+// Encoding (always in the current version)
+//
+
+fn encode_graph_loop<C: fmt::Display>(g: &Graph<C>, sb: &mut Vec<String>) {
+    match g {
+        Graph::Back(c) => sb.push(format!("B {}", escape(&c.to_string()))),
+        Graph::Forth(c, gs) => {
+            sb.push(format!("F {} {}", gs.len(), escape(&c.to_string())));
+            for g1 in gs {
+                encode_graph_loop(g1, sb);
+            }
+        }
+    }
+}
+
+pub fn encode_graph<C: fmt::Display>(g: &Graph<C>) -> String {
+    let mut sb = vec![format!("{} {}", GRAPH_HEADER, SCHEMA_VERSION)];
+    encode_graph_loop(g, &mut sb);
+    sb.join("\n")
+}
+
+fn encode_lazy_graph_loop<C: fmt::Display>(
+    l: &LazyGraph<C>,
+    sb: &mut Vec<String>,
+) {
+    match l {
+        LazyGraph::Empty() => sb.push("E".to_string()),
+        LazyGraph::Stop(c) => sb.push(format!("S {}", escape(&c.to_string()))),
+        LazyGraph::Build(c, lss) => {
+            sb.push(format!("B {} {}", lss.len(), escape(&c.to_string())));
+            for ls in lss {
+                sb.push(format!("A {}", ls.len()));
+                for l1 in ls {
+                    encode_lazy_graph_loop(l1, sb);
+                }
+            }
+        }
+    }
+}
+
+pub fn encode_lazy_graph<C: fmt::Display>(l: &LazyGraph<C>) -> String {
+    let mut sb = vec![format!("{} {}", LAZY_GRAPH_HEADER, SCHEMA_VERSION)];
+    encode_lazy_graph_loop(l, &mut sb);
+    sb.join("\n")
+}
+
+//
+// Decoding
+//
+
+fn read_version(lines: &mut Lines, header: &str) -> Result<u32, DecodeError> {
+    let line = lines.next().ok_or(DecodeError::MissingHeader)?;
+    let v = line
+        .strip_prefix(header)
+        .ok_or(DecodeError::MissingHeader)?
+        .trim();
+    v.parse()
+        .map_err(|_| DecodeError::Malformed(line.to_string()))
+}
+
+// Splits "<tag> <n> <rest>" or "<tag> <rest>".
+
+fn split_line(line: &str) -> (&str, &str) {
+    match line.split_once(' ') {
+        Some((tag, rest)) => (tag, rest),
+        None => (line, ""),
+    }
+}
+
+fn parse_conf<C: FromStr>(line: &str, s: &str) -> Result<C, DecodeError> {
+    unescape(s)
+        .parse()
+        .map_err(|_| DecodeError::Malformed(line.to_string()))
+}
+
+fn parse_count<'a>(
+    line: &str,
+    s: &'a str,
+) -> Result<(usize, &'a str), DecodeError> {
+    let (n, rest) = split_line(s);
+    n.parse()
+        .map(|n| (n, rest))
+        .map_err(|_| DecodeError::Malformed(line.to_string()))
+}
+
+fn next_line<'a>(lines: &mut Lines<'a>) -> Result<&'a str, DecodeError> {
+    lines
+        .next()
+        .ok_or(DecodeError::Malformed("unexpected end".to_string()))
+}
+
+fn decode_graph_v1<C: Clone + FromStr>(
+    lines: &mut Lines,
+) -> Result<Rc<Graph<C>>, DecodeError> {
+    let line = next_line(lines)?;
+    match split_line(line) {
+        ("B", s) => Ok(back(&parse_conf(line, s)?)),
+        ("F", s) => {
+            let (n, s1) = parse_count(line, s)?;
+            let c: C = parse_conf(line, s1)?;
+            let mut gs = Vec::with_capacity(n);
+            for _ in 0..n {
+                gs.push(decode_graph_v1(lines)?);
+            }
+            Ok(forth(&c, &gs))
+        }
+        _ => Err(DecodeError::Malformed(line.to_string())),
+    }
+}
+
+fn decode_lazy_graph_v1<C: Clone + FromStr>(
+    lines: &mut Lines,
+) -> Result<Rc<LazyGraph<C>>, DecodeError> {
+    let line = next_line(lines)?;
+    match split_line(line) {
+        ("E", "") => Ok(empty()),
+        ("S", s) => Ok(stop(&parse_conf(line, s)?)),
+        ("B", s) => {
+            let (n, s1) = parse_count(line, s)?;
+            let c: C = parse_conf(line, s1)?;
+            let mut lss = Vec::with_capacity(n);
+            for _ in 0..n {
+                let line1 = next_line(lines)?;
+                let m = match split_line(line1) {
+                    ("A", s2) => parse_count(line1, s2)?.0,
+                    _ => return Err(DecodeError::Malformed(line1.to_string())),
+                };
+                let mut ls = Vec::with_capacity(m);
+                for _ in 0..m {
+                    ls.push(decode_lazy_graph_v1(lines)?);
+                }
+                lss.push(ls);
+            }
+            Ok(build(&c, &lss))
+        }
+        _ => Err(DecodeError::Malformed(line.to_string())),
+    }
+}
+
+fn check_end(lines: &mut Lines) -> Result<(), DecodeError> {
+    match lines.next() {
+        None => Ok(()),
+        Some(line) => Err(DecodeError::Malformed(line.to_string())),
+    }
+}
+
+pub fn decode_graph_any_version<C: Clone + FromStr>(
+    s: &str,
+) -> Result<Rc<Graph<C>>, DecodeError> {
+    let mut lines = s.lines();
+    let g = match read_version(&mut lines, GRAPH_HEADER)? {
+        1 => decode_graph_v1(&mut lines)?,
+        v => return Err(DecodeError::UnsupportedVersion(v)),
+    };
+    check_end(&mut lines)?;
+    Ok(g)
+}
+
+pub fn decode_lazy_graph_any_version<C: Clone + FromStr>(
+    s: &str,
+) -> Result<Rc<LazyGraph<C>>, DecodeError> {
+    let mut lines = s.lines();
+    let l = match read_version(&mut lines, LAZY_GRAPH_HEADER)? {
+        1 => decode_lazy_graph_v1(&mut lines)?,
+        v => return Err(DecodeError::UnsupportedVersion(v)),
+    };
+    check_end(&mut lines)?;
+    Ok(l)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::lazy_mrsc;
+    use crate::counter_system;
+    use crate::counters::*;
+    use crate::mock_sc_world::mock_world;
+
+    #[test]
+    fn test_graph_round_trip() {
+        let g = forth(&1, &[back(&1), forth(&2, &[back(&1), back(&2)])]);
+        let s = encode_graph(&g);
+        assert_eq!(s, "staged-mrsc graph 1\nF 2 1\nB 1\nF 2 2\nB 1\nB 2");
+        assert_eq!(decode_graph_any_version::<isize>(&s), Ok(g));
+    }
+
+    #[test]
+    fn test_lazy_graph_round_trip() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let s = encode_lazy_graph(&l);
+        assert_eq!(decode_lazy_graph_any_version::<isize>(&s), Ok(l));
+        let s2 =
+            encode_lazy_graph(&*build(&"a\nb".to_string(), &[vec![empty()]]));
+        assert_eq!(
+            decode_lazy_graph_any_version::<String>(&s2),
+            Ok(build(&"a\nb".to_string(), &[vec![empty()]]))
+        );
+    }
+
+    counter_system! {
+        Synapse(i, d, v);
+        Start(ω, 0, 0);
+        Unsafe((d >= 1 && v >= 1) || (d >= 2));
+        Rules {
+            i >= 1 => i + d - 1, 0, v + 1;
+            v >= 1 => i + d + v - 1, 1, 0;
+            i >= 1 => i + d + v - 1, 1, 0;
+        }
+    }
+
+    #[test]
+    fn test_counters_round_trip() {
+        let s = CountersScWorld::new(Synapse, 3, 10);
+        let l = lazy_mrsc(&s, Synapse.start());
+        assert_eq!(
+            decode_lazy_graph_any_version::<NWC>(&encode_lazy_graph(&l)),
+            Ok(l.clone())
+        );
+        let g = unroll_first(&cl_min_size(&l)).unwrap();
+        assert_eq!(decode_graph_any_version::<NWC>(&encode_graph(&g)), Ok(g));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_versioned() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let s = serde_json::to_string(&Versioned::new(l.clone())).unwrap();
+        assert!(s.starts_with("{\"version\":1,\"graph\":"));
+        let v: Versioned<Rc<LazyGraph<isize>>> =
+            serde_json::from_str(&s).unwrap();
+        assert_eq!(v.into_graph(), Ok(l));
+        let g = forth(&1, &[back(&1)]);
+        let s = serde_json::to_string(&Versioned::new(g.clone())).unwrap();
+        let s2 = s.replacen("\"version\":1", "\"version\":99", 1);
+        let v: Versioned<Rc<Graph<isize>>> = serde_json::from_str(&s2).unwrap();
+        assert_eq!(v.into_graph(), Err(DecodeError::UnsupportedVersion(99)));
+        let v: Versioned<Rc<Graph<isize>>> = serde_json::from_str(&s).unwrap();
+        assert_eq!(v.into_graph(), Ok(g));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(
+            decode_graph_any_version::<isize>("B 1"),
+            Err(DecodeError::MissingHeader)
+        );
+        assert_eq!(
+            decode_graph_any_version::<isize>("staged-mrsc graph 99\nB 1"),
+            Err(DecodeError::UnsupportedVersion(99))
+        );
+        assert_eq!(
+            decode_graph_any_version::<isize>(
+                "staged-mrsc graph 1\nF 2 1\nB 1"
+            ),
+            Err(DecodeError::Malformed("unexpected end".to_string()))
+        );
+        let e: Box<dyn Error> = Box::new(DecodeError::UnsupportedVersion(99));
+        assert_eq!(e.to_string(), "unsupported schema version 99");
+    }
+}
@@ -0,0 +1,543 @@
+//
+// Arena allocation of graphs
+//
+// Allocating each node of a (lazy) graph in its own `Rc` puts much
+// pressure on the allocator when big graphs are built (e.g., for
+// Futurebus). A `GraphArena` keeps all the nodes in two vectors (one for
+// lazy graphs and one for graphs), and the nodes refer to each other
+// by indices (`LazyId` and `GraphId`). The nodes are never removed,
+// and the memory is released all at once, when the arena is dropped.
+//
+// The arena versions of `lazy_mrsc`, `unroll`, the cleaners and
+// the statistics functions correspond to the original ones:
+//     arena.to_lazy_graph(lazy_mrsc_arena(s, &mut arena, c0)) ==
+//         lazy_mrsc(s, c0)
+//     vec_map!(arena.to_graph(g); g in unroll_arena(&mut arena, l)) ==
+//         unroll(arena.to_lazy_graph(l))
+//     arena.to_lazy_graph(cl_empty_arena(&mut arena, l)) ==
+//         cl_empty(arena.to_lazy_graph(l))
+// and so on. Since nodes are identified by indices, the results for
+// shared subtrees are computed only once (and the cleaned subtrees
+// remain shared).
+//
+
+use crate::big_step_sc::{sc_step, ScEvent, ScWorld};
+use crate::graph::*;
+use crate::misc::{cartesian, History};
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct LazyId(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GraphId(usize);
+
+impl LazyId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl GraphId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ArenaLazyNode<C> {
+    Empty(),
+    Stop(C),
+    Build(C, Vec<Vec<LazyId>>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ArenaGraphNode<C> {
+    Back(C),
+    Forth(C, Vec<GraphId>),
+}
+
+pub struct GraphArena<C> {
+    lazy_nodes: Vec<ArenaLazyNode<C>>,
+    graph_nodes: Vec<ArenaGraphNode<C>>,
+}
+
+impl<C: Clone> Default for GraphArena<C> {
+    fn default() -> Self {
+        GraphArena::new()
+    }
+}
+
+impl<C: Clone> GraphArena<C> {
+    // The node 0 is the (only) empty lazy graph.
+
+    pub fn new() -> GraphArena<C> {
+        GraphArena {
+            lazy_nodes: vec![ArenaLazyNode::Empty()],
+            graph_nodes: Vec::new(),
+        }
+    }
+
+    pub fn lazy_len(&self) -> usize {
+        self.lazy_nodes.len()
+    }
+
+    pub fn graph_len(&self) -> usize {
+        self.graph_nodes.len()
+    }
+
+    pub fn lazy_node(&self, l: LazyId) -> &ArenaLazyNode<C> {
+        &self.lazy_nodes[l.0]
+    }
+
+    pub fn graph_node(&self, g: GraphId) -> &ArenaGraphNode<C> {
+        &self.graph_nodes[g.0]
+    }
+
+    pub fn empty(&self) -> LazyId {
+        LazyId(0)
+    }
+
+    pub fn is_empty_node(&self, l: LazyId) -> bool {
+        matches!(self.lazy_nodes[l.0], ArenaLazyNode::Empty())
+    }
+
+    pub fn stop(&mut self, c: &C) -> LazyId {
+        self.lazy_nodes.push(ArenaLazyNode::Stop(c.clone()));
+        LazyId(self.lazy_nodes.len() - 1)
+    }
+
+    pub fn build(&mut self, c: &C, lss: Vec<Vec<LazyId>>) -> LazyId {
+        self.lazy_nodes.push(ArenaLazyNode::Build(c.clone(), lss));
+        LazyId(self.lazy_nodes.len() - 1)
+    }
+
+    pub fn back(&mut self, c: &C) -> GraphId {
+        self.graph_nodes.push(ArenaGraphNode::Back(c.clone()));
+        GraphId(self.graph_nodes.len() - 1)
+    }
+
+    pub fn forth(&mut self, c: &C, gs: Vec<GraphId>) -> GraphId {
+        self.graph_nodes.push(ArenaGraphNode::Forth(c.clone(), gs));
+        GraphId(self.graph_nodes.len() - 1)
+    }
+
+    // Conversions from and to `Rc`-based graphs
+    // (shared subtrees remain shared).
+
+    pub fn add_lazy_graph(&mut self, l: &Rc<LazyGraph<C>>) -> LazyId {
+        self.add_lazy_graph_loop(&mut HashMap::new(), l)
+    }
+
+    fn add_lazy_graph_loop(
+        &mut self,
+        memo: &mut HashMap<*const LazyGraph<C>, LazyId>,
+        l: &Rc<LazyGraph<C>>,
+    ) -> LazyId {
+        if let Some(&id) = memo.get(&Rc::as_ptr(l)) {
+            return id;
+        }
+        let id = match &**l {
+            LazyGraph::Empty() => self.empty(),
+            LazyGraph::Stop(c) => self.stop(c),
+            LazyGraph::Build(c, lss) => {
+                let mut iss = Vec::with_capacity(lss.len());
+                for ls in lss {
+                    let mut is = Vec::with_capacity(ls.len());
+                    for l1 in ls {
+                        is.push(self.add_lazy_graph_loop(memo, l1));
+                    }
+                    iss.push(is);
+                }
+                self.build(c, iss)
+            }
+        };
+        memo.insert(Rc::as_ptr(l), id);
+        id
+    }
+
+    pub fn to_lazy_graph(&self, l: LazyId) -> Rc<LazyGraph<C>> {
+        self.to_lazy_graph_loop(&mut HashMap::new(), l)
+    }
+
+    fn to_lazy_graph_loop(
+        &self,
+        memo: &mut HashMap<LazyId, Rc<LazyGraph<C>>>,
+        l: LazyId,
+    ) -> Rc<LazyGraph<C>> {
+        if let Some(l1) = memo.get(&l) {
+            return Rc::clone(l1);
+        }
+        let l1 = match &self.lazy_nodes[l.0] {
+            ArenaLazyNode::Empty() => empty(),
+            ArenaLazyNode::Stop(c) => stop(c),
+            ArenaLazyNode::Build(c, iss) => {
+                let lss: Vec<Ls<C>> = iss
+                    .iter()
+                    .map(|is| {
+                        is.iter()
+                            .map(|&i| self.to_lazy_graph_loop(memo, i))
+                            .collect()
+                    })
+                    .collect();
+                build(c, &lss)
+            }
+        };
+        memo.insert(l, Rc::clone(&l1));
+        l1
+    }
+
+    pub fn to_graph(&self, g: GraphId) -> Rc<Graph<C>> {
+        match &self.graph_nodes[g.0] {
+            ArenaGraphNode::Back(c) => back(c),
+            ArenaGraphNode::Forth(c, is) => {
+                let gs: Gs<C> = is.iter().map(|&i| self.to_graph(i)).collect();
+                forth(c, &gs)
+            }
+        }
+    }
+}
+
+//
+// Building lazy graphs in an arena
+//
+
+fn lazy_mrsc_arena_loop<S: ScWorld>(
+    s: &S,
+    arena: &mut GraphArena<S::C>,
+    h: &History<S::C>,
+    c: S::C,
+) -> LazyId {
+    match sc_step(s, h, &c) {
+        ScEvent::Folded => arena.stop(&c),
+        ScEvent::Pruned => arena.empty(),
+        ScEvent::Expanded => {
+            let css = s.develop_iter(h, &c);
+            let h1 = h.cons(c.clone());
+            let mut iss = Vec::with_capacity(css.size_hint().0);
+            for cs in css {
+                let mut is = Vec::with_capacity(cs.len());
+                for c1 in cs {
+                    is.push(lazy_mrsc_arena_loop(s, arena, &h1, c1));
+                }
+                iss.push(is);
+            }
+            arena.build(&c, iss)
+        }
+    }
+}
+
+pub fn lazy_mrsc_arena<S: ScWorld>(
+    s: &S,
+    arena: &mut GraphArena<S::C>,
+    c0: S::C,
+) -> LazyId {
+    lazy_mrsc_arena_loop(s, arena, &History::new(), c0)
+}
+
+//
+// Unrolling
+//
+
+fn unroll_arena_loop<C: Clone>(
+    arena: &mut GraphArena<C>,
+    memo: &mut HashMap<LazyId, Vec<GraphId>>,
+    l: LazyId,
+) -> Vec<GraphId> {
+    if let Some(gs) = memo.get(&l) {
+        return gs.clone();
+    }
+    let gs = match arena.lazy_node(l).clone() {
+        ArenaLazyNode::Empty() => Vec::new(),
+        ArenaLazyNode::Stop(c) => vec![arena.back(&c)],
+        ArenaLazyNode::Build(c, iss) => {
+            let mut gs = Vec::new();
+            for is in iss {
+                let gss: Vec<Vec<GraphId>> = is
+                    .iter()
+                    .map(|&i| unroll_arena_loop(arena, memo, i))
+                    .collect();
+                for gs1 in cartesian(&gss) {
+                    gs.push(arena.forth(&c, gs1));
+                }
+            }
+            gs
+        }
+    };
+    memo.insert(l, gs.clone());
+    gs
+}
+
+pub fn unroll_arena<C: Clone>(
+    arena: &mut GraphArena<C>,
+    l: LazyId,
+) -> Vec<GraphId> {
+    unroll_arena_loop(arena, &mut HashMap::new(), l)
+}
+
+//
+// Cleaners
+//
+
+fn cl_empty_arena_loop<C: Clone>(
+    arena: &mut GraphArena<C>,
+    memo: &mut HashMap<LazyId, LazyId>,
+    l: LazyId,
+) -> LazyId {
+    if let Some(&l1) = memo.get(&l) {
+        return l1;
+    }
+    let l1 = match arena.lazy_node(l).clone() {
+        ArenaLazyNode::Empty() | ArenaLazyNode::Stop(_) => l,
+        ArenaLazyNode::Build(c, iss) => {
+            let mut iss1 = Vec::with_capacity(iss.len());
+            for is in iss {
+                let is1: Vec<LazyId> = is
+                    .iter()
+                    .map(|&i| cl_empty_arena_loop(arena, memo, i))
+                    .collect();
+                if !is1.iter().any(|&i| arena.is_empty_node(i)) {
+                    iss1.push(is1);
+                }
+            }
+            if iss1.is_empty() {
+                arena.empty()
+            } else {
+                arena.build(&c, iss1)
+            }
+        }
+    };
+    memo.insert(l, l1);
+    l1
+}
+
+pub fn cl_empty_arena<C: Clone>(
+    arena: &mut GraphArena<C>,
+    l: LazyId,
+) -> LazyId {
+    cl_empty_arena_loop(arena, &mut HashMap::new(), l)
+}
+
+fn cl_bad_conf_arena_loop<C: Clone>(
+    arena: &mut GraphArena<C>,
+    bad: &impl Fn(&C) -> bool,
+    memo: &mut HashMap<LazyId, LazyId>,
+    l: LazyId,
+) -> LazyId {
+    if let Some(&l1) = memo.get(&l) {
+        return l1;
+    }
+    let l1 = match arena.lazy_node(l).clone() {
+        ArenaLazyNode::Empty() => l,
+        ArenaLazyNode::Stop(c) => {
+            if bad(&c) {
+                arena.empty()
+            } else {
+                l
+            }
+        }
+        ArenaLazyNode::Build(c, iss) => {
+            if bad(&c) {
+                arena.empty()
+            } else {
+                let iss1: Vec<Vec<LazyId>> = iss
+                    .iter()
+                    .map(|is| {
+                        is.iter()
+                            .map(|&i| {
+                                cl_bad_conf_arena_loop(arena, bad, memo, i)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                arena.build(&c, iss1)
+            }
+        }
+    };
+    memo.insert(l, l1);
+    l1
+}
+
+pub fn cl_bad_conf_arena<C: Clone>(
+    arena: &mut GraphArena<C>,
+    bad: impl Fn(&C) -> bool,
+    l: LazyId,
+) -> LazyId {
+    cl_bad_conf_arena_loop(arena, &bad, &mut HashMap::new(), l)
+}
+
+// As in `cl_min_size`, ∞ is represented by `usize::MAX`, and the last
+// of the alternatives of minimal size is selected.
+
+fn sel_min_size_arena<C: Clone>(
+    arena: &mut GraphArena<C>,
+    memo: &mut HashMap<LazyId, (usize, LazyId)>,
+    l: LazyId,
+) -> (usize, LazyId) {
+    if let Some(&kl) = memo.get(&l) {
+        return kl;
+    }
+    let kl = match arena.lazy_node(l).clone() {
+        ArenaLazyNode::Empty() => (usize::MAX, l),
+        ArenaLazyNode::Stop(_) => (1, l),
+        ArenaLazyNode::Build(c, iss) => {
+            let mut best = (usize::MAX, Vec::new());
+            for is in iss {
+                let mut k = 0usize;
+                let mut is1 = Vec::with_capacity(is.len());
+                for i in is {
+                    let (k1, i1) = sel_min_size_arena(arena, memo, i);
+                    k = if k == usize::MAX || k1 == usize::MAX {
+                        usize::MAX
+                    } else {
+                        k + k1
+                    };
+                    is1.push(i1);
+                }
+                if k <= best.0 {
+                    best = (k, is1);
+                }
+            }
+            match best {
+                (usize::MAX, _) => (usize::MAX, arena.empty()),
+                (k, is1) => (1 + k, arena.build(&c, vec![is1])),
+            }
+        }
+    };
+    memo.insert(l, kl);
+    kl
+}
+
+pub fn cl_min_size_arena<C: Clone>(
+    arena: &mut GraphArena<C>,
+    l: LazyId,
+) -> LazyId {
+    sel_min_size_arena(arena, &mut HashMap::new(), l).1
+}
+
+//
+// Statistics
+//
+
+fn length_unroll_arena_loop<C>(
+    arena: &GraphArena<C>,
+    memo: &mut Vec<Option<usize>>,
+    l: LazyId,
+) -> usize {
+    if let Some(n) = memo[l.0] {
+        return n;
+    }
+    let n = match &arena.lazy_nodes[l.0] {
+        ArenaLazyNode::Empty() => 0,
+        ArenaLazyNode::Stop(_) => 1,
+        ArenaLazyNode::Build(_, iss) => {
+            let mut s = 0;
+            for is in iss {
+                let mut m = 1;
+                for &i in is {
+                    m *= length_unroll_arena_loop(arena, memo, i);
+                }
+                s += m;
+            }
+            s
+        }
+    };
+    memo[l.0] = Some(n);
+    n
+}
+
+pub fn length_unroll_arena<C>(arena: &GraphArena<C>, l: LazyId) -> usize {
+    let mut memo = vec![None; arena.lazy_nodes.len()];
+    length_unroll_arena_loop(arena, &mut memo, l)
+}
+
+fn size_unroll_arena_loop<C>(
+    arena: &GraphArena<C>,
+    memo: &mut Vec<Option<(usize, usize)>>,
+    l: LazyId,
+) -> (usize, usize) {
+    if let Some(kn) = memo[l.0] {
+        return kn;
+    }
+    let kn = match &arena.lazy_nodes[l.0] {
+        ArenaLazyNode::Empty() => (0, 0),
+        ArenaLazyNode::Stop(_) => (1, 1),
+        ArenaLazyNode::Build(_, iss) => {
+            let (mut k, mut n) = (0, 0);
+            for is in iss {
+                let (mut k1, mut n1) = (1, 0);
+                for &i in is {
+                    let (k2, n2) = size_unroll_arena_loop(arena, memo, i);
+                    (k1, n1) = (k1 * k2, k1 * n2 + k2 * n1);
+                }
+                (k, n) = (k + k1, n + k1 + n1);
+            }
+            (k, n)
+        }
+    };
+    memo[l.0] = Some(kn);
+    kn
+}
+
+pub fn size_unroll_arena<C>(
+    arena: &GraphArena<C>,
+    l: LazyId,
+) -> (usize, usize) {
+    let mut memo = vec![None; arena.lazy_nodes.len()];
+    size_unroll_arena_loop(arena, &mut memo, l)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::lazy_mrsc;
+    use crate::mock_sc_world::mock_world;
+    use crate::statistics::{length_unroll, size_unroll};
+
+    fn bad(c: &isize) -> bool {
+        *c >= 3
+    }
+
+    #[test]
+    fn test_lazy_mrsc_arena() {
+        let s = mock_world();
+        let mut arena = GraphArena::new();
+        let l = lazy_mrsc_arena(&s, &mut arena, 0);
+        let rl = lazy_mrsc(&s, 0);
+        assert_eq!(arena.to_lazy_graph(l), rl);
+        let l2 = arena.add_lazy_graph(&rl);
+        assert_eq!(arena.to_lazy_graph(l2), rl);
+        let gs = unroll_arena(&mut arena, l);
+        assert_eq!(
+            gs.iter().map(|&g| arena.to_graph(g)).collect::<Vec<_>>(),
+            unroll(&rl)
+        );
+    }
+
+    #[test]
+    fn test_cleaners_arena() {
+        let s = mock_world();
+        let mut arena = GraphArena::new();
+        let l = lazy_mrsc_arena(&s, &mut arena, 0);
+        let rl = lazy_mrsc(&s, 0);
+        let l1 = cl_bad_conf_arena(&mut arena, bad, l);
+        assert_eq!(arena.to_lazy_graph(l1), cl_bad_conf(bad, &rl));
+        let l2 = cl_empty_arena(&mut arena, l1);
+        assert_eq!(arena.to_lazy_graph(l2), cl_empty_and_bad(bad, &rl));
+        let l3 = cl_min_size_arena(&mut arena, l);
+        assert_eq!(arena.to_lazy_graph(l3), cl_min_size(&rl));
+    }
+
+    #[test]
+    fn test_statistics_arena() {
+        let s = mock_world();
+        let mut arena = GraphArena::new();
+        let l = lazy_mrsc_arena(&s, &mut arena, 0);
+        let rl = lazy_mrsc(&s, 0);
+        assert_eq!(length_unroll_arena(&arena, l), length_unroll(&rl));
+        assert_eq!(size_unroll_arena(&arena, l), size_unroll(&rl));
+    }
+}
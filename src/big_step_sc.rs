@@ -32,6 +32,10 @@
 //   configurations such that `cs ∈ develop(c)`. Then `c` can be "reduced to"
 //   (or "decomposed into") configurations in `cs`.
 //
+// * `develop_with_history(h, c)` is the same as `develop(c)`, but a world
+//   may take into account the history `h` that leads to `c`
+//   (for example, in order to decide how to generalize `c`).
+//
 //   Suppose that driving is deterministic and, given a configuration `c`,
 //   produces a list of configurations `drive(c)`. Suppose that rebuilding
 //   (generalization, application of lemmas) is non-deterministic and
@@ -41,6 +45,16 @@
 //
 //       develop(c) = List(drive(c)) ::: rebuild(c).map(List(_))
 //
+//   A world has to implement `develop`. A world that implements `drive`
+//   and `rebuild` can define `develop(c)` as `drive_and_rebuild(self, c)`,
+//   which is the above. Conversely, by default `drive(c)` is the first
+//   alternative produced by `develop(c)`, and `rebuild(c)` consists
+//   of the remaining alternatives (each of them being expected to consist
+//   of a single configuration). (Hence, `drive_and_rebuild` must not be
+//   used as `develop` unless both `drive` and `rebuild` are implemented.)
+//   Generic code (statistics, strategies, logging) may use `drive` and
+//   `rebuild` to tell driving steps from rebuildings.
+//
 // * `History` is a list of configuration that have been produced
 //   in order to reach the current configuration.
 //
@@ -51,12 +65,23 @@
 // * `is_foldable_to_history(c, h)` means that `c` is foldable to a configuration
 //   in the history `h`.
 
+use crate::error::{ResourceLimits, StagedMrscError};
 use crate::graph::*;
-use crate::misc::{cartesian, History};
+#[cfg(feature = "rayon")]
+use crate::misc::cartesian;
+use crate::misc::{CartesianIter, History};
 
-use iter_comprehensions::{map, vec as vec_map};
-use itertools::Itertools;
+#[cfg(feature = "rayon")]
+use crate::sync_graph::{graphs_from_arc, ArcGraph, ArcGs};
+use iter_comprehensions::vec as vec_map;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt;
 use std::rc::Rc;
+#[cfg(feature = "rayon")]
+use std::sync::Arc;
 
 pub trait ScWorld {
     type C: Clone;
@@ -67,6 +92,35 @@ pub trait ScWorld {
 
     fn develop(&self, c: &Self::C) -> Vec<Vec<Self::C>>;
 
+    fn drive(&self, c: &Self::C) -> Vec<Self::C> {
+        self.develop(c).into_iter().next().unwrap_or_default()
+    }
+
+    fn rebuild(&self, c: &Self::C) -> Vec<Self::C> {
+        self.develop(c).into_iter().skip(1).flatten().collect()
+    }
+
+    fn develop_with_history(
+        &self,
+        _h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Vec<Vec<Self::C>> {
+        self.develop(c)
+    }
+
+    // The same as `develop_with_history`, but the alternatives
+    // are produced on demand. All the drivers use `develop_iter` (and
+    // the adapters forward it), so that a world with a big number of
+    // alternatives need not build all of them up front.
+
+    fn develop_iter<'a>(
+        &'a self,
+        h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Box<dyn Iterator<Item = Vec<Self::C>> + 'a> {
+        Box::new(self.develop_with_history(h, c).into_iter())
+    }
+
     fn is_foldable_to_history(
         &self,
         c: &Self::C,
@@ -76,24 +130,100 @@ pub trait ScWorld {
     }
 }
 
+// `develop` for the worlds that implement `drive` and `rebuild`.
+
+pub fn drive_and_rebuild<S>(s: &S, c: &S::C) -> Vec<Vec<S::C>>
+where
+    S: ScWorld + ?Sized,
+{
+    let mut css = vec![s.drive(c)];
+    css.extend(s.rebuild(c).into_iter().map(|c1| vec![c1]));
+    css
+}
+
 // Big-step multi-result supercompilation
 // (The naive version builds Cartesian products immediately.)
+//
+// `naive_mrsc` builds the graphs for the subtrees of an alternative
+// and streams their Cartesian product into `forth` nodes, so that
+// the products of the graph lists are never materialized.
+//
+// `naive_mrsc_iter` produces the graphs one at a time, without
+// building the lists of graphs for the subtrees. The graphs for
+// a configuration in an alternative are re-built for each combination
+// of the graphs for the configurations preceding it. Thus, only
+// the current combination is kept in memory (at each level), and
+// the first graph is produced as soon as it is found, at the cost of
+// developing the configurations again and again.
+//
+//     naive_mrsc_iter(s, c0).collect::<Vec<_>>() == naive_mrsc(s, c0)
 
 fn naive_mrsc_loop<S>(s: &S, h: &History<S::C>, c: S::C) -> Gs<S::C>
 where
     S: ScWorld,
 {
-    if s.is_foldable_to_history(&c, &h) {
-        return vec![back(&c)];
-    } else if s.is_dangerous(&h) {
-        return vec![];
-    } else {
-        let css = s.develop(&c);
-        let h1 = h.cons(c.clone());
-        let gsss = map!(cartesian(&vec_map!(naive_mrsc_loop(s, &h1, c1); c1 in cs));
-                cs in css);
-        return vec_map!(forth(&c, &gs); gs in Itertools::concat(gsss));
+    match sc_step(s, h, &c) {
+        ScEvent::Folded => vec![back(&c)],
+        ScEvent::Pruned => vec![],
+        ScEvent::Expanded => {
+            let h1 = h.cons(c.clone());
+            let mut gs = Vec::new();
+            for cs in s.develop_iter(h, &c) {
+                let gss = vec_map!(naive_mrsc_loop(s, &h1, c1); c1 in cs);
+                gs.extend(CartesianIter::new(gss).map(|gs1| forth(&c, &gs1)));
+            }
+            gs
+        }
+    }
+}
+
+fn naive_mrsc_iter_loop<'a, S>(
+    s: &'a S,
+    h: &History<S::C>,
+    c: S::C,
+) -> Box<dyn Iterator<Item = Rc<Graph<S::C>>> + 'a>
+where
+    S: ScWorld,
+{
+    match sc_step(s, h, &c) {
+        ScEvent::Folded => Box::new(std::iter::once(back(&c))),
+        ScEvent::Pruned => Box::new(std::iter::empty()),
+        ScEvent::Expanded => {
+            let css = s.develop_iter(h, &c);
+            let h1 = h.cons(c.clone());
+            Box::new(css.flat_map(move |cs| {
+                let c = c.clone();
+                naive_mrsc_product(s, &h1, &Rc::new(cs), 0)
+                    .map(move |gs| forth(&c, &gs))
+            }))
+        }
+    }
+}
+
+// The combinations of the graphs for `cs[k..]`.
+
+fn naive_mrsc_product<'a, S>(
+    s: &'a S,
+    h: &History<S::C>,
+    cs: &Rc<Vec<S::C>>,
+    k: usize,
+) -> Box<dyn Iterator<Item = Gs<S::C>> + 'a>
+where
+    S: ScWorld,
+{
+    if k == cs.len() {
+        return Box::new(std::iter::once(Vec::new()));
     }
+    let (h, cs) = (h.clone(), Rc::clone(cs));
+    let gs = naive_mrsc_iter_loop(s, &h, cs[k].clone());
+    Box::new(gs.flat_map(move |g| {
+        naive_mrsc_product(s, &h, &cs, k + 1).map(move |gs1| {
+            let mut gs = Vec::with_capacity(gs1.len() + 1);
+            gs.push(Rc::clone(&g));
+            gs.extend(gs1);
+            gs
+        })
+    }))
 }
 
 pub fn naive_mrsc<S>(s: &S, c0: S::C) -> Gs<S::C>
@@ -103,6 +233,67 @@ where
     naive_mrsc_loop(s, &History::new(), c0)
 }
 
+pub fn naive_mrsc_iter<S>(
+    s: &S,
+    c0: S::C,
+) -> impl Iterator<Item = Rc<Graph<S::C>>> + '_
+where
+    S: ScWorld,
+{
+    naive_mrsc_iter_loop(s, &History::new(), c0)
+}
+
+// Parallel naive multi-result supercompilation
+//
+// The subtrees produced for different configurations are independent,
+// so that `naive_mrsc_par` (enabled by the `rayon` feature) develops them
+// in parallel. Since `Rc` cannot be shared between threads, the graphs
+// are first built as `ArcGraph`s (see `sync_graph`) and then converted
+// to `Graph`. (The history, whose nodes are `Arc`s, is shared
+// by the threads.)
+//
+//     naive_mrsc_par(s, c0) == naive_mrsc(s, c0)
+
+#[cfg(feature = "rayon")]
+fn naive_mrsc_par_loop<S>(s: &S, h: &History<S::C>, c: S::C) -> ArcGs<S::C>
+where
+    S: ScWorld + Sync,
+    S::C: Send + Sync,
+{
+    let css = match sc_step(s, h, &c) {
+        ScEvent::Folded => return vec![Arc::new(ArcGraph::Back(c))],
+        ScEvent::Pruned => return vec![],
+        ScEvent::Expanded => s.develop_iter(h, &c).collect::<Vec<_>>(),
+    };
+    let h1 = h.cons(c.clone());
+    let gsss: Vec<Vec<ArcGs<S::C>>> = css
+        .into_par_iter()
+        .map(|cs| {
+            let gss: Vec<ArcGs<S::C>> = cs
+                .into_par_iter()
+                .map(|c1| naive_mrsc_par_loop(s, &h1, c1))
+                .collect();
+            cartesian(&gss)
+        })
+        .collect();
+    let mut gs = Vec::new();
+    for gss in gsss {
+        for gs1 in gss {
+            gs.push(Arc::new(ArcGraph::Forth(c.clone(), gs1)));
+        }
+    }
+    gs
+}
+
+#[cfg(feature = "rayon")]
+pub fn naive_mrsc_par<S>(s: &S, c0: S::C) -> Gs<S::C>
+where
+    S: ScWorld + Sync,
+    S::C: Send + Sync,
+{
+    graphs_from_arc(&naive_mrsc_par_loop(s, &History::new(), c0))
+}
+
 // "Lazy" multi-result supercompilation.
 // (Cartesian products are not immediately built.)
 //
@@ -110,20 +301,115 @@ where
 // with get-graphs being an "interpreter" that evaluates the "program"
 // returned by lazy_mrsc.
 
-fn lazy_mrsc_loop<S>(s: &S, h: &History<S::C>, c: S::C) -> Rc<LazyGraph<S::C>>
+// Events reported to the hook of `lazy_mrsc_with_hook`, together
+// with the length of the current history (the depth of the node).
+//
+// * `Folded`: the configuration has been folded to the history.
+// * `Pruned`: the whistle has blown.
+// * `Expanded`: the configuration has been developed.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScEvent {
+    Folded,
+    Pruned,
+    Expanded,
+}
+
+// The step shared by all the drivers: a configuration `c` reached by
+// the history `h` is folded to the history, if possible. Otherwise,
+// it is pruned, if the whistle blows, or else it has to be developed
+// (by `develop_iter`, which is left to the driver, since some drivers
+// do something else before developing `c`).
+
+pub fn sc_step<S>(s: &S, h: &History<S::C>, c: &S::C) -> ScEvent
 where
-    S: ScWorld,
+    S: ScWorld + ?Sized,
 {
-    if s.is_foldable_to_history(&c, &h) {
-        stop(&c)
-    } else if s.is_dangerous(&h) {
-        empty()
+    if s.is_foldable_to_history(c, h) {
+        ScEvent::Folded
+    } else if s.is_dangerous(h) {
+        ScEvent::Pruned
     } else {
-        let css = s.develop(&c);
-        let h1 = h.cons(c.clone());
-        let ls: Vec<Ls<S::C>> = vec_map!(vec_map!(lazy_mrsc_loop(s, &h1, c1); c1 in cs);
-        cs in css);
-        build(&c, &ls)
+        ScEvent::Expanded
+    }
+}
+
+// `lazy_mrsc` keeps the nodes being developed in an explicit stack
+// (rather than using recursion), so that the depth of the lazy graph is
+// only limited by the heap. The configurations are developed (and the
+// hook is called) in the same order as by a depth-first, left-to-right
+// recursive traversal.
+
+struct LazyFrame<'a, C> {
+    c: C,
+    h1: History<C>,
+    css: Box<dyn Iterator<Item = Vec<C>> + 'a>,
+    // The rest of the alternative being developed (if any).
+    cs: Option<std::vec::IntoIter<C>>,
+    lss: Vec<Ls<C>>,
+    ls: Ls<C>,
+}
+
+// The hook may stop the development by returning an error.
+
+fn lazy_mrsc_loop<S, E>(
+    s: &S,
+    hook: &mut dyn FnMut(ScEvent, usize) -> Result<(), E>,
+    h: &History<S::C>,
+    c: S::C,
+) -> Result<Rc<LazyGraph<S::C>>, E>
+where
+    S: ScWorld,
+{
+    let mut stack: Vec<LazyFrame<'_, S::C>> = Vec::new();
+    let mut next = Some((h.clone(), c));
+    loop {
+        if let Some((h, c)) = next.take() {
+            let e = sc_step(s, &h, &c);
+            hook(e, h.length())?;
+            let l = match e {
+                ScEvent::Folded => Some(stop(&c)),
+                ScEvent::Pruned => Some(empty()),
+                ScEvent::Expanded => {
+                    let css = s.develop_iter(&h, &c);
+                    let h1 = h.cons(c.clone());
+                    stack.push(LazyFrame {
+                        lss: Vec::with_capacity(css.size_hint().0),
+                        c,
+                        h1,
+                        css,
+                        cs: None,
+                        ls: Vec::new(),
+                    });
+                    None
+                }
+            };
+            if let Some(l) = l {
+                match stack.last_mut() {
+                    None => return Ok(l),
+                    Some(f) => f.ls.push(l),
+                }
+            }
+        }
+        let f = stack.last_mut().unwrap();
+        if let Some(cs) = &mut f.cs {
+            if let Some(c1) = cs.next() {
+                next = Some((f.h1.clone(), c1));
+                continue;
+            }
+            f.lss.push(std::mem::take(&mut f.ls));
+        }
+        match f.css.next() {
+            Some(cs) => f.cs = Some(cs.into_iter()),
+            None => {
+                let f = stack.pop().unwrap();
+                let l = build(&f.c, &f.lss);
+                match stack.last_mut() {
+                    None => return Ok(l),
+                    Some(f1) => f1.ls.push(l),
+                }
+            }
+        }
     }
 }
 
@@ -131,13 +417,396 @@ pub fn lazy_mrsc<S>(s: &S, c0: S::C) -> Rc<LazyGraph<S::C>>
 where
     S: ScWorld,
 {
-    lazy_mrsc_loop(s, &History::new(), c0)
+    lazy_mrsc_with_hook(s, c0, &mut |_, _| {})
+}
+
+pub fn lazy_mrsc_with_hook<S>(
+    s: &S,
+    c0: S::C,
+    hook: &mut dyn FnMut(ScEvent, usize),
+) -> Rc<LazyGraph<S::C>>
+where
+    S: ScWorld,
+{
+    let mut hook1 = |e, d| {
+        hook(e, d);
+        Ok::<(), Infallible>(())
+    };
+    match lazy_mrsc_loop(s, &mut hook1, &History::new(), c0) {
+        Ok(l) => l,
+        Err(e) => match e {},
+    }
+}
+
+// `lazy_mrsc_checked` stops as soon as the depth of a node or the number
+// of nodes exceeds the limits (`limits.max_graphs` being ignored).
+// The nodes are counted as they are produced, so that a successful
+// result has at most `max_nodes` nodes (in the sense of `lazy_graph_size`).
+
+pub fn lazy_mrsc_checked<S>(
+    s: &S,
+    c0: S::C,
+    limits: &ResourceLimits,
+) -> Result<Rc<LazyGraph<S::C>>, StagedMrscError>
+where
+    S: ScWorld,
+{
+    let mut nodes = 0;
+    let mut hook = |_, depth| {
+        nodes += 1;
+        match (limits.max_depth, limits.max_nodes) {
+            (Some(d), _) if depth > d => Err(StagedMrscError::DepthLimit(d)),
+            (_, Some(n)) if nodes > n => Err(StagedMrscError::NodeLimit(n)),
+            _ => Ok(()),
+        }
+    };
+    lazy_mrsc_loop(s, &mut hook, &History::new(), c0)
+}
+
+//
+// Marking the leaves pruned by the whistle
+//
+// In the lazy graphs produced by `lazy_mrsc`, the subtrees pruned
+// by the whistle are `Empty()`, and they disappear from the graphs
+// produced by `unroll`. `lazy_mrsc_marked` keeps them as leaves:
+// a folded configuration `c` becomes `Stop(Conf(c))`, and a configuration
+// `c` at which the whistle has blown becomes `Stop(Pruned(c))`.
+// Thus, a graph produced by `unroll` is a complete proof iff it contains
+// no pruned leaves, while the other graphs are partial proofs, which
+// may be useful for tuning the whistle.
+//
+//     unmark(lazy_mrsc_marked(s, c)) == lazy_mrsc(s, c)
+//
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Marked<C> {
+    Conf(C),
+    Pruned(C),
+}
+
+impl<C> Marked<C> {
+    pub fn conf(&self) -> &C {
+        match self {
+            Marked::Conf(c) => c,
+            Marked::Pruned(c) => c,
+        }
+    }
+
+    pub fn is_pruned(&self) -> bool {
+        matches!(self, Marked::Pruned(_))
+    }
+}
+
+impl<C: fmt::Display> fmt::Display for Marked<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Marked::Conf(c) => write!(f, "{}", c),
+            Marked::Pruned(c) => write!(f, "{}!", c),
+        }
+    }
+}
+
+fn lazy_mrsc_marked_loop<S>(
+    s: &S,
+    h: &History<S::C>,
+    c: S::C,
+) -> Rc<LazyGraph<Marked<S::C>>>
+where
+    S: ScWorld,
+{
+    match sc_step(s, h, &c) {
+        ScEvent::Folded => stop(&Marked::Conf(c)),
+        ScEvent::Pruned => stop(&Marked::Pruned(c)),
+        ScEvent::Expanded => {
+            let css = s.develop_iter(h, &c);
+            let h1 = h.cons(c.clone());
+            let lss = vec_map!(
+                vec_map!(lazy_mrsc_marked_loop(s, &h1, c1); c1 in cs);
+                cs in css);
+            build(&Marked::Conf(c), &lss)
+        }
+    }
+}
+
+pub fn lazy_mrsc_marked<S>(s: &S, c0: S::C) -> Rc<LazyGraph<Marked<S::C>>>
+where
+    S: ScWorld,
+{
+    lazy_mrsc_marked_loop(s, &History::new(), c0)
+}
+
+// Replaces the pruned leaves with `Empty()`.
+
+pub fn unmark<C: Clone>(l: &LazyGraph<Marked<C>>) -> Rc<LazyGraph<C>> {
+    match l {
+        LazyGraph::Empty() => empty(),
+        LazyGraph::Stop(Marked::Pruned(_)) => empty(),
+        LazyGraph::Stop(m) => stop(m.conf()),
+        LazyGraph::Build(m, lss) => {
+            let lss1 = vec_map!(vec_map!(unmark(l1); l1 in ls); ls in lss);
+            build(m.conf(), &lss1)
+        }
+    }
+}
+
+pub fn pruned_leaves<C>(g: &Graph<Marked<C>>) -> usize {
+    g.preorder()
+        .filter(|(_, m, kind)| *kind == NodeKind::Back && m.is_pruned())
+        .count()
+}
+
+//
+// Fold obligations
+//
+// A back node `Back(c)` means that `c` is foldable to some configuration
+// in the path leading to it. `fold_obligations(s, g)` makes these claims
+// explicit: for each back node it returns the path to the node (a list
+// of child indices), the configuration `c`, and the nearest ancestor `c'`
+// together with its depth, such that `s.is_foldable_to(c, c')`.
+// If there is no such ancestor, `ancestor` is `None`. Then `c` may still
+// be folded to a preceding sibling (a back node introduced by
+// `compress_siblings`): `sibling` is the index and the configuration
+// of the first preceding sibling `Forth(c', ..)` such that
+// `s.is_foldable_to(c, c')`. If neither exists, the obligation does
+// not hold.
+//
+// Thus fold steps can be checked one by one, without re-reading
+// the whole graph.
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct FoldObligation<C> {
+    pub path: Vec<usize>,
+    pub conf: C,
+    pub ancestor: Option<(usize, C)>,
+    pub sibling: Option<(usize, C)>,
+}
+
+impl<C> FoldObligation<C> {
+    pub fn holds(&self) -> bool {
+        self.ancestor.is_some() || self.sibling.is_some()
+    }
+}
+
+impl<C: fmt::Display> fmt::Display for FoldObligation<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = vec_map!(i.to_string(); i in self.path.iter());
+        write!(f, "[{}] {} ", path.join("."), self.conf)?;
+        match (&self.ancestor, &self.sibling) {
+            (Some((d, c)), _) => {
+                write!(f, "is covered by {} (depth {})", c, d)
+            }
+            (None, Some((i, c))) => {
+                write!(f, "is covered by the sibling {} (index {})", c, i)
+            }
+            (None, None) => write!(f, "is NOT covered by any ancestor"),
+        }
+    }
+}
+
+fn fold_obligations_loop<S: ScWorld>(
+    s: &S,
+    ancestors: &mut Vec<S::C>,
+    path: &mut Vec<usize>,
+    siblings: &[Rc<Graph<S::C>>],
+    g: &Graph<S::C>,
+    obs: &mut Vec<FoldObligation<S::C>>,
+) {
+    match g {
+        Graph::Back(c) => {
+            let ancestor = ancestors
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(_, c1)| s.is_foldable_to(c, c1))
+                .map(|(d, c1)| (d, c1.clone()));
+            let sibling =
+                match ancestor {
+                    Some(_) => None,
+                    None => siblings.iter().enumerate().find_map(|(i, g1)| {
+                        match &**g1 {
+                            Graph::Forth(c1, _) if s.is_foldable_to(c, c1) => {
+                                Some((i, c1.clone()))
+                            }
+                            _ => None,
+                        }
+                    }),
+                };
+            obs.push(FoldObligation {
+                path: path.clone(),
+                conf: c.clone(),
+                ancestor,
+                sibling,
+            });
+        }
+        Graph::Forth(c, gs) => {
+            ancestors.push(c.clone());
+            for (i, g1) in gs.iter().enumerate() {
+                path.push(i);
+                fold_obligations_loop(s, ancestors, path, &gs[..i], g1, obs);
+                path.pop();
+            }
+            ancestors.pop();
+        }
+    }
+}
+
+pub fn fold_obligations<S: ScWorld>(
+    s: &S,
+    g: &Graph<S::C>,
+) -> Vec<FoldObligation<S::C>> {
+    let mut obs = Vec::new();
+    fold_obligations_loop(s, &mut vec![], &mut vec![], &[], g, &mut obs);
+    obs
+}
+
+//
+// Bounded runs and the expansion frontier
+//
+// `BoundedRun::new(c0)` starts a run of lazy multi-result supercompilation
+// that develops configurations on demand: `run.expand(s, max_nodes)`
+// develops at most `max_nodes` configurations, breadth-first.
+// The configurations that have been reached, but not examined yet
+// (together with their histories) form the "frontier", which is returned
+// by `run.frontier()` as data. Thus a caller may inspect the frontier,
+// decide which nodes are worth developing, and resume the run
+// by `run.expand_nodes(s, ids, max_nodes)`, which first develops
+// the nodes `ids` (in this order) and then proceeds breadth-first.
+//
+// `run.graph()` returns the part of the lazy graph built so far,
+// the frontier nodes being replaced with `Empty()` (as in
+// `force_graph8_bfs`). Once the frontier is empty,
+//     run.graph() == lazy_mrsc(s, c0)
+//
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct FrontierNode<C> {
+    pub id: usize,
+    pub history: History<C>,
+    pub conf: C,
+}
+
+#[derive(Clone, Debug)]
+enum RunNode<C> {
+    Pending(History<C>, C),
+    Folded(C),
+    Pruned,
+    Built(C, Vec<Vec<usize>>),
+}
+
+#[derive(Clone, Debug)]
+pub struct BoundedRun<C> {
+    nodes: Vec<RunNode<C>>,
+    queue: VecDeque<usize>,
+}
+
+impl<C: Clone> BoundedRun<C> {
+    pub fn new(c0: C) -> BoundedRun<C> {
+        BoundedRun {
+            nodes: vec![RunNode::Pending(History::new(), c0)],
+            queue: VecDeque::from(vec![0]),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn frontier(&self) -> Vec<FrontierNode<C>> {
+        let mut fr = Vec::with_capacity(self.queue.len());
+        for &id in &self.queue {
+            if let RunNode::Pending(h, c) = &self.nodes[id] {
+                fr.push(FrontierNode {
+                    id,
+                    history: h.clone(),
+                    conf: c.clone(),
+                });
+            }
+        }
+        fr
+    }
+
+    // Returns the number of configurations developed.
+
+    pub fn expand<S>(&mut self, s: &S, max_nodes: usize) -> usize
+    where
+        S: ScWorld<C = C>,
+    {
+        let mut developed = 0;
+        while let Some(id) = self.queue.pop_front() {
+            let (h, c) = match &self.nodes[id] {
+                RunNode::Pending(h, c) => (h.clone(), c.clone()),
+                _ => continue,
+            };
+            match sc_step(s, &h, &c) {
+                ScEvent::Folded => self.nodes[id] = RunNode::Folded(c),
+                ScEvent::Pruned => self.nodes[id] = RunNode::Pruned,
+                ScEvent::Expanded if developed >= max_nodes => {
+                    self.queue.push_front(id);
+                    break;
+                }
+                ScEvent::Expanded => {
+                    developed += 1;
+                    let css = s.develop_iter(&h, &c);
+                    let h1 = h.cons(c.clone());
+                    let mut iss = Vec::with_capacity(css.size_hint().0);
+                    for cs in css {
+                        let mut is = Vec::with_capacity(cs.len());
+                        for c1 in cs {
+                            is.push(self.nodes.len());
+                            self.queue.push_back(self.nodes.len());
+                            self.nodes.push(RunNode::Pending(h1.clone(), c1));
+                        }
+                        iss.push(is);
+                    }
+                    self.nodes[id] = RunNode::Built(c, iss);
+                }
+            }
+        }
+        developed
+    }
+
+    // The ids that are not in the frontier are ignored.
+
+    pub fn expand_nodes<S>(
+        &mut self,
+        s: &S,
+        ids: &[usize],
+        max_nodes: usize,
+    ) -> usize
+    where
+        S: ScWorld<C = C>,
+    {
+        for &id in ids.iter().rev() {
+            if let Some(i) = self.queue.iter().position(|&id1| id1 == id) {
+                self.queue.remove(i);
+                self.queue.push_front(id);
+            }
+        }
+        self.expand(s, max_nodes)
+    }
+
+    pub fn graph(&self) -> Rc<LazyGraph<C>> {
+        self.graph_loop(0)
+    }
+
+    fn graph_loop(&self, id: usize) -> Rc<LazyGraph<C>> {
+        match &self.nodes[id] {
+            RunNode::Pending(_, _) | RunNode::Pruned => empty(),
+            RunNode::Folded(c) => stop(c),
+            RunNode::Built(c, iss) => {
+                let lss = vec_map!(vec_map!(self.graph_loop(*i); i in is);
+                    is in iss);
+                build(c, &lss)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::mock_sc_world::mock_world;
     use std::rc::Rc;
 
     fn gs3() -> Vec<Rc<Graph<isize>>> {
@@ -153,11 +822,100 @@ mod tests {
     }
 
     fn naive_mrsc_isize(c: isize) -> Gs<isize> {
-        naive_mrsc(&0isize, c)
+        naive_mrsc(&mock_world(), c)
     }
 
     fn lazy_mrsc_isize(c: isize) -> Rc<LazyGraph<isize>> {
-        lazy_mrsc(&0isize, c)
+        lazy_mrsc(&mock_world(), c)
+    }
+
+    // A chain 0 -> 1 -> ... -> n + 1, the last configuration being
+    // foldable to 0.
+
+    struct Chain(usize);
+
+    impl ScWorld for Chain {
+        type C = usize;
+
+        fn is_dangerous(&self, _h: &History<usize>) -> bool {
+            false
+        }
+
+        fn is_foldable_to(&self, c1: &usize, c2: &usize) -> bool {
+            *c1 == self.0 + 1 && *c2 == 0
+        }
+
+        fn develop(&self, c: &usize) -> Vec<Vec<usize>> {
+            vec![vec![c + 1]]
+        }
+    }
+
+    // `develop` assembled from `drive` and `rebuild`.
+
+    struct Halve;
+
+    impl ScWorld for Halve {
+        type C = isize;
+
+        fn is_dangerous(&self, h: &History<isize>) -> bool {
+            h.length() > 3
+        }
+
+        fn is_foldable_to(&self, c1: &isize, c2: &isize) -> bool {
+            c1 == c2
+        }
+
+        fn develop(&self, c: &isize) -> Vec<Vec<isize>> {
+            drive_and_rebuild(self, c)
+        }
+
+        fn drive(&self, c: &isize) -> Vec<isize> {
+            if *c > 1 {
+                vec![c / 2, c - c / 2]
+            } else {
+                vec![]
+            }
+        }
+
+        fn rebuild(&self, c: &isize) -> Vec<isize> {
+            if *c > 1 {
+                vec![1]
+            } else {
+                vec![]
+            }
+        }
+    }
+
+    #[test]
+    fn test_drive_and_rebuild() {
+        assert_eq!(Halve.develop(&3), vec![vec![1, 2], vec![1]]);
+        assert_eq!(Halve.develop(&1), vec![Vec::<isize>::new()]);
+        assert_eq!(unroll(&lazy_mrsc(&Halve, 2)).len(), 2);
+
+        // `drive` and `rebuild` derived from `develop`.
+        assert_eq!(Chain(3).drive(&1), vec![2]);
+        assert_eq!(Chain(3).rebuild(&1), Vec::<usize>::new());
+        let s = mock_world();
+        assert_eq!(s.drive(&0), s.develop(&0)[0]);
+        assert_eq!(s.rebuild(&0).len(), s.develop(&0).len() - 1);
+    }
+
+    #[test]
+    fn test_deep_lazy_mrsc() {
+        // The stack is too small for recursive versions of the
+        // functions (and of dropping the graphs).
+        let n = 5000;
+        let child = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || {
+                let l = lazy_mrsc(&Chain(n), 0);
+                let l1 = cl_min_size(&cl_empty(&l));
+                let gs = unroll(&l1);
+                assert_eq!(gs.len(), 1);
+                assert_eq!(gs[0].preorder().count(), n + 2);
+            })
+            .unwrap();
+        child.join().unwrap();
     }
 
     #[test]
@@ -165,11 +923,117 @@ mod tests {
         assert_eq!(naive_mrsc_isize(0), gs3())
     }
 
+    #[test]
+    fn test_naive_mrsc_iter() {
+        let s = mock_world();
+        assert_eq!(naive_mrsc_iter(&s, 0).collect::<Vec<_>>(), gs3());
+        assert_eq!(naive_mrsc_iter(&s, 0).next(), Some(gs3()[0].clone()));
+
+        // Each subtree of the root has 2^60 graphs.
+        let s = crate::mock_sc_world::MockScWorld::new(
+            |c: &usize| match *c {
+                0 => vec![vec![1, 1]],
+                c if c < 60 => vec![vec![c + 1], vec![c + 1]],
+                _ => vec![vec![]],
+            },
+            |_| vec![],
+            |_| false,
+        );
+        let g = naive_mrsc_iter(&s, 0).next().unwrap();
+        assert_eq!(g.preorder().count(), 121);
+    }
+
+    #[test]
+    fn test_lazy_mrsc_checked() {
+        let s = mock_world();
+        let l = lazy_mrsc_isize(0);
+        let limits = ResourceLimits::default();
+        assert_eq!(lazy_mrsc_checked(&s, 0, &limits), Ok(Rc::clone(&l)));
+        let limits = ResourceLimits {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            lazy_mrsc_checked(&s, 0, &limits),
+            Err(StagedMrscError::DepthLimit(1))
+        );
+        let limits = ResourceLimits {
+            max_nodes: Some(crate::statistics::lazy_graph_size(&l)),
+            ..Default::default()
+        };
+        assert_eq!(lazy_mrsc_checked(&s, 0, &limits), Ok(Rc::clone(&l)));
+        let limits = ResourceLimits {
+            max_nodes: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            lazy_mrsc_checked(&s, 0, &limits),
+            Err(StagedMrscError::NodeLimit(3))
+        );
+    }
+
     #[test]
     fn test_unroll_lazy_mrsc() {
         assert_eq!(unroll(&lazy_mrsc_isize(0)), gs3());
     }
 
+    #[test]
+    fn test_lazy_mrsc_marked() {
+        let s = mock_world();
+        let l = lazy_mrsc_marked(&s, 0);
+        assert_eq!(unmark(&l), lazy_mrsc(&s, 0));
+        let gs = unroll(&l);
+        let complete: Vec<_> =
+            gs.iter().filter(|g| pruned_leaves(g) == 0).collect();
+        assert_eq!(complete.len(), gs3().len());
+        assert_eq!(gs.len(), gs3().len() + 1);
+        assert_eq!(
+            graph_pretty_printer(&gs[gs.len() - 1])
+                .lines()
+                .last()
+                .unwrap()
+                .trim(),
+            "|__4!*"
+        );
+    }
+
+    #[test]
+    fn test_fold_obligations() {
+        let s = mock_world();
+        let obs = fold_obligations(&s, &gs3()[2]);
+        assert_eq!(
+            obs,
+            vec![
+                FoldObligation {
+                    path: vec![0, 0, 0, 0],
+                    conf: 0,
+                    ancestor: Some((0, 0)),
+                    sibling: None,
+                },
+                FoldObligation {
+                    path: vec![0, 0, 0, 1],
+                    conf: 2,
+                    ancestor: Some((2, 2)),
+                    sibling: None,
+                },
+            ]
+        );
+        assert_eq!(obs[1].to_string(), "[0.0.0.1] 2 is covered by 2 (depth 2)");
+        let obs = fold_obligations(&s, &forth(&1, &[back(&5)]));
+        assert_eq!(obs[0].ancestor, None);
+        assert!(!obs[0].holds());
+        // A back node folded to a preceding sibling.
+        let g = forth(&1, &[back(&3), forth(&5, &[back(&5)]), back(&5)]);
+        let obs = fold_obligations(&s, &g);
+        assert!(!obs[0].holds());
+        assert_eq!(obs[1].ancestor, Some((1, 5)));
+        assert_eq!((obs[2].ancestor, obs[2].sibling), (None, Some((1, 5))));
+        assert_eq!(
+            obs[2].to_string(),
+            "[2] 5 is covered by the sibling 5 (index 1)"
+        );
+    }
+
     #[test]
     fn test_min_size_cl() {
         assert_eq!(
@@ -177,4 +1041,44 @@ mod tests {
             [forth(&0, &[forth(&1, &[forth(&2, &[back(&1)])])])]
         );
     }
+
+    #[test]
+    fn test_bounded_run() {
+        let s = mock_world();
+        let mut run = BoundedRun::new(0);
+        assert_eq!(run.expand(&s, 2), 2);
+        assert!(!run.is_complete());
+        let fr = run.frontier();
+        assert_eq!(vec_map!(f.conf; f in fr.iter()), vec![2]);
+        assert_eq!(fr[0].history, History::new().cons(0).cons(1));
+        assert_eq!(unroll(&run.graph()), vec![]);
+        run.expand(&s, usize::MAX);
+        assert!(run.is_complete());
+        assert_eq!(run.frontier(), vec![]);
+        assert_eq!(run.graph(), lazy_mrsc(&s, 0));
+    }
+
+    #[test]
+    fn test_expand_nodes() {
+        // A binary tree.
+        let s = crate::mock_sc_world::MockScWorld::new(
+            |c: &isize| {
+                if *c < 3 {
+                    vec![vec![2 * c + 1, 2 * c + 2]]
+                } else {
+                    vec![]
+                }
+            },
+            |_| vec![],
+            |h| h.length() > 5,
+        );
+        let mut run = BoundedRun::new(0);
+        run.expand(&s, 1);
+        let fr = run.frontier();
+        assert_eq!(vec_map!(f.conf; f in fr.iter()), vec![1, 2]);
+        assert_eq!(run.expand_nodes(&s, &[fr[1].id], 1), 1);
+        assert_eq!(vec_map!(f.conf; f in run.frontier().iter()), vec![1, 5, 6]);
+        run.expand(&s, usize::MAX);
+        assert_eq!(run.graph(), lazy_mrsc(&s, 0));
+    }
 }
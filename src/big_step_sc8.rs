@@ -21,14 +21,14 @@ import StagedMRSC.BigStepSc:
     conf_type, is_foldable_to, is_dangerous, is_foldable_to_history, develop
 */
 
-use crate::big_step_sc::ScWorld;
+use crate::big_step_sc::{sc_step, ScEvent, ScWorld};
 use crate::graph::*;
 use crate::misc::*;
 
-use iter_comprehensions::{map, vec as vec_map};
-use lazy_st::*;
-use std::cell::RefCell;
-use std::rc::Rc;
+use iter_comprehensions::vec as vec_map;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::rc::{Rc, Weak};
 
 //
 // Infinite trees/graphs
@@ -80,11 +80,10 @@ fn mk_lazy_val<T>(f: Box<dyn FnOnce() -> T>) -> LazyVal<T> {
 }
 */
 
-// #[derive(Clone)]
 pub enum LazyGraph8<C: Clone> {
   Empty8(),
   Stop8(C),
-  Build8(C, Rc<Lazy<Vec<L8s<C>>>>),
+  Build8(C, Rc<Thunk8<C>>),
 }
 
 pub type L8s<C> = Vec<Rc<LazyGraph8<C>>>;
@@ -99,10 +98,156 @@ pub fn stop8<C: Clone>(c: &C) -> Rc<LazyGraph8<C>> {
   Rc::new(Stop8(c.clone()))
 }
 
-pub fn build8<C: Clone>(c: &C, l8ss: &Rc<Lazy<Vec<L8s<C>>>>) -> Rc<LazyGraph8<C>> {
+pub fn build8<C: Clone>(c: &C, l8ss: &Rc<Thunk8<C>>) -> Rc<LazyGraph8<C>> {
   Rc::new(Build8(c.clone(), Rc::clone(l8ss)))
 }
 
+//
+// Thunks
+//
+
+// The subtrees of a `Build8` node are produced by a thunk.
+// Once forced, a thunk keeps its value, so that repeated traversals
+// of a graph8 do not repeat the work.
+//
+// However, a thunk may be attached to a `MemoBudget`, which limits the
+// number of forced thunks that keep their values. When the limit is
+// exceeded, the least recently used thunks forget their values, to be
+// re-forced on demand. Hence, a huge graph8 can be traversed repeatedly
+// within a fixed memory budget.
+
+pub struct Thunk8<C: Clone> {
+  gen: Box<dyn Fn() -> Vec<L8s<C>>>,
+  value: RefCell<Option<Rc<Vec<L8s<C>>>>>,
+  budget: Option<Rc<MemoBudget>>,
+  stamp: Cell<u64>,
+}
+
+trait Evictable {
+  fn evict(&self, stamp: u64) -> bool;
+  fn is_current(&self, stamp: u64) -> bool;
+}
+
+pub struct MemoBudget {
+  capacity: usize,
+  clock: Cell<u64>,
+  resident: Cell<usize>,
+  queue: RefCell<VecDeque<(u64, Weak<dyn Evictable>)>>,
+}
+
+impl MemoBudget {
+  pub fn new(capacity: usize) -> Rc<MemoBudget> {
+    Rc::new(MemoBudget {
+      capacity,
+      clock: Cell::new(0),
+      resident: Cell::new(0),
+      queue: RefCell::new(VecDeque::new()),
+    })
+  }
+
+  // The number of forced thunks that currently keep their values.
+
+  pub fn resident(&self) -> usize {
+    self.resident.get()
+  }
+
+  // An entry of the queue becomes stale when its thunk is used again
+  // (or is evicted, or dropped). Once the stale entries outnumber
+  // the resident thunks (each of which has a single current entry),
+  // they are removed, so that the size of the queue stays proportional
+  // to the capacity.
+
+  fn push(&self, stamp: u64, t: Weak<dyn Evictable>) {
+    let mut queue = self.queue.borrow_mut();
+    queue.push_back((stamp, t));
+    if queue.len() > 2 * self.resident.get() + 16 {
+      queue.retain(|(s, t)| t.upgrade().is_some_and(|t| t.is_current(*s)));
+    }
+  }
+
+  fn touch(&self, stamp: &Cell<u64>, t: Weak<dyn Evictable>, forced: bool) {
+    let now = self.clock.get() + 1;
+    self.clock.set(now);
+    stamp.set(now);
+    self.push(now, t);
+    if forced {
+      self.resident.set(self.resident.get() + 1);
+    }
+    while self.resident.get() > self.capacity {
+      let entry = self.queue.borrow_mut().pop_front();
+      match entry {
+        None => break,
+        Some((s, t)) => {
+          if let Some(t) = t.upgrade() {
+            if t.evict(s) {
+              self.resident.set(self.resident.get() - 1);
+            }
+          }
+        }
+      }
+    }
+  }
+}
+
+impl<C: Clone + 'static> Thunk8<C> {
+  pub fn new(
+    gen: impl Fn() -> Vec<L8s<C>> + 'static,
+    budget: &Option<Rc<MemoBudget>>,
+  ) -> Rc<Thunk8<C>> {
+    Rc::new(Thunk8 {
+      gen: Box::new(gen),
+      value: RefCell::new(None),
+      budget: budget.clone(),
+      stamp: Cell::new(0),
+    })
+  }
+
+  pub fn force(this: &Rc<Thunk8<C>>) -> Rc<Vec<L8s<C>>> {
+    let cached = this.value.borrow().clone();
+    let (v, forced) = match cached {
+      Some(v) => (v, false),
+      None => {
+        let v = Rc::new((this.gen)());
+        *this.value.borrow_mut() = Some(Rc::clone(&v));
+        (v, true)
+      }
+    };
+    if let Some(budget) = &this.budget {
+      let t: Rc<dyn Evictable> = this.clone();
+      budget.touch(&this.stamp, Rc::downgrade(&t), forced);
+    }
+    v
+  }
+
+  pub fn is_forced(&self) -> bool {
+    self.value.borrow().is_some()
+  }
+}
+
+impl<C: Clone> Evictable for Thunk8<C> {
+  // Only the most recent use of a thunk is taken into account.
+  fn evict(&self, stamp: u64) -> bool {
+    if self.is_current(stamp) {
+      *self.value.borrow_mut() = None;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn is_current(&self, stamp: u64) -> bool {
+    stamp == self.stamp.get() && self.value.borrow().is_some()
+  }
+}
+
+impl<C: Clone> Drop for Thunk8<C> {
+  fn drop(&mut self) {
+    if let (Some(budget), Some(_)) = (&self.budget, self.value.get_mut()) {
+      budget.resident.set(budget.resident.get() - 1);
+    }
+  }
+}
+
 // build_graph8
 
 /*
@@ -122,23 +267,33 @@ end
 
 fn build_graph8_loop<S>(
   s: &'static S,
+  budget: &Option<Rc<MemoBudget>>,
   h: &History<S::C>,
   c: &S::C,
 ) -> Rc<LazyGraph8<S::C>>
 where
   S: ScWorld,
+  S::C: 'static,
 {
-  if s.is_foldable_to_history(c, h) {
-    stop8(c)
-  } else if s.is_dangerous(h) {
-    empty8()
-  } else {
-    let css = s.develop(c);
-    let h1 = h.cons(c.clone());
-    let l8ss: Rc<Lazy<Vec<L8s<S::C>>>> = Rc::new(lazy!(
-      vec_map!(vec_map!(build_graph8_loop(s, &h1, &c1); c1 in cs); cs in css)
-    ));
-    build8(&c, &l8ss)
+  match sc_step(s, h, c) {
+    ScEvent::Folded => stop8(c),
+    ScEvent::Pruned => empty8(),
+    ScEvent::Expanded => {
+      let h0 = h.clone();
+      let h1 = h.cons(c.clone());
+      let c1 = c.clone();
+      let budget1 = budget.clone();
+      let l8ss = Thunk8::new(
+        move || {
+          let css = s.develop_iter(&h0, &c1);
+          vec_map!(
+            vec_map!(build_graph8_loop(s, &budget1, &h1, &c2); c2 in cs);
+            cs in css)
+        },
+        budget,
+      );
+      build8(c, &l8ss)
+    }
   }
 }
 
@@ -152,8 +307,24 @@ end
 pub fn build_graph8<S>(s: &'static S, c0: &S::C) -> Rc<LazyGraph8<S::C>>
 where
   S: ScWorld,
+  S::C: 'static,
+{
+  build_graph8_loop(s, &None, &History::new(), c0)
+}
+
+// The same as `build_graph8`, but the number of forced thunks
+// that keep their values is limited by `budget`.
+
+pub fn build_graph8_bounded<S>(
+  s: &'static S,
+  c0: &S::C,
+  budget: &Rc<MemoBudget>,
+) -> Rc<LazyGraph8<S::C>>
+where
+  S: ScWorld,
+  S::C: 'static,
 {
-  build_graph8_loop(s, &History::new(), c0)
+  build_graph8_loop(s, &Some(Rc::clone(budget)), &History::new(), c0)
 }
 
 // prune_graph8
@@ -188,6 +359,7 @@ fn prune_graph8_loop<S>(
 ) -> Rc<LazyGraph<S::C>>
 where
   S: ScWorld,
+  S::C: 'static,
 {
   match &**l {
     Empty8() => empty(),
@@ -198,7 +370,7 @@ where
       } else {
         let h1 = h.cons(c.clone());
         let lss = vec_map!(vec_map!(prune_graph8_loop(s, &h1, &l1); l1 in ls);
-                    ls in (*l8ss).clone());
+                    ls in Thunk8::force(l8ss).iter());
         build(&c, &lss)
       }
     }
@@ -218,10 +390,67 @@ pub fn prune_graph8<S>(
 ) -> Rc<LazyGraph<S::C>>
 where
   S: ScWorld,
+  S::C: 'static,
 {
   prune_graph8_loop(s, &History::new(), l0)
 }
 
+// force_graph8_bfs
+//
+// Consumers of a graph8 (such as `prune_graph8`) force thunks
+// depth-first. If the graph8 is infinite (or just huge), a limit on
+// the number of forced thunks is exhausted by the first branch.
+// `force_graph8_bfs(l, max_nodes)` forces the thunks breadth-first,
+// at most `max_nodes` of them, and returns the finite prefix of `l`
+// thus explored. The `Build8` nodes whose thunks have not been forced
+// are replaced with `Empty()` (no graphs are known for them).
+//
+// The values of the thunks are kept by the scheduler itself, so that
+// the prefix is not affected by a `MemoBudget`.
+
+fn prefix_graph8<C: Clone + 'static>(
+  forced: &HashMap<*const Thunk8<C>, Rc<Vec<L8s<C>>>>,
+  l: &Rc<LazyGraph8<C>>,
+) -> Rc<LazyGraph<C>> {
+  match &**l {
+    Empty8() => empty(),
+    Stop8(c) => stop(c),
+    Build8(c, l8ss) => match forced.get(&Rc::as_ptr(l8ss)) {
+      None => empty(),
+      Some(l8ss) => {
+        let lss = vec_map!(vec_map!(prefix_graph8(forced, l1); l1 in ls);
+                    ls in l8ss.iter());
+        build(c, &lss)
+      }
+    },
+  }
+}
+
+pub fn force_graph8_bfs<C: Clone + 'static>(
+  l: &Rc<LazyGraph8<C>>,
+  max_nodes: usize,
+) -> Rc<LazyGraph<C>> {
+  let mut forced = HashMap::new();
+  let mut queue = VecDeque::new();
+  queue.push_back(Rc::clone(l));
+  while forced.len() < max_nodes {
+    let l1 = match queue.pop_front() {
+      None => break,
+      Some(l1) => l1,
+    };
+    if let Build8(_, l8ss) = &*l1 {
+      let v = Thunk8::force(l8ss);
+      for ls in v.iter() {
+        for l2 in ls {
+          queue.push_back(Rc::clone(l2));
+        }
+      }
+      forced.insert(Rc::as_ptr(l8ss), v);
+    }
+  }
+  prefix_graph8(&forced, l)
+}
+
 //
 // Now that we have decomposed `lazy_mrsc`
 //     lazy_mrsc ≗ prune_graph8 ∘ build_graph8
@@ -265,28 +494,45 @@ function cl8_bad_conf(bad, l::Build8{C})::LazyGraph8{C} where {C}
 end
 */
 
-fn cl8_bad_conf<C : 'static + Clone>(
-    bad: fn(&C) -> bool,
-    l: &'static Rc<LazyGraph8<C>>,
-) -> Rc<LazyGraph8<C>>
-{
-    match &**l {
-        Empty8() => empty8(),
-        Stop8(c) => {
-            if bad(c) {
-                empty8()
-            } else {
-                stop8(c)
-            }
-        }
-        Build8(c, l8ss) => {
-            let l8ss1: Rc<Lazy<Vec<L8s<C>>>> = Rc::new(lazy!(
-                vec_map!(vec_map!(cl8_bad_conf(bad, &l1); l1 in ls);
-                    ls in *l8ss)
-            ));
-            build8(&c, &l8ss1)
-        }
+pub fn cl8_bad_conf<C: 'static + Clone>(
+  bad: impl Fn(&C) -> bool + 'static,
+  l: &Rc<LazyGraph8<C>>,
+) -> Rc<LazyGraph8<C>> {
+  cl8_bad_conf_loop(&(Rc::new(bad) as Rc<dyn Fn(&C) -> bool>), l)
+}
+
+// The predicate is shared by the thunks of the cleaned graph.
+
+fn cl8_bad_conf_loop<C: 'static + Clone>(
+  bad: &Rc<dyn Fn(&C) -> bool>,
+  l: &Rc<LazyGraph8<C>>,
+) -> Rc<LazyGraph8<C>> {
+  match &**l {
+    Empty8() => empty8(),
+    Stop8(c) => {
+      if bad(c) {
+        empty8()
+      } else {
+        stop8(c)
+      }
     }
+    Build8(c, l8ss) => {
+      if bad(c) {
+        empty8()
+      } else {
+        let l8ss0 = Rc::clone(l8ss);
+        let bad = Rc::clone(bad);
+        let l8ss1 = Thunk8::new(
+          move || {
+            vec_map!(vec_map!(cl8_bad_conf_loop(&bad, l1); l1 in ls);
+              ls in Thunk8::force(&l8ss0).iter())
+          },
+          &l8ss.budget,
+        );
+        build8(c, &l8ss1)
+      }
+    }
+  }
 }
 
 /*
@@ -377,4 +623,71 @@ end
 #[cfg(test)]
 mod tests {
   use super::*;
+
+  use crate::big_step_sc::lazy_mrsc;
+  use crate::mock_sc_world::{mock_world, MockScWorld};
+
+  fn world() -> &'static MockScWorld<isize> {
+    Box::leak(Box::new(mock_world()))
+  }
+
+  #[test]
+  fn test_prune_graph8() {
+    let s = world();
+    let l8 = build_graph8(s, &0);
+    assert_eq!(prune_graph8(s, &l8), lazy_mrsc(s, 0));
+    assert_eq!(prune_graph8(s, &l8), lazy_mrsc(s, 0));
+  }
+
+  #[test]
+  fn test_cl8_bad_conf() {
+    let s = world();
+    let bad = |c: &isize| *c == 3;
+    let l8 = cl8_bad_conf(bad, &build_graph8(s, &0));
+    let l = lazy_mrsc(s, 0);
+    assert_eq!(cl_empty(&prune_graph8(s, &l8)), cl_empty_and_bad(bad, &l));
+  }
+
+  #[test]
+  fn test_force_graph8_bfs() {
+    let s = world();
+    let l8 = build_graph8(s, &0);
+    assert_eq!(force_graph8_bfs(&l8, usize::MAX), lazy_mrsc(s, 0));
+    assert_eq!(force_graph8_bfs(&l8, 0), empty());
+
+    // An infinite graph8: the whistle never blows.
+    let s: &'static MockScWorld<isize> = Box::leak(Box::new(MockScWorld::new(
+      |c: &isize| vec![vec![c + 1], vec![c + 2]],
+      |_c: &isize| vec![],
+      |_h: &History<isize>| false,
+    )));
+    let l8 = build_graph8(s, &0);
+    let node = |c, l1, l2| build(&c, &[vec![l1], vec![l2]]);
+    assert_eq!(
+      force_graph8_bfs(&l8, 3),
+      node(0, node(1, empty(), empty()), node(2, empty(), empty()))
+    );
+  }
+
+  #[test]
+  fn test_memo_budget() {
+    let s = world();
+    let budget = MemoBudget::new(2);
+    let l8 = build_graph8_bounded(s, &0, &budget);
+    let l = prune_graph8(s, &l8);
+    assert!(budget.resident() <= 2);
+    assert_eq!(l, lazy_mrsc(s, 0));
+    assert_eq!(prune_graph8(s, &l8), l);
+    assert!(budget.resident() <= 2);
+    // The queue only keeps a few stale entries.
+    for _ in 0..100 {
+      prune_graph8(s, &l8);
+    }
+    assert!(budget.queue.borrow().len() <= 2 * 2 + 17);
+    let l8_unbounded = build_graph8(s, &0);
+    prune_graph8(s, &l8_unbounded);
+    if let Build8(_, t) = &*l8_unbounded {
+      assert!(t.is_forced());
+    }
+  }
 }
@@ -1,15 +1,20 @@
 use crate::big_step_sc::*;
-use crate::misc::{cartesian, History};
+use crate::graph::{back, forth, Graph, LazyGraph};
+use crate::misc::{CartesianIter, History};
 
 use iter_comprehensions::vec as vec_map;
-use itertools::zip;
 use std::cmp::{Ordering, PartialOrd};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::convert::From;
+use std::error::Error;
 use std::fmt;
-use std::marker::PhantomData;
-use std::ops::{Add, Sub};
+use std::iter::zip;
+use std::ops::{Add, Div, Rem, Sub};
+use std::rc::Rc;
+use std::str::FromStr;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NW {
     N(isize),
     W(),
@@ -87,6 +92,34 @@ impl Sub<isize> for NW {
     }
 }
 
+// Division and remainder by a constant. The result is unknown (ω),
+// if the argument is unknown. Note that ω is compared with a number
+// as if it were equal to it: `ω == k`, `ω <= k` and `ω >= k` hold,
+// while `ω != k`, `ω < k` and `ω > k` do not. Thus, a guard like
+// `i % 2 == 0` is satisfied by ω, but `i % 2 != 0` is not.
+
+impl Div<isize> for NW {
+    type Output = NW;
+
+    fn div(self, j: isize) -> NW {
+        match self {
+            N(i) => N(i / j),
+            W() => W(),
+        }
+    }
+}
+
+impl Rem<isize> for NW {
+    type Output = NW;
+
+    fn rem(self, j: isize) -> NW {
+        match self {
+            N(i) => N(i % j),
+            W() => W(),
+        }
+    }
+}
+
 impl PartialOrd<isize> for NW {
     fn partial_cmp(&self, j: &isize) -> Option<Ordering> {
         match self {
@@ -111,6 +144,76 @@ impl PartialEq<isize> for NW {
     }
 }
 
+// By default, ω is compared with a number as if it were equal to it,
+// so that the guards `ω == k`, `ω <= k` and `ω >= k` are satisfied
+// (optimistic semantics). In a rule marked as `strict` (see
+// `counter_system!`), the counters in the guard are `StrictNW`s,
+// ω being interpreted as a number greater than any given one
+// (pessimistic semantics): `ω != k`, `ω >= k` and `ω > k` hold,
+// while `ω == k`, `ω <= k` and `ω < k` do not. This is the interpretation needed
+// to model lower bounds on the number of processes.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StrictNW(pub NW);
+
+impl PartialOrd<isize> for StrictNW {
+    fn partial_cmp(&self, j: &isize) -> Option<Ordering> {
+        match self.0 {
+            N(i) => i.partial_cmp(j),
+            W() => Some(Ordering::Greater),
+        }
+    }
+}
+
+impl PartialEq<isize> for StrictNW {
+    fn eq(&self, j: &isize) -> bool {
+        match self.0 {
+            N(i) => i == *j,
+            W() => false,
+        }
+    }
+}
+
+impl Add<isize> for StrictNW {
+    type Output = StrictNW;
+
+    fn add(self, j: isize) -> StrictNW {
+        StrictNW(self.0 + j)
+    }
+}
+
+impl Add<StrictNW> for StrictNW {
+    type Output = StrictNW;
+
+    fn add(self, nw: StrictNW) -> StrictNW {
+        StrictNW(self.0 + nw.0)
+    }
+}
+
+impl Sub<isize> for StrictNW {
+    type Output = StrictNW;
+
+    fn sub(self, j: isize) -> StrictNW {
+        StrictNW(self.0 - j)
+    }
+}
+
+impl Div<isize> for StrictNW {
+    type Output = StrictNW;
+
+    fn div(self, j: isize) -> StrictNW {
+        StrictNW(self.0 / j)
+    }
+}
+
+impl Rem<isize> for StrictNW {
+    type Output = StrictNW;
+
+    fn rem(self, j: isize) -> StrictNW {
+        StrictNW(self.0 % j)
+    }
+}
+
 fn is_in(nwi: &NW, nwj: &NW) -> bool {
     match (nwi, nwj) {
         (N(i), N(j)) => i == j,
@@ -119,7 +222,8 @@ fn is_in(nwi: &NW, nwj: &NW) -> bool {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NWC(pub Vec<NW>);
 
 impl fmt::Display for NWC {
@@ -129,250 +233,2778 @@ impl fmt::Display for NWC {
     }
 }
 
-pub trait CountersWorld {
-    fn start() -> NWC;
-    fn rules(c: &NWC) -> Vec<(bool, NWC)>;
-    fn is_unsafe(c: &NWC) -> bool;
-}
+// `NW` and `NWC` are read back from their `Display` form (such as
+// `(1,ω,2)`), so that graphs of counter systems can be archived
+// and parsed. `omega` is accepted in place of `ω`, and spaces
+// around the components are ignored.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseNWError(pub String);
 
-pub struct CountersScWorld<CW: CountersWorld> {
-    cw: PhantomData<CW>,
-    max_nw: isize,
-    max_depth: usize,
+impl fmt::Display for ParseNWError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot parse `{}`", self.0)
+    }
 }
 
-impl<CW: CountersWorld> CountersScWorld<CW> {
-    pub fn new(
-        _cw: CW,
-        max_nw: isize,
-        max_depth: usize,
-    ) -> CountersScWorld<CW> {
-        CountersScWorld {
-            cw: PhantomData,
-            max_nw: max_nw,
-            max_depth: max_depth,
+impl Error for ParseNWError {}
+
+impl FromStr for NW {
+    type Err = ParseNWError;
+
+    fn from_str(s: &str) -> Result<NW, ParseNWError> {
+        match s.trim() {
+            "ω" | "omega" => Ok(W()),
+            t => t.parse().map(N).map_err(|_| ParseNWError(s.to_string())),
         }
     }
 }
 
-fn is_too_big_nw(nw: NW, max_nw: isize) -> bool {
-    match nw {
-        W() => false,
-        N(i) => i >= max_nw,
+impl FromStr for NWC {
+    type Err = ParseNWError;
+
+    fn from_str(s: &str) -> Result<NWC, ParseNWError> {
+        let inner = s
+            .trim()
+            .strip_prefix('(')
+            .and_then(|t| t.strip_suffix(')'))
+            .ok_or_else(|| ParseNWError(s.to_string()))?;
+        if inner.trim().is_empty() {
+            return Ok(NWC(vec![]));
+        }
+        let nws: Result<Vec<NW>, _> =
+            inner.split(',').map(NW::from_str).collect();
+        nws.map(NWC).map_err(|_| ParseNWError(s.to_string()))
     }
 }
 
-fn is_too_big(c: &NWC, max_nw: isize) -> bool {
-    c.0.iter().any(|&nw| is_too_big_nw(nw, max_nw))
-}
+//
+// Shapes of configurations
+//
 
-fn drive<CW: CountersWorld>(c: &NWC) -> Vec<NWC> {
-    vec_map!(pr.1; pr in CW::rules(c), pr.0)
-}
+// The shape of a configuration keeps its ω-pattern and the relative
+// order of its finite values, each finite value being replaced with
+// its rank among the distinct finite values of the configuration.
+// For example, the shape of (ω,3,0,3) is (ω,#1,#0,#1).
 
-fn rebuild1(nw: &NW) -> Vec<NW> {
-    match nw {
-        N(_) => vec![nw.clone(), W()],
-        W() => vec![W()],
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Shape(pub Vec<Option<usize>>);
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rs = vec_map!(match r {
+            Some(r) => format!("#{}", r),
+            None => "ω".to_string(),
+        }; r in &self.0);
+        write!(f, "({})", rs.join(","))
     }
 }
 
-fn rebuild(c: &NWC) -> Vec<Vec<NWC>> {
-    let nwss: Vec<Vec<NW>> = cartesian(&vec_map!(rebuild1(nw); nw in &c.0));
-    let cs = vec_map!(NWC(nws); nws in nwss);
-    vec_map!(vec![c1]; c1 in cs, &c1 != c)
+pub fn shape(c: &NWC) -> Shape {
+    let mut is = Vec::new();
+    for nw in &c.0 {
+        if let N(i) = nw {
+            is.push(*i);
+        }
+    }
+    is.sort();
+    is.dedup();
+    Shape(vec_map!(match nw {
+        N(i) => is.binary_search(i).ok(),
+        W() => None,
+    }; nw in &c.0))
 }
 
-impl<CW: CountersWorld> ScWorld for CountersScWorld<CW> {
-    type C = NWC;
-
-    fn is_dangerous(&self, h: &History<Self::C>) -> bool {
-        h.any(|c| is_too_big(c, self.max_nw)) || h.length() >= self.max_depth
+fn add_shapes(g: &Graph<NWC>, counts: &mut BTreeMap<Shape, usize>) {
+    match g {
+        Graph::Back(c) => *counts.entry(shape(c)).or_insert(0) += 1,
+        Graph::Forth(c, gs) => {
+            *counts.entry(shape(c)).or_insert(0) += 1;
+            for g1 in gs {
+                add_shapes(g1, counts);
+            }
+        }
     }
+}
 
-    fn is_foldable_to(&self, c1: &Self::C, c2: &Self::C) -> bool {
-        zip(&c1.0, &c2.0).all(|(nw1, nw2)| is_in(nw1, nw2))
-    }
+// Counts the nodes of each shape in a collection of graphs.
 
-    fn develop(&self, c: &Self::C) -> Vec<Vec<Self::C>> {
-        [vec![drive::<CW>(c)], rebuild(c)].concat()
+pub fn shape_counts<'a>(
+    gs: impl IntoIterator<Item = &'a Graph<NWC>>,
+) -> BTreeMap<Shape, usize> {
+    let mut counts = BTreeMap::new();
+    for g in gs {
+        add_shapes(g, &mut counts);
     }
+    counts
 }
 
-#[macro_export]
-macro_rules! counter_system {
-    (@mk_params $c:ident, $($i:ident),*) => {
-        let mut _k = 0;
-        counter_system!(@mk_params_tail $c, _k, $($i),*)
-    };
-    (@mk_params_tail $c:ident, $k:ident, $($i:ident),*) => {
-        $(
-            #[allow(unused_variables)]
-            let $i = $c.0[$k]; $k += 1;
-        )*
-    };
-    (@to_nwc $($e:expr),*) => {
-        NWC(vec![$({let _nw:NW = $e.into(); _nw}),*])
-    };
-    (
-        $name:ident($($params:ident),*);
-        Start($($start:expr),*);
-        Unsafe($unsafe:expr);
-        Rules{
-            $($p:expr => $($e:expr),*;)*
-        }
-    ) => {
-        #[derive(Debug)]
-        struct $name;
-        impl CountersWorld for $name {
-            fn start() -> NWC {
-                counter_system!(@to_nwc $($start),*)
-            }
-            fn is_unsafe(_c: &NWC) -> bool {
-                counter_system!(@mk_params _c, $($params),*);
-                $unsafe
-            }
-
-            fn rules(_c: &NWC) -> Vec<(bool, NWC)> {
-                counter_system!(@mk_params _c, $($params),*);
+// Groups configurations by their shapes.
 
-                vec![
-                    $(($p, counter_system!(@to_nwc $($e),*))),*
-                ]
-            }
-        }
+pub fn group_by_shape<'a>(
+    cs: impl IntoIterator<Item = &'a NWC>,
+) -> BTreeMap<Shape, Vec<NWC>> {
+    let mut groups: BTreeMap<Shape, Vec<NWC>> = BTreeMap::new();
+    for c in cs {
+        groups.entry(shape(c)).or_default().push(c.clone());
     }
+    groups
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// A counter world may carry data (for example, the parameters
+// of a family of protocols), so that its methods take `&self`.
 
-    use crate::graph::*;
-    use std::rc::Rc;
+pub trait CountersWorld {
+    fn start(&self) -> NWC;
+    fn rules(&self, c: &NWC) -> Vec<(bool, NWC)>;
+    fn is_unsafe(&self, c: &NWC) -> bool;
 
-    #[test]
-    fn test_nw_add() {
-        assert_eq!(N(1) + N(2), N(3));
-        assert_eq!(N(3) + W(), W());
-        assert_eq!(N(3) + 25, N(28));
+    // The name of the rule `r` (the `r`-th element of the list returned
+    // by `rules`). By default, rules are named by their indices.
+    fn rule_name(&self, r: usize) -> String {
+        r.to_string()
     }
 
-    #[test]
-    fn test_nw_cmp() {
-        assert!(N(2) >= 1);
-        assert!(N(2) >= 2);
-        assert!(!(N(1) >= 2));
-        assert!(W() >= 1);
-        assert!(N(2) == N(2));
-        assert!(N(2) == 2);
-        assert!(W() == 2);
+    // The same as `rules`, but each rule is accompanied by its name.
+    fn named_rules(&self, c: &NWC) -> Vec<(String, bool, NWC)> {
+        let rs = self.rules(c).into_iter().enumerate();
+        rs.map(|(r, (e, c1))| (self.rule_name(r), e, c1)).collect()
     }
 
-    #[test]
-    fn test_is_in() {
-        assert!(is_in(&N(2), &N(2)));
-        assert!(!(is_in(&N(2), &N(3))));
-        assert!(is_in(&N(2), &W()));
-        assert!(is_in(&W(), &W()));
-        assert!(!(is_in(&W(), &N(3))));
+    // Groups of interchangeable coordinates. Folding is performed
+    // modulo permutations of the coordinates within each group.
+    fn symmetries(&self) -> Vec<Vec<usize>> {
+        vec![]
     }
 
-    #[test]
-    fn test_into_nw() {
-        let nw25 = NW::from(25);
-        assert_eq!(nw25, N(25));
-        let nw30 = {
-            let _tmp: NW = 30.into();
-            _tmp
-        };
-        assert_eq!(nw30, N(30));
-        let w1 = NW::from(ω);
-        assert_eq!(w1, W());
-        let w2 = {
-            let _tmp: NW = ω.into();
-            _tmp
-        };
-        assert_eq!(w2, W());
+    // A family of start configurations, for initial conditions that cannot
+    // be described by a single configuration (e.g., "any number
+    // of processes are idle, and at most one is exclusive" is described
+    // by `(ω, 0)` and `(ω, 1)`). By default, the family consists
+    // of `start()`.
+    fn starts(&self) -> Vec<NWC> {
+        vec![self.start()]
     }
 
-    macro_rules! nwc {
-        ($($e:expr),*) => {
-            NWC(vec![$({let _nw:NW = $e.into(); _nw}),*])
-        };
-    }
-    #[test]
-    fn test_nwc() {
-        let i = N(10);
-        assert_eq!(
-            format!("{:?}", nwc!(25 + 1, ω, i + 1)),
-            "NWC([N(26), W, N(11)])"
-        );
-        assert_eq!(format!("{:?}", nwc!()), "NWC([])");
+    // The counters read and written by the rules and the counters
+    // read by `is_unsafe`, if they are known statically. Partial-order
+    // reduction is only performed for the worlds that provide them
+    // (see `drive_reduced`).
+    fn footprints(&self) -> Option<Footprints> {
+        None
     }
+}
 
-    #[test]
-    fn test_display_nwc() {
-        assert_eq!(nwc!(1, ω, 2).to_string(), "(1,ω,2)");
-        assert_eq!(nwc!().to_string(), "()");
+// The counters a rule reads (in its guard and in the right-hand sides)
+// and the counters it may change. Over-approximations are fine.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Footprint {
+    pub reads: Vec<usize>,
+    pub writes: Vec<usize>,
+}
+
+impl Footprint {
+    // Two rules are independent if neither of them writes a counter
+    // the other one reads or writes.
+    pub fn is_independent_of(&self, other: &Footprint) -> bool {
+        let disjoint =
+            |xs: &[usize], ys: &[usize]| xs.iter().all(|x| !ys.contains(x));
+        disjoint(&self.writes, &other.reads)
+            && disjoint(&self.writes, &other.writes)
+            && disjoint(&other.writes, &self.reads)
     }
+}
 
-    #[derive(Debug)]
-    struct TestCW0;
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Footprints {
+    pub rules: Vec<Footprint>,
+    pub unsafe_reads: Vec<usize>,
+}
 
-    impl CountersWorld for TestCW0 {
-        fn start() -> NWC {
-            nwc!(2, 0)
+// The policies are type parameters (rather than boxed trait objects),
+// so that a counter world is `Sync` whenever its policies are, and can
+// be shared between threads (as in `naive_mrsc_par`).
+
+pub struct CountersScWorld<
+    CW: CountersWorld,
+    Wh = BoundsWhistle,
+    Wi = PowersetWidening,
+> {
+    cw: CW,
+    whistle: Wh,
+    widening: Wi,
+    // The footprints, if partial-order reduction is enabled.
+    por: Option<Footprints>,
+    symmetries: SymmetryGroups,
+}
+
+// The groups of interchangeable coordinates are found once, since
+// `CountersWorld::symmetries` may be costly (for `counter_system!`,
+// it looks up the names of the counters), while `is_foldable_to`
+// is called at each step.
+
+struct SymmetryGroups {
+    groups: Vec<Vec<usize>>,
+    // Whether the `k`-th coordinate belongs to a group.
+    in_group: Vec<bool>,
+}
+
+impl SymmetryGroups {
+    fn new<CW: CountersWorld>(cw: &CW) -> SymmetryGroups {
+        let groups = cw.symmetries();
+        let n = groups.iter().flatten().map(|&k| k + 1).max().unwrap_or(0);
+        let mut in_group = vec![false; n];
+        for &k in groups.iter().flatten() {
+            in_group[k] = true;
         }
+        SymmetryGroups { groups, in_group }
+    }
 
-        fn rules(_c: &NWC) -> Vec<(bool, NWC)> {
-            let mut _k = 0;
-            let i = _c.0[_k];
-            _k += 1;
-            let j = _c.0[_k];
-            _k += 1;
+    fn contains(&self, k: usize) -> bool {
+        self.in_group.get(k).copied().unwrap_or(false)
+    }
+}
 
-            vec![
-                (i >= 1, nwc!(i - 1, j + 1)), //
-                (j >= 1, nwc!(i + 1, j - 1)), //
-            ]
+impl<CW: CountersWorld> CountersScWorld<CW> {
+    pub fn new(cw: CW, max_nw: isize, max_depth: usize) -> CountersScWorld<CW> {
+        CountersScWorld {
+            symmetries: SymmetryGroups::new(&cw),
+            cw,
+            whistle: BoundsWhistle { max_nw, max_depth },
+            widening: PowersetWidening,
+            por: None,
         }
+    }
+}
 
-        fn is_unsafe(_: &NWC) -> bool {
-            false
+impl<CW, Wh, Wi> CountersScWorld<CW, Wh, Wi>
+where
+    CW: CountersWorld,
+    Wh: WhistlePolicy,
+    Wi: WideningPolicy,
+{
+    pub fn world(&self) -> &CW {
+        &self.cw
+    }
+
+    pub fn with_widening<Wi1: WideningPolicy>(
+        self,
+        widening: Wi1,
+    ) -> CountersScWorld<CW, Wh, Wi1> {
+        CountersScWorld {
+            cw: self.cw,
+            whistle: self.whistle,
+            widening,
+            por: self.por,
+            symmetries: self.symmetries,
         }
     }
 
-    counter_system! {
-        TestCW1(i,j);
-        Start(2, 0);
-        Unsafe(false);
-        Rules{
-            i >= 1 => i - 1, j + 1;
-            j >= 1 => i + 1, j - 1;
+    // Replaces the whistle given by `max_nw` and `max_depth`.
+
+    pub fn with_whistle<Wh1: WhistlePolicy>(
+        self,
+        whistle: Wh1,
+    ) -> CountersScWorld<CW, Wh1, Wi> {
+        CountersScWorld {
+            cw: self.cw,
+            whistle,
+            widening: self.widening,
+            por: self.por,
+            symmetries: self.symmetries,
         }
     }
 
-    fn mg() -> Rc<Graph<NWC>> {
-        forth(
-            &nwc!(2, 0),
-            &[forth(&nwc!(ω, ω), &[back(&nwc!(ω, ω)), back(&nwc!(ω, ω))])],
-        )
+    // Enables partial-order reduction in driving (see `drive_reduced`).
+    // It has no effect if the world provides no footprints.
+
+    pub fn with_partial_order_reduction(mut self) -> Self {
+        self.por = self.cw.footprints();
+        self
     }
 
-    fn run_counters_sc_world<CW: CountersWorld>(cw: CW, m: isize, d: usize) {
-        let s = CountersScWorld::new(cw, m, d);
-        let start_conf = CW::start();
-        let gs = naive_mrsc(&s, start_conf.clone());
-        let l = lazy_mrsc(&s, start_conf);
-        assert_eq!(unroll(&l), gs);
-        let ml = cl_min_size(&l);
-        assert_eq!(&unroll(&ml)[0], &mg());
+    // With partial-order reduction, a successor `c1` of `c` closes
+    // a cycle if `c1`, or a generalization of `c1`, is foldable to
+    // the history of `c1`.
+
+    fn drive_with_history(&self, h: &History<NWC>, c: &NWC) -> Vec<NWC> {
+        let fps = match &self.por {
+            Some(fps) => fps,
+            None => return drive(&self.cw, c),
+        };
+        let h1 = h.cons(c.clone());
+        let closes_cycle = |c1: &NWC| {
+            self.is_foldable_to_history(c1, &h1)
+                || self
+                    .widening
+                    .widen_iter(&h1, c1)
+                    .any(|c2| self.is_foldable_to_history(&c2, &h1))
+        };
+        drive_reduced(&self.cw, fps, c, |c1| !closes_cycle(c1))
     }
+}
 
-    #[test]
-    fn test_counters_sc_world() {
-        run_counters_sc_world(TestCW0, 3, 10);
-        run_counters_sc_world(TestCW1, 3, 10);
+fn is_too_big_nw(nw: NW, max_nw: isize) -> bool {
+    match nw {
+        W() => false,
+        N(i) => i >= max_nw,
+    }
+}
+
+fn is_too_big(c: &NWC, max_nw: isize) -> bool {
+    c.0.iter().any(|&nw| is_too_big_nw(nw, max_nw))
+}
+
+fn drive<CW: CountersWorld>(cw: &CW, c: &NWC) -> Vec<NWC> {
+    vec_map!(pr.1; pr in cw.rules(c), pr.0)
+}
+
+// Partial-order reduction
+//
+// Suppose that an enabled rule `r` is independent of all other rules
+// (see `Footprint`), including those that are disabled at `c`, and
+// does not write the counters read by `is_unsafe`. Then no other rule
+// can enable or disable `r`, or be enabled or disabled by `r`, so that
+// any path from `c` can be reordered to start with `r`, and reaches
+// an unsafe configuration iff the reordered one does. Hence it is
+// sufficient to explore only the successor produced by `r`.
+// (This is the "ample set" of size 1 in the sense of partial-order
+// reduction.) In addition, `r` is checked to commute at `c` with
+// the other enabled rules: `r1` and `r2` commute at `c` if both are
+// enabled at `c`, each of them remains enabled after applying the other,
+// and applying them in either order gives the same configuration.
+//
+// Independence is checked statically, since checking it only against
+// the rules enabled at `c` is unsound: a rule disabled at `c` may become
+// enabled later, and then interfere with `r`.
+//
+// However, the other rules must not be postponed forever, which happens
+// if the successor closes a cycle (the "cycle proviso"). Hence, a rule
+// is not chosen if the successor is `c` itself, and `CountersScWorld`
+// does not choose a rule if the successor (or a generalization of it)
+// is foldable to the history. Then all the enabled rules are applied.
+
+fn commute<CW: CountersWorld>(
+    cw: &CW,
+    rs: &[(bool, NWC)],
+    i: usize,
+    j: usize,
+) -> bool {
+    // The number of rules may depend on the configuration.
+    match (cw.rules(&rs[i].1).get(j), cw.rules(&rs[j].1).get(i)) {
+        (Some((eij, cij)), Some((eji, cji))) => *eij && *eji && cij == cji,
+        _ => false,
+    }
+}
+
+// A successor produced by a rule forming an ample set by itself is
+// only chosen if `admit` accepts it.
+
+fn drive_reduced<CW: CountersWorld>(
+    cw: &CW,
+    fps: &Footprints,
+    c: &NWC,
+    admit: impl Fn(&NWC) -> bool,
+) -> Vec<NWC> {
+    let rs = cw.rules(c);
+    let enabled = vec_map!(i; i in 0..rs.len(), rs[i].0);
+    if fps.rules.len() != rs.len() {
+        return vec_map!(rs[i].1.clone(); i in enabled);
+    }
+    let is_independent = |i: usize| {
+        let fp = &fps.rules[i];
+        fp.writes.iter().all(|k| !fps.unsafe_reads.contains(k))
+            && (0..rs.len())
+                .all(|j| j == i || fp.is_independent_of(&fps.rules[j]))
+    };
+    for &i in &enabled {
+        if is_independent(i)
+            && rs[i].1 != *c
+            && enabled.iter().all(|&j| j == i || commute(cw, &rs, i, j))
+            && admit(&rs[i].1)
+        {
+            return vec![rs[i].1.clone()];
+        }
+    }
+    vec_map!(rs[i].1.clone(); i in enabled)
+}
+
+fn rebuild1(nw: &NW) -> Vec<NW> {
+    match nw {
+        N(_) => vec![nw.clone(), W()],
+        W() => vec![W()],
+    }
+}
+
+// Generalizes `c` by replacing with ω all subsets of the coordinates
+// satisfying `p`.
+
+// The configurations are produced on demand, so that the 2^n
+// generalizations need not be stored.
+
+fn rebuild_where_iter(
+    c: &NWC,
+    p: &dyn Fn(usize) -> bool,
+) -> impl Iterator<Item = NWC> {
+    let nwss = vec_map!(
+        if p(k) { rebuild1(&c.0[k]) } else { vec![c.0[k]] };
+        k in 0..c.0.len());
+    let c = c.clone();
+    CartesianIter::new(nwss).map(NWC).filter(move |c1| *c1 != c)
+}
+
+fn rebuild_where(c: &NWC, p: impl Fn(usize) -> bool) -> Vec<NWC> {
+    rebuild_where_iter(c, &p).collect()
+}
+
+//
+// Widening policies
+//
+
+// A widening policy decides how a configuration `c` can be rebuilt
+// (generalized) by replacing some of its coordinates with ω.
+// `h` is the history leading to `c`.
+
+pub trait WideningPolicy {
+    fn widen(&self, h: &History<NWC>, c: &NWC) -> Vec<NWC>;
+
+    // The same as `widen`, but the configurations are produced on demand.
+
+    fn widen_iter<'a>(
+        &'a self,
+        h: &History<NWC>,
+        c: &NWC,
+    ) -> Box<dyn Iterator<Item = NWC> + 'a> {
+        Box::new(self.widen(h, c).into_iter())
+    }
+}
+
+// Any subset of the coordinates may be widened.
+
+pub struct PowersetWidening;
+
+impl WideningPolicy for PowersetWidening {
+    fn widen(&self, _h: &History<NWC>, c: &NWC) -> Vec<NWC> {
+        rebuild_where(c, |_| true)
+    }
+
+    fn widen_iter<'a>(
+        &'a self,
+        _h: &History<NWC>,
+        c: &NWC,
+    ) -> Box<dyn Iterator<Item = NWC> + 'a> {
+        Box::new(rebuild_where_iter(c, &|_| true))
+    }
+}
+
+// A coordinate may be widened only if its value has reached
+// the corresponding threshold. The coordinates without a threshold
+// (beyond the end of the vector) are never widened.
+
+pub struct ThresholdWidening(pub Vec<isize>);
+
+impl ThresholdWidening {
+    fn reached(&self, c: &NWC, k: usize) -> bool {
+        self.0.get(k).is_some_and(|&t| c.0[k] >= t)
+    }
+}
+
+impl WideningPolicy for ThresholdWidening {
+    fn widen(&self, _h: &History<NWC>, c: &NWC) -> Vec<NWC> {
+        rebuild_where(c, |k| self.reached(c, k))
+    }
+
+    fn widen_iter<'a>(
+        &'a self,
+        _h: &History<NWC>,
+        c: &NWC,
+    ) -> Box<dyn Iterator<Item = NWC> + 'a> {
+        Box::new(rebuild_where_iter(c, &|k| self.reached(c, k)))
+    }
+}
+
+// For each configuration `c1` in the history, the coordinates
+// that have grown on the way from `c1` to `c` are widened.
+
+pub struct GrowthWidening;
+
+fn grown(c1: &NWC, c: &NWC) -> Vec<usize> {
+    let mut ks = Vec::new();
+    for (k, (nw1, nw)) in zip(&c1.0, &c.0).enumerate() {
+        if let (N(i1), N(i)) = (nw1, nw) {
+            if i > i1 {
+                ks.push(k);
+            }
+        }
+    }
+    ks
+}
+
+impl WideningPolicy for GrowthWidening {
+    fn widen(&self, h: &History<NWC>, c: &NWC) -> Vec<NWC> {
+        let mut cs: Vec<NWC> = Vec::new();
+        for c1 in h.iter() {
+            let ks = grown(c1, c);
+            if ks.is_empty() {
+                continue;
+            }
+            let mut nws = c.0.clone();
+            for k in ks {
+                nws[k] = W();
+            }
+            let c2 = NWC(nws);
+            if !cs.contains(&c2) {
+                cs.push(c2);
+            }
+        }
+        cs
+    }
+}
+
+// Widening is postponed until `c` has been visited `delay` times,
+// `c` visiting each configuration `c1` of the history that it embeds
+// (`is_embedded(c1, c)`, i.e. `c` has been reached from `c1` without
+// decreasing any counter).
+
+pub struct DelayedWidening<P: WideningPolicy> {
+    pub delay: usize,
+    pub policy: P,
+}
+
+impl<P: WideningPolicy> DelayedWidening<P> {
+    fn is_delayed(&self, h: &History<NWC>, c: &NWC) -> bool {
+        h.iter()
+            .filter(|c1| is_embedded(c1, c))
+            .take(self.delay)
+            .count()
+            < self.delay
+    }
+}
+
+impl<P: WideningPolicy> WideningPolicy for DelayedWidening<P> {
+    fn widen(&self, h: &History<NWC>, c: &NWC) -> Vec<NWC> {
+        if self.is_delayed(h, c) {
+            vec![]
+        } else {
+            self.policy.widen(h, c)
+        }
+    }
+
+    fn widen_iter<'a>(
+        &'a self,
+        h: &History<NWC>,
+        c: &NWC,
+    ) -> Box<dyn Iterator<Item = NWC> + 'a> {
+        if self.is_delayed(h, c) {
+            Box::new(std::iter::empty())
+        } else {
+            self.policy.widen_iter(h, c)
+        }
+    }
+}
+
+//
+// Whistle policies
+//
+
+// A whistle policy decides whether the history `h` (the most recent
+// configuration first) has become dangerous, so that the current
+// configuration is not to be developed.
+
+pub trait WhistlePolicy {
+    fn is_dangerous(&self, h: &History<NWC>) -> bool;
+}
+
+// The whistle of `CountersScWorld::new(cw, max_nw, max_depth)`:
+// some counter has reached `max_nw`, or the history is `max_depth`
+// long.
+
+pub struct BoundsWhistle {
+    pub max_nw: isize,
+    pub max_depth: usize,
+}
+
+impl WhistlePolicy for BoundsWhistle {
+    fn is_dangerous(&self, h: &History<NWC>) -> bool {
+        h.any(|c| is_too_big(c, self.max_nw)) || h.length() >= self.max_depth
+    }
+}
+
+// `DepthWhistle(n)`: the history is at least `n` long.
+
+pub struct DepthWhistle(pub usize);
+
+impl WhistlePolicy for DepthWhistle {
+    fn is_dangerous(&self, h: &History<NWC>) -> bool {
+        h.length() >= self.0
+    }
+}
+
+// `SumWhistle(n)`: the sum of the finite counters of a configuration
+// has reached `n`.
+
+pub struct SumWhistle(pub isize);
+
+fn finite_sum(c: &NWC) -> isize {
+    c.0.iter()
+        .map(|nw| match nw {
+            N(i) => *i,
+            W() => 0,
+        })
+        .sum()
+}
+
+impl WhistlePolicy for SumWhistle {
+    fn is_dangerous(&self, h: &History<NWC>) -> bool {
+        h.any(|c| finite_sum(c) >= self.0)
+    }
+}
+
+// `CoordWhistle(ms)`: the counter `k` of a configuration has reached
+// `ms[k]` (for some `k`).
+
+pub struct CoordWhistle(pub Vec<isize>);
+
+impl WhistlePolicy for CoordWhistle {
+    fn is_dangerous(&self, h: &History<NWC>) -> bool {
+        h.any(|c| zip(&c.0, &self.0).any(|(nw, m)| is_too_big_nw(*nw, *m)))
+    }
+}
+
+// Homeomorphic embedding
+//
+// `c1` is embedded in `c` if `c1[k] <= c[k]` for all `k`, ω being
+// greater than any number. This is a well-quasi-order (Dickson's lemma),
+// so that any infinite sequence of configurations contains a pair
+// `c1`, ..., `c` with `c1` embedded in `c`.
+//
+// `embedding_whistle(h)` blows if the most recent configuration of `h`
+// embeds an earlier one having ω in the same coordinates. (Generalizing
+// a configuration always produces a configuration it is embedded in, so
+// that without this restriction the whistle would prune any branch
+// after the first generalization. The restricted relation is still
+// a well-quasi-order, since the sets of ω-coordinates are finitely
+// many.) Hence, the whistle blows on any infinite branch, without
+// bounds having to be chosen in advance. It can be used as `is_dangerous`
+// of any world whose configurations are `NWC`s, and as a whistle policy
+// (`EmbeddingWhistle`) of `CountersScWorld`.
+
+pub fn is_embedded(c1: &NWC, c: &NWC) -> bool {
+    zip(&c1.0, &c.0).all(|(nw1, nw)| match (nw1, nw) {
+        (N(i1), N(i)) => i1 <= i,
+        (_, W()) => true,
+        (W(), N(_)) => false,
+    })
+}
+
+fn same_omegas(c1: &NWC, c: &NWC) -> bool {
+    zip(&c1.0, &c.0).all(|(nw1, nw)| (*nw1 == W()) == (*nw == W()))
+}
+
+pub fn embedding_whistle(h: &History<NWC>) -> bool {
+    let mut cs = h.iter();
+    match cs.next() {
+        None => false,
+        Some(c) => cs.any(|c1| same_omegas(c1, c) && is_embedded(c1, c)),
+    }
+}
+
+pub struct EmbeddingWhistle;
+
+impl WhistlePolicy for EmbeddingWhistle {
+    fn is_dangerous(&self, h: &History<NWC>) -> bool {
+        embedding_whistle(h)
+    }
+}
+
+// The whistle blows if any of the whistles blows.
+
+pub struct AnyWhistle(pub Vec<Box<dyn WhistlePolicy>>);
+
+impl WhistlePolicy for AnyWhistle {
+    fn is_dangerous(&self, h: &History<NWC>) -> bool {
+        self.0.iter().any(|w| w.is_dangerous(h))
+    }
+}
+
+// Foldability modulo permutations within groups of coordinates.
+// Within a group, `c1` is foldable to `c2` iff the finite values of `c2`
+// form a sub-multiset of the finite values of `c1`: the remaining
+// values of `c1` can then be mapped to the ω-coordinates of `c2`.
+
+fn is_group_in(g: &[usize], c1: &NWC, c2: &NWC) -> bool {
+    let mut nws1 = vec_map!(c1.0[*k]; k in g);
+    for k in g {
+        if let N(_) = c2.0[*k] {
+            match nws1.iter().position(|nw1| *nw1 == c2.0[*k]) {
+                Some(i) => {
+                    nws1.swap_remove(i);
+                }
+                None => return false,
+            }
+        }
+    }
+    true
+}
+
+fn is_foldable_to_modulo(sg: &SymmetryGroups, c1: &NWC, c2: &NWC) -> bool {
+    zip(&c1.0, &c2.0)
+        .enumerate()
+        .all(|(k, (nw1, nw2))| sg.contains(k) || is_in(nw1, nw2))
+        && sg.groups.iter().all(|g| is_group_in(g, c1, c2))
+}
+
+impl<CW, Wh, Wi> ScWorld for CountersScWorld<CW, Wh, Wi>
+where
+    CW: CountersWorld,
+    Wh: WhistlePolicy,
+    Wi: WideningPolicy,
+{
+    type C = NWC;
+
+    fn is_dangerous(&self, h: &History<Self::C>) -> bool {
+        self.whistle.is_dangerous(h)
+    }
+
+    fn is_foldable_to(&self, c1: &Self::C, c2: &Self::C) -> bool {
+        is_foldable_to_modulo(&self.symmetries, c1, c2)
+    }
+
+    fn develop(&self, c: &Self::C) -> Vec<Vec<Self::C>> {
+        drive_and_rebuild(self, c)
+    }
+
+    fn drive(&self, c: &Self::C) -> Vec<Self::C> {
+        self.drive_with_history(&History::new(), c)
+    }
+
+    fn rebuild(&self, c: &Self::C) -> Vec<Self::C> {
+        self.widening.widen(&History::new(), c)
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Vec<Vec<Self::C>> {
+        let rebuilt = self.widening.widen(h, c);
+        let driven = self.drive_with_history(h, c);
+        [vec![driven], vec_map!(vec![c1]; c1 in rebuilt)].concat()
+    }
+
+    fn develop_iter<'a>(
+        &'a self,
+        h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Box<dyn Iterator<Item = Vec<Self::C>> + 'a> {
+        let driven = self.drive_with_history(h, c);
+        let rebuilt = self.widening.widen_iter(h, c).map(|c1| vec![c1]);
+        Box::new(std::iter::once(driven).chain(rebuilt))
+    }
+}
+
+//
+// Fold evidence
+//
+// `fold_evidence(cw, c1, c2)` explains why `c1` is (or is not)
+// foldable to `c2`. For each coordinate `k` outside the symmetry groups
+// it tells whether `c1[k] ∈ c2[k]`, and for each group whether `c1` is
+// foldable to `c2` modulo permutations within the group.
+//
+// `fold_obligations_report(s, g)` lists the fold obligations of `g`
+// (see `fold_obligations`), each followed by its evidence.
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum FoldEvidence {
+    Coord(usize, NW, NW, bool),
+    Group(Vec<usize>, bool),
+}
+
+fn holds(b: bool) -> &'static str {
+    if b {
+        "holds"
+    } else {
+        "FAILS"
+    }
+}
+
+impl fmt::Display for FoldEvidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FoldEvidence::Coord(k, nw1, nw2, b) => {
+                write!(f, "#{}: {} ∈ {} {}", k, nw1, nw2, holds(*b))
+            }
+            FoldEvidence::Group(g, b) => {
+                let ks = vec_map!(k.to_string(); k in g.iter());
+                write!(
+                    f,
+                    "#{}: modulo permutations {}",
+                    ks.join(","),
+                    holds(*b)
+                )
+            }
+        }
+    }
+}
+
+pub fn fold_evidence<CW: CountersWorld>(
+    cw: &CW,
+    c1: &NWC,
+    c2: &NWC,
+) -> Vec<FoldEvidence> {
+    let groups = cw.symmetries();
+    let in_group = |k: usize| groups.iter().any(|g| g.contains(&k));
+    let mut es = Vec::new();
+    for (k, (nw1, nw2)) in zip(&c1.0, &c2.0).enumerate() {
+        if !in_group(k) {
+            es.push(FoldEvidence::Coord(k, *nw1, *nw2, is_in(nw1, nw2)));
+        }
+    }
+    for g in &groups {
+        es.push(FoldEvidence::Group(g.clone(), is_group_in(g, c1, c2)));
+    }
+    es
+}
+
+pub fn fold_obligations_report<CW, Wh, Wi>(
+    s: &CountersScWorld<CW, Wh, Wi>,
+    g: &Graph<NWC>,
+) -> String
+where
+    CW: CountersWorld,
+    Wh: WhistlePolicy,
+    Wi: WideningPolicy,
+{
+    let mut lines = Vec::new();
+    for ob in fold_obligations(s, g) {
+        lines.push(ob.to_string());
+        if let Some((_, c2)) = ob.ancestor.as_ref().or(ob.sibling.as_ref()) {
+            for e in fold_evidence(&s.cw, &ob.conf, c2) {
+                lines.push(format!("    {}", e));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+//
+// ω-instantiation
+//
+// A residual graph for a counter system is an abstract proof: each
+// configuration in the graph represents a set of concrete configurations
+// (ω standing for any number). `instantiate(cw, g, n, max_states)`
+// checks the proof on a concrete instance of the system: ω's in the start
+// configuration are replaced with `n`, and all the concrete configurations
+// reachable from it are enumerated. Each of them must be safe
+// and covered by a configuration in `g` (in the sense of foldability).
+// At most `max_states` configurations are enumerated.
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum InstanceCheck {
+    // All reachable configurations are covered.
+    Closed(usize),
+    // The number of configurations exceeds `max_states`.
+    Incomplete(usize),
+    // A reachable configuration is not covered by the graph.
+    Escaped(NWC),
+    Unsafe(NWC),
+}
+
+fn instantiate_nw(nw: &NW, n: isize) -> NW {
+    match nw {
+        W() => N(n),
+        N(i) => N(*i),
+    }
+}
+
+pub fn instantiate<CW: CountersWorld>(
+    cw: &CW,
+    g: &Graph<NWC>,
+    n: isize,
+    max_states: usize,
+) -> InstanceCheck {
+    let sg = SymmetryGroups::new(cw);
+    let cs: Vec<&NWC> = g.preorder().map(|n| n.1).collect();
+    let start = NWC(vec_map!(instantiate_nw(nw, n); nw in cw.start().0.iter()));
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(start.clone());
+    queue.push_back(start);
+    while let Some(c) = queue.pop_front() {
+        if cw.is_unsafe(&c) {
+            return InstanceCheck::Unsafe(c);
+        }
+        if !cs.iter().any(|c1| is_foldable_to_modulo(&sg, &c, c1)) {
+            return InstanceCheck::Escaped(c);
+        }
+        for (enabled, c1) in cw.rules(&c) {
+            if enabled && !seen.contains(&c1) {
+                if seen.len() >= max_states {
+                    return InstanceCheck::Incomplete(seen.len());
+                }
+                seen.insert(c1.clone());
+                queue.push_back(c1);
+            }
+        }
+    }
+    InstanceCheck::Closed(seen.len())
+}
+
+//
+// Dead rules and constant coordinates
+//
+// `dead_code_analysis(cw, l)` inspects an (uncleaned) lazy graph `l`
+// produced for a counter world and reports
+// * the rules (given by their indices) that are not enabled in any
+//   developed configuration, and
+// * the coordinates that keep their initial value in all configurations.
+// Both usually indicate mistakes in the model, such as a mis-indexed
+// coordinate or a wrong guard.
+//
+// Only driving steps are followed (the first alternative of each `Build`
+// node), since generalization would make all guards satisfiable
+// by replacing coordinates with ω.
+//
+// Note that the findings only hold within the bounds of `l`:
+// the configurations pruned by the whistle (`Empty()` nodes) are not
+// examined, so that an "unfired" rule may still be enabled (and
+// a "constant" coordinate may still change) beyond the bounds.
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct DeadCodeReport {
+    // The rules not fired within the bounds of the lazy graph.
+    pub unfired_rules: Vec<usize>,
+    // The coordinates not changed within the bounds of the lazy graph.
+    pub constant_coordinates: Vec<usize>,
+}
+
+fn dead_code_loop<CW: CountersWorld>(
+    cw: &CW,
+    l: &LazyGraph<NWC>,
+    start: &NWC,
+    fired: &mut Vec<bool>,
+    changed: &mut Vec<bool>,
+) {
+    let c = match l {
+        LazyGraph::Empty() => return,
+        LazyGraph::Stop(c) => c,
+        LazyGraph::Build(c, _) => c,
+    };
+    for (k, (nw, nw0)) in zip(&c.0, &start.0).enumerate() {
+        changed[k] |= nw != nw0;
+    }
+    if let LazyGraph::Build(_, lss) = l {
+        for (r, (enabled, _)) in cw.rules(c).into_iter().enumerate() {
+            if r >= fired.len() {
+                fired.resize(r + 1, false);
+            }
+            fired[r] |= enabled;
+        }
+        for l1 in lss.first().into_iter().flatten() {
+            dead_code_loop(cw, l1, start, fired, changed);
+        }
+    }
+}
+
+pub fn dead_code_analysis<CW: CountersWorld>(
+    cw: &CW,
+    l: &LazyGraph<NWC>,
+) -> DeadCodeReport {
+    let start = cw.start();
+    let mut fired = vec![false; cw.rules(&start).len()];
+    let mut changed = vec![false; start.0.len()];
+    dead_code_loop(cw, l, &start, &mut fired, &mut changed);
+    DeadCodeReport {
+        unfired_rules: vec_map!(r; r in 0..fired.len(), !fired[r]),
+        constant_coordinates: vec_map!(k; k in 0..changed.len(), !changed[k]),
+    }
+}
+
+//
+// Linting counter systems
+//
+// `lint(cw, max_value)` looks for suspicious patterns in the definition
+// of a counter world, which usually indicate modeling mistakes.
+// Since rules are given by functions, the definition is examined by
+// probing: all configurations whose coordinates are in
+// {0, ..., max_value, ω} are tried. The following is reported:
+// * a rule that is enabled in a configuration, but makes a coordinate
+//   negative (with a witness),
+// * a rule that is not enabled in any probe (its guard is unsatisfiable),
+// * a coordinate that is never read: changing its value affects neither
+//   the guards, nor the values of the other coordinates, nor `is_unsafe`,
+// * an unsafe predicate that is trivially true (or false) on all probes,
+// * an unsafe start configuration.
+//
+// Note that the number of probes is `(max_value + 2)^n`, and that
+// the patterns are only detected within the probed range.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Lint {
+    NegativeUpdate {
+        rule: usize,
+        conf: NWC,
+        coordinate: usize,
+    },
+    UnsatisfiableGuard(usize),
+    UnreadCoordinate(usize),
+    AlwaysUnsafe,
+    NeverUnsafe,
+    UnsafeStart(NWC),
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lint::NegativeUpdate {
+                rule,
+                conf,
+                coordinate,
+            } => write!(
+                f,
+                "rule {} makes coordinate {} negative in {}",
+                rule, coordinate, conf
+            ),
+            Lint::UnsatisfiableGuard(r) => {
+                write!(f, "the guard of rule {} is never satisfied", r)
+            }
+            Lint::UnreadCoordinate(k) => {
+                write!(f, "coordinate {} is never read", k)
+            }
+            Lint::AlwaysUnsafe => {
+                write!(f, "the unsafe predicate is always true")
+            }
+            Lint::NeverUnsafe => {
+                write!(f, "the unsafe predicate is always false")
+            }
+            Lint::UnsafeStart(c) => {
+                write!(f, "the start configuration {} is unsafe", c)
+            }
+        }
+    }
+}
+
+fn lint_probes(n: usize, values: &[NW]) -> Vec<NWC> {
+    let mut cs = vec![vec![]];
+    for _ in 0..n {
+        let mut cs1 = Vec::with_capacity(cs.len() * values.len());
+        for c in &cs {
+            for nw in values {
+                let mut c1: Vec<NW> = c.clone();
+                c1.push(*nw);
+                cs1.push(c1);
+            }
+        }
+        cs = cs1;
+    }
+    vec_map!(NWC(c); c in cs)
+}
+
+// Whether `c` and `c1` (differing only in the coordinate `k`)
+// can be told apart without reading the coordinate `k`.
+
+fn reads_coordinate<CW: CountersWorld>(
+    cw: &CW,
+    k: usize,
+    c: &NWC,
+    c1: &NWC,
+) -> bool {
+    if cw.is_unsafe(c) != cw.is_unsafe(c1) {
+        return true;
+    }
+    zip(cw.rules(c), cw.rules(c1)).any(|((e, d), (e1, d1))| {
+        e != e1
+            || zip(&d.0, &d1.0)
+                .enumerate()
+                .any(|(j, (nw, nw1))| j != k && nw != nw1)
+    })
+}
+
+pub fn lint<CW: CountersWorld>(cw: &CW, max_value: isize) -> Vec<Lint> {
+    let n = cw.start().0.len();
+    let mut values = vec_map!(N(i); i in 0..=max_value);
+    values.push(W());
+    let probes = lint_probes(n, &values);
+    let mut lints = Vec::new();
+
+    for c in cw.starts() {
+        if cw.is_unsafe(&c) {
+            lints.push(Lint::UnsafeStart(c));
+        }
+    }
+
+    let mut satisfiable = vec![false; cw.rules(&cw.start()).len()];
+    let mut negative = vec![false; satisfiable.len()];
+    for c in &probes {
+        for (r, (enabled, c1)) in cw.rules(c).into_iter().enumerate() {
+            if !enabled {
+                continue;
+            }
+            satisfiable[r] = true;
+            if negative[r] {
+                continue;
+            }
+            if let Some(k) = c1.0.iter().position(|nw| *nw < 0) {
+                negative[r] = true;
+                lints.push(Lint::NegativeUpdate {
+                    rule: r,
+                    conf: c.clone(),
+                    coordinate: k,
+                });
+            }
+        }
+    }
+    for (r, &sat) in satisfiable.iter().enumerate() {
+        if !sat {
+            lints.push(Lint::UnsatisfiableGuard(r));
+        }
+    }
+
+    // It is sufficient to compare the configurations that differ
+    // in adjacent values of a coordinate.
+    for k in 0..n {
+        let read = probes.iter().any(|c| {
+            let i = values.iter().position(|nw| *nw == c.0[k]).unwrap();
+            i + 1 < values.len() && {
+                let mut c1 = c.clone();
+                c1.0[k] = values[i + 1];
+                reads_coordinate(cw, k, c, &c1)
+            }
+        });
+        if !read {
+            lints.push(Lint::UnreadCoordinate(k));
+        }
+    }
+
+    if probes.iter().all(|c| cw.is_unsafe(c)) {
+        lints.push(Lint::AlwaysUnsafe);
+    } else if !probes.iter().any(|c| cw.is_unsafe(c)) {
+        lints.push(Lint::NeverUnsafe);
+    }
+    lints
+}
+
+//
+// Shortest unsafe traces
+//
+
+// A breadth-first exploration of the transition relation of a counter
+// world, independent of supercompilation. Returns the shortest sequence
+// of rules (given by their indices) leading from the start configuration
+// to an unsafe one, provided that there is such a sequence whose length
+// does not exceed `max_steps`.
+
+pub fn shortest_unsafe_trace<CW: CountersWorld>(
+    cw: &CW,
+    max_steps: usize,
+) -> Option<Vec<usize>> {
+    unsafe_trace(cw, cw.start(), max_steps).map(|t| t.rules)
+}
+
+//
+// Bounded model checking
+//
+// `bmc(cw, k)` unrolls the transition relation of `cw` to depth `k`
+// (without generalization and folding) and returns a trace leading
+// to an unsafe configuration, if there is one. Thus, bounded model
+// checking looks for bugs, while supercompilation looks for proofs.
+// ω's in the start configuration are kept (ω standing for
+// "sufficiently many processes"), while `bmc_instance(cw, n, k)`
+// replaces them with `n`, producing a concrete trace. `bmc` tries all
+// the start configurations (see `CountersWorld::starts`), returning
+// the shortest trace found, while `bmc_instance` only uses `start()`.
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Trace {
+    // `confs[i + 1]` is produced from `confs[i]` by the rule `rules[i]`.
+    pub confs: Vec<NWC>,
+    pub rules: Vec<usize>,
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.confs[0])?;
+        for (r, c) in zip(&self.rules, &self.confs[1..]) {
+            write!(f, " -{}-> {}", r, c)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn bmc<CW: CountersWorld>(cw: CW, k: usize) -> Option<Trace> {
+    cw.starts()
+        .into_iter()
+        .filter_map(|c| unsafe_trace(&cw, c, k))
+        .min_by_key(|t| t.rules.len())
+}
+
+pub fn bmc_instance<CW: CountersWorld>(
+    cw: CW,
+    n: isize,
+    k: usize,
+) -> Option<Trace> {
+    let start = NWC(vec_map!(instantiate_nw(nw, n); nw in cw.start().0.iter()));
+    unsafe_trace(&cw, start, k)
+}
+
+fn unsafe_trace<CW: CountersWorld>(
+    cw: &CW,
+    start: NWC,
+    max_steps: usize,
+) -> Option<Trace> {
+    let mut parents: HashMap<NWC, Option<(NWC, usize)>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    parents.insert(start.clone(), None);
+    queue.push_back((start, 0usize));
+    while let Some((c, depth)) = queue.pop_front() {
+        if cw.is_unsafe(&c) {
+            return Some(trace_to(&parents, &c));
+        }
+        if depth >= max_steps {
+            continue;
+        }
+        for (r, (enabled, c1)) in cw.rules(&c).into_iter().enumerate() {
+            if enabled && !parents.contains_key(&c1) {
+                parents.insert(c1.clone(), Some((c.clone(), r)));
+                queue.push_back((c1, depth + 1));
+            }
+        }
+    }
+    None
+}
+
+fn trace_to(parents: &HashMap<NWC, Option<(NWC, usize)>>, c: &NWC) -> Trace {
+    let mut cs = vec![c.clone()];
+    let mut rs = Vec::new();
+    let mut c = c;
+    while let Some((c1, r)) = &parents[c] {
+        rs.push(*r);
+        cs.push(c1.clone());
+        c = c1;
+    }
+    cs.reverse();
+    rs.reverse();
+    Trace {
+        confs: cs,
+        rules: rs,
+    }
+}
+
+//
+// Rule provenance
+//
+// `rule_provenance(cw, c, c1)` is the name of the first rule enabled
+// at `c` that produces `c1` (`None` if `c1` is produced from `c`
+// by generalization rather than by a rule). `graph_with_rules(cw, g)`
+// prefixes each configuration in `g` (but the root) with the name
+// of the rule producing it from its parent (or with `~`, if it is
+// produced by generalization), so that
+//     graph_pretty_printer(&graph_with_rules(cw, &g))
+// shows how the proof is produced. `named_trace(cw, t)` shows a trace
+// with the names of the rules instead of their indices.
+
+pub fn rule_provenance<CW: CountersWorld>(
+    cw: &CW,
+    c: &NWC,
+    c1: &NWC,
+) -> Option<String> {
+    cw.named_rules(c)
+        .into_iter()
+        .find(|(_, e, c2)| *e && c2 == c1)
+        .map(|(n, _, _)| n)
+}
+
+fn graph_with_rules_loop<CW: CountersWorld>(
+    cw: &CW,
+    g: &Graph<NWC>,
+    label: String,
+) -> Rc<Graph<String>> {
+    match g {
+        Graph::Back(_) => back(&label),
+        Graph::Forth(c, gs) => {
+            let gs1 = vec_map!({
+                let c1 = g1.root_conf();
+                let label1 = match rule_provenance(cw, c, c1) {
+                    Some(n) => format!("{}: {}", n, c1),
+                    None => format!("~ {}", c1),
+                };
+                graph_with_rules_loop(cw, g1, label1)
+            }; g1 in gs);
+            forth(&label, &gs1)
+        }
+    }
+}
+
+pub fn graph_with_rules<CW: CountersWorld>(
+    cw: &CW,
+    g: &Graph<NWC>,
+) -> Rc<Graph<String>> {
+    graph_with_rules_loop(cw, g, g.root_conf().to_string())
+}
+
+pub fn named_trace<CW: CountersWorld>(cw: &CW, t: &Trace) -> String {
+    let mut s = t.confs[0].to_string();
+    for (r, c) in zip(&t.rules, &t.confs[1..]) {
+        s.push_str(&format!(" -{}-> {}", cw.rule_name(*r), c));
+    }
+    s
+}
+
+//
+// Counter systems given by matrices
+//
+// A `MatrixSystem` is a counter system given by numeric data (which may be
+// produced by other tools) rather than by Rust expressions.
+//
+// * `start[k]` is the initial value of the k-th counter.
+// * Rule `r` is enabled at `c` iff `c[k] >= guards[r][k]` for all `k`.
+//   Then `c[k]` is replaced with `c[k] + deltas[r][k]`.
+// * `c` is unsafe iff, for some row `u` of `unsafe_bounds`,
+//   `c[k] >= u[k]` for all `k`.
+//
+// A `MatrixSystem` is itself a `CountersWorld`, so that it can be loaded
+// at runtime. `matrix_counter_system!(Name, e)` defines a unit struct
+// `Name` whose behavior is given by the `MatrixSystem` produced by `e`.
+// (`e` is evaluated once per thread.)
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MatrixError {
+    RuleCount(usize, usize),
+    RowLength(usize, usize),
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatrixError::RuleCount(g, d) => {
+                write!(f, "{} guard rows, but {} delta rows", g, d)
+            }
+            MatrixError::RowLength(n, m) => {
+                write!(f, "a row of length {} for {} counters", m, n)
+            }
+        }
+    }
+}
+
+impl Error for MatrixError {}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct MatrixSystem {
+    start: Vec<NW>,
+    guards: Vec<Vec<isize>>,
+    deltas: Vec<Vec<isize>>,
+    unsafe_bounds: Vec<Vec<isize>>,
+}
+
+impl MatrixSystem {
+    pub fn new(
+        start: Vec<NW>,
+        guards: Vec<Vec<isize>>,
+        deltas: Vec<Vec<isize>>,
+        unsafe_bounds: Vec<Vec<isize>>,
+    ) -> Result<MatrixSystem, MatrixError> {
+        let n = start.len();
+        if guards.len() != deltas.len() {
+            return Err(MatrixError::RuleCount(guards.len(), deltas.len()));
+        }
+        for row in guards.iter().chain(&deltas).chain(&unsafe_bounds) {
+            if row.len() != n {
+                return Err(MatrixError::RowLength(n, row.len()));
+            }
+        }
+        Ok(MatrixSystem {
+            start,
+            guards,
+            deltas,
+            unsafe_bounds,
+        })
+    }
+}
+
+impl CountersWorld for MatrixSystem {
+    fn start(&self) -> NWC {
+        NWC(self.start.clone())
+    }
+
+    fn rules(&self, c: &NWC) -> Vec<(bool, NWC)> {
+        zip(&self.guards, &self.deltas)
+            .map(|(g, d)| {
+                let enabled = zip(&c.0, g).all(|(nw, &i)| *nw >= i);
+                let c1 = vec_map!(c.0[k] + d[k]; k in 0..c.0.len());
+                (enabled, NWC(c1))
+            })
+            .collect()
+    }
+
+    fn is_unsafe(&self, c: &NWC) -> bool {
+        self.unsafe_bounds
+            .iter()
+            .any(|u| zip(&c.0, u).all(|(nw, &i)| *nw >= i))
+    }
+
+    // A bound that is not positive holds for any value of the counter.
+
+    fn footprints(&self) -> Option<Footprints> {
+        let positive =
+            |row: &[isize]| vec_map!(k; k in 0..row.len(), row[k] > 0);
+        let rules = zip(&self.guards, &self.deltas)
+            .map(|(g, d)| {
+                let writes = vec_map!(k; k in 0..d.len(), d[k] != 0);
+                let mut reads = positive(g);
+                reads.extend(&writes);
+                reads.sort_unstable();
+                reads.dedup();
+                Footprint { reads, writes }
+            })
+            .collect();
+        let mut unsafe_reads: Vec<usize> = self
+            .unsafe_bounds
+            .iter()
+            .flat_map(|u| positive(u))
+            .collect();
+        unsafe_reads.sort_unstable();
+        unsafe_reads.dedup();
+        Some(Footprints {
+            rules,
+            unsafe_reads,
+        })
+    }
+}
+
+#[macro_export]
+macro_rules! matrix_counter_system {
+    ($name:ident, $system:expr) => {
+        #[derive(Debug)]
+        struct $name;
+        impl $name {
+            fn system() -> std::rc::Rc<$crate::counters::MatrixSystem> {
+                thread_local! {
+                    static SYSTEM:
+                        std::rc::Rc<$crate::counters::MatrixSystem> =
+                        std::rc::Rc::new($system);
+                }
+                SYSTEM.with(|s| s.clone())
+            }
+        }
+        impl $crate::counters::CountersWorld for $name {
+            fn start(&self) -> $crate::counters::NWC {
+                $crate::counters::CountersWorld::start(&*$name::system())
+            }
+            fn rules(
+                &self,
+                c: &$crate::counters::NWC,
+            ) -> Vec<(bool, $crate::counters::NWC)> {
+                $crate::counters::CountersWorld::rules(&*$name::system(), c)
+            }
+            fn is_unsafe(&self, c: &$crate::counters::NWC) -> bool {
+                $crate::counters::CountersWorld::is_unsafe(&*$name::system(), c)
+            }
+            fn footprints(&self) -> Option<$crate::counters::Footprints> {
+                $crate::counters::CountersWorld::footprints(&*$name::system())
+            }
+        }
+    };
+}
+
+// Rules can be written in two forms.
+//
+// * Positional: `guard => e1, ..., en;` where `ei` is the new value
+//   of the i-th counter.
+// * Assignments: `guard => { x := e; y := e'; };` where the counters
+//   not mentioned keep their values. All right-hand sides are evaluated
+//   in the old configuration, so that a transfer is written as
+//   `{ y := y + x; x := 0; }` and a reset as `{ x := 0; }`.
+//   Under ω, a reset (and the source of a transfer) becomes exactly 0,
+//   while the target of a transfer from ω becomes ω.
+//
+// A rule can be given a name, as in `rm: i >= 1 => ...;`, which is
+// reported by `CountersWorld::rule_name` (the unnamed rules being named
+// by their indices).
+//
+// A rule can be prefixed with `strict`, as in `strict i == 0 => ...;`.
+// Then its guard is evaluated with the pessimistic semantics of ω
+// (see `StrictNW`).
+//
+// The optional clause `Symmetries([x, y], ...);` declares groups
+// of interchangeable counters (see `CountersWorld::symmetries`).
+//
+// The optional clause `Consts(K, ...);` (following the counters) declares
+// symbolic constants, which may be used in the start configurations,
+// in the unsafe condition and in the rules. Then the system is a struct
+// holding the values of the constants, so that a family of systems is
+// defined at once. For example, given
+//     counter_system! {
+//         Tokens(i, j);
+//         Consts(K);
+//         Start(K, 0);
+//         Unsafe(j > K);
+//         Rules{ i >= 1 => i - 1, j + 1; }
+//     }
+// `Tokens { K: 3 }` is the system starting with 3 tokens.
+
+#[doc(hidden)]
+pub fn counter_index(names: &[&str], x: &str) -> usize {
+    names
+        .iter()
+        .position(|&n| n == x)
+        .unwrap_or_else(|| panic!("unknown counter `{}`", x))
+}
+
+// The footprints of the rules defined by `counter_system!` are found
+// in the source text of the rules: a rule reads the counters whose names
+// occur in its guard or in the right-hand sides of the counters it
+// writes. A counter is written unless its new value is given
+// by its own name.
+
+#[doc(hidden)]
+pub fn counters_in(names: &[&str], src: &str) -> Vec<usize> {
+    let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let mut ks: Vec<usize> = src
+        .split(|ch: char| !is_word(ch))
+        .filter_map(|w| names.iter().position(|&n| n == w))
+        .collect();
+    ks.sort_unstable();
+    ks.dedup();
+    ks
+}
+
+#[doc(hidden)]
+pub fn assignment_footprint(
+    names: &[&str],
+    guard: &str,
+    xs: &[&str],
+    us: &[&str],
+) -> Footprint {
+    let mut reads = counters_in(names, guard);
+    let mut writes = Vec::new();
+    for (&x, &u) in zip(xs, us) {
+        if x != u.trim() {
+            writes.push(counter_index(names, x));
+            reads.extend(counters_in(names, u));
+        }
+    }
+    reads.sort_unstable();
+    reads.dedup();
+    writes.sort_unstable();
+    writes.dedup();
+    Footprint { reads, writes }
+}
+
+#[doc(hidden)]
+pub fn positional_footprint(
+    names: &[&str],
+    guard: &str,
+    es: &[&str],
+) -> Footprint {
+    let xs = &names[..es.len().min(names.len())];
+    assignment_footprint(names, guard, xs, es)
+}
+
+#[macro_export]
+macro_rules! counter_system {
+    (@mk_params $c:ident, $($i:ident),*) => {
+        let mut _k = 0;
+        $crate::counter_system!(@mk_params_tail $c, _k, $($i),*)
+    };
+    (@mk_params_tail $c:ident, $k:ident, $($i:ident),*) => {
+        $(
+            #[allow(unused_variables)]
+            let $i = $c.0[$k]; $k += 1;
+        )*
+    };
+    (@to_nwc $($e:expr),*) => {
+        $crate::counters::NWC(vec![$({
+            let _nw: $crate::counters::NW = $e.into();
+            _nw
+        }),*])
+    };
+    (@rules $c:ident [$($params:ident),*] [$($acc:tt)*]) => {
+        vec![$($acc)*]
+    };
+    (@rules $c:ident [$($params:ident),*] [$($acc:tt)*]
+        $n:ident : $($rest:tt)*
+    ) => {
+        $crate::counter_system!(@rules $c [$($params),*] [$($acc)*] $($rest)*)
+    };
+    (@rules $c:ident [$($params:ident),*] [$($acc:tt)*]
+        strict $p:expr => $($rest:tt)*
+    ) => {
+        $crate::counter_system!(@rules $c [$($params),*] [$($acc)*] {
+            $(
+                #[allow(unused_variables)]
+                let $params = $crate::counters::StrictNW($params);
+            )*
+            $p
+        } => $($rest)*)
+    };
+    (@rules $c:ident [$($params:ident),*] [$($acc:tt)*]
+        $p:expr => { $($x:ident := $u:expr;)* }; $($rest:tt)*
+    ) => {
+        $crate::counter_system!(@rules $c [$($params),*] [$($acc)* ($p, {
+            let _names = [$(stringify!($params)),*];
+            let mut _nwc = $c.clone();
+            $(
+                let _k = $crate::counters::counter_index(
+                    &_names,
+                    stringify!($x),
+                );
+                _nwc.0[_k] = {let _nw: $crate::counters::NW = $u.into(); _nw};
+            )*
+            _nwc
+        }),] $($rest)*)
+    };
+    (@rules $c:ident [$($params:ident),*] [$($acc:tt)*]
+        $p:expr => $($e:expr),*; $($rest:tt)*
+    ) => {
+        $crate::counter_system!(@rules $c [$($params),*]
+            [$($acc)* ($p, $crate::counter_system!(@to_nwc $($e),*)),]
+            $($rest)*)
+    };
+    (@struct $name:ident) => {
+        #[derive(Debug)]
+        struct $name;
+    };
+    (@struct $name:ident $($k:ident),+) => {
+        #[allow(non_snake_case)]
+        #[derive(Clone, Copy, Debug)]
+        struct $name {
+            $($k: isize),+
+        }
+    };
+    (@names [$($acc:tt)*]) => {
+        [$($acc)*]
+    };
+    (@names [$($acc:tt)*] $n:ident : $($rest:tt)*) => {
+        $crate::counter_system!(
+            @names_rule [$($acc)* Some(stringify!($n)),] $($rest)*)
+    };
+    (@names [$($acc:tt)*] $($rest:tt)*) => {
+        $crate::counter_system!(@names_rule [$($acc)* None,] $($rest)*)
+    };
+    (@names_rule [$($acc:tt)*] strict $p:expr => $($rest:tt)*) => {
+        $crate::counter_system!(@names_rule [$($acc)*] $p => $($rest)*)
+    };
+    (@names_rule [$($acc:tt)*]
+        $p:expr => { $($x:ident := $u:expr;)* }; $($rest:tt)*
+    ) => {
+        $crate::counter_system!(@names [$($acc)*] $($rest)*)
+    };
+    (@names_rule [$($acc:tt)*] $p:expr => $($e:expr),*; $($rest:tt)*) => {
+        $crate::counter_system!(@names [$($acc)*] $($rest)*)
+    };
+    (@footprints $ns:ident [$($acc:tt)*]) => {
+        vec![$($acc)*]
+    };
+    (@footprints $ns:ident [$($acc:tt)*] $n:ident : $($rest:tt)*) => {
+        $crate::counter_system!(@footprints $ns [$($acc)*] $($rest)*)
+    };
+    (@footprints $ns:ident [$($acc:tt)*] strict $p:expr => $($rest:tt)*) => {
+        $crate::counter_system!(@footprints $ns [$($acc)*] $p => $($rest)*)
+    };
+    (@footprints $ns:ident [$($acc:tt)*]
+        $p:expr => { $($x:ident := $u:expr;)* }; $($rest:tt)*
+    ) => {
+        $crate::counter_system!(@footprints $ns [$($acc)*
+            $crate::counters::assignment_footprint(
+                &$ns,
+                stringify!($p),
+                &[$(stringify!($x)),*],
+                &[$(stringify!($u)),*],
+            ),
+        ] $($rest)*)
+    };
+    (@footprints $ns:ident [$($acc:tt)*]
+        $p:expr => $($e:expr),*; $($rest:tt)*
+    ) => {
+        $crate::counter_system!(@footprints $ns [$($acc)*
+            $crate::counters::positional_footprint(
+                &$ns,
+                stringify!($p),
+                &[$(stringify!($e)),*],
+            ),
+        ] $($rest)*)
+    };
+    (
+        $name:ident($($params:ident),*);
+        $(Consts($($k:ident),*);)?
+        Start($($start:expr),*);
+        $(Starts($(($($starts:expr),*)),*);)?
+        Unsafe($unsafe:expr);
+        $(Symmetries($([$($sym:ident),*]),*);)?
+        Rules{
+            $($rules:tt)*
+        }
+    ) => {
+        $crate::counter_system!(@struct $name $($($k),*)?);
+        impl $crate::counters::CountersWorld for $name {
+            fn start(&self) -> $crate::counters::NWC {
+                $($(
+                    #[allow(non_snake_case, unused_variables)]
+                    let $k: isize = self.$k;
+                )*)?
+                $crate::counter_system!(@to_nwc $($start),*)
+            }
+            fn starts(&self) -> Vec<$crate::counters::NWC> {
+                $($(
+                    #[allow(non_snake_case, unused_variables)]
+                    let $k: isize = self.$k;
+                )*)?
+                let cs: Vec<$crate::counters::NWC> = vec![$($(
+                    $crate::counter_system!(@to_nwc $($starts),*)
+                ),*)?];
+                if cs.is_empty() {
+                    vec![$crate::counters::CountersWorld::start(self)]
+                } else {
+                    cs
+                }
+            }
+            fn is_unsafe(&self, _c: &$crate::counters::NWC) -> bool {
+                $($(
+                    #[allow(non_snake_case, unused_variables)]
+                    let $k: isize = self.$k;
+                )*)?
+                $crate::counter_system!(@mk_params _c, $($params),*);
+                $unsafe
+            }
+
+            fn rules(
+                &self,
+                _c: &$crate::counters::NWC,
+            ) -> Vec<(bool, $crate::counters::NWC)> {
+                $($(
+                    #[allow(non_snake_case, unused_variables)]
+                    let $k: isize = self.$k;
+                )*)?
+                $crate::counter_system!(@mk_params _c, $($params),*);
+
+                $crate::counter_system!(@rules _c [$($params),*] [] $($rules)*)
+            }
+
+            fn rule_name(&self, r: usize) -> String {
+                let names: &[Option<&str>] =
+                    &$crate::counter_system!(@names [] $($rules)*);
+                match names.get(r) {
+                    Some(Some(n)) => n.to_string(),
+                    _ => r.to_string(),
+                }
+            }
+
+            fn symmetries(&self) -> Vec<Vec<usize>> {
+                let _names = [$(stringify!($params)),*];
+                vec![$($(
+                    vec![$($crate::counters::counter_index(
+                        &_names,
+                        stringify!($sym),
+                    )),*]
+                ),*)?]
+            }
+
+            fn footprints(&self) -> Option<$crate::counters::Footprints> {
+                let _names = [$(stringify!($params)),*];
+                Some($crate::counters::Footprints {
+                    rules: $crate::counter_system!(
+                        @footprints _names [] $($rules)*),
+                    unsafe_reads: $crate::counters::counters_in(
+                        &_names,
+                        stringify!($unsafe),
+                    ),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::graph::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_nw_add() {
+        assert_eq!(N(1) + N(2), N(3));
+        assert_eq!(N(3) + W(), W());
+        assert_eq!(N(3) + 25, N(28));
+    }
+
+    #[test]
+    fn test_nw_div_rem() {
+        assert_eq!(N(7) / 2, N(3));
+        assert_eq!(N(7) % 2, N(1));
+        assert_eq!(W() / 2, W());
+        assert_eq!(W() % 2, W());
+        assert!(N(4) % 2 == 0);
+        assert!(!(N(5) % 2 == 0));
+        assert!(W() % 2 == 0);
+        // ω compares as equal to any number.
+        assert_eq!(
+            [W() == 5, W() != 5, W() < 5, W() <= 5, W() > 5, W() >= 5],
+            [true, false, false, true, false, true]
+        );
+    }
+
+    #[test]
+    fn test_nw_cmp() {
+        assert!(N(2) >= 1);
+        assert!(N(2) >= 2);
+        assert!(!(N(1) >= 2));
+        assert!(W() >= 1);
+        assert!(N(2) == N(2));
+        assert!(N(2) == 2);
+        assert!(W() == 2);
+    }
+
+    #[test]
+    fn test_is_in() {
+        assert!(is_in(&N(2), &N(2)));
+        assert!(!(is_in(&N(2), &N(3))));
+        assert!(is_in(&N(2), &W()));
+        assert!(is_in(&W(), &W()));
+        assert!(!(is_in(&W(), &N(3))));
+    }
+
+    #[test]
+    fn test_into_nw() {
+        let nw25 = NW::from(25);
+        assert_eq!(nw25, N(25));
+        let nw30 = {
+            let _tmp: NW = 30.into();
+            _tmp
+        };
+        assert_eq!(nw30, N(30));
+        let w1 = NW::from(ω);
+        assert_eq!(w1, W());
+        let w2 = {
+            let _tmp: NW = ω.into();
+            _tmp
+        };
+        assert_eq!(w2, W());
+    }
+
+    macro_rules! nwc {
+        ($($e:expr),*) => {
+            NWC(vec![$({let _nw:NW = $e.into(); _nw}),*])
+        };
+    }
+    #[test]
+    fn test_nwc() {
+        let i = N(10);
+        assert_eq!(
+            format!("{:?}", nwc!(25 + 1, ω, i + 1)),
+            "NWC([N(26), W, N(11)])"
+        );
+        assert_eq!(format!("{:?}", nwc!()), "NWC([])");
+    }
+
+    #[test]
+    fn test_display_nwc() {
+        assert_eq!(nwc!(1, ω, 2).to_string(), "(1,ω,2)");
+        assert_eq!(nwc!().to_string(), "()");
+    }
+
+    #[test]
+    fn test_parse_nwc() {
+        assert_eq!("ω".parse(), Ok(W()));
+        assert_eq!(" omega".parse(), Ok(W()));
+        assert_eq!("-3".parse(), Ok(N(-3)));
+        assert_eq!("x".parse::<NW>(), Err(ParseNWError("x".to_string())));
+        for c in [nwc!(1, ω, 2), nwc!(), nwc!(ω)] {
+            assert_eq!(c.to_string().parse(), Ok(c));
+        }
+        assert_eq!("( 1, omega )".parse(), Ok(nwc!(1, ω)));
+        assert_eq!(
+            "(1,2".parse::<NWC>(),
+            Err(ParseNWError("(1,2".to_string()))
+        );
+        assert_eq!(
+            "(1,,2)".parse::<NWC>(),
+            Err(ParseNWError("(1,,2)".to_string()))
+        );
+    }
+
+    counter_system! {
+        TestCW5(i, j);
+        Start(ω, 0);
+        Unsafe(false);
+        Rules{
+            i >= 2 && i % 2 == 0 => i / 2, j + i / 2;
+        }
+    }
+
+    #[test]
+    fn test_div_rem_rules() {
+        assert_eq!(TestCW5.rules(&nwc!(6, 1)), vec![(true, nwc!(3, 4))]);
+        assert_eq!(TestCW5.rules(&nwc!(5, 1)), vec![(false, nwc!(2, 3))]);
+        assert_eq!(TestCW5.rules(&nwc!(ω, 1)), vec![(true, nwc!(ω, ω))]);
+    }
+
+    #[derive(Debug)]
+    struct TestCW0;
+
+    impl CountersWorld for TestCW0 {
+        fn start(&self) -> NWC {
+            nwc!(2, 0)
+        }
+
+        fn rules(&self, _c: &NWC) -> Vec<(bool, NWC)> {
+            let mut _k = 0;
+            let i = _c.0[_k];
+            _k += 1;
+            let j = _c.0[_k];
+            _k += 1;
+
+            vec![
+                (i >= 1, nwc!(i - 1, j + 1)), //
+                (j >= 1, nwc!(i + 1, j - 1)), //
+            ]
+        }
+
+        fn is_unsafe(&self, _: &NWC) -> bool {
+            false
+        }
+    }
+
+    counter_system! {
+        TestCW1(i,j);
+        Start(2, 0);
+        Unsafe(false);
+        Rules{
+            i >= 1 => i - 1, j + 1;
+            j >= 1 => i + 1, j - 1;
+        }
+    }
+
+    counter_system! {
+        TestCW2(i, j);
+        Start(ω, 0);
+        Unsafe(false);
+        Rules{
+            i >= 1 => { j := j + i; i := 0; };
+            j >= 1 => { i := 1; };
+            j >= 1 => i + 1, j - 1;
+        }
+    }
+
+    #[test]
+    fn test_reset_and_transfer() {
+        assert_eq!(
+            TestCW2.rules(&nwc!(ω, 1)),
+            vec![
+                (true, nwc!(0, ω)),
+                (true, nwc!(1, 1)),
+                (true, nwc!(ω, 0))
+            ]
+        );
+        assert_eq!(
+            TestCW2.rules(&nwc!(2, 3)),
+            vec![
+                (true, nwc!(0, 5)),
+                (true, nwc!(1, 3)),
+                (true, nwc!(3, 2))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_widening_policies() {
+        let h = History::new().cons(nwc!(1, 0, ω));
+        let c = nwc!(2, 0, ω);
+        assert_eq!(
+            PowersetWidening.widen(&h, &c),
+            vec![nwc!(2, ω, ω), nwc!(ω, 0, ω), nwc!(ω, ω, ω)]
+        );
+        assert_eq!(
+            ThresholdWidening(vec![3, 0, 0]).widen(&h, &c),
+            vec![nwc!(2, ω, ω)]
+        );
+        assert_eq!(GrowthWidening.widen(&h, &c), vec![nwc!(ω, 0, ω)]);
+        let delayed = DelayedWidening {
+            delay: 2,
+            policy: PowersetWidening,
+        };
+        assert_eq!(delayed.widen(&h, &c), vec![]);
+        assert_eq!(delayed.widen(&h.cons(c.clone()), &c).len(), 3);
+        // (5,5,ω) is not visited by c.
+        assert_eq!(delayed.widen(&h.cons(nwc!(5, 5, ω)), &c), vec![]);
+        assert_eq!(
+            ThresholdWidening(vec![1]).widen(&h, &c),
+            vec![nwc!(ω, 0, ω)]
+        );
+        let policies: Vec<Box<dyn WideningPolicy>> = vec![
+            Box::new(PowersetWidening),
+            Box::new(ThresholdWidening(vec![2, 0, 0])),
+            Box::new(GrowthWidening),
+            Box::new(delayed),
+        ];
+        for p in policies {
+            for h in [h.clone(), h.cons(c.clone())] {
+                assert_eq!(
+                    p.widen_iter(&h, &c).collect::<Vec<_>>(),
+                    p.widen(&h, &c)
+                );
+            }
+        }
+        let s = CountersScWorld::new(TestCW1, 3, 10);
+        let c = TestCW1.start();
+        assert_eq!(
+            s.develop_iter(&h, &c).collect::<Vec<_>>(),
+            s.develop_with_history(&h, &c)
+        );
+    }
+
+    counter_system! {
+        Grow(i);
+        Start(0);
+        Unsafe(false);
+        Rules{
+            i >= 0 => i + 1;
+        }
+    }
+
+    #[test]
+    fn test_whistle_policies() {
+        let h = History::new().cons(nwc!(1, 0, ω));
+        assert!(!EmbeddingWhistle.is_dangerous(&h));
+        assert!(EmbeddingWhistle.is_dangerous(&h.cons(nwc!(2, 0, ω))));
+        assert!(!EmbeddingWhistle.is_dangerous(&h.cons(nwc!(2, ω, ω))));
+        assert!(is_embedded(&nwc!(1, 0, ω), &nwc!(2, ω, ω)));
+        assert!(!is_embedded(&nwc!(ω, 0, ω), &nwc!(2, ω, ω)));
+        assert!(!EmbeddingWhistle.is_dangerous(&h.cons(nwc!(ω, 0, 0))));
+        assert!(!EmbeddingWhistle.is_dangerous(&h.cons(nwc!(0, 1, ω))));
+        assert!(DepthWhistle(1).is_dangerous(&h));
+        assert!(!DepthWhistle(2).is_dangerous(&h));
+        assert!(SumWhistle(1).is_dangerous(&h));
+        assert!(!SumWhistle(2).is_dangerous(&h));
+        assert!(CoordWhistle(vec![3, 0, 0]).is_dangerous(&h));
+        assert!(!CoordWhistle(vec![3, 1, 0]).is_dangerous(&h));
+        let any = AnyWhistle(vec![
+            Box::new(DepthWhistle(2)),
+            Box::new(SumWhistle(1)),
+        ]);
+        assert!(any.is_dangerous(&h));
+
+        let s = CountersScWorld::new(TestCW1, 3, 10);
+        let s1 =
+            CountersScWorld::new(TestCW1, 0, 0).with_whistle(BoundsWhistle {
+                max_nw: 3,
+                max_depth: 10,
+            });
+        assert_eq!(
+            lazy_mrsc(&s1, TestCW1.start()),
+            lazy_mrsc(&s, TestCW1.start())
+        );
+
+        // No bounds are needed.
+        let s = CountersScWorld::new(Grow, 0, 0).with_whistle(EmbeddingWhistle);
+        let l = lazy_mrsc(&s, Grow.start());
+        let gs = unroll(&l);
+        assert_eq!(gs.len(), 1);
+        assert_eq!(graph_size(&gs[0]), 3);
+    }
+
+    #[test]
+    fn test_shape() {
+        assert_eq!(shape(&nwc!(ω, 3, 0, 3)).to_string(), "(ω,#1,#0,#1)");
+        assert_eq!(shape(&nwc!(5, 7)), shape(&nwc!(0, 1)));
+        assert_ne!(shape(&nwc!(5, 7)), shape(&nwc!(1, 0)));
+        let g = forth(&nwc!(1, ω), &[back(&nwc!(2, ω)), back(&nwc!(ω, ω))]);
+        let counts = shape_counts(vec![&*g, &*g]);
+        assert_eq!(counts[&shape(&nwc!(0, ω))], 4);
+        assert_eq!(counts[&shape(&nwc!(ω, ω))], 2);
+        let groups = group_by_shape(&[nwc!(1, ω), nwc!(ω, ω), nwc!(3, ω)]);
+        assert_eq!(groups[&shape(&nwc!(0, ω))], vec![nwc!(1, ω), nwc!(3, ω)]);
+    }
+
+    counter_system! {
+        TestCW4(i, a, b);
+        Start(3, 0, 0);
+        Unsafe(b >= 2);
+        Rules{
+            i >= 1 => i - 1, a + 1, b;
+            a >= 1 => i, a - 1, b + 1;
+        }
+    }
+
+    #[test]
+    fn test_shortest_unsafe_trace() {
+        assert_eq!(shortest_unsafe_trace(&TestCW4, 3), None);
+        assert_eq!(shortest_unsafe_trace(&TestCW4, 4), Some(vec![0, 0, 1, 1]));
+        assert_eq!(shortest_unsafe_trace(&TestCW1, 10), None);
+    }
+
+    counter_system! {
+        TestNamed(i, a, b);
+        Start(3, 0, 0);
+        Unsafe(b >= 2);
+        Rules{
+            take: i >= 1 => i - 1, a + 1, b;
+            a >= 1 => { a := a - 1; b := b + 1; };
+            reset: strict b >= 1 => { b := 0; };
+        }
+    }
+
+    #[test]
+    fn test_named_rules() {
+        assert_eq!(TestNamed.rule_name(0), "take");
+        assert_eq!(TestNamed.rule_name(1), "1");
+        assert_eq!(TestNamed.rule_name(2), "reset");
+        assert_eq!(TestCW4.rule_name(1), "1");
+        let c = nwc!(2, 1, 1);
+        let rs = TestNamed.named_rules(&c);
+        assert_eq!(
+            vec_map!((r.0.as_str(), r.1); r in &rs),
+            vec![("take", true), ("1", true), ("reset", true)]
+        );
+        assert_eq!(
+            vec_map!((r.0, r.1); r in TestNamed.rules(&c)),
+            vec_map!((r.1, r.2); r in rs)
+        );
+        assert_eq!(
+            rule_provenance(&TestNamed, &c, &nwc!(2, 1, 0)),
+            Some("reset".to_string())
+        );
+        assert_eq!(rule_provenance(&TestNamed, &c, &nwc!(ω, 1, 1)), None);
+
+        let t = bmc(TestNamed, 5).unwrap();
+        assert_eq!(
+            named_trace(&TestNamed, &t),
+            "(3,0,0) -take-> (2,1,0) -take-> (1,2,0) -1-> (1,1,1) -1-> (1,0,2)"
+        );
+        let g = forth(
+            &nwc!(3, 0, 0),
+            &[
+                forth(&nwc!(ω, 1, 0), &[back(&nwc!(ω, 2, 0))]),
+                back(&nwc!(2, 1, 0)),
+            ],
+        );
+        assert_eq!(
+            graph_with_rules(&TestNamed, &g),
+            forth(
+                &"(3,0,0)".to_string(),
+                &[
+                    forth(
+                        &"~ (ω,1,0)".to_string(),
+                        &[back(&"take: (ω,2,0)".to_string())]
+                    ),
+                    back(&"take: (2,1,0)".to_string()),
+                ]
+            )
+        );
+    }
+
+    counter_system! {
+        TestCW3(i, a, b);
+        Start(ω, 0, 0);
+        Unsafe(false);
+        Symmetries([a, b]);
+        Rules{
+            i >= 1 => i - 1, a + 1, b;
+        }
+    }
+
+    #[test]
+    fn test_bmc() {
+        let t = bmc(TestCW6, 10).unwrap();
+        assert_eq!(t.rules, [0, 0, 2]);
+        assert_eq!(
+            t.to_string(),
+            "(0,0,0) -0-> (1,0,0) -0-> (2,0,0) -2-> (2,0,1)"
+        );
+        assert_eq!(bmc(TestCW6, 2), None);
+        assert_eq!(bmc(TestCW9, 10), None);
+        assert_eq!(bmc_instance(TestCW9, 3, 10), None);
+    }
+
+    #[test]
+    fn test_symmetries() {
+        assert_eq!(TestCW3.symmetries(), vec![vec![1, 2]]);
+        assert_eq!(TestCW2.symmetries(), Vec::<Vec<usize>>::new());
+        assert_eq!(SymmetryGroups::new(&TestCW3).in_group, [false, true, true]);
+        let s = CountersScWorld::new(TestCW3, 3, 10);
+        assert!(s.is_foldable_to(&nwc!(ω, 1, 0), &nwc!(ω, 0, 1)));
+        assert!(s.is_foldable_to(&nwc!(ω, 2, ω), &nwc!(ω, ω, 2)));
+        assert!(!s.is_foldable_to(&nwc!(ω, 2, ω), &nwc!(ω, 2, 2)));
+        assert!(!s.is_foldable_to(&nwc!(1, 1, 0), &nwc!(2, 0, 1)));
+        let s2 = CountersScWorld::new(TestCW2, 3, 10);
+        assert!(!s2.is_foldable_to(&nwc!(1, 0), &nwc!(0, 1)));
+    }
+
+    #[test]
+    fn test_fold_evidence() {
+        let es = fold_evidence(&TestCW3, &nwc!(2, 1, 0), &nwc!(ω, 0, 1));
+        assert_eq!(
+            es,
+            vec![
+                FoldEvidence::Coord(0, N(2), W(), true),
+                FoldEvidence::Group(vec![1, 2], true)
+            ]
+        );
+        assert_eq!(es[0].to_string(), "#0: 2 ∈ ω holds");
+        let es = fold_evidence(&TestCW1, &nwc!(ω, 1), &nwc!(2, 1));
+        assert_eq!(es[0].to_string(), "#0: ω ∈ 2 FAILS");
+        let s = CountersScWorld::new(TestCW1, 3, 10);
+        assert_eq!(
+            fold_obligations_report(&s, &mg()),
+            [
+                "[0.0] (ω,ω) is covered by (ω,ω) (depth 1)",
+                "    #0: ω ∈ ω holds",
+                "    #1: ω ∈ ω holds",
+                "[0.1] (ω,ω) is covered by (ω,ω) (depth 1)",
+                "    #0: ω ∈ ω holds",
+                "    #1: ω ∈ ω holds",
+            ]
+            .join("\n")
+        );
+    }
+
+    counter_system! {
+        TestCW6(a, b, c);
+        Start(0, 0, 0);
+        Unsafe(c >= 1);
+        Rules{
+            a < 2 => a + 1, b, c;
+            b < 2 => a, b + 1, c;
+            a >= 2 => a, b, c + 1;
+        }
+    }
+
+    #[test]
+    fn test_footprints() {
+        let fps = TestCW6.footprints().unwrap();
+        assert_eq!(
+            vec_map!((fp.reads.clone(), fp.writes.clone()); fp in &fps.rules),
+            vec![
+                (vec![0], vec![0]),
+                (vec![1], vec![1]),
+                (vec![0, 2], vec![2])
+            ]
+        );
+        assert_eq!(fps.unsafe_reads, vec![2]);
+        assert!(fps.rules[1].is_independent_of(&fps.rules[0]));
+        assert!(!fps.rules[0].is_independent_of(&fps.rules[2]));
+        assert_eq!(TestCW0.footprints(), None);
+    }
+
+    #[test]
+    fn test_partial_order_reduction() {
+        let fps = TestCW6.footprints().unwrap();
+        // The first rule enables the third one, while the second rule
+        // is independent of both.
+        assert_eq!(
+            drive_reduced(&TestCW6, &fps, &nwc!(0, 0, 0), |_| true),
+            vec![nwc!(0, 1, 0)]
+        );
+        assert_eq!(
+            drive_reduced(&TestCW6, &fps, &nwc!(2, 0, 0), |_| true),
+            vec![nwc!(2, 1, 0)]
+        );
+        let s = CountersScWorld::new(TestCW6, 3, 5);
+        let s_por =
+            CountersScWorld::new(TestCW6, 3, 5).with_partial_order_reduction();
+        let l = lazy_mrsc(&s, TestCW6.start());
+        let l_por = lazy_mrsc(&s_por, TestCW6.start());
+        assert!(
+            crate::statistics::length_unroll(&l_por)
+                < crate::statistics::length_unroll(&l)
+        );
+        assert!(unroll(&l_por)
+            .iter()
+            .any(|g| g.preorder().any(|n| TestCW6.is_unsafe(n.1))));
+    }
+
+    // `R` commutes at the start with `A`, the only other enabled rule,
+    // but disables `B`, which becomes enabled after `A`.
+
+    counter_system! {
+        TestCW14(a, b, r, u);
+        Start(1, 0, 1, 0);
+        Unsafe(u >= 1);
+        Rules{
+            r >= 1 => a, b, r - 1, u;
+            a >= 1 => a - 1, b + 1, r, u;
+            b >= 1 && r >= 1 => a, b - 1, r, u + 1;
+        }
+    }
+
+    #[test]
+    fn test_partial_order_reduction_disabled_rules() {
+        let fps = TestCW14.footprints().unwrap();
+        assert_eq!(
+            drive_reduced(&TestCW14, &fps, &TestCW14.start(), |_| true),
+            vec![nwc!(1, 0, 0, 0), nwc!(0, 1, 1, 0)]
+        );
+        let (r, _) = crate::verification::verify(TestCW14, 3, 10);
+        assert_eq!(r.outcome, crate::verification::Outcome::NoSolution);
+        let s = CountersScWorld::new(TestCW14, 3, 10)
+            .with_partial_order_reduction();
+        let l = lazy_mrsc(&s, TestCW14.start());
+        let sl = cl_empty_and_bad(|c: &NWC| TestCW14.is_unsafe(c), &l);
+        assert!(unroll(&sl).is_empty());
+    }
+
+    // The first rule commutes with the second one, but applying
+    // the first rule alone leads to a cycle, in which the second rule
+    // (leading to an unsafe configuration) is postponed forever.
+
+    counter_system! {
+        TestCW13(a, b);
+        Start(0, 0);
+        Unsafe(b >= 1);
+        Rules{
+            a >= 0 => a + 1, b;
+            b == 0 => a, b + 1;
+        }
+    }
+
+    #[test]
+    fn test_partial_order_reduction_cycle_proviso() {
+        let bad = |c: &NWC| TestCW13.is_unsafe(c);
+        for s in [
+            CountersScWorld::new(TestCW13, 3, 10),
+            CountersScWorld::new(TestCW13, 3, 10)
+                .with_partial_order_reduction(),
+        ] {
+            let l = lazy_mrsc(&s, TestCW13.start());
+            assert!(!unroll(&l).is_empty());
+            assert_eq!(unroll(&cl_empty_and_bad(bad, &l)), vec![]);
+        }
+    }
+
+    matrix_counter_system!(
+        TestCW7,
+        MatrixSystem::new(
+            vec![N(2), N(0)],
+            vec![vec![1, 0], vec![0, 1]],
+            vec![vec![-1, 1], vec![1, -1]],
+            vec![vec![3, 0]],
+        )
+        .unwrap()
+    );
+
+    #[test]
+    fn test_matrix_system() {
+        assert_eq!(TestCW7.start(), TestCW1.start());
+        for c in [nwc!(2, 0), nwc!(1, 1), nwc!(ω, 0), nwc!(0, ω)] {
+            assert_eq!(TestCW7.rules(&c), TestCW1.rules(&c));
+        }
+        assert!(TestCW7.is_unsafe(&nwc!(ω, 0)));
+        assert!(!TestCW7.is_unsafe(&nwc!(2, 5)));
+        assert_eq!(
+            MatrixSystem::new(vec![N(0)], vec![vec![0]], vec![], vec![]),
+            Err(MatrixError::RuleCount(1, 0))
+        );
+        assert_eq!(
+            MatrixSystem::new(
+                vec![N(0)],
+                vec![vec![0]],
+                vec![vec![1, 1]],
+                vec![]
+            ),
+            Err(MatrixError::RowLength(1, 2))
+        );
+        let e: Box<dyn Error> = Box::new(MatrixError::RowLength(1, 2));
+        assert_eq!(e.to_string(), "a row of length 2 for 1 counters");
+        let m = TestCW7::system();
+        assert_eq!(bmc((*m).clone(), 5), bmc(TestCW7, 5));
+        let fps = TestCW7.footprints().unwrap();
+        assert_eq!(fps.rules, TestCW1.footprints().unwrap().rules);
+        assert_eq!(fps.unsafe_reads, vec![0]);
+    }
+
+    // A family of counter systems parameterized at runtime: `n` tokens
+    // are moved between two places, and the system is unsafe if
+    // `max` tokens are in the second place.
+
+    struct Tokens {
+        n: isize,
+        max: isize,
+    }
+
+    impl CountersWorld for Tokens {
+        fn start(&self) -> NWC {
+            nwc!(self.n, 0)
+        }
+
+        fn rules(&self, c: &NWC) -> Vec<(bool, NWC)> {
+            let (i, j) = (c.0[0], c.0[1]);
+            vec![(i >= 1, nwc!(i - 1, j + 1)), (j >= 1, nwc!(i + 1, j - 1))]
+        }
+
+        fn is_unsafe(&self, c: &NWC) -> bool {
+            c.0[1] >= self.max
+        }
+    }
+
+    #[test]
+    fn test_parameterized_world() {
+        for n in 1..5 {
+            let cw = Tokens { n, max: n + 1 };
+            assert_eq!(shortest_unsafe_trace(&cw, 10), None);
+            let s = CountersScWorld::new(cw, 10, 10);
+            let l = lazy_mrsc(&s, s.world().start());
+            let sl = cl_empty_and_bad(|c: &NWC| s.world().is_unsafe(c), &l);
+            assert!(!unroll(&sl).is_empty());
+            let cw = Tokens { n, max: n };
+            assert_eq!(
+                shortest_unsafe_trace(&cw, 10).unwrap().len(),
+                n as usize
+            );
+        }
+    }
+
+    counter_system! {
+        TestConsts(i, j);
+        Consts(K, M);
+        Start(K, 0);
+        Starts((K, 1));
+        Unsafe(j > M);
+        Rules{
+            move: i >= 1 => i - 1, j + 1;
+            drop: j >= K => i, j - K;
+        }
+    }
+
+    #[test]
+    fn test_consts() {
+        let cw = TestConsts { K: 2, M: 3 };
+        assert_eq!(cw.start(), nwc!(2, 0));
+        assert_eq!(cw.starts(), vec![nwc!(2, 1)]);
+        assert!(cw.is_unsafe(&nwc!(0, 4)));
+        assert!(!cw.is_unsafe(&nwc!(0, 3)));
+        assert_eq!(
+            cw.rules(&nwc!(1, 2)),
+            vec![(true, nwc!(0, 3)), (true, nwc!(1, 0))]
+        );
+        assert_eq!(cw.rule_name(1), "drop");
+        assert_eq!(shortest_unsafe_trace(&cw, 10), None);
+
+        let cw = TestConsts { K: 5, M: 3 };
+        assert_eq!(cw.start(), nwc!(5, 0));
+        assert!(!cw.rules(&nwc!(1, 2))[1].0);
+        assert_eq!(shortest_unsafe_trace(&cw, 10).unwrap().len(), 4);
+    }
+
+    counter_system! {
+        TestCW8(i, j, k);
+        Start(2, 0, 0);
+        Unsafe(false);
+        Rules{
+            i >= 1 => i - 1, j + 1, k;
+            j >= 1 => i + 1, j - 1, k;
+            k >= 1 => i, j + 1, k - 1;
+        }
+    }
+
+    #[test]
+    fn test_dead_code_analysis() {
+        let s = CountersScWorld::new(TestCW8, 3, 5);
+        let l = lazy_mrsc(&s, TestCW8.start());
+        assert_eq!(
+            dead_code_analysis(&TestCW8, &l),
+            DeadCodeReport {
+                unfired_rules: vec![2],
+                constant_coordinates: vec![2],
+            }
+        );
+        let s = CountersScWorld::new(TestCW1, 3, 5);
+        let l = lazy_mrsc(&s, TestCW1.start());
+        assert_eq!(dead_code_analysis(&TestCW1, &l), DeadCodeReport::default());
+    }
+
+    counter_system! {
+        TestCW9(i, c);
+        Start(ω, 0);
+        Unsafe(c >= 2);
+        Rules{
+            i >= 1 && c == 0 => i - 1, c + 1;
+            c >= 1 => i + 1, c - 1;
+        }
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let s = CountersScWorld::new(TestCW9, 3, 10);
+        let l = lazy_mrsc(&s, TestCW9.start());
+        let l = cl_empty_and_bad(|c: &NWC| TestCW9.is_unsafe(c), &l);
+        let g = unroll(&cl_min_size(&l))[0].clone();
+        for n in 0..5 {
+            assert_eq!(
+                instantiate(&TestCW9, &g, n, 1000),
+                InstanceCheck::Closed(if n == 0 { 1 } else { 2 })
+            );
+        }
+        assert_eq!(
+            instantiate(&TestCW9, &g, 5, 1),
+            InstanceCheck::Incomplete(1)
+        );
+        let g1 = forth(&nwc!(ω, 0), &[back(&nwc!(ω, 0))]);
+        assert_eq!(
+            instantiate(&TestCW9, &g1, 2, 1000),
+            InstanceCheck::Escaped(nwc!(1, 1))
+        );
+        assert_eq!(
+            instantiate(&TestCW2, &g1, 2, 1000),
+            InstanceCheck::Escaped(nwc!(0, 2))
+        );
+    }
+
+    counter_system! {
+        TestCW10(i, c);
+        Start(ω, 0);
+        Unsafe(false);
+        Rules{
+            c == 0 => i, 1;
+            strict i == 0 => i, 2;
+            strict i >= 2 && c < 1 => i - 2, c + 1;
+            strict i + c < 3 => { c := 0; };
+        }
+    }
+
+    #[test]
+    fn test_strict_guards() {
+        assert!(StrictNW(W()) >= 5);
+        assert!(StrictNW(W()) > 5);
+        assert!(StrictNW(W()) != 5);
+        assert_eq!(StrictNW(W()).partial_cmp(&5), Some(Ordering::Greater));
+        assert!(StrictNW(N(1)) < 5);
+        let w = StrictNW(W());
+        assert_eq!(
+            [w == 5, w != 5, w < 5, w <= 5, w > 5, w >= 5],
+            [false, true, false, false, true, true]
+        );
+        assert_eq!(StrictNW(N(7)) / 2, StrictNW(N(3)));
+        assert_eq!(StrictNW(N(7)) % 2, StrictNW(N(1)));
+        assert_eq!(w / 2, w);
+        assert!(w % 2 != 0);
+        assert_eq!(
+            TestCW10.rules(&nwc!(ω, 0)),
+            vec![
+                (true, nwc!(ω, 1)),
+                (false, nwc!(ω, 2)),
+                (true, nwc!(ω, 1)),
+                (false, nwc!(ω, 0))
+            ]
+        );
+        assert_eq!(
+            TestCW10.rules(&nwc!(0, 2)),
+            vec![
+                (false, nwc!(0, 1)),
+                (true, nwc!(0, 2)),
+                (false, nwc!(-2, 3)),
+                (true, nwc!(0, 0))
+            ]
+        );
+    }
+
+    counter_system! {
+        TestCW11(i, a, b);
+        Start(ω, 0, 0);
+        Unsafe(false);
+        Symmetries([a, b]);
+        Rules{
+            i >= 1 => i - 1, a + 1, b;
+            i >= 1 => i - 1, a, b + 1;
+        }
+    }
+
+    #[test]
+    fn test_compress_siblings() {
+        let s = CountersScWorld::new(TestCW11, 2, 10);
+        let l = lazy_mrsc(&s, TestCW11.start());
+        let gs = unroll(&cl_empty(&l));
+        let gs1: Vec<Rc<Graph<NWC>>> = gs
+            .iter()
+            .map(|g| compress_siblings(|c1, c2| s.is_foldable_to(c1, c2), g))
+            .collect();
+        let size: usize = gs.iter().map(|g| graph_size(g)).sum();
+        let size1: usize = gs1.iter().map(|g| graph_size(g)).sum();
+        assert_eq!((gs.len(), size, size1), (21, 212, 201));
+        let obs: Vec<_> =
+            gs1.iter().flat_map(|g1| fold_obligations(&s, g1)).collect();
+        assert!(obs.iter().all(|ob| ob.holds()));
+        assert!(obs.iter().any(|ob| ob.sibling.is_some()));
+        for (g, g1) in gs.iter().zip(&gs1) {
+            for n in 0..4 {
+                assert_eq!(
+                    instantiate(&TestCW11, g1, n, 1000),
+                    instantiate(&TestCW11, g, n, 1000)
+                );
+            }
+        }
+    }
+
+    counter_system! {
+        Buggy(i, j, k);
+        Start(ω, 0, 0);
+        Unsafe(false);
+        Rules{
+            i >= 1 => i - 1, j + 1, k;
+            i >= 0 => i + j, j - 1, k;
+            false => i, j, k + 1;
+        }
+    }
+
+    counter_system! {
+        AlwaysBad(i);
+        Start(0);
+        Unsafe(i >= 0);
+        Rules{
+            i >= 1 => i - 1;
+        }
+    }
+
+    counter_system! {
+        Clean(i, j);
+        Start(1, 0);
+        Unsafe(i >= 2);
+        Rules{
+            i >= 1 => i - 1, j + 1;
+            j >= 1 => i + 1, j - 1;
+        }
+    }
+
+    #[test]
+    fn test_lint() {
+        assert_eq!(
+            lint(&Buggy, 1),
+            vec![
+                Lint::NegativeUpdate {
+                    rule: 1,
+                    conf: nwc!(0, 0, 0),
+                    coordinate: 1
+                },
+                Lint::UnsatisfiableGuard(2),
+                Lint::UnreadCoordinate(2),
+                Lint::NeverUnsafe
+            ]
+        );
+        assert_eq!(
+            lint(&AlwaysBad, 2),
+            vec![Lint::UnsafeStart(nwc!(0)), Lint::AlwaysUnsafe]
+        );
+        assert_eq!(lint(&Clean, 2), vec![]);
+        assert_eq!(
+            Lint::UnreadCoordinate(2).to_string(),
+            "coordinate 2 is never read"
+        );
+    }
+
+    fn mg() -> Rc<Graph<NWC>> {
+        forth(
+            &nwc!(2, 0),
+            &[forth(&nwc!(ω, ω), &[back(&nwc!(ω, ω)), back(&nwc!(ω, ω))])],
+        )
+    }
+
+    fn run_counters_sc_world<CW: CountersWorld>(cw: CW, m: isize, d: usize) {
+        let s = CountersScWorld::new(cw, m, d);
+        let start_conf = s.world().start();
+        let gs = naive_mrsc(&s, start_conf.clone());
+        let l = lazy_mrsc(&s, start_conf);
+        assert_eq!(unroll(&l), gs);
+        let ml = cl_min_size(&l);
+        assert_eq!(&unroll(&ml)[0], &mg());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let c = NWC(vec![N(1), W()]);
+        let s = serde_json::to_string(&c).unwrap();
+        assert_eq!(serde_json::from_str::<NWC>(&s).unwrap(), c);
+    }
+
+    // A policy need not be `Sync` (unless the world is to be shared
+    // between threads).
+
+    struct CountingWhistle(std::cell::Cell<usize>, BoundsWhistle);
+
+    impl WhistlePolicy for CountingWhistle {
+        fn is_dangerous(&self, h: &History<NWC>) -> bool {
+            self.0.set(self.0.get() + 1);
+            self.1.is_dangerous(h)
+        }
+    }
+
+    #[test]
+    fn test_non_sync_policy() {
+        let w = CountingWhistle(
+            std::cell::Cell::new(0),
+            BoundsWhistle {
+                max_nw: 3,
+                max_depth: 10,
+            },
+        );
+        let s = CountersScWorld::new(TestCW1, 0, 0).with_whistle(w);
+        let s1 = CountersScWorld::new(TestCW1, 3, 10);
+        let c0 = TestCW1.start();
+        assert_eq!(naive_mrsc(&s, c0.clone()), naive_mrsc(&s1, c0));
+        assert!(s.whistle.0.get() > 0);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_naive_mrsc_par() {
+        let s = CountersScWorld::new(TestCW11, 2, 10);
+        let c0 = TestCW11.start();
+        assert_eq!(naive_mrsc_par(&s, c0.clone()), naive_mrsc(&s, c0));
+        let s = CountersScWorld::new(TestCW1, 3, 10);
+        let c0 = TestCW1.start();
+        assert_eq!(naive_mrsc_par(&s, c0.clone()), naive_mrsc(&s, c0));
+        let s = s.with_widening(DelayedWidening {
+            delay: 2,
+            policy: GrowthWidening,
+        });
+        let c0 = TestCW1.start();
+        assert_eq!(naive_mrsc_par(&s, c0.clone()), naive_mrsc(&s, c0));
+    }
+
+    #[test]
+    fn test_counters_sc_world() {
+        run_counters_sc_world(TestCW0, 3, 10);
+        run_counters_sc_world(TestCW1, 3, 10);
+        run_counters_sc_world(TestCW7, 3, 10);
+    }
+
+    // The macros refer to the items of the crate by their full paths,
+    // so that nothing else has to be imported.
+
+    mod macro_paths {
+        use crate::counters::ω;
+
+        counter_system! {
+            Paths(i, j);
+            Consts(K);
+            Start(ω, 0);
+            Unsafe(j >= K);
+            Symmetries([i, j]);
+            Rules{
+                strict i >= 1 => { i := i - 1; j := j + 1; };
+                j >= 1 => i + 1, j - 1;
+            }
+        }
+
+        matrix_counter_system!(
+            MatrixPaths,
+            crate::counters::MatrixSystem::new(
+                vec![1.into(), 0.into()],
+                vec![vec![1, 0]],
+                vec![vec![-1, 1]],
+                vec![vec![0, 2]],
+            )
+            .unwrap()
+        );
+
+        #[test]
+        fn test_macro_paths() {
+            use crate::counters::CountersWorld;
+            let cw = Paths { K: 2 };
+            assert_eq!(cw.starts(), vec![cw.start()]);
+            assert_eq!(cw.symmetries(), vec![vec![0, 1]]);
+            assert_eq!(cw.rules(&cw.start()).len(), 2);
+            assert!(!MatrixPaths.is_unsafe(&MatrixPaths.start()));
+        }
     }
 }
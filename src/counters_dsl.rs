@@ -0,0 +1,1110 @@
+//
+// A runtime language for counter systems
+//
+// `counter_system!` defines a counter system at compile time.
+// A `DslSystem` is a counter system read at runtime from a text written
+// in (almost) the same language, so that protocols can be loaded
+// by a command-line tool or a test harness without recompiling the crate.
+// For example,
+//     Synapse(i, d, v);
+//     Start(ω, 0, 0);
+//     Unsafe((d >= 1 && v >= 1) || (d >= 2));
+//     Rules {
+//         i >= 1 => i + d - 1, 0, v + 1;
+//         rh: v >= 1 => i + d + v - 1, 1, 0;
+//         i >= 1 => { i := i + d + v - 1; d := 1; v := 0; };
+//     }
+//
+// The clauses (`Consts`, `Starts` and `Symmetries` being optional) come
+// in the same order as in `counter_system!`, and rules have the same
+// forms (named, `strict`, positional and assignments). The values
+// of the constants are given in the text, as in
+// `Consts(K = 3, M = K + 1);`, or by `DslSystem::parse_with`, as in
+// `DslSystem::parse_with(src, &[("K", 5)])`, the values given overriding
+// those in the text. A constant may be declared without a value
+// (`Consts(K, M = K + 1);`), which has then to be given.
+// `omega` may be written for `ω`, and `//` starts a comment.
+//
+// Expressions are built from counters, constants, numbers and ω by means
+// of `+`, `-`, `/`, `%` (the right operands of `/` and `%` being
+// non-zero numbers or constants) and parentheses. Conditions are built
+// from comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) of expressions,
+// `true`, `false`, `!`, `&&`, `||` and parentheses.
+//
+// An expression containing counters or ω denotes an `NW` (as in
+// `counter_system!`), while the other expressions denote numbers.
+// An `NW` is compared with a number as `NW` does it (or as `StrictNW`
+// does it, in the guard of a strict rule). If the left operand is
+// a number, and the right one is an `NW`, the operands are swapped.
+// Two `NW`s can only be compared by `==` and `!=`, which compare them
+// structurally (ω being equal to ω only), since `counter_system!`
+// does not accept the other comparisons.
+//
+
+use crate::counters::{
+    CountersWorld, Footprint, Footprints, StrictNW, NW, NWC,
+};
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use NW::{N, W};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DslError {
+    // The file cannot be read.
+    Io(String),
+    // A syntax error at the given line and column (counted from 1).
+    Syntax(usize, usize, String),
+    // A value is given for a constant that is not declared.
+    UnknownConst(String),
+}
+
+impl fmt::Display for DslError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DslError::Io(msg) => write!(f, "{}", msg),
+            DslError::Syntax(line, col, msg) => {
+                write!(f, "{}:{}: {}", line, col, msg)
+            }
+            DslError::UnknownConst(k) => write!(f, "unknown constant `{}`", k),
+        }
+    }
+}
+
+impl Error for DslError {}
+
+//
+// Syntax trees
+//
+
+#[derive(Clone, PartialEq, Debug)]
+enum Expr {
+    Const(NW),
+    Counter(usize),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, isize),
+    Rem(Box<Expr>, isize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    // The comparison with the operands swapped.
+    fn reverse(self) -> Cmp {
+        match self {
+            Cmp::Lt => Cmp::Gt,
+            Cmp::Le => Cmp::Ge,
+            Cmp::Gt => Cmp::Lt,
+            Cmp::Ge => Cmp::Le,
+            cmp => cmp,
+        }
+    }
+}
+
+// `Cmp` compares an `NW` with a number, and `Same` compares two `NW`s.
+
+#[derive(Clone, PartialEq, Debug)]
+enum Cond {
+    Bool(bool),
+    Cmp(Cmp, Expr, isize),
+    Same(Expr, Expr),
+    Not(Box<Cond>),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Rhs {
+    Positional(Vec<Expr>),
+    Assignments(Vec<(usize, Expr)>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Rule {
+    name: Option<String>,
+    strict: bool,
+    guard: Cond,
+    rhs: Rhs,
+}
+
+impl Expr {
+    fn eval(&self, c: &NWC) -> NW {
+        match self {
+            Expr::Const(nw) => *nw,
+            Expr::Counter(k) => c.0[*k],
+            Expr::Add(e1, e2) => e1.eval(c) + e2.eval(c),
+            Expr::Sub(e1, e2) => e1.eval(c) - e2.eval(c),
+            Expr::Div(e, j) => e.eval(c) / *j,
+            Expr::Rem(e, j) => e.eval(c) % *j,
+        }
+    }
+
+    // The value of an expression not containing counters.
+    fn value(&self) -> Option<NW> {
+        match self {
+            Expr::Const(nw) => Some(*nw),
+            Expr::Counter(_) => None,
+            Expr::Add(e1, e2) => Some(e1.value()? + e2.value()?),
+            Expr::Sub(e1, e2) => Some(e1.value()? - e2.value()?),
+            Expr::Div(e, j) => Some(e.value()? / *j),
+            Expr::Rem(e, j) => Some(e.value()? % *j),
+        }
+    }
+
+    fn counters(&self, ks: &mut Vec<usize>) {
+        match self {
+            Expr::Const(_) => {}
+            Expr::Counter(k) => ks.push(*k),
+            Expr::Add(e1, e2) | Expr::Sub(e1, e2) => {
+                e1.counters(ks);
+                e2.counters(ks);
+            }
+            Expr::Div(e, _) | Expr::Rem(e, _) => e.counters(ks),
+        }
+    }
+}
+
+impl Cond {
+    fn eval(&self, c: &NWC, strict: bool) -> bool {
+        match self {
+            Cond::Bool(b) => *b,
+            Cond::Cmp(cmp, e, j) => {
+                let nw = e.eval(c);
+                let o = if strict {
+                    StrictNW(nw).partial_cmp(j)
+                } else {
+                    nw.partial_cmp(j)
+                };
+                match cmp {
+                    Cmp::Eq => o == Some(Ordering::Equal),
+                    Cmp::Ne => o != Some(Ordering::Equal),
+                    Cmp::Lt => o == Some(Ordering::Less),
+                    Cmp::Le => {
+                        matches!(o, Some(Ordering::Less | Ordering::Equal))
+                    }
+                    Cmp::Gt => o == Some(Ordering::Greater),
+                    Cmp::Ge => {
+                        matches!(o, Some(Ordering::Greater | Ordering::Equal))
+                    }
+                }
+            }
+            Cond::Same(e1, e2) => e1.eval(c) == e2.eval(c),
+            Cond::Not(p) => !p.eval(c, strict),
+            Cond::And(p1, p2) => p1.eval(c, strict) && p2.eval(c, strict),
+            Cond::Or(p1, p2) => p1.eval(c, strict) || p2.eval(c, strict),
+        }
+    }
+
+    fn counters(&self, ks: &mut Vec<usize>) {
+        match self {
+            Cond::Bool(_) => {}
+            Cond::Cmp(_, e, _) => e.counters(ks),
+            Cond::Same(e1, e2) => {
+                e1.counters(ks);
+                e2.counters(ks);
+            }
+            Cond::Not(p) => p.counters(ks),
+            Cond::And(p1, p2) | Cond::Or(p1, p2) => {
+                p1.counters(ks);
+                p2.counters(ks);
+            }
+        }
+    }
+}
+
+fn sorted(mut ks: Vec<usize>) -> Vec<usize> {
+    ks.sort_unstable();
+    ks.dedup();
+    ks
+}
+
+impl Rule {
+    fn apply(&self, c: &NWC) -> (bool, NWC) {
+        let enabled = self.guard.eval(c, self.strict);
+        let c1 = match &self.rhs {
+            Rhs::Positional(es) => NWC(es.iter().map(|e| e.eval(c)).collect()),
+            Rhs::Assignments(asgs) => {
+                let mut c1 = c.clone();
+                for (k, e) in asgs {
+                    c1.0[*k] = e.eval(c);
+                }
+                c1
+            }
+        };
+        (enabled, c1)
+    }
+
+    // A counter is written unless its new value is the counter itself.
+    fn footprint(&self) -> Footprint {
+        let asgs: Vec<(usize, &Expr)> = match &self.rhs {
+            Rhs::Positional(es) => es.iter().enumerate().collect(),
+            Rhs::Assignments(asgs) => {
+                asgs.iter().map(|(k, e)| (*k, e)).collect()
+            }
+        };
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        self.guard.counters(&mut reads);
+        for (k, e) in asgs {
+            if *e != Expr::Counter(k) {
+                writes.push(k);
+                e.counters(&mut reads);
+            }
+        }
+        Footprint {
+            reads: sorted(reads),
+            writes: sorted(writes),
+        }
+    }
+}
+
+//
+// The lexer
+//
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Ident(String),
+    Num(isize),
+    Omega,
+    Sym(&'static str),
+    Eof,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(x) => write!(f, "`{}`", x),
+            Token::Num(i) => write!(f, "`{}`", i),
+            Token::Omega => write!(f, "`ω`"),
+            Token::Sym(s) => write!(f, "`{}`", s),
+            Token::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+// Longer symbols come first.
+const SYMBOLS: [&str; 25] = [
+    ":=", "=>", "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", "[",
+    "]", ",", ";", ":", "=", "<", ">", "+", "-", "/", "%", "!",
+];
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize, usize)>, DslError> {
+    let mut tokens = Vec::new();
+    for (l, line) in src.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or("");
+        let cs: Vec<(usize, char)> = line.char_indices().collect();
+        let mut i = 0;
+        while i < cs.len() {
+            let (start, ch) = cs[i];
+            let pos = (l + 1, i + 1);
+            if ch.is_whitespace() {
+                i += 1;
+            } else if ch.is_ascii_digit() {
+                let mut j = i;
+                while j < cs.len() && cs[j].1.is_ascii_digit() {
+                    j += 1;
+                }
+                let end = cs.get(j).map_or(line.len(), |&(e, _)| e);
+                let n = line[start..end].parse().map_err(|_| {
+                    DslError::Syntax(pos.0, pos.1, "number too big".to_string())
+                })?;
+                tokens.push((Token::Num(n), pos.0, pos.1));
+                i = j;
+            } else if ch.is_alphabetic() || ch == '_' {
+                let mut j = i;
+                while j < cs.len()
+                    && (cs[j].1.is_alphanumeric() || cs[j].1 == '_')
+                {
+                    j += 1;
+                }
+                let end = cs.get(j).map_or(line.len(), |&(e, _)| e);
+                let token = match &line[start..end] {
+                    "ω" | "omega" => Token::Omega,
+                    x => Token::Ident(x.to_string()),
+                };
+                tokens.push((token, pos.0, pos.1));
+                i = j;
+            } else {
+                let rest = &line[start..];
+                match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                    Some(&s) => {
+                        tokens.push((Token::Sym(s), pos.0, pos.1));
+                        i += s.len();
+                    }
+                    None => {
+                        let msg = format!("unexpected character `{}`", ch);
+                        return Err(DslError::Syntax(pos.0, pos.1, msg));
+                    }
+                }
+            }
+        }
+    }
+    let l = src.lines().count();
+    let c = src.lines().last().map_or(0, |line| line.chars().count());
+    tokens.push((Token::Eof, l.max(1), c + 1));
+    Ok(tokens)
+}
+
+//
+// The parser
+//
+
+// Both conditions and expressions may be parenthesized, so that
+// the parser does not know in advance which of them it is parsing.
+
+enum Node {
+    E(Expr),
+    C(Cond),
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize, usize)>,
+    pos: usize,
+    counters: Vec<String>,
+    consts: HashMap<String, isize>,
+    // The values given for the constants.
+    given: HashMap<String, isize>,
+}
+
+type PResult<T> = Result<T, DslError>;
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn peek_at(&self, k: usize) -> &Token {
+        let i = (self.pos + k).min(self.tokens.len() - 1);
+        &self.tokens[i].0
+    }
+
+    fn error<T>(&self, msg: String) -> PResult<T> {
+        let (_, line, col) = self.tokens[self.pos];
+        Err(DslError::Syntax(line, col, msg))
+    }
+
+    fn unexpected<T>(&self, what: &str) -> PResult<T> {
+        self.error(format!("expected {}, found {}", what, self.peek()))
+    }
+
+    fn next(&mut self) -> Token {
+        let t = self.peek().clone();
+        if t != Token::Eof {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn is_sym(&self, s: &str) -> bool {
+        matches!(self.peek(), Token::Sym(s1) if *s1 == s)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let found = self.is_sym(s);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, s: &str) -> PResult<()> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            self.unexpected(&format!("`{}`", s))
+        }
+    }
+
+    fn is_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Token::Ident(x) if x == kw)
+    }
+
+    fn keyword(&mut self, kw: &str) -> PResult<()> {
+        if self.is_keyword(kw) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.unexpected(&format!("`{}`", kw))
+        }
+    }
+
+    fn ident(&mut self) -> PResult<String> {
+        match self.peek().clone() {
+            Token::Ident(x) => {
+                self.pos += 1;
+                Ok(x)
+            }
+            _ => self.unexpected("a name"),
+        }
+    }
+
+    fn counter(&mut self) -> PResult<usize> {
+        let x = self.ident()?;
+        match self.counters.iter().position(|y| *y == x) {
+            Some(k) => Ok(k),
+            None => {
+                self.pos -= 1;
+                self.error(format!("unknown counter `{}`", x))
+            }
+        }
+    }
+
+    // A comma-separated list in parentheses.
+    fn list<T>(
+        &mut self,
+        item: impl Fn(&mut Parser) -> PResult<T>,
+    ) -> PResult<Vec<T>> {
+        let mut items = Vec::new();
+        self.expect("(")?;
+        if !self.eat(")") {
+            loop {
+                items.push(item(self)?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        Ok(items)
+    }
+
+    fn cond(&mut self) -> PResult<Cond> {
+        let n = self.or()?;
+        self.to_cond(n)
+    }
+
+    fn expr(&mut self) -> PResult<Expr> {
+        let n = self.or()?;
+        self.to_expr(n)
+    }
+
+    // An expression not containing counters.
+    fn value(&mut self) -> PResult<NW> {
+        let start = self.pos;
+        let e = self.expr()?;
+        match e.value() {
+            Some(nw) => Ok(nw),
+            None => {
+                self.pos = start;
+                self.error("counters cannot be used here".to_string())
+            }
+        }
+    }
+
+    fn to_cond(&self, n: Node) -> PResult<Cond> {
+        match n {
+            Node::C(p) => Ok(p),
+            Node::E(_) => self.error("a condition expected".to_string()),
+        }
+    }
+
+    fn to_expr(&self, n: Node) -> PResult<Expr> {
+        match n {
+            Node::E(e) => Ok(e),
+            Node::C(_) => self.error("an expression expected".to_string()),
+        }
+    }
+
+    fn or(&mut self) -> PResult<Node> {
+        let mut n = self.and()?;
+        while self.eat("||") {
+            let p1 = self.to_cond(n)?;
+            let n2 = self.and()?;
+            let p2 = self.to_cond(n2)?;
+            n = Node::C(Cond::Or(Box::new(p1), Box::new(p2)));
+        }
+        Ok(n)
+    }
+
+    fn and(&mut self) -> PResult<Node> {
+        let mut n = self.comparison()?;
+        while self.eat("&&") {
+            let p1 = self.to_cond(n)?;
+            let n2 = self.comparison()?;
+            let p2 = self.to_cond(n2)?;
+            n = Node::C(Cond::And(Box::new(p1), Box::new(p2)));
+        }
+        Ok(n)
+    }
+
+    fn comparison(&mut self) -> PResult<Node> {
+        let n = self.sum()?;
+        let cmp = match self.peek() {
+            Token::Sym("==") => Cmp::Eq,
+            Token::Sym("!=") => Cmp::Ne,
+            Token::Sym("<") => Cmp::Lt,
+            Token::Sym("<=") => Cmp::Le,
+            Token::Sym(">") => Cmp::Gt,
+            Token::Sym(">=") => Cmp::Ge,
+            _ => return Ok(n),
+        };
+        let e1 = self.to_expr(n)?;
+        let at = self.pos;
+        self.pos += 1;
+        let n2 = self.sum()?;
+        let e2 = self.to_expr(n2)?;
+        let p = match (e1.value(), e2.value()) {
+            (_, Some(N(j))) => Cond::Cmp(cmp, e1, j),
+            (Some(N(i)), _) => Cond::Cmp(cmp.reverse(), e2, i),
+            _ if cmp == Cmp::Eq => Cond::Same(e1, e2),
+            _ if cmp == Cmp::Ne => Cond::Not(Box::new(Cond::Same(e1, e2))),
+            _ => {
+                self.pos = at;
+                let msg = format!(
+                    "{} cannot compare two expressions with counters or ω",
+                    self.peek()
+                );
+                return self.error(msg);
+            }
+        };
+        Ok(Node::C(p))
+    }
+
+    fn sum(&mut self) -> PResult<Node> {
+        let mut n = self.product()?;
+        loop {
+            let add = if self.eat("+") {
+                true
+            } else if self.eat("-") {
+                false
+            } else {
+                return Ok(n);
+            };
+            let e1 = Box::new(self.to_expr(n)?);
+            let n2 = self.product()?;
+            let e2 = Box::new(self.to_expr(n2)?);
+            n = Node::E(if add {
+                Expr::Add(e1, e2)
+            } else {
+                Expr::Sub(e1, e2)
+            });
+        }
+    }
+
+    fn product(&mut self) -> PResult<Node> {
+        let mut n = self.primary()?;
+        loop {
+            let div = if self.eat("/") {
+                true
+            } else if self.eat("%") {
+                false
+            } else {
+                return Ok(n);
+            };
+            let e = Box::new(self.to_expr(n)?);
+            let j = match self.next() {
+                Token::Num(j) => j,
+                Token::Ident(x) if self.consts.contains_key(&x) => {
+                    self.consts[&x]
+                }
+                _ => {
+                    self.pos -= 1;
+                    return self.unexpected("a number or a constant");
+                }
+            };
+            if j == 0 {
+                self.pos -= 1;
+                return self.error("division by zero".to_string());
+            }
+            n = Node::E(if div {
+                Expr::Div(e, j)
+            } else {
+                Expr::Rem(e, j)
+            });
+        }
+    }
+
+    fn primary(&mut self) -> PResult<Node> {
+        match self.next() {
+            Token::Num(i) => Ok(Node::E(Expr::Const(N(i)))),
+            Token::Omega => Ok(Node::E(Expr::Const(W()))),
+            Token::Ident(x) if x == "true" => Ok(Node::C(Cond::Bool(true))),
+            Token::Ident(x) if x == "false" => Ok(Node::C(Cond::Bool(false))),
+            Token::Ident(x) => {
+                if let Some(k) = self.counters.iter().position(|y| *y == x) {
+                    Ok(Node::E(Expr::Counter(k)))
+                } else if let Some(i) = self.consts.get(&x) {
+                    Ok(Node::E(Expr::Const(N(*i))))
+                } else {
+                    self.pos -= 1;
+                    self.error(format!("unknown name `{}`", x))
+                }
+            }
+            Token::Sym("(") => {
+                let n = self.or()?;
+                self.expect(")")?;
+                Ok(n)
+            }
+            Token::Sym("!") => {
+                let n = self.primary()?;
+                let p = self.to_cond(n)?;
+                Ok(Node::C(Cond::Not(Box::new(p))))
+            }
+            Token::Eof => self.unexpected("an expression"),
+            _ => {
+                self.pos -= 1;
+                self.unexpected("an expression")
+            }
+        }
+    }
+
+    fn configuration(&mut self) -> PResult<NWC> {
+        let n = self.counters.len();
+        let (_, line, col) = self.tokens[self.pos];
+        let nws = self.list(|p| p.value())?;
+        if nws.len() != n {
+            let msg = format!("{} values for {} counters", nws.len(), n);
+            return Err(DslError::Syntax(line, col, msg));
+        }
+        Ok(NWC(nws))
+    }
+
+    fn rule(&mut self) -> PResult<Rule> {
+        let mut name = None;
+        if *self.peek_at(1) == Token::Sym(":") {
+            if let Token::Ident(x) = self.peek().clone() {
+                self.pos += 2;
+                name = Some(x);
+            }
+        }
+        let strict = self.is_keyword("strict");
+        if strict {
+            self.pos += 1;
+        }
+        let guard = self.cond()?;
+        self.expect("=>")?;
+        let rhs = if self.eat("{") {
+            let mut asgs = Vec::new();
+            while !self.eat("}") {
+                let k = self.counter()?;
+                self.expect(":=")?;
+                asgs.push((k, self.expr()?));
+                self.expect(";")?;
+            }
+            Rhs::Assignments(asgs)
+        } else {
+            let (_, line, col) = self.tokens[self.pos];
+            let mut es = vec![self.expr()?];
+            while self.eat(",") {
+                es.push(self.expr()?);
+            }
+            let n = self.counters.len();
+            if es.len() != n {
+                let msg = format!("{} values for {} counters", es.len(), n);
+                return Err(DslError::Syntax(line, col, msg));
+            }
+            Rhs::Positional(es)
+        };
+        self.expect(";")?;
+        Ok(Rule {
+            name,
+            strict,
+            guard,
+            rhs,
+        })
+    }
+
+    fn system(&mut self) -> PResult<DslSystem> {
+        let name = self.ident()?;
+        self.counters = self.list(|p| p.ident())?;
+        self.expect(";")?;
+
+        if self.is_keyword("Consts") {
+            self.pos += 1;
+            // A constant may be defined in terms of the preceding ones.
+            self.list(|p| {
+                let k = p.ident()?;
+                let default = if p.eat("=") {
+                    match p.value()? {
+                        N(i) => Some(i),
+                        W() => {
+                            return p
+                                .error("a constant cannot be ω".to_string())
+                        }
+                    }
+                } else {
+                    None
+                };
+                match p.given.get(&k).copied().or(default) {
+                    Some(i) => Ok(p.consts.insert(k, i)),
+                    None => {
+                        p.pos -= 1;
+                        p.error(format!("no value given for `{}`", k))
+                    }
+                }
+            })?;
+            self.expect(";")?;
+        }
+
+        self.keyword("Start")?;
+        let start = self.configuration()?;
+        self.expect(";")?;
+
+        let mut starts = Vec::new();
+        if self.is_keyword("Starts") {
+            self.pos += 1;
+            starts = self.list(|p| p.configuration())?;
+            self.expect(";")?;
+        }
+
+        self.keyword("Unsafe")?;
+        self.expect("(")?;
+        let unsafe_cond = self.cond()?;
+        self.expect(")")?;
+        self.expect(";")?;
+
+        let mut symmetries = Vec::new();
+        if self.is_keyword("Symmetries") {
+            self.pos += 1;
+            symmetries = self.list(|p| {
+                let mut ks = Vec::new();
+                p.expect("[")?;
+                if !p.eat("]") {
+                    loop {
+                        ks.push(p.counter()?);
+                        if p.eat("]") {
+                            break;
+                        }
+                        p.expect(",")?;
+                    }
+                }
+                Ok(ks)
+            })?;
+            self.expect(";")?;
+        }
+
+        self.keyword("Rules")?;
+        self.expect("{")?;
+        let mut rules = Vec::new();
+        while !self.eat("}") {
+            rules.push(self.rule()?);
+        }
+        if *self.peek() != Token::Eof {
+            return self.unexpected("end of input");
+        }
+
+        Ok(DslSystem {
+            name,
+            counters: self.counters.clone(),
+            start,
+            starts,
+            unsafe_cond,
+            symmetries,
+            rules,
+        })
+    }
+}
+
+//
+// Counter systems loaded at runtime
+//
+
+#[derive(Clone)]
+pub struct DslSystem {
+    name: String,
+    counters: Vec<String>,
+    start: NWC,
+    starts: Vec<NWC>,
+    unsafe_cond: Cond,
+    symmetries: Vec<Vec<usize>>,
+    rules: Vec<Rule>,
+}
+
+impl DslSystem {
+    pub fn parse(src: &str) -> Result<DslSystem, DslError> {
+        DslSystem::parse_with(src, &[])
+    }
+
+    // `consts` gives the values of some constants (overriding those
+    // given in `src`).
+
+    pub fn parse_with(
+        src: &str,
+        consts: &[(&str, isize)],
+    ) -> Result<DslSystem, DslError> {
+        let mut p = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+            counters: Vec::new(),
+            consts: HashMap::new(),
+            given: consts.iter().map(|&(k, i)| (k.to_string(), i)).collect(),
+        };
+        let cw = p.system()?;
+        match consts.iter().find(|(k, _)| !p.consts.contains_key(*k)) {
+            Some((k, _)) => Err(DslError::UnknownConst(k.to_string())),
+            None => Ok(cw),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<DslSystem, DslError> {
+        let path = path.as_ref();
+        let src = fs::read_to_string(path)
+            .map_err(|e| DslError::Io(format!("{}: {}", path.display(), e)))?;
+        DslSystem::parse(&src)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn counters(&self) -> &[String] {
+        &self.counters
+    }
+}
+
+impl FromStr for DslSystem {
+    type Err = DslError;
+
+    fn from_str(src: &str) -> Result<DslSystem, DslError> {
+        DslSystem::parse(src)
+    }
+}
+
+// As for the systems defined by `counter_system!`, the debug
+// representation is the name of the system (used by `verify`, etc.).
+
+impl fmt::Debug for DslSystem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl CountersWorld for DslSystem {
+    fn start(&self) -> NWC {
+        self.start.clone()
+    }
+
+    fn rules(&self, c: &NWC) -> Vec<(bool, NWC)> {
+        self.rules.iter().map(|r| r.apply(c)).collect()
+    }
+
+    fn is_unsafe(&self, c: &NWC) -> bool {
+        self.unsafe_cond.eval(c, false)
+    }
+
+    fn rule_name(&self, r: usize) -> String {
+        match self.rules.get(r).and_then(|r| r.name.as_ref()) {
+            Some(n) => n.clone(),
+            None => r.to_string(),
+        }
+    }
+
+    fn symmetries(&self) -> Vec<Vec<usize>> {
+        self.symmetries.clone()
+    }
+
+    fn starts(&self) -> Vec<NWC> {
+        if self.starts.is_empty() {
+            vec![self.start()]
+        } else {
+            self.starts.clone()
+        }
+    }
+
+    fn footprints(&self) -> Option<Footprints> {
+        let mut unsafe_reads = Vec::new();
+        self.unsafe_cond.counters(&mut unsafe_reads);
+        Some(Footprints {
+            rules: self.rules.iter().map(|r| r.footprint()).collect(),
+            unsafe_reads: sorted(unsafe_reads),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::counter_system;
+    use crate::counters::{shortest_unsafe_trace, ω};
+    use crate::verification::{verify, Outcome};
+
+    use iter_comprehensions::vec as vec_map;
+
+    counter_system! {
+        Sample(a, b, c);
+        Start(ω, 0, 0);
+        Starts((ω, 0, 0), (ω, 1, 0));
+        Unsafe(b >= 2 || (b + c) % 3 == 2 && !(c < 1));
+        Symmetries([b, c]);
+        Rules{
+            take: a >= 1 && b == 0 => a - 1, b + 1, c;
+            b >= 1 => { b := b - 1; c := c + 1; };
+            reset: strict c >= 2 => { a := a + c; c := 0; };
+            a / 2 != 1 || c > 0 => a, b, (c + 1) / 2;
+            strict a <= 3 => 0, a % 3, c;
+            swap: a == b || b != c + 1 => b, a, c;
+            strict a == c => a, b, c + 1;
+        }
+    }
+
+    const SAMPLE: &str = "
+        // The same as `Sample`.
+        Sample(a, b, c);
+        Start(omega, 0, 0);
+        Starts((ω, 0, 0), (ω, 1, 0));
+        Unsafe(b >= 2 || (b + c) % 3 == 2 && !(c < 1));
+        Symmetries([b, c]);
+        Rules{
+            take: a >= 1 && b == 0 => a - 1, b + 1, c;
+            b >= 1 => { b := b - 1; c := c + 1; };
+            reset: strict c >= 2 => { a := a + c; c := 0; };
+            a / 2 != 1 || c > 0 => a, b, (c + 1) / 2;
+            strict a <= 3 => 0, a % 3, c;
+            swap: a == b || b != c + 1 => b, a, c;
+            strict a == c => a, b, c + 1;
+        }
+    ";
+
+    #[test]
+    fn test_parse_like_macro() {
+        let cw: DslSystem = SAMPLE.parse().unwrap();
+        assert_eq!(format!("{:?}", cw), "Sample");
+        assert_eq!(cw.counters(), ["a", "b", "c"]);
+        assert_eq!(cw.start(), Sample.start());
+        assert_eq!(cw.starts(), Sample.starts());
+        assert_eq!(cw.symmetries(), Sample.symmetries());
+        assert_eq!(cw.footprints(), Sample.footprints());
+        assert_eq!(
+            vec_map!(cw.rule_name(r); r in 0..7),
+            vec_map!(Sample.rule_name(r); r in 0..7)
+        );
+        let nws = [N(0), N(1), N(2), N(3), W()];
+        for &a in &nws {
+            for &b in &nws {
+                for &c in &nws {
+                    let c = NWC(vec![a, b, c]);
+                    assert_eq!(cw.rules(&c), Sample.rules(&c), "{}", c);
+                    assert_eq!(cw.is_unsafe(&c), Sample.is_unsafe(&c));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_parsed() {
+        let cw = DslSystem::parse(
+            "Synapse(i, d, v);
+             Start(ω, 0, 0);
+             Unsafe((d >= 1 && v >= 1) || (d >= 2));
+             Rules {
+                 i >= 1 => i + d - 1, 0, v + 1;
+                 v >= 1 => i + d + v - 1, 1, 0;
+                 i >= 1 => i + d + v - 1, 1, 0;
+             }",
+        )
+        .unwrap();
+        let (r, mg) = verify(cw, 3, 10);
+        assert_eq!(r.protocol, "Synapse");
+        assert_eq!(r.outcome, Outcome::Proved);
+        assert!(mg.is_some());
+    }
+
+    #[test]
+    fn test_consts() {
+        let src = "Tokens(i, j);
+                   Consts(K = 3, M = K - 1);
+                   Start(K, 0);
+                   Unsafe(j > M);
+                   Rules { i >= 1 => i - 1, j + 1; }";
+        let cw = DslSystem::parse(src).unwrap();
+        assert_eq!(cw.start(), NWC(vec![N(3), N(0)]));
+        assert_eq!(shortest_unsafe_trace(&cw, 10), Some(vec![0, 0, 0]));
+        let cw = DslSystem::parse_with(src, &[("K", 1)]).unwrap();
+        assert_eq!(cw.start(), NWC(vec![N(1), N(0)]));
+        assert_eq!(shortest_unsafe_trace(&cw, 10), Some(vec![0]));
+        let cw = DslSystem::parse_with(src, &[("M", 0)]).unwrap();
+        assert_eq!(shortest_unsafe_trace(&cw, 10), Some(vec![0]));
+        assert_eq!(
+            DslSystem::parse_with(src, &[("N", 1)]).unwrap_err(),
+            DslError::UnknownConst("N".to_string())
+        );
+
+        // A constant without a default value.
+        let src = src.replace("K = 3", "K");
+        let cw = DslSystem::parse_with(&src, &[("K", 2)]).unwrap();
+        assert_eq!(cw.start(), NWC(vec![N(2), N(0)]));
+        assert_eq!(
+            syntax_error(&src),
+            DslError::Syntax(2, 27, "no value given for `K`".to_string())
+        );
+    }
+
+    fn syntax_error(src: &str) -> DslError {
+        DslSystem::parse(src).unwrap_err()
+    }
+
+    #[test]
+    fn test_errors() {
+        let header = "S(x, y);\nStart(0, 0);\nUnsafe(x >= 1);\n";
+        assert_eq!(
+            syntax_error(&format!("{}Rules {{ z >= 1 => x, y; }}", header)),
+            DslError::Syntax(4, 9, "unknown name `z`".to_string())
+        );
+        assert_eq!(
+            syntax_error(&format!("{}Rules {{ x >= 1 => x; }}", header)),
+            DslError::Syntax(4, 19, "1 values for 2 counters".to_string())
+        );
+        assert_eq!(
+            syntax_error(&format!("{}Rules {{ x => x, y; }}", header)),
+            DslError::Syntax(4, 11, "a condition expected".to_string())
+        );
+        assert_eq!(
+            syntax_error(&format!("{}Rules {{ x >= 1 => x / y, y; }}", header)),
+            DslError::Syntax(
+                4,
+                23,
+                "expected a number or a constant, found `y`".to_string()
+            )
+        );
+        assert_eq!(
+            syntax_error(&format!("{}Rules {{ x < y + 1 => x, y; }}", header)),
+            DslError::Syntax(
+                4,
+                11,
+                "`<` cannot compare two expressions with counters or ω"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            syntax_error("S(x, y);\nStart(x, 0);"),
+            DslError::Syntax(2, 7, "counters cannot be used here".to_string())
+        );
+        assert_eq!(
+            syntax_error("S(x);\nStart(0);\nUnsafe(x >= 1)"),
+            DslError::Syntax(
+                3,
+                15,
+                "expected `;`, found end of input".to_string()
+            )
+        );
+        assert_eq!(
+            syntax_error("S(x);\nStart(0) # 1;"),
+            DslError::Syntax(2, 10, "unexpected character `#`".to_string())
+        );
+        assert_eq!(
+            format!("{}", syntax_error("S(x) Start(0);")),
+            "1:6: expected `;`, found `Start`"
+        );
+        assert!(matches!(
+            DslSystem::load("/nonexistent/protocol.cs"),
+            Err(DslError::Io(_))
+        ));
+    }
+}
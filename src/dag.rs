@@ -0,0 +1,346 @@
+//
+// Hash-consed lazy graphs
+//
+// Many subtrees of a lazy graph produced by `lazy_mrsc` are structurally
+// identical (e.g., the subtrees for the same configuration reached with
+// histories that make no difference). A `LazyGraphDag` keeps each
+// distinct node only once: a node is interned by looking it up in
+// a hash table, the children being already interned, so that two nodes
+// get the same `DagId` iff the corresponding lazy graphs are equal.
+//
+// The conversions preserve the meaning of lazy graphs:
+//     dag.to_lazy_graph(dag.intern_lazy_graph(l)) == l
+// but the result of `to_lazy_graph` is maximally shared.
+//
+// `clean_fused_dag` applies a node cleaner (see `fusion`), computing
+// the result for each distinct node only once:
+//     dag.to_lazy_graph(clean_fused_dag(&mut dag, n, id)) ==
+//         clean_fused(n, dag.to_lazy_graph(id))
+//
+
+use crate::big_step_sc::{sc_step, ScEvent, ScWorld};
+use crate::fusion::NodeCleaner;
+use crate::graph::*;
+use crate::misc::History;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct DagId(usize);
+
+impl DagId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum DagNode<C> {
+    Empty(),
+    Stop(C),
+    Build(C, Vec<Vec<DagId>>),
+}
+
+pub struct LazyGraphDag<C> {
+    nodes: Vec<DagNode<C>>,
+    table: HashMap<DagNode<C>, DagId>,
+}
+
+impl<C: Clone + Eq + Hash> Default for LazyGraphDag<C> {
+    fn default() -> Self {
+        LazyGraphDag::new()
+    }
+}
+
+impl<C: Clone + Eq + Hash> LazyGraphDag<C> {
+    pub fn new() -> LazyGraphDag<C> {
+        LazyGraphDag {
+            nodes: Vec::new(),
+            table: HashMap::new(),
+        }
+    }
+
+    // The number of distinct nodes.
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: DagId) -> &DagNode<C> {
+        &self.nodes[id.0]
+    }
+
+    pub fn intern(&mut self, node: DagNode<C>) -> DagId {
+        if let Some(&id) = self.table.get(&node) {
+            return id;
+        }
+        let id = DagId(self.nodes.len());
+        self.nodes.push(node.clone());
+        self.table.insert(node, id);
+        id
+    }
+
+    pub fn empty(&mut self) -> DagId {
+        self.intern(DagNode::Empty())
+    }
+
+    pub fn stop(&mut self, c: &C) -> DagId {
+        self.intern(DagNode::Stop(c.clone()))
+    }
+
+    pub fn build(&mut self, c: &C, lss: Vec<Vec<DagId>>) -> DagId {
+        self.intern(DagNode::Build(c.clone(), lss))
+    }
+
+    pub fn intern_lazy_graph(&mut self, l: &Rc<LazyGraph<C>>) -> DagId {
+        self.intern_lazy_graph_loop(&mut HashMap::new(), l)
+    }
+
+    fn intern_lazy_graph_loop(
+        &mut self,
+        memo: &mut HashMap<*const LazyGraph<C>, DagId>,
+        l: &Rc<LazyGraph<C>>,
+    ) -> DagId {
+        if let Some(&id) = memo.get(&Rc::as_ptr(l)) {
+            return id;
+        }
+        let id = match &**l {
+            LazyGraph::Empty() => self.empty(),
+            LazyGraph::Stop(c) => self.stop(c),
+            LazyGraph::Build(c, lss) => {
+                let mut iss = Vec::with_capacity(lss.len());
+                for ls in lss {
+                    let mut is = Vec::with_capacity(ls.len());
+                    for l1 in ls {
+                        is.push(self.intern_lazy_graph_loop(memo, l1));
+                    }
+                    iss.push(is);
+                }
+                self.build(c, iss)
+            }
+        };
+        memo.insert(Rc::as_ptr(l), id);
+        id
+    }
+
+    pub fn to_lazy_graph(&self, id: DagId) -> Rc<LazyGraph<C>> {
+        self.to_lazy_graph_loop(&mut HashMap::new(), id)
+    }
+
+    fn to_lazy_graph_loop(
+        &self,
+        memo: &mut HashMap<DagId, Rc<LazyGraph<C>>>,
+        id: DagId,
+    ) -> Rc<LazyGraph<C>> {
+        if let Some(l) = memo.get(&id) {
+            return Rc::clone(l);
+        }
+        let l = match &self.nodes[id.0] {
+            DagNode::Empty() => empty(),
+            DagNode::Stop(c) => stop(c),
+            DagNode::Build(c, iss) => {
+                let lss: Vec<Ls<C>> = iss
+                    .iter()
+                    .map(|is| {
+                        is.iter()
+                            .map(|&i| self.to_lazy_graph_loop(memo, i))
+                            .collect()
+                    })
+                    .collect();
+                build(c, &lss)
+            }
+        };
+        memo.insert(id, Rc::clone(&l));
+        l
+    }
+}
+
+// `lazy_mrsc_dag(s, dag, c0)` builds the hash-consed lazy graph directly,
+// without building a `LazyGraph` first.
+
+fn lazy_mrsc_dag_loop<S>(
+    s: &S,
+    dag: &mut LazyGraphDag<S::C>,
+    h: &History<S::C>,
+    c: S::C,
+) -> DagId
+where
+    S: ScWorld,
+    S::C: Eq + Hash,
+{
+    match sc_step(s, h, &c) {
+        ScEvent::Folded => dag.stop(&c),
+        ScEvent::Pruned => dag.empty(),
+        ScEvent::Expanded => {
+            let css = s.develop_iter(h, &c);
+            let h1 = h.cons(c.clone());
+            let mut iss = Vec::with_capacity(css.size_hint().0);
+            for cs in css {
+                let mut is = Vec::with_capacity(cs.len());
+                for c1 in cs {
+                    is.push(lazy_mrsc_dag_loop(s, dag, &h1, c1));
+                }
+                iss.push(is);
+            }
+            dag.build(&c, iss)
+        }
+    }
+}
+
+pub fn lazy_mrsc_dag<S>(s: &S, dag: &mut LazyGraphDag<S::C>, c0: S::C) -> DagId
+where
+    S: ScWorld,
+    S::C: Eq + Hash,
+{
+    lazy_mrsc_dag_loop(s, dag, &History::new(), c0)
+}
+
+fn clean_fused_dag_loop<C: Clone + Eq + Hash, N: NodeCleaner<C>>(
+    dag: &mut LazyGraphDag<C>,
+    n: &N,
+    memo: &mut HashMap<DagId, (N::S, DagId)>,
+    id: DagId,
+) -> (N::S, DagId) {
+    if let Some(r) = memo.get(&id) {
+        return r.clone();
+    }
+    let r = match dag.node(id).clone() {
+        DagNode::Empty() => (n.empty(), id),
+        DagNode::Stop(c) => match n.stop(&c) {
+            (s, true) => (s, id),
+            (s, false) => (s, dag.empty()),
+        },
+        DagNode::Build(c, iss) => {
+            let mut alts = Vec::with_capacity(iss.len());
+            let mut iss1 = Vec::with_capacity(iss.len());
+            for is in iss {
+                let (ss, is1): (Vec<N::S>, Vec<DagId>) = is
+                    .iter()
+                    .map(|&i| clean_fused_dag_loop(dag, n, memo, i))
+                    .unzip();
+                alts.push(ss);
+                iss1.push(is1);
+            }
+            match n.build(&c, &alts) {
+                (s, None) => (s, dag.empty()),
+                (s, Some(ks)) => {
+                    let iss2 =
+                        ks.into_iter().map(|k| iss1[k].clone()).collect();
+                    (s, dag.build(&c, iss2))
+                }
+            }
+        }
+    };
+    memo.insert(id, r.clone());
+    r
+}
+
+pub fn clean_fused_dag<C: Clone + Eq + Hash, N: NodeCleaner<C>>(
+    dag: &mut LazyGraphDag<C>,
+    n: &N,
+    id: DagId,
+) -> DagId {
+    clean_fused_dag_loop(dag, n, &mut HashMap::new(), id).1
+}
+
+// `length_unroll` for hash-consed lazy graphs.
+
+fn length_unroll_dag_loop<C>(
+    dag: &LazyGraphDag<C>,
+    memo: &mut Vec<Option<usize>>,
+    id: DagId,
+) -> usize {
+    if let Some(k) = memo[id.0] {
+        return k;
+    }
+    let k = match &dag.nodes[id.0] {
+        DagNode::Empty() => 0,
+        DagNode::Stop(_) => 1,
+        DagNode::Build(_, iss) => {
+            let mut s = 0;
+            for is in iss {
+                let mut m = 1;
+                for &i in is {
+                    m *= length_unroll_dag_loop(dag, memo, i);
+                }
+                s += m;
+            }
+            s
+        }
+    };
+    memo[id.0] = Some(k);
+    k
+}
+
+pub fn length_unroll_dag<C>(dag: &LazyGraphDag<C>, id: DagId) -> usize {
+    let mut memo = vec![None; dag.nodes.len()];
+    length_unroll_dag_loop(dag, &mut memo, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::lazy_mrsc;
+    use crate::counter_system;
+    use crate::counters::*;
+    use crate::fusion::{clean_fused, fuse, NcBadConf, NcEmpty, NcMinSize};
+    use crate::mock_sc_world::mock_world;
+    use crate::statistics::{lazy_graph_size, length_unroll};
+
+    counter_system! {
+        Pump(i, j);
+        Start(ω, 0);
+        Unsafe(false);
+        Rules{
+            i >= 1 => i - 1, j + 1;
+            j >= 1 => i + 1, j - 1;
+        }
+    }
+
+    #[test]
+    fn test_hash_consing() {
+        let mut dag = LazyGraphDag::new();
+        let a = dag.stop(&1);
+        let b = dag.stop(&1);
+        assert_eq!(a, b);
+        let c = dag.build(&0, vec![vec![a], vec![b]]);
+        assert_eq!(dag.build(&0, vec![vec![a], vec![a]]), c);
+        assert_eq!(dag.len(), 2);
+    }
+
+    #[test]
+    fn test_lazy_mrsc_dag() {
+        let s = mock_world();
+        let mut dag = LazyGraphDag::new();
+        let id = lazy_mrsc_dag(&s, &mut dag, 0);
+        let l = lazy_mrsc(&s, 0);
+        assert_eq!(dag.to_lazy_graph(id), l);
+        assert_eq!(dag.intern_lazy_graph(&l), id);
+        assert_eq!(length_unroll_dag(&dag, id), length_unroll(&l));
+
+        let s = CountersScWorld::new(Pump, 3, 10);
+        let mut dag = LazyGraphDag::new();
+        let id = lazy_mrsc_dag(&s, &mut dag, Pump.start());
+        let l = lazy_mrsc(&s, Pump.start());
+        assert_eq!(dag.to_lazy_graph(id), l);
+        assert!(dag.len() < lazy_graph_size(&l));
+    }
+
+    #[test]
+    fn test_clean_fused_dag() {
+        let bad = |c: &isize| *c >= 3;
+        let l = lazy_mrsc(&mock_world(), 0);
+        let mut dag = LazyGraphDag::new();
+        let id = dag.intern_lazy_graph(&l);
+        let n = fuse(fuse(NcBadConf(bad), NcEmpty), NcMinSize);
+        let id1 = clean_fused_dag(&mut dag, &n, id);
+        assert_eq!(dag.to_lazy_graph(id1), clean_fused(&n, &l));
+    }
+}
@@ -0,0 +1,56 @@
+//
+// Errors
+//
+// Supercompilation may take too much time or memory, and the numbers
+// of graphs may be too big to be represented by `usize`. The unchecked
+// entry points (`lazy_mrsc`, `unroll`, `length_unroll`, etc.) do not
+// care: they run until the memory is exhausted, or overflow. Their
+// checked counterparts (`lazy_mrsc_checked`, `unroll_checked`,
+// `length_unroll_checked`, `size_unroll_checked`) stop as soon as
+// a limit has been exceeded (or an arithmetic operation has overflowed)
+// and report the reason as a `StagedMrscError`.
+//
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StagedMrscError {
+    // The depth of the lazy graph has exceeded the limit.
+    DepthLimit(usize),
+    // The number of nodes of the lazy graph has exceeded the limit.
+    NodeLimit(usize),
+    // The number of graphs to be produced by `unroll` exceeds the limit.
+    GraphLimit(usize),
+    // An arithmetic overflow in computing statistics.
+    Overflow,
+}
+
+impl fmt::Display for StagedMrscError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StagedMrscError::DepthLimit(n) => {
+                write!(f, "the depth limit ({}) has been exceeded", n)
+            }
+            StagedMrscError::NodeLimit(n) => {
+                write!(f, "the node limit ({}) has been exceeded", n)
+            }
+            StagedMrscError::GraphLimit(n) => {
+                write!(f, "the graph limit ({}) has been exceeded", n)
+            }
+            StagedMrscError::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+impl Error for StagedMrscError {}
+
+// The limits for the checked entry points (`None` meaning no limit).
+// The depth of the root is 0.
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct ResourceLimits {
+    pub max_depth: Option<usize>,
+    pub max_nodes: Option<usize>,
+    pub max_graphs: Option<usize>,
+}
@@ -0,0 +1,264 @@
+//
+// Fusion of cleaners
+//
+// Many cleaners (`cl_empty`, `cl_bad_conf`, `cl_min_size`) are
+// "bottom-up": the result of cleaning a node is determined by
+// the configuration in the node and by the results of cleaning
+// its subtrees. Such a cleaner can be given by a per-node transition
+// function, computing a "state" of the node from the states of its
+// subtrees, and deciding which alternatives are to be kept.
+//
+// The point is that a pipeline of such cleaners can be applied
+// in a single traversal of a lazy graph, by tupling the states
+// of the cleaners:
+//     clean_fused(fuse(n1, n2), l) == clean2(clean1(l))
+//
+
+use crate::graph::*;
+
+use std::rc::Rc;
+
+use LazyGraph::{Build, Empty, Stop};
+
+pub trait NodeCleaner<C> {
+    type S: Clone;
+
+    // The state of an empty node.
+    fn empty(&self) -> Self::S;
+
+    // The state of `Stop(c)` and whether the node is to be kept
+    // (otherwise, it is replaced with `Empty()`).
+    fn stop(&self, c: &C) -> (Self::S, bool);
+
+    // The state of `Build(c, lss)`, given the states of the subtrees,
+    // and the indices of the alternatives to be kept
+    // (or `None`, if the node is to be replaced with `Empty()`).
+    fn build(
+        &self,
+        c: &C,
+        alts: &[Vec<Self::S>],
+    ) -> (Self::S, Option<Vec<usize>>);
+}
+
+fn clean_fused_loop<C: Clone, N: NodeCleaner<C>>(
+    n: &N,
+    l: &LazyGraph<C>,
+) -> (N::S, Rc<LazyGraph<C>>) {
+    match l {
+        Empty() => (n.empty(), empty()),
+        Stop(c) => match n.stop(c) {
+            (s, true) => (s, stop(c)),
+            (s, false) => (s, empty()),
+        },
+        Build(c, lss) => {
+            let mut alts = Vec::with_capacity(lss.len());
+            let mut lss1 = Vec::with_capacity(lss.len());
+            for ls in lss {
+                let (ss, ls1): (Vec<N::S>, Ls<C>) =
+                    ls.iter().map(|l1| clean_fused_loop(n, l1)).unzip();
+                alts.push(ss);
+                lss1.push(ls1);
+            }
+            match n.build(c, &alts) {
+                (s, None) => (s, empty()),
+                (s, Some(is)) => {
+                    let lss2: Vec<Ls<C>> =
+                        is.into_iter().map(|i| lss1[i].clone()).collect();
+                    (s, build(c, &lss2))
+                }
+            }
+        }
+    }
+}
+
+pub fn clean_fused<C: Clone, N: NodeCleaner<C>>(
+    n: &N,
+    l: &LazyGraph<C>,
+) -> Rc<LazyGraph<C>> {
+    clean_fused_loop(n, l).1
+}
+
+// Also returns the state of the root.
+
+pub fn clean_fused_with_state<C: Clone, N: NodeCleaner<C>>(
+    n: &N,
+    l: &LazyGraph<C>,
+) -> (N::S, Rc<LazyGraph<C>>) {
+    clean_fused_loop(n, l)
+}
+
+// `fuse(n1, n2)` first applies `n1` and then `n2`. `n2` only sees
+// the alternatives kept by `n1`, and the nodes removed by `n1`
+// are empty from the viewpoint of `n2`.
+
+pub struct Fused<N1, N2>(pub N1, pub N2);
+
+pub fn fuse<N1, N2>(n1: N1, n2: N2) -> Fused<N1, N2> {
+    Fused(n1, n2)
+}
+
+impl<C, N1: NodeCleaner<C>, N2: NodeCleaner<C>> NodeCleaner<C>
+    for Fused<N1, N2>
+{
+    type S = (N1::S, N2::S);
+
+    fn empty(&self) -> Self::S {
+        (self.0.empty(), self.1.empty())
+    }
+
+    fn stop(&self, c: &C) -> (Self::S, bool) {
+        match self.0.stop(c) {
+            (s1, false) => ((s1, self.1.empty()), false),
+            (s1, true) => {
+                let (s2, k2) = self.1.stop(c);
+                ((s1, s2), k2)
+            }
+        }
+    }
+
+    fn build(
+        &self,
+        c: &C,
+        alts: &[Vec<Self::S>],
+    ) -> (Self::S, Option<Vec<usize>>) {
+        let alts1: Vec<Vec<N1::S>> = alts
+            .iter()
+            .map(|ss| ss.iter().map(|s| s.0.clone()).collect())
+            .collect();
+        match self.0.build(c, &alts1) {
+            (s1, None) => ((s1, self.1.empty()), None),
+            (s1, Some(is1)) => {
+                let alts2: Vec<Vec<N2::S>> = is1
+                    .iter()
+                    .map(|&i| alts[i].iter().map(|s| s.1.clone()).collect())
+                    .collect();
+                let (s2, is2) = self.1.build(c, &alts2);
+                let is =
+                    is2.map(|is2| is2.into_iter().map(|j| is1[j]).collect());
+                ((s1, s2), is)
+            }
+        }
+    }
+}
+
+//
+// Node cleaners corresponding to the cleaners in `graph`.
+//
+
+// `cl_empty`. The state tells whether the node is empty.
+
+pub struct NcEmpty;
+
+impl<C> NodeCleaner<C> for NcEmpty {
+    type S = bool;
+
+    fn empty(&self) -> bool {
+        true
+    }
+
+    fn stop(&self, _c: &C) -> (bool, bool) {
+        (false, true)
+    }
+
+    fn build(&self, _c: &C, alts: &[Vec<bool>]) -> (bool, Option<Vec<usize>>) {
+        let is: Vec<usize> = (0..alts.len())
+            .filter(|&i| !alts[i].iter().any(|&e| e))
+            .collect();
+        if is.is_empty() {
+            (true, None)
+        } else {
+            (false, Some(is))
+        }
+    }
+}
+
+// `cl_bad_conf`.
+
+pub struct NcBadConf<F>(pub F);
+
+impl<C, F: Fn(&C) -> bool> NodeCleaner<C> for NcBadConf<F> {
+    type S = ();
+
+    fn empty(&self) {}
+
+    fn stop(&self, c: &C) -> ((), bool) {
+        ((), !(self.0)(c))
+    }
+
+    fn build(&self, c: &C, alts: &[Vec<()>]) -> ((), Option<Vec<usize>>) {
+        if (self.0)(c) {
+            ((), None)
+        } else {
+            ((), Some((0..alts.len()).collect()))
+        }
+    }
+}
+
+// `cl_min_size`. The state is the size of the smallest graph
+// (`usize::MAX` representing ∞).
+
+pub struct NcMinSize;
+
+impl<C> NodeCleaner<C> for NcMinSize {
+    type S = usize;
+
+    fn empty(&self) -> usize {
+        usize::MAX
+    }
+
+    fn stop(&self, _c: &C) -> (usize, bool) {
+        (1, true)
+    }
+
+    fn build(
+        &self,
+        _c: &C,
+        alts: &[Vec<usize>],
+    ) -> (usize, Option<Vec<usize>>) {
+        let mut best = (usize::MAX, 0);
+        for (i, ks) in alts.iter().enumerate() {
+            let k = ks.iter().fold(0, |k, &k1| {
+                if k == usize::MAX || k1 == usize::MAX {
+                    usize::MAX
+                } else {
+                    k + k1
+                }
+            });
+            if k <= best.0 {
+                best = (k, i);
+            }
+        }
+        match best {
+            (usize::MAX, _) => (usize::MAX, None),
+            (k, i) => (1 + k, Some(vec![i])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::lazy_mrsc;
+    use crate::mock_sc_world::mock_world;
+
+    fn bad_i(c: &isize) -> bool {
+        *c >= 3
+    }
+
+    #[test]
+    fn test_clean_fused() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        assert_eq!(clean_fused(&NcEmpty, &l), cl_empty(&l));
+        assert_eq!(clean_fused(&NcMinSize, &l), cl_min_size(&l));
+        assert_eq!(
+            clean_fused(&fuse(NcBadConf(bad_i), NcEmpty), &l),
+            cl_empty_and_bad(bad_i, &l)
+        );
+        let n = fuse(fuse(NcBadConf(bad_i), NcEmpty), NcMinSize);
+        assert_eq!(
+            clean_fused(&n, &l),
+            cl_min_size(&cl_empty_and_bad(bad_i, &l))
+        );
+    }
+}
@@ -4,10 +4,17 @@
 
 use crate::misc::cartesian;
 
-use iter_comprehensions::{map, sum as vec_sum, vec as vec_map};
+use iter_comprehensions::{map, vec as vec_map};
 use itertools::Itertools;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::error::Error;
 use std::fmt;
+use std::hash::Hash;
+use std::ops::Add;
 use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 // A `Graph[C]` is supposed to represent a residual program.
 // Technically, a `Graph[C]` is a tree, with `back` nodes being
@@ -35,8 +42,14 @@ use std::rc::Rc;
 //      two-level supercompilation).
 
 // Graph
+//
+// With the `serde` feature, graphs (and lazy graphs) can be serialized.
+// The children of a node are serialized by value, hence, shared subtrees
+// are duplicated (and are no longer shared after deserialization).
+// Whole graphs are to be stored in an `archive::Versioned` envelope.
 
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Graph<C> {
     Back(C),
     Forth(C, Gs<C>),
@@ -46,6 +59,22 @@ pub type Gs<C> = Vec<Rc<Graph<C>>>;
 
 use Graph::{Back, Forth};
 
+// Deep graphs are dropped without recursion: the children that are not
+// shared are detached and dropped one by one.
+
+impl<C> Drop for Graph<C> {
+    fn drop(&mut self) {
+        if let Forth(_, gs) = self {
+            let mut stack: Gs<C> = std::mem::take(gs);
+            while let Some(g) = stack.pop() {
+                if let Ok(Forth(_, gs1)) = &mut Rc::try_unwrap(g) {
+                    stack.append(gs1);
+                }
+            }
+        }
+    }
+}
+
 pub fn back<C: Clone>(c: &C) -> Rc<Graph<C>> {
     Rc::new(Back(c.clone()))
 }
@@ -85,6 +114,401 @@ pub fn graph_pretty_printer<C: fmt::Display>(g: &Graph<C>) -> String {
     graph_pretty_printer_loop(g, 0)
 }
 
+impl<C: fmt::Display> fmt::Display for Graph<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", graph_pretty_printer(self))
+    }
+}
+
+// Truncated printing
+//
+// For big graphs, the output of `graph_pretty_printer` may be huge.
+// `graph_pretty_printer_limited(g, &limits)` stops at the depth
+// `limits.max_depth` (the root being at the depth 0) and after printing
+// `limits.max_nodes` nodes. Each subtree omitted because of the depth
+// is replaced with a line `|__... (n nodes)`, `n` being the size
+// of the subtree. Once `limits.max_nodes` nodes have been printed,
+// the remaining siblings are replaced with a single line
+// `|__... (k subtrees, n nodes)`, `n` being the size of the `k` subtrees.
+// Without limits, the output is the same as that of
+// `graph_pretty_printer`. (But the truncated output cannot be read back
+// by `parse_graph`.)
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PrintLimits {
+    pub max_depth: Option<usize>,
+    pub max_nodes: Option<usize>,
+}
+
+fn count_of(n: usize, what: &str) -> String {
+    match n {
+        1 => format!("1 {}", what),
+        _ => format!("{} {}s", n, what),
+    }
+}
+
+fn omitted_line(ind: &str, k: usize, n: usize) -> String {
+    match k {
+        1 => format!("{}|__... ({})", ind, count_of(n, "node")),
+        _ => format!(
+            "{}|__... ({}, {})",
+            ind,
+            count_of(k, "subtree"),
+            count_of(n, "node")
+        ),
+    }
+}
+
+fn graph_pretty_printer_limited_loop<C: fmt::Display>(
+    g: &Graph<C>,
+    limits: &PrintLimits,
+    depth: usize,
+    printed: &mut usize,
+    lines: &mut Vec<String>,
+) {
+    let ind = " ".repeat(2 * depth);
+    let too_deep = matches!(limits.max_depth, Some(d) if depth > d);
+    let too_many = matches!(limits.max_nodes, Some(n) if *printed >= n);
+    if too_deep || too_many {
+        lines.push(omitted_line(&ind, 1, graph_size(g)));
+        return;
+    }
+    *printed += 1;
+    match g {
+        Back(c) => lines.push(format!("{}|__{}*", ind, c)),
+        Forth(c, gs) => {
+            lines.push(format!("{}|__{}", ind, c));
+            for (i, g1) in gs.iter().enumerate() {
+                lines.push(format!("  {}|", ind));
+                if matches!(limits.max_nodes, Some(n) if *printed >= n) {
+                    let n = gs[i..].iter().map(|g2| graph_size(g2)).sum();
+                    lines.push(omitted_line(
+                        &format!("  {}", ind),
+                        gs.len() - i,
+                        n,
+                    ));
+                    break;
+                }
+                graph_pretty_printer_limited_loop(
+                    g1,
+                    limits,
+                    depth + 1,
+                    printed,
+                    lines,
+                );
+            }
+        }
+    }
+}
+
+pub fn graph_pretty_printer_limited<C: fmt::Display>(
+    g: &Graph<C>,
+    limits: &PrintLimits,
+) -> String {
+    let mut lines = Vec::new();
+    graph_pretty_printer_limited_loop(g, limits, 0, &mut 0, &mut lines);
+    lines.join("\n")
+}
+
+// Parsing the output of `graph_pretty_printer`
+//
+// `parse_graph(s)` is the inverse of `graph_pretty_printer`, provided
+// that the configurations are printed on a single line and read back
+// by `FromStr`. (A trailing `*` marks a back node, so that
+// the configurations are not supposed to end with `*`.)
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    UnexpectedEnd,
+    // The number of the line (starting from 1) and the line.
+    Malformed(usize, String),
+    BadConfiguration(usize, String),
+    TrailingInput(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            ParseError::Malformed(n, line) => {
+                write!(f, "line {}: malformed line: {}", n, line)
+            }
+            ParseError::BadConfiguration(n, c) => {
+                write!(f, "line {}: bad configuration: {}", n, c)
+            }
+            ParseError::TrailingInput(n) => {
+                write!(f, "line {}: trailing input", n)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+fn parse_graph_loop<C: FromStr + Clone>(
+    lines: &[&str],
+    i: &mut usize,
+    indent: usize,
+) -> Result<Rc<Graph<C>>, ParseError> {
+    let ind = " ".repeat(indent);
+    let line = lines.get(*i).ok_or(ParseError::UnexpectedEnd)?;
+    let n = *i + 1;
+    let rest = line
+        .strip_prefix(&ind)
+        .and_then(|l| l.strip_prefix("|__"))
+        .ok_or_else(|| ParseError::Malformed(n, line.to_string()))?;
+    *i += 1;
+    let (text, is_back) = match rest.strip_suffix('*') {
+        Some(text) => (text, true),
+        None => (rest, false),
+    };
+    let c: C = text
+        .parse()
+        .map_err(|_| ParseError::BadConfiguration(n, text.to_string()))?;
+    if is_back {
+        return Ok(back(&c));
+    }
+    let bar = format!("{}  |", ind);
+    let mut gs = Vec::new();
+    while lines.get(*i) == Some(&bar.as_str()) {
+        *i += 1;
+        gs.push(parse_graph_loop(lines, i, indent + 2)?);
+    }
+    Ok(forth(&c, &gs))
+}
+
+pub fn parse_graph<C: FromStr + Clone>(
+    s: &str,
+) -> Result<Rc<Graph<C>>, ParseError> {
+    let lines: Vec<&str> = s.trim_end().lines().collect();
+    let mut i = 0;
+    let g = parse_graph_loop(&lines, &mut i, 0)?;
+    if i < lines.len() {
+        return Err(ParseError::TrailingInput(i + 1));
+    }
+    Ok(g)
+}
+
+// Traversals
+//
+// `preorder`, `postorder` and `bfs` enumerate the nodes of a graph,
+// yielding, for each node, its depth, its configuration and its kind.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeKind {
+    Back,
+    Forth,
+}
+
+fn node_info<C>(g: &Graph<C>) -> (&C, NodeKind, &[Rc<Graph<C>>]) {
+    match g {
+        Back(c) => (c, NodeKind::Back, &[]),
+        Forth(c, gs) => (c, NodeKind::Forth, gs),
+    }
+}
+
+pub struct Preorder<'a, C> {
+    stack: Vec<(usize, &'a Graph<C>)>,
+}
+
+impl<'a, C> Iterator for Preorder<'a, C> {
+    type Item = (usize, &'a C, NodeKind);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (d, g) = self.stack.pop()?;
+        let (c, kind, gs) = node_info(g);
+        for g1 in gs.iter().rev() {
+            self.stack.push((d + 1, g1));
+        }
+        Some((d, c, kind))
+    }
+}
+
+pub struct Postorder<'a, C> {
+    stack: Vec<(usize, &'a Graph<C>, bool)>,
+}
+
+impl<'a, C> Iterator for Postorder<'a, C> {
+    type Item = (usize, &'a C, NodeKind);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (d, g, expanded) = self.stack.pop()?;
+            let (c, kind, gs) = node_info(g);
+            if expanded || gs.is_empty() {
+                return Some((d, c, kind));
+            }
+            self.stack.push((d, g, true));
+            for g1 in gs.iter().rev() {
+                self.stack.push((d + 1, g1, false));
+            }
+        }
+    }
+}
+
+pub struct Bfs<'a, C> {
+    queue: VecDeque<(usize, &'a Graph<C>)>,
+}
+
+impl<'a, C> Iterator for Bfs<'a, C> {
+    type Item = (usize, &'a C, NodeKind);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (d, g) = self.queue.pop_front()?;
+        let (c, kind, gs) = node_info(g);
+        for g1 in gs {
+            self.queue.push_back((d + 1, g1));
+        }
+        Some((d, c, kind))
+    }
+}
+
+impl<C> Graph<C> {
+    pub fn preorder(&self) -> Preorder<'_, C> {
+        Preorder {
+            stack: vec![(0, self)],
+        }
+    }
+
+    pub fn postorder(&self) -> Postorder<'_, C> {
+        Postorder {
+            stack: vec![(0, self, false)],
+        }
+    }
+
+    pub fn bfs(&self) -> Bfs<'_, C> {
+        Bfs {
+            queue: VecDeque::from(vec![(0, self)]),
+        }
+    }
+
+    // The configurations of the nodes, in preorder
+    // (with or without their depths).
+
+    pub fn confs(&self) -> impl Iterator<Item = &C> + '_ {
+        self.preorder().map(|(_, c, _)| c)
+    }
+
+    pub fn confs_with_depth(&self) -> impl Iterator<Item = (usize, &C)> + '_ {
+        self.preorder().map(|(d, c, _)| (d, c))
+    }
+}
+
+// Combinators
+//
+// `g.map_conf(f)` replaces each configuration `c` in `g` with `f(c)`,
+// preserving the shape of `g`.
+//
+// `g.fold(back_f, forth_f)` computes a value bottom-up: a back node
+// with the configuration `c` gives `back_f(c)`, and a forth node gives
+// `forth_f(c, rs)`, where `rs` are the values of its subtrees.
+// For example, `graph_size` is
+//     g.fold(|_| 1, |_, rs| 1 + rs.iter().sum::<usize>())
+//
+// `g.any_conf(p)` and `g.all_conf(p)` check whether `p` holds for some
+// (all) of the configurations in `g` (stopping as soon as the answer
+// is known).
+
+fn map_conf_loop<C, D>(f: &impl Fn(&C) -> D, g: &Graph<C>) -> Graph<D> {
+    match g {
+        Back(c) => Back(f(c)),
+        Forth(c, gs) => {
+            Forth(f(c), vec_map!(Rc::new(map_conf_loop(f, g1)); g1 in gs))
+        }
+    }
+}
+
+fn fold_loop<C, R>(
+    back_f: &impl Fn(&C) -> R,
+    forth_f: &impl Fn(&C, Vec<R>) -> R,
+    g: &Graph<C>,
+) -> R {
+    match g {
+        Back(c) => back_f(c),
+        Forth(c, gs) => {
+            forth_f(c, vec_map!(fold_loop(back_f, forth_f, g1); g1 in gs))
+        }
+    }
+}
+
+impl<C> Graph<C> {
+    pub fn map_conf<D>(&self, f: impl Fn(&C) -> D) -> Graph<D> {
+        map_conf_loop(&f, self)
+    }
+
+    pub fn fold<R>(
+        &self,
+        back_f: impl Fn(&C) -> R,
+        forth_f: impl Fn(&C, Vec<R>) -> R,
+    ) -> R {
+        fold_loop(&back_f, &forth_f, self)
+    }
+
+    pub fn any_conf(&self, p: impl Fn(&C) -> bool) -> bool {
+        self.confs().any(p)
+    }
+
+    pub fn all_conf(&self, p: impl Fn(&C) -> bool) -> bool {
+        self.confs().all(p)
+    }
+}
+
+// Accessors
+//
+// The depth of a graph is the length of its longest path (so that
+// the depth of a single node is 0). The leaves are the back nodes
+// and the forth nodes without children.
+
+impl<C> Graph<C> {
+    pub fn root_conf(&self) -> &C {
+        match self {
+            Back(c) => c,
+            Forth(c, _) => c,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.fold(|_| 0, |_, ds| ds.into_iter().max().map_or(0, |d| d + 1))
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.fold(|_| 1, |_, ns| ns.iter().sum::<usize>().max(1))
+    }
+
+    pub fn back_count(&self) -> usize {
+        graph_backs(self)
+    }
+}
+
+// Subtrees
+//
+// `subgraph_at(g, path)` returns the subtree of `g` reached by following
+// `path`, a list of child indices (or `None`, if there is no such
+// subtree).
+//
+// Note that the subtree is returned as it is: its back nodes may refer
+// to the ancestors of its root, which are not in the subtree. (For
+// example, in `forth(1, [forth(2, [back(1)])])` the subtree at `[0]`
+// has a back node without a target.) So the subtree is a graph
+// by itself only if all its back nodes are folded to its own nodes,
+// which is to be checked by the caller (e.g. by means of
+// `ScWorld::is_foldable_to`), since `Back(c)` does not record
+// the node it refers to.
+
+pub fn subgraph_at<C>(
+    g: &Rc<Graph<C>>,
+    path: &[usize],
+) -> Option<Rc<Graph<C>>> {
+    let mut g1 = g;
+    for &i in path {
+        match &**g1 {
+            Back(_) => return None,
+            Forth(_, gs) => g1 = gs.get(i)?,
+        }
+    }
+    Some(Rc::clone(g1))
+}
+
 //
 // Lazy graphs of configurations
 //
@@ -99,6 +523,7 @@ pub fn graph_pretty_printer<C: fmt::Display>(g: &Graph<C>) -> String {
 // LazyGraph
 
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LazyGraph<C> {
     Empty(),
     Stop(C),
@@ -109,6 +534,55 @@ pub type Ls<C> = Vec<Rc<LazyGraph<C>>>;
 
 use LazyGraph::{Build, Empty, Stop};
 
+// Deep lazy graphs are dropped without recursion, as graphs are.
+
+impl<C> Drop for LazyGraph<C> {
+    fn drop(&mut self) {
+        if let Build(_, lss) = self {
+            let mut stack: Ls<C> =
+                std::mem::take(lss).into_iter().flatten().collect();
+            while let Some(l) = stack.pop() {
+                if let Ok(Build(_, lss1)) = &mut Rc::try_unwrap(l) {
+                    stack.extend(std::mem::take(lss1).into_iter().flatten());
+                }
+            }
+        }
+    }
+}
+
+// Lazy graphs are displayed on a single line: `Empty()` as `∅`,
+// `Stop(c)` as `c*` and `Build(c, lss)` as `c[ls1 | ... | lsn]`,
+// the components of an alternative being separated by commas
+// (and an alternative with no components being written as `()`).
+// For example, `1[2[1*, 2*] | 3*]`.
+
+impl<C: fmt::Display> fmt::Display for LazyGraph<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Empty() => write!(f, "∅"),
+            Stop(c) => write!(f, "{}*", c),
+            Build(c, lss) => {
+                write!(f, "{}[", c)?;
+                for (i, ls) in lss.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    if ls.is_empty() {
+                        write!(f, "()")?;
+                    }
+                    for (j, l) in ls.iter().enumerate() {
+                        if j > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", l)?;
+                    }
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
 pub fn empty<C: Clone>() -> Rc<LazyGraph<C>> {
     Rc::new(Empty())
 }
@@ -124,167 +598,1017 @@ pub fn build<C: Clone>(c: &C, lss: &[Ls<C>]) -> Rc<LazyGraph<C>> {
     ))
 }
 
-// The semantics of a `LazyGraph a` is formally defined by
-// the interpreter `unroll` that generates a list of `Graph a` from
-// the `LazyGraph a` by executing commands recorded in the `LazyGraph a`.
-
-pub fn unroll<C: Clone>(l: &LazyGraph<C>) -> Gs<C> {
-    match l {
-        Empty() => Vec::new(),
-        Stop(c) => vec![back(c)],
-        Build(c, lss) => {
-            let gss = Itertools::concat(
-                map!(cartesian(&vec_map!(unroll(l); l in ls)); ls in lss),
-            );
-            vec_map!(forth(c, &gs); gs in gss)
+// `sublazy_at(l, path)` returns the lazy subgraph of `l` reached by
+// following `path`, a list of pairs (alternative index, child index).
+
+pub fn sublazy_at<C>(
+    l: &Rc<LazyGraph<C>>,
+    path: &[(usize, usize)],
+) -> Option<Rc<LazyGraph<C>>> {
+    let mut l1 = l;
+    for &(i, j) in path {
+        match &**l1 {
+            Build(_, lss) => l1 = lss.get(i)?.get(j)?,
+            _ => return None,
         }
     }
+    Some(Rc::clone(l1))
 }
 
-// Usually, we are not interested in the whole bag `unroll(l)`.
-// The goal is to find "the best" or "most interesting" graphs.
-// Hence, there should be developed some techniques of extracting
-// useful information from a `LazyGraph[C]` without evaluating
-// `unroll(l)` directly.
-
-// This can be formulated in the following form.
-// Suppose that a function `select` filters bags of graphs,
-// removing "bad" graphs, so that
-//     select (unroll l)
-// generates the bag of "good" graphs.
-// Let us find a function `extract` such that
-//     extract(l) == select(unroll(l))
-// In many cases, `extract` may be more efficient (by several orders
-// of magnitude) than the composition `select . unroll`.
-// Sometimes, `extract` can be formulated in terms of "cleaners" of
-// lazy graphs. Let `clean` be a function that transforms lazy graphs,
-// such that
-//     unroll(clean(l)) ⊆ unroll(l)
-// Then `extract` can be constructed in the following way:
-//     extract(l) == unroll(clean(l))
-// Theoretically speaking, `clean` is the result of "promoting" `select`:
-//     (select compose unroll)(l) == (unroll compose clean)(l)
-// The nice property of cleaners is that they are composable:
-// given `clean1` and `clean2`, `clean2 compose clean1` is also a cleaner.
-
-//
-// Some filters
-//
-
-// Removing graphs that contain "bad" configurations.
-// Configurations represent states of a computation process.
-// Some of these states may be "bad" with respect to the problem
-// that is to be solved by means of supercompilation.
+// `l.map_conf(f)` replaces each configuration `c` in `l` with `f(c)`,
+// preserving the structure of `l` (including the sharing of subtrees),
+// so that
+//     unroll(l.map_conf(f)) == unroll(l).map(|g| g.map_conf(f))
+// `l.try_map_conf(f)` stops at the first error returned by `f`.
 
-fn bad_graph<C>(bad: fn(&C) -> bool, g: &Graph<C>) -> bool {
-    match g {
-        Back(c) => bad(c),
-        Forth(c, gs) => bad(c) || gs.iter().any(|g| bad_graph(bad, g)),
-    }
+fn try_map_conf_loop<C, D, E>(
+    f: &impl Fn(&C) -> Result<D, E>,
+    memo: &mut HashMap<*const LazyGraph<C>, Rc<LazyGraph<D>>>,
+    l: &LazyGraph<C>,
+) -> Result<LazyGraph<D>, E> {
+    Ok(match l {
+        Empty() => Empty(),
+        Stop(c) => Stop(f(c)?),
+        Build(c, lss) => {
+            let c1 = f(c)?;
+            let mut lss1 = Vec::with_capacity(lss.len());
+            for ls in lss {
+                let mut ls1 = Vec::with_capacity(ls.len());
+                for l1 in ls {
+                    let m = match memo.get(&Rc::as_ptr(l1)) {
+                        Some(m) => Rc::clone(m),
+                        None => {
+                            let m = Rc::new(try_map_conf_loop(f, memo, l1)?);
+                            memo.insert(Rc::as_ptr(l1), Rc::clone(&m));
+                            m
+                        }
+                    };
+                    ls1.push(m);
+                }
+                lss1.push(ls1);
+            }
+            Build(c1, lss1)
+        }
+    })
 }
 
-// This filter removes the graphs containing "bad" configurations.
+impl<C> LazyGraph<C> {
+    pub fn map_conf<D>(&self, f: impl Fn(&C) -> D) -> LazyGraph<D> {
+        match self.try_map_conf(|c| Ok::<D, Infallible>(f(c))) {
+            Ok(l) => l,
+            Err(e) => match e {},
+        }
+    }
 
-pub fn fl_bad_conf<C>(bad: fn(&C) -> bool, gs: Gs<C>) -> Gs<C> {
-    vec_map!(g; g in gs, !bad_graph(bad, &g))
+    pub fn try_map_conf<D, E>(
+        &self,
+        f: impl Fn(&C) -> Result<D, E>,
+    ) -> Result<LazyGraph<D>, E> {
+        try_map_conf_loop(&f, &mut HashMap::new(), self)
+    }
 }
 
 //
-// Some cleaners
+// Node identifiers
 //
+// `index_graph(g)` and `index_lazy_graph(l)` assign to the nodes
+// integer identifiers: the numbers of the nodes in preorder.
+// Thus the identifiers depend only on the shape of the graph and
+// are the same in all tools exporting or exploring the graph.
+// The result is a `NodeTable` mapping identifiers to paths
+// (as accepted by `subgraph_at` and `sublazy_at`) and configurations.
+// (`Empty()` nodes have no configuration.)
+
+#[derive(Clone, Debug)]
+pub struct NodeTable<P, C> {
+    pub paths: Vec<P>,
+    pub confs: Vec<C>,
+    ids: HashMap<P, usize>,
+}
 
-// `cl_empty` removes subtrees that represent empty sets of graphs.
+impl<P: Clone + Eq + Hash, C> NodeTable<P, C> {
+    fn new() -> NodeTable<P, C> {
+        NodeTable {
+            paths: Vec::new(),
+            confs: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
 
-pub fn cl_empty<C: Clone>(l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
-    match l {
-        Empty() => empty(),
-        Stop(c) => stop(c),
-        Build(c, lss) => cl_empty_build(c, &cl_empty_lss(lss)),
+    fn push(&mut self, path: &P, c: C) {
+        self.ids.insert(path.clone(), self.paths.len());
+        self.paths.push(path.clone());
+        self.confs.push(c);
     }
-}
 
-fn cl_empty_build<C: Clone>(c: &C, lss: &[Ls<C>]) -> Rc<LazyGraph<C>> {
-    if lss.is_empty() {
-        empty()
-    } else {
-        build(c, lss)
+    pub fn len(&self) -> usize {
+        self.paths.len()
     }
-}
 
-fn cl_empty_lss<C: Clone>(lss: &Vec<Ls<C>>) -> Vec<Ls<C>> {
-    lss.into_iter().filter_map(|ls| cl_empty_ls(ls)).collect()
-}
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
 
-fn cl_empty_ls<C: Clone>(ls: &Ls<C>) -> Option<Ls<C>> {
-    let ls1 = vec_map!(cl_empty(l); l in ls);
-    if ls1.clone().into_iter().any(|l| is_lg_empty(&l)) {
-        None
-    } else {
-        Some(ls1)
+    pub fn id_of(&self, path: &P) -> Option<usize> {
+        self.ids.get(path).copied()
     }
 }
 
-fn is_lg_empty<C>(l: &LazyGraph<C>) -> bool {
-    match l {
-        Empty() => true,
-        _ => false,
+fn index_graph_loop<C: Clone>(
+    g: &Graph<C>,
+    path: &mut Vec<usize>,
+    t: &mut NodeTable<Vec<usize>, C>,
+) {
+    match g {
+        Back(c) => t.push(path, c.clone()),
+        Forth(c, gs) => {
+            t.push(path, c.clone());
+            for (i, g1) in gs.iter().enumerate() {
+                path.push(i);
+                index_graph_loop(g1, path, t);
+                path.pop();
+            }
+        }
     }
 }
 
-// Removing graphs that contain "bad" configurations.
-// The cleaner `cl_bad_conf` corresponds to the filter `fl_bad_conf`.
-// `cl_bad_conf` exploits the fact that "badness" is monotonic,
-// in the sense that a single "bad" configuration spoils the whole
-// graph.
+pub fn index_graph<C: Clone>(g: &Graph<C>) -> NodeTable<Vec<usize>, C> {
+    let mut t = NodeTable::new();
+    index_graph_loop(g, &mut vec![], &mut t);
+    t
+}
 
-fn cl_bad_conf<C: Clone>(
-    bad: fn(&C) -> bool,
+fn index_lazy_graph_loop<C: Clone>(
     l: &LazyGraph<C>,
-) -> Rc<LazyGraph<C>> {
+    path: &mut Vec<(usize, usize)>,
+    t: &mut NodeTable<Vec<(usize, usize)>, Option<C>>,
+) {
     match l {
-        Empty() => empty(),
-        Stop(c) => {
-            if bad(c) {
-                empty()
-            } else {
-                stop(c)
-            }
-        }
+        Empty() => t.push(path, None),
+        Stop(c) => t.push(path, Some(c.clone())),
         Build(c, lss) => {
-            if bad(c) {
-                empty()
-            } else {
-                build(
-                    c,
-                    &vec_map!(vec_map!(cl_bad_conf(bad, l); l in ls); ls in lss),
-                )
+            t.push(path, Some(c.clone()));
+            for (i, ls) in lss.iter().enumerate() {
+                for (j, l1) in ls.iter().enumerate() {
+                    path.push((i, j));
+                    index_lazy_graph_loop(l1, path, t);
+                    path.pop();
+                }
             }
         }
     }
 }
 
+pub fn index_lazy_graph<C: Clone>(
+    l: &LazyGraph<C>,
+) -> NodeTable<Vec<(usize, usize)>, Option<C>> {
+    let mut t = NodeTable::new();
+    index_lazy_graph_loop(l, &mut vec![], &mut t);
+    t
+}
+
+// Folding lazy graphs
 //
-// The graph returned by `cl_bad_conf` may be cleaned by `cl_empty`.
+// `l.fold(empty_f, stop_f, build_f)` computes a value bottom-up,
+// `build_f(c, rss)` receiving the values of the subtrees grouped by
+// alternatives. The subtrees are visited in the depth-first, left-to-right
+// order, the shared subtrees being visited as many times as they occur.
 //
+// The traversal uses an explicit stack (rather than recursion), so that
+// the depth of `l` is only limited by the heap. `unroll`, `cl_empty` and
+// `cl_min_size` are defined in terms of `fold`, which makes them usable
+// with deep lazy graphs (e.g., produced with a big `max_depth`).
+
+struct FoldFrame<'a, C, R> {
+    c: &'a C,
+    lss: &'a [Ls<C>],
+    // The position of the next subtree.
+    i: usize,
+    j: usize,
+    rss: Vec<Vec<R>>,
+    rs: Vec<R>,
+}
+
+impl<C> LazyGraph<C> {
+    pub fn fold<R>(
+        &self,
+        mut empty_f: impl FnMut() -> R,
+        mut stop_f: impl FnMut(&C) -> R,
+        mut build_f: impl FnMut(&C, Vec<Vec<R>>) -> R,
+    ) -> R {
+        let mut stack: Vec<FoldFrame<C, R>> = Vec::new();
+        let mut next = Some(self);
+        loop {
+            let mut result = None;
+            match next.take() {
+                Some(Empty()) => result = Some(empty_f()),
+                Some(Stop(c)) => result = Some(stop_f(c)),
+                Some(Build(c, lss)) => stack.push(FoldFrame {
+                    c,
+                    lss,
+                    i: 0,
+                    j: 0,
+                    rss: Vec::with_capacity(lss.len()),
+                    rs: Vec::new(),
+                }),
+                None => {}
+            }
+            if let Some(r) = result {
+                match stack.last_mut() {
+                    None => return r,
+                    Some(f) => {
+                        f.rs.push(r);
+                        f.j += 1;
+                    }
+                }
+            }
+            let f = stack.last_mut().unwrap();
+            let lss = f.lss;
+            if f.i < lss.len() {
+                if f.j < lss[f.i].len() {
+                    next = Some(&*lss[f.i][f.j]);
+                } else {
+                    f.rss.push(std::mem::take(&mut f.rs));
+                    f.i += 1;
+                    f.j = 0;
+                }
+            } else {
+                let f = stack.pop().unwrap();
+                let r = build_f(f.c, f.rss);
+                match stack.last_mut() {
+                    None => return r,
+                    Some(f1) => {
+                        f1.rs.push(r);
+                        f1.j += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The semantics of a `LazyGraph a` is formally defined by
+// the interpreter `unroll` that generates a list of `Graph a` from
+// the `LazyGraph a` by executing commands recorded in the `LazyGraph a`.
+
+pub fn unroll<C: Clone>(l: &LazyGraph<C>) -> Gs<C> {
+    l.fold(
+        Vec::new,
+        |c| vec![back(c)],
+        |c, gsss| {
+            let gss = Itertools::concat(map!(cartesian(&gss); gss in gsss));
+            vec_map!(forth(c, &gs); gs in gss)
+        },
+    )
+}
+
+// `unroll_first(l)` returns the first graph produced by `unroll(l)`
+// (if any), without building the Cartesian products. If `l` contains
+// no empty subtrees (e.g., has been cleaned by `cl_empty` or
+// `cl_min_size`), the time is proportional to the size of the graph.
+// Otherwise, the alternatives leading to empty subtrees are tried and
+// abandoned.
+
+pub fn unroll_first<C: Clone>(l: &LazyGraph<C>) -> Option<Rc<Graph<C>>> {
+    match l {
+        Empty() => None,
+        Stop(c) => Some(back(c)),
+        Build(c, lss) => lss.iter().find_map(|ls| {
+            let gs: Option<Gs<C>> =
+                ls.iter().map(|l1| unroll_first(l1)).collect();
+            gs.map(|gs| forth(c, &gs))
+        }),
+    }
+}
+
+// Membership
+//
+// `contains(l, g)` checks whether `g` is one of the graphs produced by
+// `unroll(l)`, without unrolling `l`:
+//     contains(l, g) == unroll(l).contains(g)
+// The lazy graph is traversed along `g`, so that the time is proportional
+// to the size of `g` multiplied by the number of alternatives tried
+// at each node.
+
+pub fn contains<C: PartialEq>(l: &LazyGraph<C>, g: &Graph<C>) -> bool {
+    match (l, g) {
+        (Stop(c1), Back(c2)) => c1 == c2,
+        (Build(c1, lss), Forth(c2, gs)) => {
+            c1 == c2
+                && lss.iter().any(|ls| {
+                    ls.len() == gs.len()
+                        && ls.iter().zip(gs).all(|(l1, g1)| contains(l1, g1))
+                })
+        }
+        _ => false,
+    }
+}
+
+// Addressing graphs by choices
+//
+// A graph represented by a lazy graph `l` is determined by the choice
+// of an alternative at each `Build` node it passes through. Hence, it can
+// be addressed by the list of the chosen alternatives, in the order in
+// which the `Build` nodes are encountered by a depth-first, left-to-right
+// traversal of the graph. `unroll_at(l, path)` returns the graph
+// addressed by `path` (or `None`, if `path` is not a complete, valid
+// address, or leads to an empty subtree).
+//
+// `num_alternatives(l, path)` returns the number of alternatives
+// at the `Build` node to be reached next, after the choices in `path`
+// have been made (or `None`, if `path` is complete or invalid).
+// Thus, a search procedure can extend a path one choice at a time.
+
+enum ChoiceStep<C> {
+    Done(Rc<Graph<C>>),
+    Choose(usize),
+    Invalid,
+}
+
+fn walk_choices<C: Clone>(
+    l: &LazyGraph<C>,
+    path: &[usize],
+    i: &mut usize,
+) -> ChoiceStep<C> {
+    match l {
+        Empty() => ChoiceStep::Invalid,
+        Stop(c) => ChoiceStep::Done(back(c)),
+        Build(c, lss) => {
+            let k = match path.get(*i) {
+                None => return ChoiceStep::Choose(lss.len()),
+                Some(&k) => k,
+            };
+            let ls = match lss.get(k) {
+                None => return ChoiceStep::Invalid,
+                Some(ls) => ls,
+            };
+            *i += 1;
+            let mut gs = Vec::with_capacity(ls.len());
+            for l1 in ls {
+                match walk_choices(l1, path, i) {
+                    ChoiceStep::Done(g) => gs.push(g),
+                    step => return step,
+                }
+            }
+            ChoiceStep::Done(forth(c, &gs))
+        }
+    }
+}
+
+pub fn unroll_at<C: Clone>(
+    l: &LazyGraph<C>,
+    path: &[usize],
+) -> Option<Rc<Graph<C>>> {
+    let mut i = 0;
+    match walk_choices(l, path, &mut i) {
+        ChoiceStep::Done(g) if i == path.len() => Some(g),
+        _ => None,
+    }
+}
+
+pub fn num_alternatives<C: Clone>(
+    l: &LazyGraph<C>,
+    path: &[usize],
+) -> Option<usize> {
+    match walk_choices(l, path, &mut 0) {
+        ChoiceStep::Choose(n) => Some(n),
+        _ => None,
+    }
+}
+
+// `unroll_iter(l)` enumerates the same graphs as `unroll(l)`, in the same
+// order, but lazily: the Cartesian products are generated on demand,
+// so that one can take the first graphs without generating all of them.
+// (The iterators of the subtrees are restarted when the product
+// "carries over", hence, some graphs of the subtrees are regenerated.)
+
+pub struct UnrollIter<C> {
+    l: Rc<LazyGraph<C>>,
+    // The alternative being enumerated and the state of its product.
+    alt: usize,
+    product: Option<ProductState<C>>,
+    done: bool,
+}
+
+struct ProductState<C> {
+    iters: Vec<UnrollIter<C>>,
+    current: Gs<C>,
+    fresh: bool,
+}
+
+pub fn unroll_iter<C: Clone>(l: &LazyGraph<C>) -> UnrollIter<C> {
+    UnrollIter {
+        l: Rc::new(l.clone()),
+        alt: 0,
+        product: None,
+        done: false,
+    }
+}
+
+fn start_product<C: Clone>(ls: &Ls<C>) -> Option<ProductState<C>> {
+    let mut iters = vec_map!(unroll_iter(&**l); l in ls);
+    let mut current = Vec::with_capacity(ls.len());
+    for it in iters.iter_mut() {
+        current.push(it.next()?);
+    }
+    Some(ProductState {
+        iters,
+        current,
+        fresh: true,
+    })
+}
+
+// Advances the product, the last component varying fastest.
+
+fn advance_product<C: Clone>(ls: &Ls<C>, p: &mut ProductState<C>) -> bool {
+    for i in (0..ls.len()).rev() {
+        if let Some(g) = p.iters[i].next() {
+            p.current[i] = g;
+            return true;
+        }
+        p.iters[i] = unroll_iter(&ls[i]);
+        match p.iters[i].next() {
+            Some(g) => p.current[i] = g,
+            None => return false,
+        }
+    }
+    false
+}
+
+impl<C: Clone> Iterator for UnrollIter<C> {
+    type Item = Rc<Graph<C>>;
+
+    fn next(&mut self) -> Option<Rc<Graph<C>>> {
+        if self.done {
+            return None;
+        }
+        let l = Rc::clone(&self.l);
+        match &*l {
+            Empty() => {
+                self.done = true;
+                None
+            }
+            Stop(c) => {
+                self.done = true;
+                Some(back(c))
+            }
+            Build(c, lss) => loop {
+                if self.alt >= lss.len() {
+                    self.done = true;
+                    return None;
+                }
+                let ls = &lss[self.alt];
+                match &mut self.product {
+                    None => {
+                        self.product = start_product(ls);
+                        if self.product.is_none() {
+                            self.alt += 1;
+                        }
+                    }
+                    Some(p) => {
+                        if p.fresh {
+                            p.fresh = false;
+                            return Some(forth(c, &p.current));
+                        }
+                        if advance_product(ls, p) {
+                            return Some(forth(c, &p.current));
+                        }
+                        self.product = None;
+                        self.alt += 1;
+                    }
+                }
+            },
+        }
+    }
+}
+
+//
+// Union and intersection of lazy graphs
+//
+
+// `lg_union(l1, l2)` represents the graphs represented by `l1` or `l2`:
+//     unroll(lg_union(l1, l2)) == unroll(l1) ∪ unroll(l2)
+// (modulo duplicates). Since all graphs represented by a lazy graph
+// have the same root, the union is only possible if the roots of `l1`
+// and `l2` coincide (or one of them is empty). Otherwise, `None` is
+// returned.
+
+pub fn lg_union<C: Clone + PartialEq>(
+    l1: &Rc<LazyGraph<C>>,
+    l2: &Rc<LazyGraph<C>>,
+) -> Option<Rc<LazyGraph<C>>> {
+    match (&**l1, &**l2) {
+        (Empty(), _) => Some(Rc::clone(l2)),
+        (_, Empty()) => Some(Rc::clone(l1)),
+        (Stop(c1), Stop(c2)) if c1 == c2 => Some(Rc::clone(l1)),
+        (Build(c1, lss1), Build(c2, lss2)) if c1 == c2 => {
+            let mut lss = lss1.clone();
+            for ls in lss2 {
+                if !lss.contains(ls) {
+                    lss.push(ls.clone());
+                }
+            }
+            Some(build(c1, &lss))
+        }
+        _ => None,
+    }
+}
+
+// `lg_intersect(l1, l2)` represents some of the graphs that are
+// represented by both `l1` and `l2`:
+//     unroll(lg_intersect(l1, l2)) ⊆ unroll(l1) ∩ unroll(l2)
+// Alternatives are intersected component-wise, which is exact,
+// unless the same graph can be produced by several alternatives.
+
+pub fn lg_intersect<C: Clone + PartialEq>(
+    l1: &LazyGraph<C>,
+    l2: &LazyGraph<C>,
+) -> Rc<LazyGraph<C>> {
+    match (l1, l2) {
+        (Stop(c1), Stop(c2)) if c1 == c2 => stop(c1),
+        (Build(c1, lss1), Build(c2, lss2)) if c1 == c2 => {
+            let mut lss: Vec<Ls<C>> = Vec::new();
+            for ls1 in lss1 {
+                for ls2 in lss2 {
+                    if ls1.len() != ls2.len() {
+                        continue;
+                    }
+                    let ls = vec_map!(lg_intersect(&ls1[i], &ls2[i]);
+                        i in 0..ls1.len());
+                    if !ls.iter().any(|l| is_lg_empty(l)) && !lss.contains(&ls)
+                    {
+                        lss.push(ls);
+                    }
+                }
+            }
+            cl_empty_build(c1, &lss)
+        }
+        _ => empty(),
+    }
+}
+
+//
+// Equivalence and inclusion of lazy graphs
+//
+
+// `lg_subset(l1, l2)` decides whether every graph represented by `l1`
+// is also represented by `l2`, and `lg_equiv(l1, l2)` decides whether
+// `l1` and `l2` represent the same set of graphs:
+//     lg_subset(l1, l2) == unroll(l1) ⊆ unroll(l2)
+//     lg_equiv(l1, l2) == (unroll(l1) and unroll(l2) are equal as sets)
+// Thus, the order of alternatives and the duplicates are disregarded.
+// For example, different pipelines of cleaners can be checked to produce
+// the same graphs, without unrolling the results.
+//
+// Both are reduced to the question of whether there is a graph
+// represented by all lazy graphs in `pos` and by none in `neg`.
+// At a `Build` node, an alternative is chosen for each lazy graph
+// in `pos`, so that the question is whether the product of the
+// (intersected) components is covered by the products given by the
+// alternatives in `neg`. A product is not covered iff there is a graph
+// `g1` in the first component, such that the rest of the product is not
+// covered by the alternatives whose first components contain `g1`.
+// Hence, the subsets of the alternatives in `neg` that may contain the
+// first graph are enumerated. In the worst case, this is exponential
+// in the number of alternatives, but never requires the graphs
+// to be unrolled.
+
+fn same_root<C: PartialEq>(l1: &LazyGraph<C>, l2: &LazyGraph<C>) -> bool {
+    match (l1, l2) {
+        (Stop(c1), Stop(c2)) => c1 == c2,
+        (Build(c1, _), Build(c2, _)) => c1 == c2,
+        _ => false,
+    }
+}
+
+fn lg_exists<C: PartialEq>(
+    pos: &[&LazyGraph<C>],
+    neg: &[&LazyGraph<C>],
+) -> bool {
+    if neg.iter().any(|n| pos.iter().any(|p| p == n)) {
+        return false;
+    }
+    if !pos.iter().all(|p| same_root(pos[0], p)) {
+        return false;
+    }
+    let neg: Vec<&LazyGraph<C>> = neg
+        .iter()
+        .copied()
+        .filter(|n| same_root(pos[0], n))
+        .collect();
+    match pos[0] {
+        Empty() => false,
+        Stop(_) => neg.is_empty(),
+        Build(..) => {
+            let lsss: Vec<&Vec<Ls<C>>> = pos
+                .iter()
+                .map(|p| match p {
+                    Build(_, lss) => lss,
+                    _ => unreachable!(),
+                })
+                .collect();
+            lsss.into_iter()
+                .multi_cartesian_product()
+                .any(|alts| lg_exists_alts(&alts, &neg))
+        }
+    }
+}
+
+// `alts` are the chosen alternatives of the lazy graphs in `pos`.
+
+fn lg_exists_alts<C: PartialEq>(
+    alts: &[&Ls<C>],
+    neg: &[&LazyGraph<C>],
+) -> bool {
+    let n = alts[0].len();
+    if alts.iter().any(|ls| ls.len() != n) {
+        return false;
+    }
+    let xs: Vec<Vec<&LazyGraph<C>>> =
+        vec_map!(vec_map!(&*ls[k]; ls in alts); k in 0..n);
+    let mut negs: Vec<Vec<&LazyGraph<C>>> = Vec::new();
+    for l in neg {
+        if let Build(_, lss) = l {
+            for ls in lss {
+                if ls.len() == n {
+                    negs.push(vec_map!(&**l1; l1 in ls));
+                }
+            }
+        }
+    }
+    lg_exists_product(&xs, &negs)
+}
+
+// Is there a tuple in the product of `xs` (each component being
+// the intersection of a list of lazy graphs) that is not in any of
+// the products `negs`?
+
+fn lg_exists_product<C: PartialEq>(
+    xs: &[Vec<&LazyGraph<C>>],
+    negs: &[Vec<&LazyGraph<C>>],
+) -> bool {
+    if xs.is_empty() {
+        return negs.is_empty();
+    }
+    // Only the alternatives whose first component may share graphs
+    // with the first component of `xs` matter.
+    let negs: Vec<&Vec<&LazyGraph<C>>> = negs
+        .iter()
+        .filter(|ns| same_root(xs[0][0], ns[0]))
+        .collect();
+    lg_exists_product_loop(xs, &negs, &mut Vec::new())
+}
+
+// `inside[i]` tells whether the first graph of the tuple is
+// in the first component of `negs[i]`.
+
+fn lg_exists_product_loop<C: PartialEq>(
+    xs: &[Vec<&LazyGraph<C>>],
+    negs: &[&Vec<&LazyGraph<C>>],
+    inside: &mut Vec<bool>,
+) -> bool {
+    if inside.len() == negs.len() {
+        let mut pos = xs[0].clone();
+        let mut neg = Vec::new();
+        let mut rest = Vec::new();
+        for (ns, &b) in negs.iter().zip(inside.iter()) {
+            if b {
+                pos.push(ns[0]);
+                rest.push(ns[1..].to_vec());
+            } else {
+                neg.push(ns[0]);
+            }
+        }
+        return lg_exists(&pos, &neg) && lg_exists_product(&xs[1..], &rest);
+    }
+    for b in [false, true] {
+        inside.push(b);
+        let found = lg_exists_product_loop(xs, negs, inside);
+        inside.pop();
+        if found {
+            return true;
+        }
+    }
+    false
+}
+
+pub fn lg_subset<C: PartialEq>(l1: &LazyGraph<C>, l2: &LazyGraph<C>) -> bool {
+    !lg_exists(&[l1], &[l2])
+}
+
+pub fn lg_equiv<C: PartialEq>(l1: &LazyGraph<C>, l2: &LazyGraph<C>) -> bool {
+    lg_subset(l1, l2) && lg_subset(l2, l1)
+}
+
+// Usually, we are not interested in the whole bag `unroll(l)`.
+// The goal is to find "the best" or "most interesting" graphs.
+// Hence, there should be developed some techniques of extracting
+// useful information from a `LazyGraph[C]` without evaluating
+// `unroll(l)` directly.
+
+// This can be formulated in the following form.
+// Suppose that a function `select` filters bags of graphs,
+// removing "bad" graphs, so that
+//     select (unroll l)
+// generates the bag of "good" graphs.
+// Let us find a function `extract` such that
+//     extract(l) == select(unroll(l))
+// In many cases, `extract` may be more efficient (by several orders
+// of magnitude) than the composition `select . unroll`.
+// Sometimes, `extract` can be formulated in terms of "cleaners" of
+// lazy graphs. Let `clean` be a function that transforms lazy graphs,
+// such that
+//     unroll(clean(l)) ⊆ unroll(l)
+// Then `extract` can be constructed in the following way:
+//     extract(l) == unroll(clean(l))
+// Theoretically speaking, `clean` is the result of "promoting" `select`:
+//     (select compose unroll)(l) == (unroll compose clean)(l)
+// The nice property of cleaners is that they are composable:
+// given `clean1` and `clean2`, `clean2 compose clean1` is also a cleaner.
+
+//
+// Some filters
+//
+
+// Removing graphs that contain "bad" configurations.
+// Configurations represent states of a computation process.
+// Some of these states may be "bad" with respect to the problem
+// that is to be solved by means of supercompilation.
+
+fn bad_graph<C>(bad: impl Fn(&C) -> bool, g: &Graph<C>) -> bool {
+    g.any_conf(bad)
+}
+
+// This filter removes the graphs containing "bad" configurations.
+
+pub fn fl_bad_conf<C>(bad: impl Fn(&C) -> bool, gs: Gs<C>) -> Gs<C> {
+    vec_map!(g; g in gs, !bad_graph(&bad, &g))
+}
+
+//
+// Some cleaners
+//
+
+// `cl_empty` removes subtrees that represent empty sets of graphs.
+
+pub fn cl_empty<C: Clone>(l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+    l.fold(empty, stop, |c, lss| {
+        let lss1 =
+            vec_map!(ls; ls in lss, !ls.iter().any(|l1| is_lg_empty(l1)));
+        cl_empty_build(c, &lss1)
+    })
+}
+
+fn cl_empty_build<C: Clone>(c: &C, lss: &[Ls<C>]) -> Rc<LazyGraph<C>> {
+    if lss.is_empty() {
+        empty()
+    } else {
+        build(c, lss)
+    }
+}
+
+fn is_lg_empty<C>(l: &LazyGraph<C>) -> bool {
+    match l {
+        Empty() => true,
+        _ => false,
+    }
+}
+
+// Removing graphs that contain "bad" configurations.
+// The cleaner `cl_bad_conf` corresponds to the filter `fl_bad_conf`.
+// `cl_bad_conf` exploits the fact that "badness" is monotonic,
+// in the sense that a single "bad" configuration spoils the whole
+// graph.
+
+pub fn cl_bad_conf<C: Clone>(
+    bad: impl Fn(&C) -> bool,
+    l: &LazyGraph<C>,
+) -> Rc<LazyGraph<C>> {
+    cl_bad_conf_loop(&bad, l)
+}
+
+fn cl_bad_conf_loop<C: Clone>(
+    bad: &impl Fn(&C) -> bool,
+    l: &LazyGraph<C>,
+) -> Rc<LazyGraph<C>> {
+    match l {
+        Empty() => empty(),
+        Stop(c) => {
+            if bad(c) {
+                empty()
+            } else {
+                stop(c)
+            }
+        }
+        Build(c, lss) => {
+            if bad(c) {
+                empty()
+            } else {
+                build(
+                    c,
+                    &vec_map!(vec_map!(cl_bad_conf_loop(bad, l); l in ls); ls in lss),
+                )
+            }
+        }
+    }
+}
+
+//
+// The graph returned by `cl_bad_conf` may be cleaned by `cl_empty`.
+//
+// `cl_empty_and_bad` fuses the two cleaners, so that
+//     cl_empty_and_bad(bad, l) == cl_empty(cl_bad_conf(bad, l))
+// but the lazy graph is traversed only once, and no intermediate graph
+// is built. Besides, the traversal of an alternative stops as soon as
+// one of its components turns out to be empty. (The two are timed
+// against each other by `examples/fused_cleaner.rs`.)
+//
+
+pub fn cl_empty_and_bad<C: Clone>(
+    bad: impl Fn(&C) -> bool,
+    l: &LazyGraph<C>,
+) -> Rc<LazyGraph<C>> {
+    cl_empty_and_bad_loop(&bad, l)
+}
+
+fn cl_empty_and_bad_loop<C: Clone>(
+    bad: &impl Fn(&C) -> bool,
+    l: &LazyGraph<C>,
+) -> Rc<LazyGraph<C>> {
+    match l {
+        Empty() => empty(),
+        Stop(c) => {
+            if bad(c) {
+                empty()
+            } else {
+                stop(c)
+            }
+        }
+        Build(c, lss) => {
+            if bad(c) {
+                empty()
+            } else {
+                let lss1: Vec<Ls<C>> = lss
+                    .iter()
+                    .filter_map(|ls| cl_empty_and_bad_ls(bad, ls))
+                    .collect();
+                cl_empty_build(c, &lss1)
+            }
+        }
+    }
+}
+
+fn cl_empty_and_bad_ls<C: Clone>(
+    bad: &impl Fn(&C) -> bool,
+    ls: &Ls<C>,
+) -> Option<Ls<C>> {
+    let mut ls1 = Vec::with_capacity(ls.len());
+    for l in ls {
+        let l1 = cl_empty_and_bad_loop(bad, l);
+        if is_lg_empty(&l1) {
+            return None;
+        }
+        ls1.push(l1);
+    }
+    Some(ls1)
+}
+
+//
+// Requiring every path to end with a fold
+//
+// For liveness-like properties, a graph is a proof only if every path
+// in it ends with a fold (a back node). Other leaves are either cut-offs
+// (the whistle has blown, which is represented by `Empty()` in lazy
+// graphs, as in `cl_empty`), or dead ends `Forth(c, [])`, produced
+// by alternatives containing no configurations (e.g., no rules can
+// be applied to `c`).
+//
+// `cl_fold_leaves` removes the graphs containing leaves other than folds,
+// so that
+//     unroll(cl_fold_leaves(l)) == fl_fold_leaves(unroll(l))
+//
+
+pub fn every_path_folds<C>(g: &Graph<C>) -> bool {
+    match g {
+        Back(_) => true,
+        Forth(_, gs) => {
+            !gs.is_empty() && gs.iter().all(|g1| every_path_folds(g1))
+        }
+    }
+}
+
+pub fn fl_fold_leaves<C>(gs: Gs<C>) -> Gs<C> {
+    vec_map!(g; g in gs, every_path_folds(&g))
+}
 
-pub fn cl_empty_and_bad<C: Clone>(
-    bad: fn(&C) -> bool,
-    l: &LazyGraph<C>,
-) -> Rc<LazyGraph<C>> {
-    cl_empty(&cl_bad_conf(bad, l))
+pub fn cl_fold_leaves<C: Clone>(l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+    match l {
+        Empty() => empty(),
+        Stop(c) => stop(c),
+        Build(c, lss) => {
+            let lss1: Vec<Ls<C>> = lss
+                .iter()
+                .filter(|ls| !ls.is_empty())
+                .filter_map(|ls| {
+                    let ls1 = vec_map!(cl_fold_leaves(l1); l1 in ls);
+                    if ls1.iter().any(|l1| is_lg_empty(l1)) {
+                        None
+                    } else {
+                        Some(ls1)
+                    }
+                })
+                .collect();
+            cl_empty_build(c, &lss1)
+        }
+    }
 }
 
 //
-// Extracting a graph of minimal size (if any).
+// Merging equivalent sibling subproofs
+//
+// Two graphs are equivalent up to foldability if they have the same
+// shape, and the configurations in the corresponding nodes are
+// foldable to each other. (For instance, if a counter world declares
+// symmetries, the subproofs for `(ω, 1, 0)` and `(ω, 0, 1)` may be
+// equivalent.)
+//
+// `compress_siblings(is_foldable_to, g)` replaces each subtree of `g`
+// that is equivalent to one of its preceding siblings with a back node,
+// whose configuration is folded to the sibling (rather than to
+// an ancestor). The back nodes inside the removed subtree could only
+// refer to the nodes on the path to them, which are matched by
+// the nodes of the sibling, so that no other back nodes need
+// re-targeting. (`fold_obligations` accepts such back nodes, reporting
+// the sibling, and `instantiate` only relies on the configurations
+// of the graph, those of the sibling covering those removed.)
 //
 
-pub fn graph_size<C>(g: &Graph<C>) -> usize {
+pub fn graph_equiv_modulo<C>(
+    is_foldable_to: impl Fn(&C, &C) -> bool,
+    g1: &Graph<C>,
+    g2: &Graph<C>,
+) -> bool {
+    graph_equiv_modulo_loop(&is_foldable_to, g1, g2)
+}
+
+fn graph_equiv_modulo_loop<C>(
+    is_foldable_to: &impl Fn(&C, &C) -> bool,
+    g1: &Graph<C>,
+    g2: &Graph<C>,
+) -> bool {
+    let equiv = |c1, c2| is_foldable_to(c1, c2) && is_foldable_to(c2, c1);
+    match (g1, g2) {
+        (Back(c1), Back(c2)) => equiv(c1, c2),
+        (Forth(c1, gs1), Forth(c2, gs2)) => {
+            equiv(c1, c2)
+                && gs1.len() == gs2.len()
+                && gs1.iter().zip(gs2).all(|(g1, g2)| {
+                    graph_equiv_modulo_loop(is_foldable_to, g1, g2)
+                })
+        }
+        _ => false,
+    }
+}
+
+pub fn compress_siblings<C: Clone>(
+    is_foldable_to: impl Fn(&C, &C) -> bool,
+    g: &Graph<C>,
+) -> Rc<Graph<C>> {
+    compress_siblings_loop(&is_foldable_to, g)
+}
+
+fn compress_siblings_loop<C: Clone>(
+    is_foldable_to: &impl Fn(&C, &C) -> bool,
+    g: &Graph<C>,
+) -> Rc<Graph<C>> {
     match g {
-        Back(_) => 1,
-        Forth(_, gs) => 1 + vec_sum!(graph_size(g1); g1 in gs),
+        Back(c) => back(c),
+        Forth(c, gs) => {
+            let mut gs1 = Vec::with_capacity(gs.len());
+            for (i, g1) in gs.iter().enumerate() {
+                let merged = gs[..i].iter().any(|g0| {
+                    matches!(**g0, Forth(..))
+                        && graph_equiv_modulo_loop(is_foldable_to, g1, g0)
+                });
+                if merged {
+                    gs1.push(back(node_info(g1).0));
+                } else {
+                    gs1.push(compress_siblings_loop(is_foldable_to, g1));
+                }
+            }
+            forth(c, &gs1)
+        }
     }
 }
 
+//
+// Extracting a graph of minimal size (if any).
+//
+
+pub fn graph_size<C>(g: &Graph<C>) -> usize {
+    g.fold(|_| 1, |_, ns| 1 + ns.iter().sum::<usize>())
+}
+
 // Now we define a cleaner `cl_min_size` that produces a lazy graph
 // representing the smallest graph (or the empty set of graphs).
 
@@ -292,7 +1616,27 @@ pub fn graph_size<C>(g: &Graph<C>) -> usize {
 // (Long.MaxValue , Empty).
 
 pub fn cl_min_size<C: Clone>(l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
-    sel_min_size(l).1
+    l.fold(
+        || (usize::MAX, empty()),
+        |c| (1, stop(c)),
+        |c, kss| {
+            let mut acc = (usize::MAX, Vec::new());
+            for kls in kss {
+                let mut k = 0usize;
+                let mut ls = Vec::with_capacity(kls.len());
+                for (k1, l1) in kls {
+                    k = add_min_size(k, k1);
+                    ls.push(l1);
+                }
+                acc = select_min2((k, ls), acc);
+            }
+            match acc {
+                (usize::MAX, _) => (usize::MAX, empty()),
+                (k, ls) => (1 + k, build(c, &[ls])),
+            }
+        },
+    )
+    .1
 }
 
 fn sel_min_size<C: Clone>(l: &LazyGraph<C>) -> (usize, Rc<LazyGraph<C>>) {
@@ -314,44 +1658,674 @@ fn select_min2<T: Clone>(kx1: (usize, T), kx2: (usize, T)) -> (usize, T) {
     }
 }
 
-fn sel_min_size2<C: Clone>(lss: &[Ls<C>]) -> (usize, Ls<C>) {
-    let mut acc = (usize::MAX, Vec::<Rc<LazyGraph<C>>>::new());
-    for ls in lss {
-        acc = select_min2(sel_min_size_and(ls), acc);
+fn sel_min_size2<C: Clone>(lss: &[Ls<C>]) -> (usize, Ls<C>) {
+    let mut acc = (usize::MAX, Vec::<Rc<LazyGraph<C>>>::new());
+    for ls in lss {
+        acc = select_min2(sel_min_size_and(ls), acc);
+    }
+    acc
+}
+
+fn sel_min_size_and<C: Clone>(ls: &[Rc<LazyGraph<C>>]) -> (usize, Ls<C>) {
+    let mut k = 0usize;
+    let mut ls1 = Vec::<Rc<LazyGraph<C>>>::new();
+    for l in ls {
+        let (k1, l1) = sel_min_size(l);
+        k = add_min_size(k, k1);
+        ls1.push(l1);
+    }
+    (k, ls1)
+}
+
+fn add_min_size(x1: usize, x2: usize) -> usize {
+    if x1 == usize::MAX || x2 == usize::MAX {
+        usize::MAX
+    } else {
+        x1 + x2
+    }
+}
+
+// An anytime version of `cl_min_size`.
+//
+// `cl_min_size_anytime(l, time_limit, node_limit)` examines the `Build`
+// nodes in the same way as `cl_min_size`, until the time elapsed exceeds
+// `time_limit`, or the number of examined nodes exceeds `node_limit`.
+// After that, instead of examining all alternatives, the first non-empty
+// alternative is taken (as by `cl_first`). The result is a lazy graph
+// representing a single graph (or the empty set of graphs), the size
+// of the graph, and whether the graph is known to be minimal (i.e.,
+// the budget has not been exhausted).
+//
+// With `time_limit` being `None`, the result is deterministic. Otherwise,
+// it depends on the speed of the machine, so that `node_limit` should
+// be used if the results are to be reproduced.
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AnytimeMinSize<C> {
+    pub graph: Rc<LazyGraph<C>>,
+    pub size: Option<usize>,
+    pub optimal: bool,
+}
+
+struct SizeBudget {
+    deadline: Option<Instant>,
+    nodes_left: Option<usize>,
+    exhausted: bool,
+}
+
+impl SizeBudget {
+    fn spend(&mut self) -> bool {
+        if !self.exhausted {
+            let late = self.deadline.is_some_and(|t| Instant::now() >= t);
+            match self.nodes_left {
+                Some(0) => self.exhausted = true,
+                Some(n) => self.nodes_left = Some(n - 1),
+                None => {}
+            }
+            self.exhausted = self.exhausted || late;
+        }
+        !self.exhausted
+    }
+}
+
+fn sel_min_size_anytime<C: Clone>(
+    l: &LazyGraph<C>,
+    budget: &mut SizeBudget,
+) -> (usize, Rc<LazyGraph<C>>) {
+    match l {
+        Empty() => (usize::MAX, empty()),
+        Stop(c) => (1, stop(c)),
+        Build(c, lss) => {
+            if !budget.spend() {
+                return match sel_first(l) {
+                    None => (usize::MAX, empty()),
+                    Some(l1) => sel_min_size(&l1),
+                };
+            }
+            let mut acc = (usize::MAX, Vec::new());
+            for ls in lss {
+                let mut k = 0usize;
+                let mut ls1 = Vec::with_capacity(ls.len());
+                for l1 in ls {
+                    let (k1, l2) = sel_min_size_anytime(l1, budget);
+                    k = add_min_size(k, k1);
+                    ls1.push(l2);
+                }
+                acc = select_min2((k, ls1), acc);
+            }
+            match acc {
+                (usize::MAX, _) => (usize::MAX, empty()),
+                (k, ls) => (1 + k, build(c, &[ls])),
+            }
+        }
+    }
+}
+
+pub fn cl_min_size_anytime<C: Clone>(
+    l: &LazyGraph<C>,
+    time_limit: Option<Duration>,
+    node_limit: Option<usize>,
+) -> AnytimeMinSize<C> {
+    let mut budget = SizeBudget {
+        deadline: time_limit.map(|d| Instant::now() + d),
+        nodes_left: node_limit,
+        exhausted: false,
+    };
+    let (k, l1) = sel_min_size_anytime(l, &mut budget);
+    AnytimeMinSize {
+        graph: l1,
+        size: if k == usize::MAX { None } else { Some(k) },
+        optimal: !budget.exhausted,
+    }
+}
+
+//
+// `cl_min_size` is sound:
+//
+//  Let cl_min_size(l) == (k , l'). Then
+//     unroll(l') ⊆ unroll(l)
+//     k == graph_size (hd (unroll(l')))
+
+//
+// Minimizing an arbitrary cost
+//
+// `cl_min_cost(cost, l)` generalizes `cl_min_size`: the cost of a graph
+// is the sum of the costs of the configurations in its nodes, and
+// a graph of minimal cost is selected. (Thus `cl_min_size(l)` is
+// `cl_min_cost(|_| 1, l)`.) As in `cl_min_size`, the cost of an empty set
+// of graphs is ∞, here represented by `None`.
+
+pub fn cl_min_cost<C: Clone, M: Ord + Add<Output = M> + Clone>(
+    cost: impl Fn(&C) -> M,
+    l: &LazyGraph<C>,
+) -> Rc<LazyGraph<C>> {
+    match sel_min_cost(&cost, l) {
+        None => empty(),
+        Some((_, l1)) => l1,
+    }
+}
+
+fn sel_min_cost<C: Clone, M: Ord + Add<Output = M> + Clone>(
+    cost: &impl Fn(&C) -> M,
+    l: &LazyGraph<C>,
+) -> Option<(M, Rc<LazyGraph<C>>)> {
+    match l {
+        Empty() => None,
+        Stop(c) => Some((cost(c), stop(c))),
+        Build(c, lss) => {
+            let mut best: Option<(M, Ls<C>)> = None;
+            'alts: for ls in lss {
+                let mut m = cost(c);
+                let mut ls1 = Vec::with_capacity(ls.len());
+                for l1 in ls {
+                    match sel_min_cost(cost, l1) {
+                        None => continue 'alts,
+                        Some((m1, l2)) => {
+                            m = m + m1;
+                            ls1.push(l2);
+                        }
+                    }
+                }
+                let better = match &best {
+                    None => true,
+                    Some((m0, _)) => m <= *m0,
+                };
+                if better {
+                    best = Some((m, ls1));
+                }
+            }
+            best.map(|(m, ls1)| (m, build(c, &[ls1])))
+        }
+    }
+}
+
+//
+// Pareto-optimal graphs
+//
+// Given several additive costs (as in `cl_min_cost`), the cost of
+// a graph is a vector. A graph is Pareto-optimal if the cost of no other
+// graph is less or equal in all components and less in some component.
+// `cl_pareto(costs, l)` produces a lazy graph representing exactly
+// the Pareto-optimal graphs in `unroll(l)`.
+//
+// The subgraphs of a Pareto-optimal graph are Pareto-optimal (otherwise,
+// replacing a subgraph with one dominating it would produce a graph
+// dominating the whole graph). Hence, for each node, it is sufficient
+// to find the Pareto front of its subgraphs: the non-dominated cost
+// vectors, each with the lazy graph representing the graphs having
+// this cost. The front for a `Build` node is computed by combining
+// the fronts for the subgraphs, partial combinations being pruned as soon
+// as they are dominated.
+
+type Front<C, M> = Vec<(Vec<M>, Vec<Ls<C>>)>;
+
+fn dominates<M: Ord>(v1: &[M], v2: &[M]) -> bool {
+    v1.iter().zip(v2).all(|(m1, m2)| m1 <= m2) && v1 != v2
+}
+
+fn non_dominated<M: Ord, X>(vxs: Vec<(Vec<M>, X)>) -> Vec<(Vec<M>, X)> {
+    let keep = vec_map!(
+        !vxs.iter().any(|(v1, _)| dominates(v1, &vxs[i].0));
+        i in 0..vxs.len());
+    vxs.into_iter()
+        .zip(keep)
+        .filter_map(|(vx, k)| if k { Some(vx) } else { None })
+        .collect()
+}
+
+fn add_costs<M: Clone + Add<Output = M>>(v1: &[M], v2: &[M]) -> Vec<M> {
+    v1.iter()
+        .zip(v2)
+        .map(|(m1, m2)| m1.clone() + m2.clone())
+        .collect()
+}
+
+// The front of `l`. For `Stop(c)`, the list of alternatives is empty.
+
+fn pareto_front<C: Clone, M: Clone + Ord + Add<Output = M>>(
+    costs: &[&dyn Fn(&C) -> M],
+    l: &LazyGraph<C>,
+) -> Front<C, M> {
+    match l {
+        Empty() => vec![],
+        Stop(c) => vec![(vec_map!(cost(c); cost in costs), vec![])],
+        Build(c, lss) => {
+            let v0 = vec_map!(cost(c); cost in costs);
+            let mut cands: Vec<(Vec<M>, Ls<C>)> = Vec::new();
+            for ls in lss {
+                let mut partial = vec![(v0.clone(), Vec::new())];
+                for l1 in ls {
+                    let f1 = pareto_lazy_front(costs, l1);
+                    let mut partial1 = Vec::new();
+                    for (v, ls1) in &partial {
+                        for (v1, l2) in &f1 {
+                            let mut ls2 = ls1.clone();
+                            ls2.push(Rc::clone(l2));
+                            partial1.push((add_costs(v, v1), ls2));
+                        }
+                    }
+                    partial = non_dominated(partial1);
+                }
+                cands.extend(partial);
+            }
+            let mut front: Front<C, M> = Vec::new();
+            for (v, ls) in non_dominated(cands) {
+                match front.iter_mut().find(|(v1, _)| *v1 == v) {
+                    Some((_, lss1)) => lss1.push(ls),
+                    None => front.push((v, vec![ls])),
+                }
+            }
+            front
+        }
+    }
+}
+
+fn pareto_lazy_front<C: Clone, M: Clone + Ord + Add<Output = M>>(
+    costs: &[&dyn Fn(&C) -> M],
+    l: &LazyGraph<C>,
+) -> Vec<(Vec<M>, Rc<LazyGraph<C>>)> {
+    let front = pareto_front(costs, l);
+    match l {
+        Stop(c) => front.into_iter().map(|(v, _)| (v, stop(c))).collect(),
+        Build(c, _) => front
+            .into_iter()
+            .map(|(v, lss)| (v, build(c, &lss)))
+            .collect(),
+        Empty() => vec![],
+    }
+}
+
+pub fn cl_pareto<C: Clone, M: Clone + Ord + Add<Output = M>>(
+    costs: &[&dyn Fn(&C) -> M],
+    l: &LazyGraph<C>,
+) -> Rc<LazyGraph<C>> {
+    let front = pareto_front(costs, l);
+    match l {
+        Empty() => empty(),
+        Stop(c) => stop(c),
+        Build(c, _) => cl_empty_build(
+            c,
+            &Itertools::concat(front.into_iter().map(|vf| vf.1)),
+        ),
+    }
+}
+
+//
+// Extracting graphs with the minimal number of back nodes (folds).
+//
+
+pub fn graph_backs<C>(g: &Graph<C>) -> usize {
+    g.fold(|_| 1, |_, ns| ns.iter().sum())
+}
+
+// `cl_min_backs` keeps all graphs having the minimal number of back nodes.
+// A graph has the minimal number of back nodes iff it is produced by
+// an alternative with the minimal number of back nodes whose subgraphs
+// have, in turn, the minimal number of back nodes. Hence, it is sufficient
+// to keep, in each `Build` node, the alternatives with the minimal number
+// of back nodes.
+
+pub fn cl_min_backs<C: Clone>(l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+    sel_min_backs(l).1
+}
+
+fn sel_min_backs<C: Clone>(l: &LazyGraph<C>) -> (usize, Rc<LazyGraph<C>>) {
+    match l {
+        Empty() => (usize::MAX, empty()),
+        Stop(c) => (1, stop(c)),
+        Build(c, lss) => {
+            let mut k = usize::MAX;
+            let mut lss1: Vec<Ls<C>> = Vec::new();
+            for ls in lss {
+                let mut k1 = 0usize;
+                let mut ls1 = Vec::with_capacity(ls.len());
+                for l1 in ls {
+                    let (k2, l2) = sel_min_backs(l1);
+                    k1 = add_min_size(k1, k2);
+                    ls1.push(l2);
+                }
+                if k1 < k {
+                    k = k1;
+                    lss1.clear();
+                }
+                if k1 == k && k1 != usize::MAX {
+                    lss1.push(ls1);
+                }
+            }
+            if k == usize::MAX {
+                (usize::MAX, empty())
+            } else {
+                (k, build(c, &lss1))
+            }
+        }
+    }
+}
+
+//
+// Size-bounded graphs
+//
+// `cl_max_size(limit, l)` keeps exactly the graphs whose size does not
+// exceed `limit`:
+//     unroll(cl_max_size(limit, l)) ≅ [g in unroll(l) | graph_size(g) ≤ limit]
+// (modulo the order of graphs).
+//
+// Let `size_range(l)` be the minimal and the maximal size of the graphs
+// represented by `l`. Then an alternative can be kept as is, if its
+// maximal size fits the budget, and removed, if its minimal size does not.
+// Otherwise, the alternative is split into a number of alternatives
+// according to the exact size of the first subgraph, the budget for
+// the rest of the alternative being reduced accordingly.
+// Thus the cost of splitting is only paid for the alternatives
+// at the boundary of the bound.
+//
+// The ranges are computed once for each node (shared subtrees being
+// visited once), memoized by the addresses of the nodes, as in
+// `try_map_conf`.
+
+type SizeRanges<C> = HashMap<*const LazyGraph<C>, Option<(usize, usize)>>;
+
+fn size_range<C>(
+    memo: &mut SizeRanges<C>,
+    l: &LazyGraph<C>,
+) -> Option<(usize, usize)> {
+    let key = l as *const LazyGraph<C>;
+    if let Some(&r) = memo.get(&key) {
+        return r;
+    }
+    let r = match l {
+        Empty() => None,
+        Stop(_) => Some((1, 1)),
+        Build(_, lss) => lss
+            .iter()
+            .filter_map(|ls| size_range_ls(memo, ls))
+            .reduce(|(mn1, mx1), (mn2, mx2)| (mn1.min(mn2), mx1.max(mx2)))
+            .map(|(mn, mx)| (1 + mn, 1 + mx)),
+    };
+    memo.insert(key, r);
+    r
+}
+
+fn size_range_ls<C>(
+    memo: &mut SizeRanges<C>,
+    ls: &[Rc<LazyGraph<C>>],
+) -> Option<(usize, usize)> {
+    let mut r = (0, 0);
+    for l in ls {
+        let (mn, mx) = size_range(memo, l)?;
+        r = (r.0 + mn, r.1 + mx);
+    }
+    Some(r)
+}
+
+// The graphs in `l` whose size is in `lo..=hi`.
+
+fn cl_size_range<C: Clone>(
+    memo: &mut SizeRanges<C>,
+    l: &Rc<LazyGraph<C>>,
+    lo: usize,
+    hi: usize,
+) -> Rc<LazyGraph<C>> {
+    match size_range(memo, l) {
+        None => empty(),
+        Some((mn, mx)) if mn > hi || mx < lo => empty(),
+        Some((mn, mx)) if lo <= mn && mx <= hi => Rc::clone(l),
+        _ => match &**l {
+            Build(c, lss) => {
+                let mut lss1 = Vec::new();
+                for ls in lss {
+                    lss1.extend(cl_size_range_ls(
+                        memo,
+                        ls,
+                        lo.saturating_sub(1),
+                        hi - 1,
+                    ));
+                }
+                cl_empty_build(c, &lss1)
+            }
+            _ => empty(),
+        },
+    }
+}
+
+// The alternatives equivalent to `ls` restricted to the sizes in `lo..=hi`.
+
+fn cl_size_range_ls<C: Clone>(
+    memo: &mut SizeRanges<C>,
+    ls: &[Rc<LazyGraph<C>>],
+    lo: usize,
+    hi: usize,
+) -> Vec<Ls<C>> {
+    let (mn, mx) = match size_range_ls(memo, ls) {
+        None => return vec![],
+        Some(r) => r,
+    };
+    if mn > hi || mx < lo {
+        return vec![];
+    }
+    if lo <= mn && mx <= hi {
+        return vec![ls.to_vec()];
+    }
+    let (l1, rest) = (&ls[0], &ls[1..]);
+    let (mn1, mx1) = size_range(memo, l1).unwrap();
+    let (mnr, mxr) = size_range_ls(memo, rest).unwrap();
+    let mut lss = Vec::new();
+    for k in mn1.max(lo.saturating_sub(mxr))..=mx1.min(hi - mnr) {
+        let l1k = cl_size_range(memo, l1, k, k);
+        if is_lg_empty(&l1k) {
+            continue;
+        }
+        let rest_lo = lo.saturating_sub(k);
+        for mut ls1 in cl_size_range_ls(memo, rest, rest_lo, hi - k) {
+            ls1.insert(0, Rc::clone(&l1k));
+            lss.push(ls1);
+        }
+    }
+    lss
+}
+
+pub fn cl_max_size<C: Clone>(
+    limit: usize,
+    l: &LazyGraph<C>,
+) -> Rc<LazyGraph<C>> {
+    cl_size_range(&mut HashMap::new(), &Rc::new(l.clone()), 0, limit)
+}
+
+//
+// The k smallest graphs
+//
+// `extract_k_smallest(k, l)` returns the `k` smallest graphs in `unroll(l)`
+// (or all of them, if there are fewer than `k` graphs), ordered by size.
+//
+// The sizes are walked in increasing order, starting from the minimal
+// size. For each size `m`, the graphs of size `m` are selected
+// by `cl_size_range` and generated one at a time by `unroll_iter`,
+// until `k` graphs are found. Thus no graph of a size greater than
+// the size of the `k`-th graph is generated, and the graphs of that size
+// are only generated as long as needed (even if there are 2^60 of them).
+
+pub fn extract_k_smallest<C: Clone>(k: usize, l: &LazyGraph<C>) -> Gs<C> {
+    let mut memo = HashMap::new();
+    let l = Rc::new(l.clone());
+    let (mn, mx) = match size_range(&mut memo, &l) {
+        None => return vec![],
+        Some(r) => r,
+    };
+    let mut gs = Vec::new();
+    for m in mn..=mx {
+        if gs.len() >= k {
+            break;
+        }
+        let lm = cl_size_range(&mut memo, &l, m, m);
+        gs.extend(unroll_iter(&lm).take(k - gs.len()));
+    }
+    gs
+}
+
+//
+// Removing duplicates
+//
+// `unroll` produces a list of graphs, which may contain duplicates, if
+// a `Build` node has several equal alternatives. (For example, the same
+// configuration may be produced by different ways of rebuilding.)
+// `cl_distinct` removes such duplicate alternatives, having cleaned
+// the alternatives themselves.
+//
+// For lazy graphs produced by `lazy_mrsc`, the subtrees of a node
+// are determined by their configurations (since they have the same
+// history), so that two alternatives produce a common graph only if they
+// are equal. Hence, in this case `unroll(cl_distinct(l))` contains
+// no duplicates. (In general, a graph may still be represented by
+// two different alternatives.)
+
+pub fn cl_distinct<C: Clone + PartialEq>(l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+    match l {
+        Empty() => empty(),
+        Stop(c) => stop(c),
+        Build(c, lss) => {
+            let mut lss1: Vec<Ls<C>> = Vec::with_capacity(lss.len());
+            for ls in lss {
+                let ls1 = vec_map!(cl_distinct(l1); l1 in ls);
+                if !lss1.contains(&ls1) {
+                    lss1.push(ls1);
+                }
+            }
+            build(c, &lss1)
+        }
+    }
+}
+
+//
+// Committed choice
+//
+// `cl_first` keeps, in each `Build` node, only the first alternative
+// representing a non-empty set of graphs. Thus it produces a lazy graph
+// representing a single graph (or the empty set of graphs), the graph
+// being the first one in `unroll(l)`:
+//     unroll(cl_first(l)) == unroll(l)[..1]
+// In contrast to `cl_min_size`, the alternatives following
+// a non-empty one are not examined.
+
+pub fn cl_first<C: Clone>(l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+    sel_first(l).unwrap_or_else(empty)
+}
+
+fn sel_first<C: Clone>(l: &LazyGraph<C>) -> Option<Rc<LazyGraph<C>>> {
+    match l {
+        Empty() => None,
+        Stop(c) => Some(stop(c)),
+        Build(c, lss) => lss.iter().find_map(|ls| {
+            let ls1: Option<Ls<C>> =
+                ls.iter().map(|l1| sel_first(l1)).collect();
+            ls1.map(|ls1| build(c, &[ls1]))
+        }),
+    }
+}
+
+//
+// Cleaners as values
+//
+// A `Cleaner<C>` is a cleaner packed into a value, so that cleaners
+// with different signatures can be treated uniformly. Cleaners
+// can be composed into pipelines:
+//     ClEmpty.then(ClBadConf::new(bad)).then(ClMinSize)
+// applies `ClEmpty` first, then `ClBadConf`, then `ClMinSize`.
+//
+
+pub trait Cleaner<C> {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>>;
+
+    fn then<B: Cleaner<C>>(self, b: B) -> Then<Self, B>
+    where
+        Self: Sized,
+    {
+        Then(self, b)
+    }
+}
+
+pub struct Then<A, B>(pub A, pub B);
+
+impl<C, A: Cleaner<C>, B: Cleaner<C>> Cleaner<C> for Then<A, B> {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+        self.1.clean(&self.0.clean(l))
+    }
+}
+
+impl<C> Cleaner<C> for Box<dyn Cleaner<C>> {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+        (**self).clean(l)
+    }
+}
+
+pub struct ClEmpty;
+
+impl<C: Clone> Cleaner<C> for ClEmpty {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+        cl_empty(l)
+    }
+}
+
+pub struct ClBadConf<F> {
+    bad: F,
+}
+
+impl<F> ClBadConf<F> {
+    pub fn new(bad: F) -> ClBadConf<F> {
+        ClBadConf { bad }
+    }
+}
+
+impl<C: Clone, F: Fn(&C) -> bool> Cleaner<C> for ClBadConf<F> {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+        cl_bad_conf_loop(&self.bad, l)
+    }
+}
+
+pub struct ClMinSize;
+
+impl<C: Clone> Cleaner<C> for ClMinSize {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+        cl_min_size(l)
+    }
+}
+
+pub struct ClMinBacks;
+
+impl<C: Clone> Cleaner<C> for ClMinBacks {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+        cl_min_backs(l)
     }
-    acc
 }
 
-fn sel_min_size_and<C: Clone>(ls: &[Rc<LazyGraph<C>>]) -> (usize, Ls<C>) {
-    let mut k = 0usize;
-    let mut ls1 = Vec::<Rc<LazyGraph<C>>>::new();
-    for l in ls {
-        let (k1, l1) = sel_min_size(l);
-        k = add_min_size(k, k1);
-        ls1.push(l1);
+pub struct ClMaxSize(pub usize);
+
+impl<C: Clone> Cleaner<C> for ClMaxSize {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+        cl_max_size(self.0, l)
     }
-    (k, ls1)
 }
 
-fn add_min_size(x1: usize, x2: usize) -> usize {
-    if x1 == usize::MAX || x2 == usize::MAX {
-        usize::MAX
-    } else {
-        x1 + x2
+pub struct ClDistinct;
+
+impl<C: Clone + PartialEq> Cleaner<C> for ClDistinct {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+        cl_distinct(l)
     }
 }
 
-//
-// `cl_min_size` is sound:
-//
-//  Let cl_min_size(l) == (k , l'). Then
-//     unroll(l') ⊆ unroll(l)
-//     k == graph_size (hd (unroll(l')))
+pub struct ClFirst;
+
+impl<C: Clone> Cleaner<C> for ClFirst {
+    fn clean(&self, l: &LazyGraph<C>) -> Rc<LazyGraph<C>> {
+        cl_first(l)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::counters::{NW, NWC};
+
     type IGraph = Graph<isize>;
     type ILazyGraph = LazyGraph<isize>;
 
@@ -371,6 +2345,185 @@ mod tests {
     );
     }
 
+    #[test]
+    fn test_graph_pretty_printer_limited() {
+        let g = g1();
+        assert_eq!(
+            graph_pretty_printer_limited(&g, &PrintLimits::default()),
+            graph_pretty_printer(&g)
+        );
+        let limits = PrintLimits {
+            max_depth: Some(1),
+            max_nodes: None,
+        };
+        assert_eq!(
+            graph_pretty_printer_limited(&g, &limits),
+            "|__1\n  |\n  |__1*\n  |\n  |__2\n    |\n    |__... (1 node)\n    |\n    |__... (1 node)"
+        );
+        let limits = PrintLimits {
+            max_depth: None,
+            max_nodes: Some(2),
+        };
+        assert_eq!(
+            graph_pretty_printer_limited(&g, &limits),
+            "|__1\n  |\n  |__1*\n  |\n  |__... (3 nodes)"
+        );
+        let limits = PrintLimits {
+            max_depth: Some(0),
+            max_nodes: Some(0),
+        };
+        assert_eq!(
+            graph_pretty_printer_limited(&g, &limits),
+            "|__... (5 nodes)"
+        );
+        let g = forth(&0, &[back(&1), forth(&2, &[back(&0)]), back(&3)]);
+        let limits = PrintLimits {
+            max_depth: None,
+            max_nodes: Some(2),
+        };
+        assert_eq!(
+            graph_pretty_printer_limited(&g, &limits),
+            "|__0\n  |\n  |__1*\n  |\n  |__... (2 subtrees, 3 nodes)"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{}", g1()), graph_pretty_printer(&g1()));
+        assert_eq!(l2().to_string(), "1[2[1*, 2*] | 3[3*, 1*]]");
+        let l: Rc<ILazyGraph> = build(&1, &[vec![], vec![empty()]]);
+        assert_eq!(l.to_string(), "1[() | ∅]");
+        assert_eq!(build::<isize>(&1, &[]).to_string(), "1[]");
+    }
+
+    #[test]
+    fn test_parse_graph() {
+        assert_eq!(parse_graph(&graph_pretty_printer(&g1())), Ok(g1()));
+        let c = |i| NWC(vec![NW::W(), NW::N(i)]);
+        let g2 = forth(&c(0), &[forth(&c(1), &[back(&c(0))]), back(&c(0))]);
+        assert_eq!(parse_graph::<NWC>(&graph_pretty_printer(&g2)), Ok(g2));
+        let g = forth(&1, &[forth(&2, &[]), back(&1)]);
+        assert_eq!(parse_graph(&graph_pretty_printer(&g)), Ok(g));
+        assert_eq!(
+            parse_graph::<isize>("|__1\n  |\n  |__x*"),
+            Err(ParseError::BadConfiguration(3, "x".to_string()))
+        );
+        assert_eq!(
+            parse_graph::<isize>("|__1\n  |"),
+            Err(ParseError::UnexpectedEnd)
+        );
+        assert_eq!(
+            parse_graph::<isize>("|__1\n  |\n|__2"),
+            Err(ParseError::Malformed(3, "|__2".to_string()))
+        );
+        assert_eq!(
+            parse_graph::<isize>("|__1*\n|__2"),
+            Err(ParseError::TrailingInput(2))
+        );
+        let e: Box<dyn Error> = Box::new(ParseError::TrailingInput(2));
+        assert_eq!(e.to_string(), "line 2: trailing input");
+    }
+
+    #[test]
+    fn test_unroll_first() {
+        for l in [l2(), l3(), cl_min_size(&l3())] {
+            assert_eq!(unroll_first(&l), unroll(&l).into_iter().next());
+        }
+        let l = build(&1, &[vec![stop(&2), empty()], vec![stop(&3)]]);
+        assert_eq!(unroll_first(&l), Some(forth(&1, &[back(&3)])));
+        assert_eq!(unroll_first(&build(&1, &[vec![empty()]])), None);
+    }
+
+    #[test]
+    fn test_contains() {
+        for l in [l2(), l3()] {
+            for g in unroll(&l) {
+                assert!(contains(&l, &g));
+            }
+        }
+        let l = build(&1, &[vec![stop(&2), empty()], vec![stop(&3)]]);
+        assert!(contains(&l, &forth(&1, &[back(&3)])));
+        assert!(!contains(&l, &forth(&1, &[back(&2)])));
+        assert!(!contains(&l, &forth(&1, &[back(&2), back(&3)])));
+        assert!(!contains(&l, &back(&1)));
+        assert!(!contains(&l, &forth(&1, &[forth(&3, &[])])));
+    }
+
+    #[test]
+    fn test_unroll_at() {
+        let l = build(
+            &1,
+            &[
+                vec![build(&2, &[vec![stop(&3)], vec![stop(&4)]]), stop(&5)],
+                vec![empty()],
+                vec![],
+            ],
+        );
+        assert_eq!(num_alternatives(&l, &[]), Some(3));
+        assert_eq!(num_alternatives(&l, &[0]), Some(2));
+        assert_eq!(num_alternatives(&l, &[0, 1]), None);
+        assert_eq!(
+            unroll_at(&l, &[0, 1]),
+            Some(forth(&1, &[forth(&2, &[back(&4)]), back(&5)]))
+        );
+        assert_eq!(unroll_at(&l, &[2]), Some(forth(&1, &[])));
+        assert_eq!(unroll_at(&l, &[1]), None);
+        assert_eq!(unroll_at(&l, &[0]), None);
+        assert_eq!(unroll_at(&l, &[0, 2]), None);
+        assert_eq!(unroll_at(&l, &[2, 0]), None);
+        let gs = unroll(&l);
+        for path in [vec![0, 0], vec![0, 1], vec![2]] {
+            assert!(gs.contains(&unroll_at(&l, &path).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_cl_min_size_anytime() {
+        let l = build(
+            &1,
+            &[
+                vec![build(&2, &[vec![stop(&3), stop(&4)]]), stop(&5)],
+                vec![build(&6, &[vec![stop(&7)]])],
+            ],
+        );
+        let r = cl_min_size_anytime(&l, None, None);
+        assert_eq!(
+            (r.graph, r.size, r.optimal),
+            (cl_min_size(&l), Some(3), true)
+        );
+        let r = cl_min_size_anytime(&l, None, Some(0));
+        assert_eq!(
+            (r.graph, r.size, r.optimal),
+            (cl_first(&l), Some(5), false)
+        );
+        let r = cl_min_size_anytime(&l3(), None, Some(2));
+        assert!(!r.optimal);
+        assert!(r.size >= Some(graph_size(&unroll(&cl_min_size(&l3()))[0])));
+        let r =
+            cl_min_size_anytime(&build(&1, &[vec![empty()]]), None, Some(0));
+        assert_eq!((r.size, r.optimal), (None, false));
+    }
+
+    #[test]
+    fn test_unroll_iter() {
+        for l in [l2(), l3()] {
+            assert_eq!(unroll_iter(&l).collect::<Gs<isize>>(), unroll(&l));
+        }
+        let l = build(
+            &1,
+            &[
+                vec![build(&2, &[vec![stop(&3)], vec![stop(&4)]]), stop(&5)],
+                vec![empty(), stop(&6)],
+                vec![],
+                vec![build(&7, &[vec![stop(&8)], vec![stop(&9)]]); 2],
+            ],
+        );
+        assert_eq!(unroll_iter(&l).collect::<Gs<isize>>(), unroll(&l));
+        assert_eq!(unroll_iter(&l).count(), 7);
+        assert_eq!(unroll_iter(&l).nth(2), Some(forth(&1, &[])));
+        assert_eq!(unroll_iter(&empty::<isize>()).next(), None);
+    }
+
     #[test]
     fn test_cartesian() {
         let xs = vec![1, 2];
@@ -399,6 +2552,28 @@ mod tests {
         assert_eq!(cartesian(&zzs), &[[0; 0]; 1]);
     }
 
+    #[test]
+    fn test_traversals() {
+        let g = g1();
+        let pre: Vec<_> = g.preorder().map(|(d, c, _)| (d, *c)).collect();
+        assert_eq!(pre, [(0, 1), (1, 1), (1, 2), (2, 1), (2, 2)]);
+        let post: Vec<_> = g.postorder().map(|(d, c, _)| (d, *c)).collect();
+        assert_eq!(post, [(1, 1), (2, 1), (2, 2), (1, 2), (0, 1)]);
+        let bfs: Vec<_> = g.bfs().map(|(d, c, _)| (d, *c)).collect();
+        assert_eq!(bfs, [(0, 1), (1, 1), (1, 2), (2, 1), (2, 2)]);
+        let kinds: Vec<_> = g.preorder().map(|(_, _, k)| k).collect();
+        assert_eq!(
+            kinds,
+            [
+                NodeKind::Forth,
+                NodeKind::Back,
+                NodeKind::Forth,
+                NodeKind::Back,
+                NodeKind::Back
+            ]
+        );
+    }
+
     fn g_bad_forth() -> Rc<IGraph> {
         forth(&1, &[back(&1), forth(&-2, &[back(&3), back(&4)])])
     }
@@ -436,6 +2611,142 @@ mod tests {
         assert_eq!(unroll(&l2()), gs2());
     }
 
+    #[test]
+    fn test_lg_union() {
+        let l4 = build(&1, &[vec![build(&3, &[vec![stop(&4)]])]]);
+        let u = lg_union(&l2(), &l4).unwrap();
+        let mut gs = gs2();
+        gs.push(forth(&1, &[forth(&3, &[back(&4)])]));
+        assert_eq!(unroll(&u), gs);
+        assert_eq!(lg_union(&u, &l4), Some(u.clone()));
+        assert_eq!(lg_union(&empty(), &l4), Some(l4.clone()));
+        assert_eq!(lg_union(&stop(&1), &l4), None);
+        assert_eq!(lg_union(&l4, &build(&2, &[])), None);
+    }
+
+    #[test]
+    fn test_lg_intersect() {
+        let l4 = build(&1, &[vec![build(&3, &[vec![stop(&4)]])]]);
+        assert_eq!(unroll(&lg_intersect(&l3(), &l4)), unroll(&l4));
+        assert_eq!(lg_intersect(&l2(), &l4), empty());
+        assert_eq!(lg_intersect(&l2(), &l2()), l2());
+        assert_eq!(lg_intersect(&stop(&1), &stop(&2)), empty());
+    }
+
+    #[test]
+    fn test_lg_equiv() {
+        // Reordering alternatives.
+        let l2r = build(
+            &1,
+            &[
+                vec![build(&3, &[vec![stop(&3), stop(&1)]])],
+                vec![build(&2, &[vec![stop(&1), stop(&2)]])],
+            ],
+        );
+        assert!(lg_equiv(&l2(), &l2r));
+        assert!(!lg_equiv(&l2(), &l3()));
+        // A product vs. the union of its parts.
+        let ab = build(&1, &[vec![stop(&2)], vec![stop(&3)]]);
+        let cd = build(&4, &[vec![stop(&5)], vec![stop(&6)]]);
+        let l = build(&0, &[vec![ab, cd.clone()]]);
+        let l1 = build(
+            &0,
+            &[
+                vec![build(&1, &[vec![stop(&2)]]), cd.clone()],
+                vec![build(&1, &[vec![stop(&3)]]), cd],
+            ],
+        );
+        assert!(lg_equiv(&l, &l1));
+        let lp = build(
+            &0,
+            &[
+                vec![
+                    build(&1, &[vec![stop(&2)]]),
+                    build(&4, &[vec![stop(&5)]]),
+                ],
+                vec![
+                    build(&1, &[vec![stop(&3)]]),
+                    build(&4, &[vec![stop(&6)]]),
+                ],
+            ],
+        );
+        assert!(lg_subset(&lp, &l));
+        assert!(!lg_subset(&l, &lp));
+        // Empty sets.
+        assert!(lg_equiv(&build(&0, &[vec![empty()]]), &empty()));
+        assert!(lg_subset(&empty(), &l2()));
+        assert!(!lg_subset(&stop(&1), &empty()));
+    }
+
+    #[test]
+    fn test_lg_equiv_cleaners() {
+        let l = crate::big_step_sc::lazy_mrsc(&crate::testing::mock_world(), 0);
+        let bad = |c: &isize| *c >= 3;
+        assert!(lg_equiv(&l, &cl_empty(&l)));
+        assert!(lg_equiv(
+            &cl_empty(&cl_bad_conf(bad, &l)),
+            &cl_empty_and_bad(bad, &l)
+        ));
+        let m = cl_min_size(&l);
+        assert!(lg_subset(&m, &l));
+        assert!(!lg_subset(&l, &m));
+        // Agrees with unrolling.
+        let ls = [l.clone(), m, cl_bad_conf(bad, &l), cl_fold_leaves(&l)];
+        for l1 in &ls {
+            for l2 in &ls {
+                let gs2 = unroll(l2);
+                let sub = unroll(l1).iter().all(|g| gs2.contains(g));
+                assert_eq!(lg_subset(l1, l2), sub);
+            }
+        }
+    }
+
+    #[test]
+    fn test_subgraph_at() {
+        let g = g1();
+        assert_eq!(subgraph_at(&g, &[]), Some(g.clone()));
+        assert_eq!(subgraph_at(&g, &[1, 0]), Some(back(&1)));
+        assert_eq!(subgraph_at(&g, &[0, 0]), None);
+        assert_eq!(subgraph_at(&g, &[2]), None);
+        // The back node refers to the root, which is outside the subtree.
+        let g1 = forth(&1, &[forth(&2, &[back(&1)])]);
+        assert_eq!(subgraph_at(&g1, &[0]), Some(forth(&2, &[back(&1)])));
+    }
+
+    #[test]
+    fn test_sublazy_at() {
+        let l = l2();
+        assert_eq!(
+            sublazy_at(&l, &[(1, 0)]),
+            Some(build(&3, &[vec![stop(&3), stop(&1)]]))
+        );
+        assert_eq!(sublazy_at(&l, &[(1, 0), (0, 1)]), Some(stop(&1)));
+        assert_eq!(sublazy_at(&l, &[(2, 0)]), None);
+        assert_eq!(sublazy_at(&l, &[(0, 0), (0, 0), (0, 0)]), None);
+    }
+
+    #[test]
+    fn test_index_graph() {
+        let g = g1();
+        let t = index_graph(&g);
+        assert_eq!(t.len(), 5);
+        assert_eq!(t.confs, [1, 1, 2, 1, 2]);
+        assert_eq!(t.paths[3], [1, 0]);
+        assert_eq!(t.id_of(&vec![1, 1]), Some(4));
+        assert_eq!(t.id_of(&vec![2]), None);
+        for (id, path) in t.paths.iter().enumerate() {
+            let c = match &*subgraph_at(&g, path).unwrap() {
+                Back(c) => *c,
+                Forth(c, _) => *c,
+            };
+            assert_eq!(c, t.confs[id]);
+        }
+        let t = index_lazy_graph(&l_empty());
+        assert_eq!(t.confs, [Some(1), Some(2), Some(3), Some(4), None]);
+        assert_eq!(t.paths[4], [(1, 0), (0, 1)]);
+        assert_eq!(t.id_of(&vec![(1, 0)]), Some(2));
+    }
+
     #[test]
     fn test_bad_graph() {
         assert!(!bad_graph(bad_i, &g1()));
@@ -443,6 +2754,89 @@ mod tests {
         assert!(bad_graph(bad_i, &g_bad_back()));
     }
 
+    #[test]
+    fn test_combinators() {
+        let g = g1();
+        assert_eq!(
+            g.map_conf(|c| c * 10),
+            *forth(&10, &[back(&10), forth(&20, &[back(&10), back(&20)])])
+        );
+        assert_eq!(
+            g.map_conf(|c| c.to_string())
+                .map_conf(|s| s.parse().unwrap()),
+            *g
+        );
+        let depth =
+            g.fold(|_| 0, |_, ds| 1 + ds.into_iter().max().unwrap_or(0));
+        assert_eq!(depth, 2);
+        let confs = g.fold(
+            |c| vec![*c],
+            |c, css| {
+                let mut cs = vec![*c];
+                cs.extend(css.into_iter().flatten());
+                cs
+            },
+        );
+        assert_eq!(confs, vec![1, 1, 2, 1, 2]);
+        assert_eq!(graph_size(&g), 5);
+        assert_eq!(graph_backs(&g), 3);
+        assert!(g.any_conf(|c| *c == 2));
+        assert!(!g.any_conf(|c| *c == 3));
+        assert!(g.all_conf(|c| *c > 0));
+        assert!(!g.all_conf(|c| *c > 1));
+    }
+
+    #[test]
+    fn test_confs() {
+        let g = g1();
+        assert_eq!(g.confs().copied().collect::<Vec<_>>(), vec![1, 1, 2, 1, 2]);
+        assert_eq!(
+            g.confs_with_depth().collect::<Vec<_>>(),
+            vec![(0, &1), (1, &1), (1, &2), (2, &1), (2, &2)]
+        );
+        let cs: std::collections::HashSet<isize> = g.confs().copied().collect();
+        assert_eq!(cs.len(), 2);
+    }
+
+    #[test]
+    fn test_accessors() {
+        let g = g1();
+        assert_eq!(*g.root_conf(), 1);
+        assert_eq!(g.depth(), 2);
+        assert_eq!(g.leaf_count(), 3);
+        assert_eq!(g.back_count(), 3);
+        let g2 = forth(&1, &[forth(&2, &[]), back(&3)]);
+        assert_eq!(g2.depth(), 1);
+        assert_eq!(g2.leaf_count(), 2);
+        assert_eq!(g2.back_count(), 1);
+        assert_eq!(back(&5).depth(), 0);
+        assert_eq!(*back(&5).root_conf(), 5);
+    }
+
+    #[test]
+    fn test_lazy_map_conf() {
+        let l = l2();
+        let f = |c: &isize| c.to_string();
+        let l1 = l.map_conf(f);
+        assert_eq!(
+            unroll(&l1),
+            vec_map!(Rc::new(g.map_conf(f)); g in unroll(&l))
+        );
+        // Shared subtrees remain shared.
+        let s = stop(&1);
+        let l2 = build(&0, &[vec![Rc::clone(&s)], vec![Rc::clone(&s)]]);
+        match &l2.map_conf(|c| c + 1) {
+            Build(1, lss) => assert!(Rc::ptr_eq(&lss[0][0], &lss[1][0])),
+            _ => panic!(),
+        }
+        let r: Result<LazyGraph<usize>, isize> =
+            l.try_map_conf(|&c| if c < 3 { Ok(c as usize) } else { Err(c) });
+        assert_eq!(r, Err(3));
+        let r: Result<LazyGraph<usize>, isize> =
+            l.try_map_conf(|&c| Ok(c as usize));
+        assert_eq!(r.map(|l| Rc::new(l.map_conf(|&c| c as isize))), Ok(l));
+    }
+
     #[test]
     fn test_cl_empty() {
         assert_eq!(cl_empty(&l_empty()), build(&1, &[vec![stop(&2)]]));
@@ -474,12 +2868,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_capturing_predicates() {
+        let limit = 3;
+        let bad = |c: &isize| *c > limit;
+        assert_eq!(
+            cl_empty_and_bad(bad, &l3()),
+            build(&1, &[vec![build(&2, &[vec![stop(&1), stop(&2)]])]])
+        );
+        assert_eq!(
+            cl_empty(&cl_bad_conf(bad, &l3())),
+            cl_empty_and_bad(bad, &l3())
+        );
+        assert_eq!(
+            fl_bad_conf(bad, unroll(&l3())),
+            unroll(&cl_empty_and_bad(bad, &l3()))
+        );
+    }
+
     #[test]
     fn test_cl_empty_and_bad() {
         assert_eq!(cl_empty_and_bad(bad_i, &l_bad_stop()), empty());
         assert_eq!(cl_empty_and_bad(bad_i, &l_bad_build()), empty());
     }
 
+    #[test]
+    fn test_cl_fold_leaves() {
+        let l = build(
+            &1,
+            &[
+                vec![],
+                vec![stop(&1), build(&2, &[vec![], vec![stop(&2)]])],
+                vec![build(&3, &[vec![]])],
+                vec![empty()],
+            ],
+        );
+        assert_eq!(
+            cl_fold_leaves(&l),
+            build(&1, &[vec![stop(&1), build(&2, &[vec![stop(&2)]])]])
+        );
+        assert_eq!(unroll(&cl_fold_leaves(&l)), fl_fold_leaves(unroll(&l)));
+        assert_eq!(unroll(&cl_fold_leaves(&l)).len(), 1);
+        for l in [l2(), l3()] {
+            assert_eq!(unroll(&cl_fold_leaves(&l)), fl_fold_leaves(unroll(&l)));
+        }
+    }
+
+    #[test]
+    fn test_cl_empty_and_bad_fusion() {
+        let l_bad_alt = build(
+            &1,
+            &[
+                vec![stop(&1), build(&2, &[vec![stop(&3), stop(&-4)]])],
+                vec![build(&-5, &[]), stop(&6)],
+                vec![stop(&7)],
+            ],
+        );
+        for l in [
+            l2(),
+            l3(),
+            l_empty(),
+            l_bad_stop(),
+            l_bad_build(),
+            l_bad_alt,
+        ] {
+            assert_eq!(
+                cl_empty_and_bad(bad_i, &l),
+                cl_empty(&cl_bad_conf(bad_i, &l))
+            );
+        }
+    }
+
     #[test]
     fn test_graph_size() {
         assert_eq!(graph_size(&g1()), 5);
@@ -503,10 +2962,197 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_cl_min_cost() {
+        assert_eq!(cl_min_cost(|_| 1, &l3()), cl_min_size(&l3()));
+        assert_eq!(cl_min_cost(|_| 1, &l_empty()), cl_min_size(&l_empty()));
+        assert_eq!(
+            cl_min_cost(|c: &isize| if *c == 3 { 10 } else { 1 }, &l3()),
+            build(&1, &[vec![build(&2, &[vec![stop(&1), stop(&2)]])]])
+        );
+        assert_eq!(cl_min_cost(|_| 1, &build(&1, &[vec![empty()]])), empty());
+    }
+
+    #[test]
+    fn test_cl_pareto() {
+        let size = |_: &isize| 1;
+        let big = |c: &isize| if *c >= 3 { 1 } else { 0 };
+        assert_eq!(cl_pareto(&[&size], &l3()), cl_min_size(&l3()));
+        assert_eq!(cl_pareto(&[&size, &big], &l3()), l3());
+        let l = crate::big_step_sc::lazy_mrsc(&crate::testing::mock_world(), 0);
+        let gs = unroll(&l);
+        let cost = |g: &Rc<IGraph>| {
+            let cs = vec_map!(*n.1; n in g.preorder());
+            vec![cs.len(), cs.iter().filter(|c| **c >= 3).count()]
+        };
+        let expected = vec_map!(Rc::clone(g); g in gs.iter(),
+            !gs.iter().any(|g1| dominates(&cost(g1), &cost(g))));
+        let gs1 = unroll(&cl_pareto(&[&size, &big], &l));
+        crate::testing::assert_graphs_equiv(&gs1, &expected);
+        assert_eq!(cl_pareto(&[&size], &empty::<isize>()), empty());
+    }
+
+    #[test]
+    fn test_cl_min_backs() {
+        assert_eq!(graph_backs(&g1()), 3);
+        let l = build(
+            &1,
+            &[
+                vec![build(&2, &[vec![stop(&1), stop(&2)]])],
+                vec![build(&3, &[vec![stop(&4)], vec![stop(&5), empty()]])],
+                vec![build(&6, &[vec![build(&7, &[vec![stop(&1)]])]])],
+            ],
+        );
+        let gs = unroll(&cl_min_backs(&l));
+        assert_eq!(
+            gs,
+            [
+                forth(&1, &[forth(&3, &[back(&4)])]),
+                forth(&1, &[forth(&6, &[forth(&7, &[back(&1)])])])
+            ]
+        );
+        assert_eq!(cl_min_backs(&l_empty()), build(&1, &[vec![stop(&2)]]));
+    }
+
+    #[test]
+    fn test_cl_max_size() {
+        assert_eq!(cl_max_size(2, &l3()), empty());
+        assert_eq!(
+            cl_max_size(3, &l3()),
+            build(&1, &[vec![build(&3, &[vec![stop(&4)]])]])
+        );
+        assert_eq!(cl_max_size(4, &l3()), l3());
+        let l = crate::big_step_sc::lazy_mrsc(&crate::testing::mock_world(), 0);
+        let gs = unroll(&l);
+        for limit in 0..8 {
+            let gs1 = unroll(&cl_max_size(limit, &l));
+            let gs2 =
+                vec_map!(Rc::clone(g); g in gs.iter(), graph_size(g) <= limit);
+            crate::testing::assert_graphs_equiv(&gs1, &gs2);
+        }
+    }
+
+    #[test]
+    fn test_extract_k_smallest() {
+        assert_eq!(
+            extract_k_smallest(1, &l3()),
+            [forth(&1, &[forth(&3, &[back(&4)])])]
+        );
+        assert_eq!(extract_k_smallest(0, &l3()), []);
+        let l = crate::big_step_sc::lazy_mrsc(&crate::testing::mock_world(), 0);
+        let mut sizes = vec_map!(graph_size(&g); g in unroll(&l));
+        sizes.sort();
+        for k in 0..6 {
+            let gs = extract_k_smallest(k, &l);
+            assert_eq!(
+                vec_map!(graph_size(&g); g in gs),
+                sizes[..k.min(sizes.len())]
+            );
+        }
+
+        // The shared chain represents 2^59 graphs of size 60.
+        let mut chain = stop(&60);
+        for c in (1..60).rev() {
+            chain = build(&c, &[vec![Rc::clone(&chain)], vec![chain]]);
+        }
+        let l = build(&0, &[vec![Rc::clone(&chain), chain]]);
+        let gs = extract_k_smallest(3, &l);
+        assert_eq!(vec_map!(graph_size(&g); g in gs), [121, 121, 121]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let s = serde_json::to_string(&g1()).unwrap();
+        let g: Rc<IGraph> = serde_json::from_str(&s).unwrap();
+        assert_eq!(g, g1());
+        let s = serde_json::to_string(&l3()).unwrap();
+        let l: Rc<ILazyGraph> = serde_json::from_str(&s).unwrap();
+        assert_eq!(l, l3());
+    }
+
+    #[test]
+    fn test_cl_distinct() {
+        let l = build(
+            &1,
+            &[
+                vec![build(&2, &[vec![stop(&3)], vec![stop(&3)]])],
+                vec![stop(&4)],
+                vec![build(&2, &[vec![stop(&3)]])],
+            ],
+        );
+        assert_eq!(
+            cl_distinct(&l),
+            build(&1, &[vec![build(&2, &[vec![stop(&3)]])], vec![stop(&4)]])
+        );
+        assert_eq!(unroll(&l).len(), 4);
+        assert_eq!(unroll(&cl_distinct(&l)).len(), 2);
+        assert_eq!(cl_distinct(&l3()), l3());
+    }
+
+    #[test]
+    fn test_cl_first() {
+        assert_eq!(
+            cl_first(&l3()),
+            build(&1, &[vec![build(&2, &[vec![stop(&1), stop(&2)]])]])
+        );
+        assert_eq!(unroll(&cl_first(&l3()))[..], unroll(&l3())[..1]);
+        let l = build(
+            &1,
+            &[
+                vec![build(&2, &[vec![stop(&3), empty()]])],
+                vec![build(&4, &[vec![stop(&5)]])],
+            ],
+        );
+        assert_eq!(
+            cl_first(&l),
+            build(&1, &[vec![build(&4, &[vec![stop(&5)]])]])
+        );
+        assert_eq!(cl_first(&build(&1, &[vec![empty()]])), empty());
+    }
+
+    #[test]
+    fn test_cleaner_pipeline() {
+        let l = l3();
+        let p = ClBadConf::new(|c: &isize| *c == 4).then(ClEmpty);
+        assert_eq!(p.clean(&l), cl_empty(&cl_bad_conf(|c| *c == 4, &l)));
+        let p = p.then(ClMinSize);
+        assert_eq!(
+            p.clean(&l),
+            build(&1, &[vec![build(&2, &[vec![stop(&1), stop(&2)]])]])
+        );
+        let ps: Vec<Box<dyn Cleaner<isize>>> =
+            vec![Box::new(ClMinSize), Box::new(Then(ClEmpty, ClFirst))];
+        for p in &ps {
+            assert_eq!(unroll(&p.clean(&l)).len(), 1);
+        }
+    }
+
     #[test]
     fn test_cl_min_size_unroll() {
         let min_l = cl_min_size(&l3());
         let min_g = unroll(&min_l)[0].clone();
         assert_eq!(min_g, forth(&1, &[forth(&3, &[back(&4)])]));
     }
+
+    #[test]
+    fn test_compress_siblings() {
+        // Configurations are foldable to each other up to the sign.
+        let f = |c1: &isize, c2: &isize| c1.abs() == c2.abs();
+        let g1 = forth(&1, &[back(&1)]);
+        let g2 = forth(&-1, &[back(&-1)]);
+        let g3 = forth(&2, &[back(&1)]);
+        let g = forth(&0, &[g1.clone(), g2.clone(), g3.clone()]);
+        assert!(graph_equiv_modulo(f, &g1, &g2));
+        assert!(!graph_equiv_modulo(f, &g1, &g3));
+        let g_c = compress_siblings(f, &g);
+        assert_eq!(*g_c, *forth(&0, &[g1.clone(), back(&-1), g3.clone()]));
+        assert_eq!(graph_size(&g_c), 6);
+        // Back nodes are not merged into back nodes.
+        let h = forth(&0, &[back(&0), back(&0)]);
+        assert_eq!(compress_siblings(f, &h), h);
+        // Nested siblings.
+        let k = forth(&5, std::slice::from_ref(&g));
+        assert_eq!(*compress_siblings(f, &k), *forth(&5, &[g_c]));
+    }
 }
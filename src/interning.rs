@@ -0,0 +1,265 @@
+//
+// Interning configurations
+//
+// An `Interner<C>` keeps a single copy of each configuration it has seen.
+// `intern(c)` returns an `Interned<C>`, which is a shared reference
+// to that copy. Hence, equal configurations share storage, and
+// two `Interned<C>` values produced by the same interner are equal iff
+// they point to the same copy. Equality and hashing are performed
+// by pointer, and cloning does not copy the configuration.
+//
+// Note that comparing `Interned<C>` values produced by different
+// interners makes no sense.
+//
+// `intern_world(s)` wraps a world `s`, so that its configurations are
+// interned. (For example, `intern_world(CountersScWorld::new(...))` is
+// a counter world whose configurations are `Interned<NWC>`.) Graphs
+// produced by this world share the configurations, and folding first
+// checks whether the configurations are identical. The whistle of `s`
+// is applied to the history with the configurations copied out
+// (each configuration being copied once, see `HistoryCache`).
+//
+
+use crate::big_step_sc::ScWorld;
+use crate::counters::NWC;
+use crate::misc::{History, HistoryCache};
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+
+pub struct Interned<C>(Rc<C>);
+
+impl<C> Clone for Interned<C> {
+    fn clone(&self) -> Interned<C> {
+        Interned(Rc::clone(&self.0))
+    }
+}
+
+impl<C> PartialEq for Interned<C> {
+    fn eq(&self, other: &Interned<C>) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<C> Eq for Interned<C> {}
+
+impl<C> Hash for Interned<C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).hash(state)
+    }
+}
+
+impl<C> Deref for Interned<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.0
+    }
+}
+
+impl<C: fmt::Display> fmt::Display for Interned<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<C: fmt::Debug> fmt::Debug for Interned<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+pub struct Interner<C> {
+    table: RefCell<HashSet<Rc<C>>>,
+}
+
+pub type NwcInterner = Interner<NWC>;
+
+impl<C: Eq + Hash> Interner<C> {
+    pub fn new() -> Interner<C> {
+        Interner {
+            table: RefCell::new(HashSet::new()),
+        }
+    }
+
+    pub fn intern(&self, c: C) -> Interned<C> {
+        if let Some(rc) = self.table.borrow().get(&c) {
+            return Interned(Rc::clone(rc));
+        }
+        let rc = Rc::new(c);
+        self.table.borrow_mut().insert(Rc::clone(&rc));
+        Interned(rc)
+    }
+
+    // The number of distinct configurations interned.
+
+    pub fn len(&self) -> usize {
+        self.table.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.borrow().is_empty()
+    }
+}
+
+impl<C: Eq + Hash> Default for Interner<C> {
+    fn default() -> Interner<C> {
+        Interner::new()
+    }
+}
+
+pub struct InternWorld<S: ScWorld> {
+    inner: S,
+    interner: Rc<Interner<S::C>>,
+    histories: HistoryCache<Interned<S::C>, S::C>,
+}
+
+pub fn intern_world<S: ScWorld>(s: S) -> InternWorld<S>
+where
+    S::C: Eq + Hash,
+{
+    InternWorld {
+        inner: s,
+        interner: Rc::new(Interner::new()),
+        histories: HistoryCache::new(),
+    }
+}
+
+impl<S: ScWorld> InternWorld<S>
+where
+    S::C: Eq + Hash,
+{
+    // The interner, which can be used for producing initial configurations.
+
+    pub fn interner(&self) -> &Rc<Interner<S::C>> {
+        &self.interner
+    }
+
+    pub fn intern(&self, c: S::C) -> Interned<S::C> {
+        self.interner.intern(c)
+    }
+
+    // The history with the configurations copied out. The copies are
+    // cached, so that each configuration is copied once.
+
+    fn unintern_history(&self, h: &History<Interned<S::C>>) -> History<S::C> {
+        self.histories.map(h, |c| (**c).clone())
+    }
+
+    fn intern_css(&self, css: Vec<Vec<S::C>>) -> Vec<Vec<Interned<S::C>>> {
+        css.into_iter()
+            .map(|cs| cs.into_iter().map(|c| self.intern(c)).collect())
+            .collect()
+    }
+}
+
+impl<S: ScWorld> ScWorld for InternWorld<S>
+where
+    S::C: Eq + Hash,
+{
+    type C = Interned<S::C>;
+
+    fn is_dangerous(&self, h: &History<Self::C>) -> bool {
+        self.inner.is_dangerous(&self.unintern_history(h))
+    }
+
+    fn is_foldable_to(&self, c1: &Self::C, c2: &Self::C) -> bool {
+        c1 == c2 || self.inner.is_foldable_to(c1, c2)
+    }
+
+    fn develop(&self, c: &Self::C) -> Vec<Vec<Self::C>> {
+        self.intern_css(self.inner.develop(c))
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Vec<Vec<Self::C>> {
+        let h1 = self.unintern_history(h);
+        self.intern_css(self.inner.develop_with_history(&h1, c))
+    }
+
+    fn develop_iter<'a>(
+        &'a self,
+        h: &History<Self::C>,
+        c: &Self::C,
+    ) -> Box<dyn Iterator<Item = Vec<Self::C>> + 'a> {
+        let h1 = self.unintern_history(h);
+        let css = self.inner.develop_iter(&h1, c);
+        Box::new(
+            css.map(move |cs| cs.into_iter().map(|c| self.intern(c)).collect()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::lazy_mrsc;
+    use crate::counter_system;
+    use crate::counters::*;
+    use crate::graph::*;
+    use crate::mock_sc_world::mock_world;
+    use crate::statistics::lazy_graph_size;
+    use crate::verification::verify;
+
+    counter_system! {
+        Swap(i, j);
+        Start(ω, 0);
+        Unsafe(j >= 2);
+        Rules{
+            i >= 1 && j == 0 => i - 1, j + 1;
+            j >= 1 => i + 1, j - 1;
+        }
+    }
+
+    fn unintern<C: Clone>(g: &Graph<Interned<C>>) -> Rc<Graph<C>> {
+        match g {
+            Graph::Back(c) => back(&**c),
+            Graph::Forth(c, gs) => {
+                let gs1: Vec<Rc<Graph<C>>> =
+                    gs.iter().map(|g1| unintern(g1)).collect();
+                forth(&**c, &gs1)
+            }
+        }
+    }
+
+    #[test]
+    fn test_interner() {
+        let i = Interner::new();
+        let a = i.intern(vec![1, 2]);
+        let b = i.intern(vec![1, 2]);
+        let c = i.intern(vec![2, 1]);
+        assert_eq!(a, b);
+        assert!(Rc::ptr_eq(&a.0, &b.0));
+        assert_ne!(a, c);
+        assert_eq!(*a, vec![1, 2]);
+        assert_eq!(i.len(), 2);
+    }
+
+    #[test]
+    fn test_intern_world() {
+        let s = intern_world(mock_world());
+        let gs = unroll(&lazy_mrsc(&s, s.intern(0)));
+        let gs1: Vec<Rc<Graph<isize>>> =
+            gs.iter().map(|g| unintern(g)).collect();
+        assert_eq!(gs1, unroll(&lazy_mrsc(&mock_world(), 0)));
+
+        let s = intern_world(CountersScWorld::new(Swap, 3, 10));
+        let l = lazy_mrsc(&s, s.intern(Swap.start()));
+        let bad = |c: &Interned<NWC>| Swap.is_unsafe(c);
+        let g = unroll(&cl_min_size(&cl_empty_and_bad(bad, &l)))
+            .into_iter()
+            .next();
+        let (_, mg) = verify(Swap, 3, 10);
+        assert_eq!(g.map(|g| unintern(&g)), mg);
+        // Equal configurations in the lazy graph share storage.
+        assert!(s.interner().len() < lazy_graph_size(&l));
+    }
+}
@@ -0,0 +1,182 @@
+//
+// Conversion to petgraph
+//
+// With the `petgraph` feature, residual graphs and lazy graphs can be
+// converted to `petgraph::Graph`, so that the algorithms of petgraph
+// (dominators, strongly connected components, isomorphism, etc.)
+// can be applied to them.
+//
+// `graph_to_petgraph(g)` produces a tree: the node weights are
+// the configurations together with the kinds of the nodes, and
+// the edge weights are `Child(i)`, `i` being the position of the child.
+//
+// `graph_to_petgraph_with_folds(is_foldable_to, g)` also adds an edge
+// `Fold` from each `Back` node to the nearest ancestor its configuration
+// is foldable to. Thus, the cycles of the residual program become
+// cycles of the petgraph (e.g., for `tarjan_scc`).
+//
+// `lazy_graph_to_petgraph(l)` preserves the sharing of subtrees,
+// so that the result is a DAG (with a node per `Rc`). As in
+// `lazy_graph_to_dot`, each alternative of a `Build` node is represented
+// by a node `Alt`, connected to the `Build` node by an edge `Child(i)`
+// (`i` being the number of the alternative), and connected to
+// the components of the alternative by edges `Child(j)`.
+//
+// In all cases, the root is returned along with the petgraph.
+//
+
+use crate::graph::{Graph, LazyGraph, NodeKind};
+
+use petgraph::graph::NodeIndex;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PgEdge {
+    Child(usize),
+    Fold,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum PgLazyNode<C> {
+    Empty,
+    Stop(C),
+    Build(C),
+    Alt,
+}
+
+pub type PgGraph<C> = petgraph::Graph<(C, NodeKind), PgEdge>;
+
+pub type PgLazyGraph<C> = petgraph::Graph<PgLazyNode<C>, PgEdge>;
+
+fn graph_to_petgraph_loop<C: Clone>(
+    is_foldable_to: &dyn Fn(&C, &C) -> bool,
+    pg: &mut PgGraph<C>,
+    ancestors: &mut Vec<(C, NodeIndex)>,
+    g: &Graph<C>,
+) -> NodeIndex {
+    match g {
+        Graph::Back(c) => {
+            let n = pg.add_node((c.clone(), NodeKind::Back));
+            let target = ancestors
+                .iter()
+                .rev()
+                .find(|(c1, _)| is_foldable_to(c, c1))
+                .map(|(_, n1)| *n1);
+            if let Some(n1) = target {
+                pg.add_edge(n, n1, PgEdge::Fold);
+            }
+            n
+        }
+        Graph::Forth(c, gs) => {
+            let n = pg.add_node((c.clone(), NodeKind::Forth));
+            ancestors.push((c.clone(), n));
+            for (i, g1) in gs.iter().enumerate() {
+                let n1 =
+                    graph_to_petgraph_loop(is_foldable_to, pg, ancestors, g1);
+                pg.add_edge(n, n1, PgEdge::Child(i));
+            }
+            ancestors.pop();
+            n
+        }
+    }
+}
+
+pub fn graph_to_petgraph<C: Clone>(g: &Graph<C>) -> (PgGraph<C>, NodeIndex) {
+    let mut pg = PgGraph::new();
+    let root = graph_to_petgraph_loop(&|_, _| false, &mut pg, &mut vec![], g);
+    (pg, root)
+}
+
+pub fn graph_to_petgraph_with_folds<C: Clone>(
+    is_foldable_to: impl Fn(&C, &C) -> bool,
+    g: &Graph<C>,
+) -> (PgGraph<C>, NodeIndex) {
+    let mut pg = PgGraph::new();
+    let root = graph_to_petgraph_loop(&is_foldable_to, &mut pg, &mut vec![], g);
+    (pg, root)
+}
+
+fn lazy_graph_to_petgraph_loop<C: Clone>(
+    pg: &mut PgLazyGraph<C>,
+    memo: &mut HashMap<*const LazyGraph<C>, NodeIndex>,
+    l: &Rc<LazyGraph<C>>,
+) -> NodeIndex {
+    if let Some(&n) = memo.get(&Rc::as_ptr(l)) {
+        return n;
+    }
+    let n = match &**l {
+        LazyGraph::Empty() => pg.add_node(PgLazyNode::Empty),
+        LazyGraph::Stop(c) => pg.add_node(PgLazyNode::Stop(c.clone())),
+        LazyGraph::Build(c, lss) => {
+            let n = pg.add_node(PgLazyNode::Build(c.clone()));
+            for (i, ls) in lss.iter().enumerate() {
+                let alt = pg.add_node(PgLazyNode::Alt);
+                pg.add_edge(n, alt, PgEdge::Child(i));
+                for (j, l1) in ls.iter().enumerate() {
+                    let n1 = lazy_graph_to_petgraph_loop(pg, memo, l1);
+                    pg.add_edge(alt, n1, PgEdge::Child(j));
+                }
+            }
+            n
+        }
+    };
+    memo.insert(Rc::as_ptr(l), n);
+    n
+}
+
+pub fn lazy_graph_to_petgraph<C: Clone>(
+    l: &Rc<LazyGraph<C>>,
+) -> (PgLazyGraph<C>, NodeIndex) {
+    let mut pg = PgLazyGraph::new();
+    let root = lazy_graph_to_petgraph_loop(&mut pg, &mut HashMap::new(), l);
+    (pg, root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::lazy_mrsc;
+    use crate::graph::*;
+    use crate::mock_sc_world::mock_world;
+    use crate::statistics::size_unroll;
+    use petgraph::algo::{is_cyclic_directed, tarjan_scc, toposort};
+    use petgraph::visit::EdgeRef;
+
+    #[test]
+    fn test_graph_to_petgraph() {
+        let g = forth(&0, &[forth(&1, &[back(&0), back(&1)]), back(&2)]);
+        let (pg, root) = graph_to_petgraph(&g);
+        assert_eq!(pg.node_count(), graph_size(&g));
+        assert_eq!(pg.edge_count(), graph_size(&g) - 1);
+        assert_eq!(pg[root], (0, NodeKind::Forth));
+        assert!(!is_cyclic_directed(&pg));
+        let (pg, root) = graph_to_petgraph_with_folds(|c1, c2| c1 == c2, &g);
+        let folds: Vec<_> = pg
+            .edge_references()
+            .filter(|e| *e.weight() == PgEdge::Fold)
+            .map(|e| (pg[e.source()].0, pg[e.target()].0))
+            .collect();
+        assert_eq!(folds, vec![(0, 0), (1, 1)]);
+        // The root and the node `1` form a cycle.
+        let sccs = tarjan_scc(&pg);
+        let big: Vec<_> = sccs.iter().filter(|scc| scc.len() > 1).collect();
+        assert_eq!(big.len(), 1);
+        assert!(big[0].contains(&root));
+    }
+
+    #[test]
+    fn test_lazy_graph_to_petgraph() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let (pg, root) = lazy_graph_to_petgraph(&l);
+        assert_eq!(pg[root], PgLazyNode::Build(0));
+        assert!(toposort(&pg, None).is_ok());
+        let s = Rc::new(LazyGraph::Stop(1));
+        let l2 = build(&0, &[vec![Rc::clone(&s)], vec![Rc::clone(&s)]]);
+        let (pg2, _) = lazy_graph_to_petgraph(&l2);
+        // build, 2 alternatives, a single shared stop node
+        assert_eq!(pg2.node_count(), 4);
+        assert_eq!(size_unroll(&l2).0, 2);
+    }
+}
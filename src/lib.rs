@@ -4,5 +4,27 @@ pub mod big_step_sc;
 pub mod mock_sc_world;
 pub mod statistics;
 pub mod counters;
+pub mod counters_dsl;
 pub mod protocols;
 pub mod big_step_sc8;
+pub mod adapters;
+pub mod fusion;
+pub mod archive;
+pub mod annotated;
+pub mod replay;
+pub mod verification;
+pub mod abstract_interpretation;
+pub mod render;
+pub mod interning;
+pub mod rewrite;
+pub mod sampling;
+pub mod sync_graph;
+pub mod arena;
+pub mod dag;
+pub mod prelude;
+pub mod zipper;
+pub mod error;
+#[cfg(feature = "petgraph")]
+pub mod interop;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
@@ -2,13 +2,17 @@
 
 use iter_comprehensions::vec as vec_map;
 use itertools::Itertools;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
+use std::sync::{Arc, Weak};
 
 //
 // Cartesian product
 //
 
-pub fn cartesian<X: Clone>(xss: &Vec<Vec<X>>) -> Vec<Vec<X>> {
+pub(crate) fn cartesian<X: Clone>(xss: &[Vec<X>]) -> Vec<Vec<X>> {
     if xss.is_empty() {
         vec![vec![]]
     } else {
@@ -17,46 +21,343 @@ pub fn cartesian<X: Clone>(xss: &Vec<Vec<X>>) -> Vec<Vec<X>> {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub enum History<T> {
-    Nil,
-    Cons(T, Rc<History<T>>),
+// `CartesianIter` produces the same tuples as `cartesian`, in the same
+// order, but one at a time, so that the whole product is never kept
+// in memory.
+
+pub(crate) struct CartesianIter<X> {
+    xss: Vec<Vec<X>>,
+    // The positions of the next tuple (`None` if there are no more).
+    next: Option<Vec<usize>>,
+}
+
+impl<X: Clone> CartesianIter<X> {
+    pub(crate) fn new(xss: Vec<Vec<X>>) -> CartesianIter<X> {
+        let next = if xss.iter().any(|xs| xs.is_empty()) {
+            None
+        } else {
+            Some(vec![0; xss.len()])
+        };
+        CartesianIter { xss, next }
+    }
+}
+
+impl<X: Clone> Iterator for CartesianIter<X> {
+    type Item = Vec<X>;
+
+    fn next(&mut self) -> Option<Vec<X>> {
+        let is = self.next.as_mut()?;
+        let ys = vec_map!(self.xss[k][is[k]].clone(); k in 0..is.len());
+        // Advances the "odometer", the last position changing fastest.
+        let mut k = is.len();
+        loop {
+            if k == 0 {
+                self.next = None;
+                break;
+            }
+            k -= 1;
+            is[k] += 1;
+            if is[k] < self.xss[k].len() {
+                break;
+            }
+            is[k] = 0;
+        }
+        Some(ys)
+    }
 }
 
-use History::{Cons, Nil};
+// A history is a persistent list, the most recent element being the first
+// one. The nodes are shared, so that `cons` takes constant time (without
+// cloning the elements), and the length is kept together with the first
+// node. The elements are visited by reference (`iter`).
+// The nodes are reference-counted atomically, so that a history can be
+// shared between threads (as in `naive_mrsc_par`).
+//
+// A history may also be a prefix of a longer list, whose nodes
+// it shares (see `window`): then only the first `length()` elements
+// of the list belong to the history.
+//
+// A history used to be an enum with the variants `Nil` and `Cons`.
+// The histories are still built by `History::Nil` and `History::Cons(x, t)`
+// (now a constant and a function). The code taking histories apart
+// by pattern matching can match `h.view()`, which is `HistoryView::Nil`
+// or `HistoryView::Cons(x, t)`, `x` being the most recent element
+// and `t` the rest of the history.
+
+pub struct History<T> {
+    node: Option<Arc<HistoryNode<T>>>,
+    len: usize,
+}
+
+struct HistoryNode<T> {
+    head: T,
+    tail: Option<Arc<HistoryNode<T>>>,
+}
+
+impl<T> History<T> {
+    #[allow(non_upper_case_globals)]
+    pub const Nil: History<T> = History { node: None, len: 0 };
+
+    #[allow(non_snake_case)]
+    pub fn Cons(x: T, t: Rc<History<T>>) -> History<T> {
+        t.cons(x)
+    }
 
-impl<T: Clone> History<T> {
     pub fn new() -> History<T> {
-        Nil
+        History { node: None, len: 0 }
     }
 
     pub fn cons(&self, x: T) -> History<T> {
-        match *self {
-            Cons(ref h, ref t) => Cons(x, Rc::new(Cons(h.clone(), t.clone()))),
-            Nil => Cons(x, Rc::new(Nil)),
+        History {
+            node: Some(Arc::new(HistoryNode {
+                head: x,
+                tail: self.node.clone(),
+            })),
+            len: self.len + 1,
         }
     }
 
     pub fn length(&self) -> usize {
-        match &self {
-            Nil => 0,
-            Cons(_, t) => 1 + t.length(),
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn view(&self) -> HistoryView<'_, T> {
+        match &self.node {
+            Some(n) if self.len > 0 => HistoryView::Cons(
+                &n.head,
+                History {
+                    node: n.tail.clone(),
+                    len: self.len - 1,
+                },
+            ),
+            _ => HistoryView::Nil,
+        }
+    }
+
+    pub fn iter(&self) -> HistoryIter<'_, T> {
+        HistoryIter {
+            node: self.node.as_deref(),
+            len: self.len,
+        }
+    }
+
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> History<U> {
+        let mut h = History::new();
+        for x in self.iter().collect::<Vec<_>>().into_iter().rev() {
+            h = h.cons(f(x));
         }
+        h
     }
 
     pub fn any(&self, p: impl Fn(&T) -> bool) -> bool {
-        let mut list = self.clone();
-        loop {
-            match &list {
-                Nil => return false,
-                Cons(h, t) => {
-                    if p(h) {
-                        return true;
-                    }
-                    list = (**t).clone();
-                }
+        self.iter().any(p)
+    }
+
+    pub fn contains(&self, x: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.iter().any(|y| y == x)
+    }
+
+    // The oldest element (the initial configuration, for the histories
+    // produced by supercompilers).
+
+    pub fn last(&self) -> Option<&T> {
+        self.iter().last()
+    }
+
+    // The elements, the most recent one first.
+
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.iter().cloned().collect()
+    }
+
+    // The most recent `k` elements (or all of them, if there are fewer).
+    // The window shares the nodes of the history, hence, it takes
+    // constant time, and no elements are copied.
+
+    pub fn window(&self, k: usize) -> History<T> {
+        History {
+            node: self.node.clone(),
+            len: k.min(self.len),
+        }
+    }
+}
+
+impl<T> Clone for History<T> {
+    fn clone(&self) -> History<T> {
+        History {
+            node: self.node.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> Default for History<T> {
+    fn default() -> History<T> {
+        History::new()
+    }
+}
+
+impl<T: PartialEq> PartialEq for History<T> {
+    fn eq(&self, other: &History<T>) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for History<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for History<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Long histories are dropped without recursion.
+
+impl<T> Drop for History<T> {
+    fn drop(&mut self) {
+        let mut next = self.node.take();
+        while let Some(n) = next {
+            next = match Arc::into_inner(n) {
+                Some(mut n) => n.tail.take(),
+                None => None,
+            };
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum HistoryView<'a, T> {
+    Nil,
+    Cons(&'a T, History<T>),
+}
+
+// Iterates over a history, starting from the most recent element.
+
+pub struct HistoryIter<'a, T> {
+    node: Option<&'a HistoryNode<T>>,
+    // The number of the elements left.
+    len: usize,
+}
+
+impl<'a, T> Iterator for HistoryIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            return None;
+        }
+        let n = self.node?;
+        self.node = n.tail.as_deref();
+        self.len -= 1;
+        Some(&n.head)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for HistoryIter<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a History<T> {
+    type Item = &'a T;
+    type IntoIter = HistoryIter<'a, T>;
+
+    fn into_iter(self) -> HistoryIter<'a, T> {
+        self.iter()
+    }
+}
+
+// Converting histories incrementally
+//
+// A world whose configurations wrap those of another world (say,
+// `Interned<C>` wrapping `C`) has to pass a `History<C>` to the inner
+// world. Converting the whole history at each node takes time
+// proportional to its length, the elements being copied again and again.
+//
+// A `HistoryCache` remembers the converted histories (as long as
+// the original ones are alive). Hence, if `h` has already been converted,
+// converting `h.cons(x)` only converts `x`, the converted `h` being
+// shared. Each element of a history built by a supercompiler is thus
+// converted once.
+
+pub struct HistoryCache<T, U> {
+    memo: RefCell<HashMap<HistoryKey<T>, CachedHistory<T, U>>>,
+    // The size of the memo after the stale entries were last removed.
+    live: Cell<usize>,
+}
+
+// A history is identified by its first node and its length.
+
+type HistoryKey<T> = (*const HistoryNode<T>, usize);
+
+// The weak reference tells whether the node is still alive, and keeps
+// its memory allocated, so that its address is not reused while
+// the entry exists.
+
+struct CachedHistory<T, U> {
+    node: Weak<HistoryNode<T>>,
+    converted: History<U>,
+}
+
+impl<T, U> HistoryCache<T, U> {
+    pub fn new() -> HistoryCache<T, U> {
+        HistoryCache {
+            memo: RefCell::new(HashMap::new()),
+            live: Cell::new(0),
+        }
+    }
+
+    // Same as `h.map(f)`, provided that `f` is the same function
+    // at each call.
+
+    pub fn map(&self, h: &History<T>, f: impl Fn(&T) -> U) -> History<U> {
+        let mut memo = self.memo.borrow_mut();
+        // The nodes not converted yet, the most recent one first.
+        let mut pending = vec![];
+        let mut node = h.node.as_ref();
+        let mut len = h.len;
+        let mut converted = History::new();
+        while let Some(n) = node.filter(|_| len > 0) {
+            if let Some(e) = memo.get(&(Arc::as_ptr(n), len)) {
+                converted = e.converted.clone();
+                break;
             }
+            pending.push(n);
+            node = n.tail.as_ref();
+            len -= 1;
+        }
+        for n in pending.into_iter().rev() {
+            converted = converted.cons(f(&n.head));
+            memo.insert(
+                (Arc::as_ptr(n), converted.len),
+                CachedHistory {
+                    node: Arc::downgrade(n),
+                    converted: converted.clone(),
+                },
+            );
+        }
+        if memo.len() > 2 * self.live.get().max(64) {
+            memo.retain(|_, e| e.node.strong_count() > 0);
+            self.live.set(memo.len());
         }
+        converted
+    }
+}
+
+impl<T, U> Default for HistoryCache<T, U> {
+    fn default() -> HistoryCache<T, U> {
+        HistoryCache::new()
     }
 }
 
@@ -66,15 +367,100 @@ mod tests {
 
     #[test]
     fn test_list_ok() {
+        use HistoryView::{Cons, Nil};
+
         let l1: History<usize> = History::new();
         let l2 = l1.cons(3).cons(2).cons(1);
 
+        assert_eq!(l2.length(), 3);
+        assert_eq!(l2.view(), Cons(&1, l1.cons(3).cons(2)));
+        match l2.view() {
+            Cons(1, t) => match t.view() {
+                Cons(2, t) => match t.view() {
+                    Cons(3, t) => assert_eq!(t.view(), Nil),
+                    _ => panic!(),
+                },
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+        assert_eq!(l2.iter().len(), 3);
+        assert_eq!(format!("{:?}", l2), "[1, 2, 3]");
+        assert_ne!(l2, l1.cons(3).cons(2));
+        assert!(l1.is_empty() && !l2.is_empty());
+
+        assert!(l2.any(|&t| t == 2));
+        assert!(!l2.any(|&t| t == 5));
+        assert_eq!(l2.to_vec(), vec![1, 2, 3]);
+        assert!(l2.contains(&3) && !l2.contains(&4));
+        assert_eq!(l2.last(), Some(&3));
+        assert_eq!(l1.last(), None);
+        let mut xs = vec![];
+        for x in &l2 {
+            xs.push(*x);
+        }
+        assert_eq!(xs, vec![1, 2, 3]);
+
+        let w = l2.window(2);
+        assert_eq!(w.length(), 2);
+        assert_eq!(w.iter().cloned().collect::<Vec<_>>(), vec![1, 2]);
+        assert!(w.contains(&2) && !w.contains(&3));
+        assert_eq!(w, l1.cons(2).cons(1));
+        assert_eq!(w.last(), Some(&2));
+        assert_eq!(w.cons(0).to_vec(), vec![0, 1, 2]);
+        assert_eq!(format!("{:?}", w.window(1)), "[1]");
+        assert_eq!(l2.window(5), l2);
+        assert!(l2.window(0).is_empty());
+        assert_eq!(l2.window(0).view(), Nil);
+        assert_eq!(l2.map(|&t| t * 10), l1.cons(30).cons(20).cons(10));
+    }
+
+    #[test]
+    fn test_old_constructors() {
+        let l1: History<usize> = History::Nil;
+        let l2 = l1.cons(3).cons(2).cons(1);
         assert_eq!(
             l2,
-            Cons(1, Rc::new(Cons(2, Rc::new(Cons(3, Rc::new(Nil))))))
+            History::Cons(
+                1,
+                Rc::new(History::Cons(
+                    2,
+                    Rc::new(History::Cons(3, Rc::new(History::Nil)))
+                ))
+            )
         );
+        assert_eq!(l1, History::new());
+    }
 
-        assert!(l2.any(|&t| t == 2));
-        assert!(!l2.any(|&t| t == 5));
+    #[test]
+    fn test_history_cache() {
+        let calls = Cell::new(0);
+        let f = |x: &usize| {
+            calls.set(calls.get() + 1);
+            x * 10
+        };
+        let cache = HistoryCache::new();
+        let h = History::new().cons(1).cons(2);
+        assert_eq!(cache.map(&h, f), h.map(|x| x * 10));
+        assert_eq!(calls.get(), 2);
+        let h1 = h.cons(3);
+        assert_eq!(cache.map(&h1, f).to_vec(), vec![30, 20, 10]);
+        assert_eq!(calls.get(), 3);
+        assert_eq!(cache.map(&h1.window(2), f).to_vec(), vec![30, 20]);
+        assert_eq!(calls.get(), 5);
+    }
+
+    #[test]
+    fn test_cartesian_iter() {
+        let xss = vec![vec![1, 2], vec![10, 20, 30], vec![100, 200]];
+        let yss: Vec<Vec<i32>> = CartesianIter::new(xss.clone()).collect();
+        assert_eq!(yss, cartesian(&xss));
+        let no: Vec<Vec<i32>> = vec![];
+        assert_eq!(
+            CartesianIter::new(no).collect::<Vec<_>>(),
+            vec![Vec::<i32>::new()]
+        );
+        let e = vec![vec![1], vec![]];
+        assert_eq!(CartesianIter::new(e).count(), 0);
     }
 }
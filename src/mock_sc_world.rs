@@ -1,30 +1,106 @@
-use crate::big_step_sc::*;
-use crate::misc::*;
-
-fn drive(c: &isize) -> Vec<Vec<isize>> {
-    if c < &2 {
-        vec![]
-    } else {
-        vec![vec![0, c - 1], vec![c - 1]]
-    }
+// A mock world of supercompilation.
+//
+// `MockScWorld` is a world whose drive/rebuild/whistle behavior is
+// given by closures. It is used by the crate's own tests and can be used
+// by downstream code to test cleaners and extractors on small,
+// hand-made worlds.
+//
+// `MockScWorld::new(driven, rebuilt, whistle)`: `driven(c)` and
+// `rebuilt(c)` are lists of alternatives (each one a list of
+// configurations), those produced by driving `c` and by rebuilding `c`,
+// respectively, and `develop(c)` is `driven(c)` followed by `rebuilt(c)`.
+// By default, `c1` is foldable to `c2` iff `c1 == c2`.
+
+use crate::big_step_sc::ScWorld;
+use crate::misc::History;
+
+type DevelopFn<C> = Box<dyn Fn(&C) -> Vec<Vec<C>>>;
+type WhistleFn<C> = Box<dyn Fn(&History<C>) -> bool>;
+type FoldFn<C> = Box<dyn Fn(&C, &C) -> bool>;
+
+pub struct MockScWorld<C> {
+    driven: DevelopFn<C>,
+    rebuilt: DevelopFn<C>,
+    whistle: WhistleFn<C>,
+    foldable: FoldFn<C>,
 }
 
-fn rebuild(c: &isize) -> Vec<Vec<isize>> {
-    vec![vec![c + 1]]
+impl<C: Clone + PartialEq + 'static> MockScWorld<C> {
+    pub fn new(
+        driven: impl Fn(&C) -> Vec<Vec<C>> + 'static,
+        rebuilt: impl Fn(&C) -> Vec<Vec<C>> + 'static,
+        whistle: impl Fn(&History<C>) -> bool + 'static,
+    ) -> MockScWorld<C> {
+        MockScWorld {
+            driven: Box::new(driven),
+            rebuilt: Box::new(rebuilt),
+            whistle: Box::new(whistle),
+            foldable: Box::new(|c1: &C, c2: &C| c1 == c2),
+        }
+    }
+
+    // Replaces the default foldability relation (equality).
+
+    pub fn with_foldability(
+        mut self,
+        foldable: impl Fn(&C, &C) -> bool + 'static,
+    ) -> MockScWorld<C> {
+        self.foldable = Box::new(foldable);
+        self
+    }
 }
 
-impl ScWorld for isize {
-    type C = isize;
+impl<C: Clone> ScWorld for MockScWorld<C> {
+    type C = C;
 
-    fn is_dangerous(&self, h: &History<isize>) -> bool {
-        h.length() > 3
+    fn is_dangerous(&self, h: &History<C>) -> bool {
+        (self.whistle)(h)
     }
 
-    fn is_foldable_to(&self, c1: &isize, c2: &isize) -> bool {
-        c1 == c2
+    fn is_foldable_to(&self, c1: &C, c2: &C) -> bool {
+        (self.foldable)(c1, c2)
+    }
+
+    fn develop(&self, c: &C) -> Vec<Vec<C>> {
+        [(self.driven)(c), (self.rebuilt)(c)].concat()
+    }
+}
+
+// The world used throughout the crate's tests.
+// Configurations are integers, `c` is driven to `[0, c - 1]` and `[c - 1]`
+// (if `c >= 2`) and rebuilt to `c + 1`. The whistle blows when
+// the history is longer than 3.
+
+pub fn mock_world() -> MockScWorld<isize> {
+    MockScWorld::new(
+        |c: &isize| {
+            if *c < 2 {
+                vec![]
+            } else {
+                vec![vec![0, c - 1], vec![c - 1]]
+            }
+        },
+        |c: &isize| vec![vec![c + 1]],
+        |h: &History<isize>| h.length() > 3,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_world_develop() {
+        let s = mock_world();
+        assert_eq!(s.develop(&0), vec![vec![1]]);
+        assert_eq!(s.develop(&3), vec![vec![0, 2], vec![2], vec![4]]);
     }
 
-    fn develop(&self, c: &isize) -> Vec<Vec<isize>> {
-        [drive(c), rebuild(c)].concat()
+    #[test]
+    fn test_with_foldability() {
+        let s = mock_world().with_foldability(|c1, c2| c1 <= c2);
+        let h = History::new().cons(5);
+        assert!(s.is_foldable_to_history(&3, &h));
+        assert!(!s.is_foldable_to_history(&6, &h));
     }
 }
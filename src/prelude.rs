@@ -0,0 +1,85 @@
+//
+// The prelude
+//
+// A typical program defines a world (either an `ScWorld` or, via
+// `counter_system!`, a `CountersWorld`), runs a driver producing
+// a lazy graph, cleans it and extracts residual graphs (or just
+// calls `verify`). The items needed for that are spread over several
+// modules, so that they are re-exported here and can be imported with
+//     use staged_mrsc_rust::prelude::*;
+//
+// Everything else (alternative drivers, specialized cleaners,
+// renderers, etc.) is imported from its own module.
+//
+
+// Worlds and drivers.
+
+pub use crate::big_step_sc::{
+    lazy_mrsc, lazy_mrsc_checked, naive_mrsc, ScWorld,
+};
+pub use crate::error::{ResourceLimits, StagedMrscError};
+pub use crate::misc::History;
+
+// Graphs and lazy graphs.
+
+pub use crate::graph::{
+    back, build, empty, forth, graph_pretty_printer, graph_size, stop, unroll,
+    Graph, Gs, LazyGraph, Ls,
+};
+
+// Cleaners.
+
+pub use crate::graph::{
+    cl_bad_conf, cl_empty, cl_empty_and_bad, cl_min_backs, cl_min_size,
+};
+
+// Statistics.
+
+pub use crate::statistics::{
+    lazy_graph_size, length_unroll, length_unroll_checked, size_unroll,
+    size_unroll_checked, unroll_checked,
+};
+
+// Counter systems.
+
+pub use crate::counters::{
+    ω, CountersScWorld, CountersWorld, MatrixSystem, StrictNW, NW, NWC,
+};
+pub use crate::counters_dsl::DslSystem;
+pub use crate::{counter_system, matrix_counter_system};
+
+// Verification.
+
+pub use crate::verification::{
+    verify, verify_starts, Outcome, VerificationResult,
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::testing::mock_world;
+
+    counter_system! {
+        Toggle(a, b);
+        Start(ω, 0);
+        Unsafe(b >= 2);
+        Rules{
+            a >= 1 && b == 0 => a - 1, b + 1;
+            b >= 1 => a + 1, b - 1;
+        }
+    }
+
+    #[test]
+    fn test_prelude() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let l1 = cl_min_size(&cl_empty_and_bad(|c: &isize| *c >= 3, &l));
+        assert_eq!(length_unroll(&l1), unroll(&l1).len());
+
+        let (r, mg) = verify(Toggle, 3, 10);
+        assert_eq!(r.outcome, Outcome::Proved);
+        let g = mg.unwrap();
+        let s = CountersScWorld::new(Toggle, 3, 10);
+        assert!(size_unroll(&lazy_mrsc(&s, Toggle.start())).0 > 0);
+        assert!(graph_size(&g) > 0);
+    }
+}
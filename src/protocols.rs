@@ -32,16 +32,11 @@ counter_system! {
     Rules {
         i >= 1 => i - 1, m + o, s + 1, 0;
         o >= 1 => i + o + s + m - 1, 0, 0, 1;
-        // wI
-        i >= 1 => i + o + s + m - 1, 0, 0, 1;
-        // wS
-        s >= 1 => i + o + s + m - 1, 0, 0, 1;
-        // se
-        s >= 1 => i + 1, o, s - 1, m;
-        // wbm
-        m >= 1 => i + 1, o, s, m - 1;
-        // wbo
-        o >= 1 => i + 1, o - 1, s, m;
+        wI: i >= 1 => i + o + s + m - 1, 0, 0, 1;
+        wS: s >= 1 => i + o + s + m - 1, 0, 0, 1;
+        se: s >= 1 => i + 1, o, s - 1, m;
+        wbm: m >= 1 => i + 1, o, s, m - 1;
+        wbo: o >= 1 => i + 1, o - 1, s, m;
     }
 }
 
@@ -50,23 +45,17 @@ counter_system! {
     Start(1, 0, 0, ω, 0, 0);
     Unsafe(x3 >= 1 && x4 >= 1);
     Rules {
-        // r1
-        x2 >= 1 && x4 == 0 && x7 >= 1 =>
+        r1: x2 >= 1 && x4 == 0 && x7 >= 1 =>
             x2 - 1, x3 + 1, 0, x5, x6, x7;
-        // r2
-        x2 >= 1 && x6 >= 1 =>
+        r2: x2 >= 1 && x6 >= 1 =>
             x2, x3, x4 + 1, x5, x6 - 1, x7;
-        // r3
-        x3 >= 1 =>
+        r3: x3 >= 1 =>
             x2 + 1, x3 - 1, x4, x5 + 1, x6, x7;
-        // r4
-        x4 >= 1 =>
+        r4: x4 >= 1 =>
             x2, x3, x4 - 1, x5 + 1, x6, x7;
-        // r5
-        x5 >= 1 =>
+        r5: x5 >= 1 =>
             x2, x3, x4, x5 - 1, x6 + 1, x7;
-        // r6
-        x5 >= 1 =>
+        r6: x5 >= 1 =>
             x2, x3, x4, x5 - 1, x6, x7 + 1;
     }
 }
@@ -88,14 +77,10 @@ counter_system! {
     Start(ω, 0, 0, 0, 0);
     Unsafe(m >= 1 && (e + s + o) >= 1 || m >= 2 || e >= 2);
     Rules {
-        // rm
-        i >= 1 => i - 1, 0, s + e + 1, 0, o + m;
-        // wh2
-        e >= 1 => i, m + 1, s, e - 1, o;
-        // wh3
-        s + o >= 1 => i + m + s + e + o - 1, 0, 0, 1, 0;
-        // wm
-        i >= 1 => i + m + s + e + o - 1, 0, 0, 1, 0;
+        rm: i >= 1 => i - 1, 0, s + e + 1, 0, o + m;
+        wh2: e >= 1 => i, m + 1, s, e - 1, o;
+        wh3: s + o >= 1 => i + m + s + e + o - 1, 0, 0, 1, 0;
+        wm: i >= 1 => i + m + s + e + o - 1, 0, 0, 1, 0;
     }
 }
 
@@ -104,32 +89,23 @@ counter_system! {
     Start(ω, 0, 0, 0);
     Unsafe(d >= 1 && s >= 1 || d >= 2);
     Rules {
-        // r2
-        i >= 1 && e == 0 && d == 0 && s == 0 =>
+        r2: i >= 1 && e == 0 && d == 0 && s == 0 =>
             i - 1, 1, 0, 0;
-        // r3
-        i >= 1 && d >= 1 =>
+        r3: i >= 1 && d >= 1 =>
             i - 1, e, d - 1, s + 2;
-        // r4
-        i >= 1 && s + e >= 1 =>
+        r4: i >= 1 && s + e >= 1 =>
             i - 1, 0, d, s + e + 1;
-        // r6
-        e >= 1 =>
+        r6: e >= 1 =>
             i, e - 1, d + 1, s;
-        // r7
-        s >= 1 =>
+        r7: s >= 1 =>
             i + s - 1, e, d + 1, 0;
-        // r8
-        i >= 1 =>
+        r8: i >= 1 =>
             i + e + d + s - 1, 0, 1, 0;
-        // r9
-        d >= 1 =>
+        r9: d >= 1 =>
             i + 1, e, d - 1, s;
-        // r10
-        s >= 1 =>
+        r10: s >= 1 =>
             i + 1, e, d, s - 1;
-        // r11
-        e >= 1 =>
+        r11: e >= 1 =>
             i + 1, e - 1, d, s;
     }
 }
@@ -139,12 +115,9 @@ counter_system! {
     Start(ω, 0, 0, 0);
     Unsafe(e >= 1 && u + n >= 1 || e >= 2);
     Rules {
-        // rm
-        i >= 1 => i - 1, n + e, u + 1, 0;
-        // wm
-        i >= 1 => i + n + u + e - 1, 0, 0, 1;
-        // wh1
-        n + u >= 1 => i + n + u - 1, 0, 0, e + 1;
+        rm: i >= 1 => i - 1, n + e, u + 1, 0;
+        wm: i >= 1 => i + n + u + e - 1, 0, 0, 1;
+        wh1: n + u >= 1 => i + n + u - 1, 0, 0, e + 1;
     }
 }
 
@@ -153,23 +126,17 @@ counter_system! {
     Start(ω, 0, 0, 0);
     Unsafe(d >= 1 && s + e >= 1 || e >= 2 || d >= 2);
     Rules {
-        // rm1
-        i >= 1 && d == 0 && s == 0 && e == 0 =>
+        rm1: i >= 1 && d == 0 && s == 0 && e == 0 =>
             i - 1, 1, 0, 0;
-        // rm2
-        i >= 1 && d >= 1 =>
+        rm2: i >= 1 && d >= 1 =>
             i - 1, e, s + 2, d - 1;
-        // rm3
-        i >= 1 && s + e >= 1 =>
+        rm3: i >= 1 && s + e >= 1 =>
             i - 1, 0, s + e + 1, d;
-        // wh2
-        e >= 1 =>
+        wh2: e >= 1 =>
             i, e - 1, s, d + 1;
-        // wh3
-        s == 1 =>
+        wh3: s == 1 =>
             i, e + 1, 0, d;
-        // wm
-        i >= 1 =>
+        wm: i >= 1 =>
             i + e + d + s - 1, 0, 0, 1;
     }
 }
@@ -203,35 +170,25 @@ counter_system! {
         (p_r >= 1 && p_w >= 1) ||
         (p_w >= 2));
     Rules {
-        // r2
-        i >= 1 && p_w == 0 =>
+        r2: i >= 1 && p_w == 0 =>
             i - 1, 0, 0, 0, p_r + 1, p_w, p_emr + e_m, p_emw, p_su + s_u + e_u;
-        // r3
-        p_emr >= 1 =>
+        r3: p_emr >= 1 =>
             i, s_u + p_r + 1, e_u, e_m, 0, p_w, p_emr - 1, p_emw, p_su;
-        // r4
-        p_su >= 1 =>
+        r4: p_su >= 1 =>
             i, s_u + p_r + p_su, e_u, e_m, 0, p_w, p_emr, p_emw, 0;
-        // r5
-        p_r >= 2 && p_su == 0 && p_emr == 0 =>
+        r5: p_r >= 2 && p_su == 0 && p_emr == 0 =>
             i, s_u + p_r, e_u, e_m, 0, p_w, 0, p_emw, 0;
-        // r6
-        p_r == 1 && p_su == 0 && p_emr == 0 =>
+        r6: p_r == 1 && p_su == 0 && p_emr == 0 =>
             i, s_u, e_u + 1, e_m, 0, p_w, 0, p_emw, 0;
-        // wm1
-        i >= 1 && p_w == 0 =>
+        wm1: i >= 1 && p_w == 0 =>
             i + e_u + s_u + p_su + p_r + p_emr - 1, 0, 0, 0, 0, 1, 0, p_emw + e_m, 0;
-        // wm2
-        p_emw >= 1 =>
+        wm2: p_emw >= 1 =>
             i + 1, s_u, e_u, e_m + p_w, p_r, 0, p_emr, p_emw - 1, p_su;
-        // wm3
-        p_emw == 0 =>
+        wm3: p_emw == 0 =>
             i, s_u, e_u, e_m + p_w, p_r, 0, p_emr, 0, p_su;
-        // wh2
-        e_u >= 1 =>
+        wh2: e_u >= 1 =>
             i, s_u, e_u - 1, e_m + 1, p_r, p_w, p_emr, p_emw, p_su;
-        // wh2
-        s_u >= 1 =>
+        wh2: s_u >= 1 =>
             i + s_u - 1, 0, e_u, e_m + 1, p_r, p_w, p_emr, p_emw, p_su;
     }
 }
@@ -245,29 +202,21 @@ counter_system! {
            e >= 2);
 
     Rules {
-        // (1) rm1
-        i >= 1 && d == 0 && sc == 0 && sd == 0 && e == 0 =>
+        rm1: i >= 1 && d == 0 && sc == 0 && sd == 0 && e == 0 =>
             i - 1, 0, 0, 0, 1;
-        // (2) rm2
-        i >= 1 && d + sc + e + sd >= 1 =>
+        rm2: i >= 1 && d + sc + e + sd >= 1 =>
             i - 1, sc + e + 1, sd + d, 0, 0;
-        // (3) wm1
-        i >= 1 && d == 0 && sc == 0 && sd == 0 && e == 0 =>
+        wm1: i >= 1 && d == 0 && sc == 0 && sd == 0 && e == 0 =>
             i - 1, 0, 0, 1, 0;
-        // (4) wm2
-        i >= 1 && d + sc + e + sd >= 1 =>
+        wm2: i >= 1 && d + sc + e + sd >= 1 =>
             i - 1, sc + e + 1 + (sd + d), sd, 0, 0;
-        // (5) wh1
-        d >= 1 =>
+        wh1: d >= 1 =>
             i + 1, sc, sd, d - 1, e;
-        // (6) wh2
-        sc >= 1 =>
+        wh2: sc >= 1 =>
             i + 1, sc - 1, sd, d, e;
-        // (7) wh3
-        sd >= 1 =>
+        wh3: sd >= 1 =>
             i + 1, sc, sd - 1, d, e;
-        // (8) wh4
-        e >= 1 =>
+        wh4: e >= 1 =>
             i + 1, sc, sd, d, e - 1;
     }
 }
@@ -278,27 +227,17 @@ counter_system! {
 mod tests {
     use super::*;
 
-    use crate::big_step_sc::*;
+    use crate::abstract_interpretation::*;
     use crate::graph::*;
-    use crate::statistics::*;
+    use crate::verification::*;
 
     fn run_min_sc<CW: CountersWorld + Debug>(cw: CW, m: isize, d: usize) {
-        let name = format!("{:?}", cw);
-        print!("\n{} ", name);
-        let s = CountersScWorld::new(cw, m, d);
-        let l = lazy_mrsc(&s, CW::start());
-        let sl = cl_empty_and_bad(CW::is_unsafe, &l);
-
-        let (len_usl, size_usl) = size_unroll(&sl);
-        println!("({}, {})", len_usl, size_usl);
-
-        let ml = cl_min_size(&sl);
-        let gs = unroll(&ml);
-        if gs.len() == 0 {
-            println!(": No solution")
-        } else {
-            let mg = gs[0].clone();
-            println!("{}", graph_pretty_printer(&*mg));
+        let (r, mg) = verify(cw, m, d);
+        print!("\n{} ", r.protocol);
+        println!("({}, {})", r.graphs, r.nodes);
+        match mg {
+            None => println!(": No solution"),
+            Some(mg) => println!("{}", graph_pretty_printer(&*mg)),
         }
     }
 
@@ -318,4 +257,57 @@ mod tests {
         // run_min_sc(Futurebus, 3, 5);
         run_min_sc(Xerox, 3, 5);
     }
+
+    #[test]
+    fn test_rule_names() {
+        assert_eq!(MOSI.rule_name(0), "0");
+        assert_eq!(MOSI.rule_name(2), "wI");
+        assert_eq!(MOESI.rule_name(1), "wh2");
+        assert_eq!(Futurebus.rule_name(7), "wm3");
+        assert_eq!(Xerox.rule_name(7), "wh4");
+        assert_eq!(DataRace.rule_name(0), "0");
+    }
+
+    // The embedding whistle needs no bounds.
+
+    fn proved_by_embedding<CW: CountersWorld>(cw: CW) -> bool {
+        let s = CountersScWorld::new(cw, 0, 0).with_whistle(EmbeddingWhistle);
+        let l = crate::big_step_sc::lazy_mrsc(&s, s.world().start());
+        let sl = cl_empty_and_bad(|c: &NWC| s.world().is_unsafe(c), &l);
+        crate::statistics::length_unroll(&sl) > 0
+    }
+
+    #[test]
+    fn test_embedding_whistle() {
+        assert!(proved_by_embedding(Synapse));
+        assert!(proved_by_embedding(MSI));
+        assert!(proved_by_embedding(DataRace));
+    }
+
+    // Multi-result supercompilation vs. the abstract fixpoint.
+
+    fn compare_ai<CW: CountersWorld + Debug>(cw: CW, m: isize, d: usize) {
+        let ai = abstract_fixpoint(&cw, m, 100_000);
+        let (r, _) = verify(cw, m, d);
+        println!(
+            "{} {:?} {:?} {}",
+            r.protocol, r.outcome, ai.outcome, ai.nodes
+        );
+        assert_eq!(r.outcome == Outcome::Proved, ai.outcome == AiOutcome::Safe);
+    }
+
+    #[test]
+    fn compare_protocols_with_abstract_fixpoint() {
+        compare_ai(Synapse, 3, 10);
+        compare_ai(MSI, 3, 10);
+        compare_ai(MOSI, 3, 10);
+        compare_ai(ReaderWriter, 3, 5);
+        compare_ai(MESI, 3, 10);
+        compare_ai(MOESI, 3, 5);
+        compare_ai(Illinois, 3, 5);
+        compare_ai(Berkley, 3, 5);
+        compare_ai(Firefly, 3, 5);
+        compare_ai(DataRace, 3, 10);
+        compare_ai(Xerox, 3, 5);
+    }
 }
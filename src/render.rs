@@ -0,0 +1,934 @@
+//
+// Rendering graphs
+//
+// `graph_to_dot(g)` renders a residual graph in the Graphviz DOT format.
+// The nodes are numbered in preorder, `Back` nodes being drawn
+// as dashed ellipses and `Forth` nodes as boxes.
+//
+// `lazy_graph_to_dot(l)` renders a lazy graph, showing the alternatives
+// of the `Build` nodes explicitly. Each alternative is drawn as a point
+// connected to the `Build` node by a dashed "or"-edge (labelled with
+// the number of the alternative), the point being connected to
+// the subtrees of the alternative by solid "and"-edges. `Stop` nodes are
+// drawn as dashed ellipses, and `Empty` nodes as "∅".
+//
+// `graph_diff_to_dot(g1, g2)` renders two graphs (say, the proofs
+// before and after a change in a model) as a single picture.
+// The graphs are matched from the roots: two nodes match if they are
+// of the same kind and contain equal configurations, and the subtrees
+// of matching nodes are aligned as a longest common subsequence.
+// The matching nodes are drawn grey, the nodes of `g2` only green
+// (added) and the nodes of `g1` only red (removed).
+// The same differences are returned by `graph_diff(g1, g2)` as a list
+// of removed and added subtrees, and rendered as text, in the format
+// of `graph_pretty_printer`, by `graph_diff_to_text(g1, g2)`.
+//
+// `graph_to_mermaid(g)` produces a Mermaid flowchart (`graph TD`), which
+// can be embedded in Markdown. `Forth` nodes are drawn as rectangles,
+// `Back` nodes as dashed rounded boxes (class `back`), and the edges
+// leading to `Back` nodes are dotted.
+//
+// `layered_layout(g)` places the nodes of a graph in layers by depth:
+// the leaves get consecutive columns in the order of a depth-first
+// traversal, and each inner node is centered over its children.
+// `graph_to_svg(g)` draws the layout as SVG, so that pictures can be
+// produced without Graphviz.
+//
+// `graph_to_graphml(g)` and `lazy_graph_to_graphml(l)` produce GraphML,
+// which can be opened by graph tools (such as yEd or Gephi). Each node
+// has the attribute `conf` (the configuration as text) and `kind`
+// ("forth" or "back" for graphs; "build", "stop", "empty" or "alt"
+// for lazy graphs, an "alt" node representing an alternative, as
+// in `lazy_graph_to_dot`). For graphs, there is also the boolean
+// attribute `back`.
+//
+
+use crate::graph::{Graph, LazyGraph};
+
+use std::fmt::Display;
+use std::rc::Rc;
+
+// Configurations are written as quoted DOT strings.
+
+pub(crate) fn escape_label(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            _ => r.push(ch),
+        }
+    }
+    r
+}
+
+fn graph_to_dot_loop<C: Display>(
+    g: &Graph<C>,
+    k: &mut usize,
+    lines: &mut Vec<String>,
+) -> usize {
+    let id = *k;
+    *k += 1;
+    match g {
+        Graph::Back(c) => lines.push(format!(
+            "  n{} [label=\"{}\", shape=ellipse, style=dashed];",
+            id,
+            escape_label(&c.to_string())
+        )),
+        Graph::Forth(c, gs) => {
+            lines.push(format!(
+                "  n{} [label=\"{}\", shape=box];",
+                id,
+                escape_label(&c.to_string())
+            ));
+            for g1 in gs {
+                let id1 = graph_to_dot_loop(g1, k, lines);
+                lines.push(format!("  n{} -> n{};", id, id1));
+            }
+        }
+    }
+    id
+}
+
+pub fn graph_to_dot<C: Display>(g: &Graph<C>) -> String {
+    let mut lines = vec!["digraph G {".to_string()];
+    graph_to_dot_loop(g, &mut 0, &mut lines);
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+fn lazy_graph_to_dot_loop<C: Display>(
+    l: &LazyGraph<C>,
+    k: &mut usize,
+    lines: &mut Vec<String>,
+) -> usize {
+    let id = *k;
+    *k += 1;
+    match l {
+        LazyGraph::Empty() => {
+            lines.push(format!("  n{} [label=\"∅\", shape=plaintext];", id))
+        }
+        LazyGraph::Stop(c) => lines.push(format!(
+            "  n{} [label=\"{}\", shape=ellipse, style=dashed];",
+            id,
+            escape_label(&c.to_string())
+        )),
+        LazyGraph::Build(c, lss) => {
+            lines.push(format!(
+                "  n{} [label=\"{}\", shape=box];",
+                id,
+                escape_label(&c.to_string())
+            ));
+            for (i, ls) in lss.iter().enumerate() {
+                let alt = *k;
+                *k += 1;
+                lines.push(format!("  n{} [shape=point];", alt));
+                lines.push(format!(
+                    "  n{} -> n{} [style=dashed, arrowhead=none, label=\"{}\"];",
+                    id, alt, i
+                ));
+                for l1 in ls {
+                    let id1 = lazy_graph_to_dot_loop(l1, k, lines);
+                    lines.push(format!("  n{} -> n{};", alt, id1));
+                }
+            }
+        }
+    }
+    id
+}
+
+pub fn lazy_graph_to_dot<C: Display>(l: &LazyGraph<C>) -> String {
+    let mut lines = vec!["digraph G {".to_string()];
+    lazy_graph_to_dot_loop(l, &mut 0, &mut lines);
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DiffStatus {
+    Same,
+    Added,
+    Removed,
+}
+
+impl DiffStatus {
+    fn color(self) -> &'static str {
+        match self {
+            DiffStatus::Same => "grey",
+            DiffStatus::Added => "green",
+            DiffStatus::Removed => "red",
+        }
+    }
+}
+
+fn same_node<C: PartialEq>(g1: &Graph<C>, g2: &Graph<C>) -> bool {
+    match (g1, g2) {
+        (Graph::Back(c1), Graph::Back(c2)) => c1 == c2,
+        (Graph::Forth(c1, _), Graph::Forth(c2, _)) => c1 == c2,
+        _ => false,
+    }
+}
+
+fn diff_node_line<C: Display>(
+    g: &Graph<C>,
+    id: usize,
+    st: DiffStatus,
+) -> String {
+    let (c, shape) = match g {
+        Graph::Back(c) => (c, "shape=ellipse, style=dashed"),
+        Graph::Forth(c, _) => (c, "shape=box"),
+    };
+    format!(
+        "  n{} [label=\"{}\", {}, color={}, fontcolor={}];",
+        id,
+        escape_label(&c.to_string()),
+        shape,
+        st.color(),
+        st.color()
+    )
+}
+
+fn diff_edge_line(id: usize, id1: usize, st: DiffStatus) -> String {
+    format!("  n{} -> n{} [color={}];", id, id1, st.color())
+}
+
+// Renders the whole of `g` with the same status.
+
+fn diff_subtree<C: Display>(
+    g: &Graph<C>,
+    st: DiffStatus,
+    k: &mut usize,
+    lines: &mut Vec<String>,
+) -> usize {
+    let id = *k;
+    *k += 1;
+    lines.push(diff_node_line(g, id, st));
+    if let Graph::Forth(_, gs) = g {
+        for g1 in gs {
+            let id1 = diff_subtree(g1, st, k, lines);
+            lines.push(diff_edge_line(id, id1, st));
+        }
+    }
+    id
+}
+
+// Aligns `gs1` and `gs2` as a longest common subsequence of matching
+// nodes. The result is a list of pairs of positions, `None` standing
+// for a gap.
+
+fn align<C: PartialEq>(
+    gs1: &[Rc<Graph<C>>],
+    gs2: &[Rc<Graph<C>>],
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let (n1, n2) = (gs1.len(), gs2.len());
+    let mut t = vec![vec![0usize; n2 + 1]; n1 + 1];
+    for i in (0..n1).rev() {
+        for j in (0..n2).rev() {
+            t[i][j] = if same_node(&gs1[i], &gs2[j]) {
+                t[i + 1][j + 1] + 1
+            } else {
+                t[i + 1][j].max(t[i][j + 1])
+            };
+        }
+    }
+    let mut r = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n1 || j < n2 {
+        if i < n1 && j < n2 && same_node(&gs1[i], &gs2[j]) {
+            r.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        } else if j == n2 || (i < n1 && t[i + 1][j] >= t[i][j + 1]) {
+            r.push((Some(i), None));
+            i += 1;
+        } else {
+            r.push((None, Some(j)));
+            j += 1;
+        }
+    }
+    r
+}
+
+// `g1` and `g2` are supposed to match.
+
+fn graph_diff_loop<C: Display + PartialEq>(
+    g1: &Graph<C>,
+    g2: &Graph<C>,
+    k: &mut usize,
+    lines: &mut Vec<String>,
+) -> usize {
+    let id = *k;
+    *k += 1;
+    lines.push(diff_node_line(g2, id, DiffStatus::Same));
+    if let (Graph::Forth(_, gs1), Graph::Forth(_, gs2)) = (g1, g2) {
+        for pair in align(gs1, gs2) {
+            let (id1, st) = match pair {
+                (Some(i), Some(j)) => (
+                    graph_diff_loop(&gs1[i], &gs2[j], k, lines),
+                    DiffStatus::Same,
+                ),
+                (Some(i), None) => (
+                    diff_subtree(&gs1[i], DiffStatus::Removed, k, lines),
+                    DiffStatus::Removed,
+                ),
+                (None, Some(j)) => (
+                    diff_subtree(&gs2[j], DiffStatus::Added, k, lines),
+                    DiffStatus::Added,
+                ),
+                (None, None) => continue,
+            };
+            lines.push(diff_edge_line(id, id1, st));
+        }
+    }
+    id
+}
+
+pub fn graph_diff_to_dot<C: Display + PartialEq>(
+    g1: &Graph<C>,
+    g2: &Graph<C>,
+) -> String {
+    let mut lines = vec!["digraph G {".to_string()];
+    let mut k = 0;
+    if same_node(g1, g2) {
+        graph_diff_loop(g1, g2, &mut k, &mut lines);
+    } else {
+        diff_subtree(g1, DiffStatus::Removed, &mut k, &mut lines);
+        diff_subtree(g2, DiffStatus::Added, &mut k, &mut lines);
+    }
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+// `graph_diff(g1, g2)` lists the differences found by the same matching,
+// as the subtrees of `g1` that have been removed and the subtrees of `g2`
+// that have been added, in preorder. A subtree is identified by its path
+// (as in `subgraph_at`) in the graph it belongs to. The list is empty iff
+// `g1 == g2`.
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum GraphChange<C> {
+    Removed {
+        path: Vec<usize>,
+        graph: Rc<Graph<C>>,
+    },
+    Added {
+        path: Vec<usize>,
+        graph: Rc<Graph<C>>,
+    },
+}
+
+fn graph_changes_loop<C: PartialEq>(
+    g1: &Rc<Graph<C>>,
+    g2: &Rc<Graph<C>>,
+    path1: &mut Vec<usize>,
+    path2: &mut Vec<usize>,
+    changes: &mut Vec<GraphChange<C>>,
+) {
+    if let (Graph::Forth(_, gs1), Graph::Forth(_, gs2)) = (&**g1, &**g2) {
+        for pair in align(gs1, gs2) {
+            match pair {
+                (Some(i), Some(j)) => {
+                    path1.push(i);
+                    path2.push(j);
+                    graph_changes_loop(&gs1[i], &gs2[j], path1, path2, changes);
+                    path1.pop();
+                    path2.pop();
+                }
+                (Some(i), None) => {
+                    let mut path = path1.clone();
+                    path.push(i);
+                    changes.push(GraphChange::Removed {
+                        path,
+                        graph: Rc::clone(&gs1[i]),
+                    });
+                }
+                (None, Some(j)) => {
+                    let mut path = path2.clone();
+                    path.push(j);
+                    changes.push(GraphChange::Added {
+                        path,
+                        graph: Rc::clone(&gs2[j]),
+                    });
+                }
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+pub fn graph_diff<C: PartialEq>(
+    g1: &Rc<Graph<C>>,
+    g2: &Rc<Graph<C>>,
+) -> Vec<GraphChange<C>> {
+    let mut changes = Vec::new();
+    if same_node(g1, g2) {
+        graph_changes_loop(
+            g1,
+            g2,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut changes,
+        );
+    } else {
+        changes.push(GraphChange::Removed {
+            path: vec![],
+            graph: Rc::clone(g1),
+        });
+        changes.push(GraphChange::Added {
+            path: vec![],
+            graph: Rc::clone(g2),
+        });
+    }
+    changes
+}
+
+// `graph_diff_to_text(g1, g2)` renders the diff in the format of
+// `graph_pretty_printer`, each line being prefixed with "  " (common),
+// "- " (removed) or "+ " (added). Thus, removing the added lines
+// (and the prefixes) gives `graph_pretty_printer(g1)`, and removing
+// the removed lines gives `graph_pretty_printer(g2)`.
+
+fn diff_text_subtree<C: Display>(
+    g: &Graph<C>,
+    prefix: &str,
+    indent: usize,
+    lines: &mut Vec<String>,
+) {
+    let ind = " ".repeat(indent);
+    match g {
+        Graph::Back(c) => lines.push(format!("{}{}|__{}*", prefix, ind, c)),
+        Graph::Forth(c, gs) => {
+            lines.push(format!("{}{}|__{}", prefix, ind, c));
+            for g1 in gs {
+                lines.push(format!("{}  {}|", prefix, ind));
+                diff_text_subtree(g1, prefix, indent + 2, lines);
+            }
+        }
+    }
+}
+
+fn graph_diff_text_loop<C: Display + PartialEq>(
+    g1: &Graph<C>,
+    g2: &Graph<C>,
+    indent: usize,
+    lines: &mut Vec<String>,
+) {
+    let ind = " ".repeat(indent);
+    match (g1, g2) {
+        (Graph::Forth(c, gs1), Graph::Forth(_, gs2)) => {
+            lines.push(format!("  {}|__{}", ind, c));
+            for pair in align(gs1, gs2) {
+                let (prefix, g) = match pair {
+                    (Some(i), Some(j)) => {
+                        lines.push(format!("    {}|", ind));
+                        graph_diff_text_loop(
+                            &gs1[i],
+                            &gs2[j],
+                            indent + 2,
+                            lines,
+                        );
+                        continue;
+                    }
+                    (Some(i), None) => ("- ", &gs1[i]),
+                    (None, Some(j)) => ("+ ", &gs2[j]),
+                    (None, None) => continue,
+                };
+                lines.push(format!("{}  {}|", prefix, ind));
+                diff_text_subtree(g, prefix, indent + 2, lines);
+            }
+        }
+        _ => diff_text_subtree(g2, "  ", indent, lines),
+    }
+}
+
+pub fn graph_diff_to_text<C: Display + PartialEq>(
+    g1: &Graph<C>,
+    g2: &Graph<C>,
+) -> String {
+    let mut lines = Vec::new();
+    if same_node(g1, g2) {
+        graph_diff_text_loop(g1, g2, 0, &mut lines);
+    } else {
+        diff_text_subtree(g1, "- ", 0, &mut lines);
+        diff_text_subtree(g2, "+ ", 0, &mut lines);
+    }
+    lines.join("\n")
+}
+
+//
+// Mermaid
+//
+
+// Mermaid labels are quoted strings, in which `"` is written
+// as an entity.
+
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;").replace('\n', " ")
+}
+
+fn graph_to_mermaid_loop<C: Display>(
+    g: &Graph<C>,
+    k: &mut usize,
+    lines: &mut Vec<String>,
+) -> usize {
+    let id = *k;
+    *k += 1;
+    match g {
+        Graph::Back(c) => lines.push(format!(
+            "    n{}(\"{}\"):::back",
+            id,
+            escape_mermaid(&c.to_string())
+        )),
+        Graph::Forth(c, gs) => {
+            lines.push(format!(
+                "    n{}[\"{}\"]",
+                id,
+                escape_mermaid(&c.to_string())
+            ));
+            for g1 in gs {
+                let id1 = graph_to_mermaid_loop(g1, k, lines);
+                let arrow = match **g1 {
+                    Graph::Back(_) => "-.->",
+                    Graph::Forth(_, _) => "-->",
+                };
+                lines.push(format!("    n{} {} n{}", id, arrow, id1));
+            }
+        }
+    }
+    id
+}
+
+pub fn graph_to_mermaid<C: Display>(g: &Graph<C>) -> String {
+    let mut lines = vec!["graph TD".to_string()];
+    graph_to_mermaid_loop(g, &mut 0, &mut lines);
+    lines.push("    classDef back stroke-dasharray: 5 5".to_string());
+    lines.join("\n") + "\n"
+}
+
+//
+// Layered layout and SVG
+//
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct LayoutNode {
+    pub label: String,
+    pub back: bool,
+    // The depth of the node and its (possibly fractional) column.
+    pub depth: usize,
+    pub column: f64,
+}
+
+// The nodes are numbered in preorder. An edge is a pair of node numbers.
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Layout {
+    pub nodes: Vec<LayoutNode>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+fn layered_layout_loop<C: Display>(
+    g: &Graph<C>,
+    depth: usize,
+    next_column: &mut f64,
+    layout: &mut Layout,
+) -> usize {
+    let id = layout.nodes.len();
+    let (c, back, gs): (&C, bool, &[Rc<Graph<C>>]) = match g {
+        Graph::Back(c) => (c, true, &[]),
+        Graph::Forth(c, gs) => (c, false, gs),
+    };
+    layout.nodes.push(LayoutNode {
+        label: c.to_string(),
+        back,
+        depth,
+        column: 0.0,
+    });
+    let mut ids = Vec::with_capacity(gs.len());
+    for g1 in gs {
+        let id1 = layered_layout_loop(g1, depth + 1, next_column, layout);
+        layout.edges.push((id, id1));
+        ids.push(id1);
+    }
+    layout.nodes[id].column = match (ids.first(), ids.last()) {
+        (Some(&i), Some(&j)) => {
+            (layout.nodes[i].column + layout.nodes[j].column) / 2.0
+        }
+        _ => {
+            let column = *next_column;
+            *next_column += 1.0;
+            column
+        }
+    };
+    id
+}
+
+pub fn layered_layout<C: Display>(g: &Graph<C>) -> Layout {
+    let mut layout = Layout::default();
+    layered_layout_loop(g, 0, &mut 0.0, &mut layout);
+    layout
+}
+
+const SVG_CHAR_WIDTH: f64 = 8.0;
+const SVG_NODE_HEIGHT: f64 = 24.0;
+const SVG_LAYER_HEIGHT: f64 = 64.0;
+const SVG_MARGIN: f64 = 16.0;
+
+pub fn graph_to_svg<C: Display>(g: &Graph<C>) -> String {
+    let layout = layered_layout(g);
+    let max_chars = layout
+        .nodes
+        .iter()
+        .map(|n| n.label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let column_width = max_chars as f64 * SVG_CHAR_WIDTH + 2.0 * SVG_MARGIN;
+    let columns = layout
+        .nodes
+        .iter()
+        .map(|n| n.column as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let depth = layout.nodes.iter().map(|n| n.depth).max().unwrap_or(0);
+    let x = |n: &LayoutNode| (n.column + 0.5) * column_width;
+    let y = |n: &LayoutNode| {
+        SVG_MARGIN + SVG_NODE_HEIGHT / 2.0 + n.depth as f64 * SVG_LAYER_HEIGHT
+    };
+    let mut lines = vec![format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"monospace\" font-size=\"12\">",
+        columns as f64 * column_width,
+        2.0 * SVG_MARGIN + SVG_NODE_HEIGHT + depth as f64 * SVG_LAYER_HEIGHT
+    )];
+    for &(i, j) in &layout.edges {
+        let (n1, n2) = (&layout.nodes[i], &layout.nodes[j]);
+        lines.push(format!(
+            "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\"/>",
+            x(n1),
+            y(n1) + SVG_NODE_HEIGHT / 2.0,
+            x(n2),
+            y(n2) - SVG_NODE_HEIGHT / 2.0
+        ));
+    }
+    for n in &layout.nodes {
+        let w = n.label.chars().count() as f64 * SVG_CHAR_WIDTH + SVG_MARGIN;
+        if n.back {
+            lines.push(format!(
+                "  <ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" \
+                 fill=\"white\" stroke=\"black\" stroke-dasharray=\"4 2\"/>",
+                x(n),
+                y(n),
+                w / 2.0,
+                SVG_NODE_HEIGHT / 2.0
+            ));
+        } else {
+            lines.push(format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+                 fill=\"white\" stroke=\"black\"/>",
+                x(n) - w / 2.0,
+                y(n) - SVG_NODE_HEIGHT / 2.0,
+                w,
+                SVG_NODE_HEIGHT
+            ));
+        }
+        lines.push(format!(
+            "  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" \
+             dominant-baseline=\"central\">{}</text>",
+            x(n),
+            y(n),
+            escape_xml(&n.label)
+        ));
+    }
+    lines.push("</svg>".to_string());
+    lines.join("\n") + "\n"
+}
+
+//
+// GraphML
+//
+
+pub(crate) fn escape_xml(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => r.push_str("&amp;"),
+            '<' => r.push_str("&lt;"),
+            '>' => r.push_str("&gt;"),
+            '"' => r.push_str("&quot;"),
+            '\'' => r.push_str("&apos;"),
+            _ => r.push(ch),
+        }
+    }
+    r
+}
+
+const GRAPHML_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n\
+\x20 <key id=\"conf\" for=\"node\" attr.name=\"conf\" attr.type=\"string\"/>\n\
+\x20 <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>\n\
+\x20 <key id=\"back\" for=\"node\" attr.name=\"back\" attr.type=\"boolean\"/>\n\
+\x20 <graph id=\"G\" edgedefault=\"directed\">";
+
+const GRAPHML_FOOTER: &str = "  </graph>\n</graphml>";
+
+fn graphml_node(
+    id: usize,
+    conf: &str,
+    kind: &str,
+    back: Option<bool>,
+) -> String {
+    let mut r = format!("    <node id=\"n{}\">", id);
+    r.push_str(&format!("<data key=\"conf\">{}</data>", escape_xml(conf)));
+    r.push_str(&format!("<data key=\"kind\">{}</data>", kind));
+    if let Some(b) = back {
+        r.push_str(&format!("<data key=\"back\">{}</data>", b));
+    }
+    r.push_str("</node>");
+    r
+}
+
+fn graphml_edge(id: usize, id1: usize) -> String {
+    format!("    <edge source=\"n{}\" target=\"n{}\"/>", id, id1)
+}
+
+fn graph_to_graphml_loop<C: Display>(
+    g: &Graph<C>,
+    k: &mut usize,
+    lines: &mut Vec<String>,
+) -> usize {
+    let id = *k;
+    *k += 1;
+    match g {
+        Graph::Back(c) => {
+            lines.push(graphml_node(id, &c.to_string(), "back", Some(true)))
+        }
+        Graph::Forth(c, gs) => {
+            lines.push(graphml_node(id, &c.to_string(), "forth", Some(false)));
+            for g1 in gs {
+                let id1 = graph_to_graphml_loop(g1, k, lines);
+                lines.push(graphml_edge(id, id1));
+            }
+        }
+    }
+    id
+}
+
+pub fn graph_to_graphml<C: Display>(g: &Graph<C>) -> String {
+    let mut lines = vec![GRAPHML_HEADER.to_string()];
+    graph_to_graphml_loop(g, &mut 0, &mut lines);
+    lines.push(GRAPHML_FOOTER.to_string());
+    lines.join("\n") + "\n"
+}
+
+fn lazy_graph_to_graphml_loop<C: Display>(
+    l: &LazyGraph<C>,
+    k: &mut usize,
+    lines: &mut Vec<String>,
+) -> usize {
+    let id = *k;
+    *k += 1;
+    match l {
+        LazyGraph::Empty() => lines.push(graphml_node(id, "", "empty", None)),
+        LazyGraph::Stop(c) => {
+            lines.push(graphml_node(id, &c.to_string(), "stop", None))
+        }
+        LazyGraph::Build(c, lss) => {
+            lines.push(graphml_node(id, &c.to_string(), "build", None));
+            for (i, ls) in lss.iter().enumerate() {
+                let alt = *k;
+                *k += 1;
+                lines.push(graphml_node(alt, &i.to_string(), "alt", None));
+                lines.push(graphml_edge(id, alt));
+                for l1 in ls {
+                    let id1 = lazy_graph_to_graphml_loop(l1, k, lines);
+                    lines.push(graphml_edge(alt, id1));
+                }
+            }
+        }
+    }
+    id
+}
+
+pub fn lazy_graph_to_graphml<C: Display>(l: &LazyGraph<C>) -> String {
+    let mut lines = vec![GRAPHML_HEADER.to_string()];
+    lazy_graph_to_graphml_loop(l, &mut 0, &mut lines);
+    lines.push(GRAPHML_FOOTER.to_string());
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::graph::{back, build, empty, forth, graph_pretty_printer, stop};
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_graph_to_dot() {
+        let g = forth(&"x", &[back(&"x"), forth(&"\"y\"", &[])]);
+        assert_eq!(
+            graph_to_dot(&g),
+            "digraph G {\n\
+             \x20 n0 [label=\"x\", shape=box];\n\
+             \x20 n1 [label=\"x\", shape=ellipse, style=dashed];\n\
+             \x20 n0 -> n1;\n\
+             \x20 n2 [label=\"\\\"y\\\"\", shape=box];\n\
+             \x20 n0 -> n2;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_lazy_graph_to_dot() {
+        let l = build(&1, &[vec![stop(&2), empty()], vec![]]);
+        assert_eq!(
+            lazy_graph_to_dot(&l),
+            "digraph G {\n\
+             \x20 n0 [label=\"1\", shape=box];\n\
+             \x20 n1 [shape=point];\n\
+             \x20 n0 -> n1 [style=dashed, arrowhead=none, label=\"0\"];\n\
+             \x20 n2 [label=\"2\", shape=ellipse, style=dashed];\n\
+             \x20 n1 -> n2;\n\
+             \x20 n3 [label=\"∅\", shape=plaintext];\n\
+             \x20 n1 -> n3;\n\
+             \x20 n4 [shape=point];\n\
+             \x20 n0 -> n4 [style=dashed, arrowhead=none, label=\"1\"];\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_graph_diff_to_dot() {
+        let g1 = forth(&1, &[forth(&2, &[back(&1)]), back(&3)]);
+        let g2 = forth(&1, &[forth(&4, &[]), forth(&2, &[back(&1)])]);
+        let dot = graph_diff_to_dot(&g1, &g2);
+        let grey = dot.matches("[label").count()
+            - dot.matches("color=green,").count()
+            - dot.matches("color=red,").count();
+        assert_eq!(grey, 3);
+        assert!(dot.contains("[label=\"4\", shape=box, color=green,"));
+        assert!(dot
+            .contains("[label=\"3\", shape=ellipse, style=dashed, color=red,"));
+        assert_eq!(dot.matches("->").count(), 4);
+
+        let dot = graph_diff_to_dot(&g1, &back(&1));
+        assert_eq!(dot.matches("color=red,").count(), 4);
+        assert_eq!(dot.matches("color=green,").count(), 1);
+    }
+
+    #[test]
+    fn test_graph_diff() {
+        let g1 = forth(&1, &[forth(&2, &[back(&1)]), back(&3)]);
+        let g2 = forth(&1, &[forth(&4, &[]), forth(&2, &[back(&1)])]);
+        assert_eq!(graph_diff(&g1, &g1), vec![]);
+        assert_eq!(
+            graph_diff(&g1, &g2),
+            vec![
+                GraphChange::Added {
+                    path: vec![0],
+                    graph: forth(&4, &[])
+                },
+                GraphChange::Removed {
+                    path: vec![1],
+                    graph: back(&3)
+                },
+            ]
+        );
+        assert_eq!(
+            graph_diff(&g1, &back(&1)),
+            vec![
+                GraphChange::Removed {
+                    path: vec![],
+                    graph: g1.clone()
+                },
+                GraphChange::Added {
+                    path: vec![],
+                    graph: back(&1)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_graph_diff_to_text() {
+        let g1 = forth(&1, &[forth(&2, &[back(&1)]), back(&3)]);
+        let g2 = forth(&1, &[forth(&4, &[]), forth(&2, &[back(&1)])]);
+        let text = graph_diff_to_text(&g1, &g2);
+        assert_eq!(
+            text,
+            "  |__1\n+   |\n+   |__4\n    |\n    |__2\n      |\n      |__1*\n\
+             -   |\n-   |__3*"
+        );
+        let keep = |drop: &str| {
+            text.lines()
+                .filter(|line| !line.starts_with(drop))
+                .map(|line| &line[2..])
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        assert_eq!(keep("+"), graph_pretty_printer(&g1));
+        assert_eq!(keep("-"), graph_pretty_printer(&g2));
+        assert!(graph_diff_to_text(&g1, &g1)
+            .lines()
+            .all(|line| line.starts_with("  ")));
+    }
+
+    #[test]
+    fn test_graph_to_graphml() {
+        let g = forth(&"a<b", &[back(&"a<b")]);
+        let xml = graph_to_graphml(&g);
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.contains(
+            "<node id=\"n0\"><data key=\"conf\">a&lt;b</data>\
+             <data key=\"kind\">forth</data>\
+             <data key=\"back\">false</data></node>"
+        ));
+        assert!(xml.contains("<data key=\"back\">true</data>"));
+        assert!(xml.contains("<edge source=\"n0\" target=\"n1\"/>"));
+        assert!(xml.ends_with("</graphml>\n"));
+
+        let l = build(&1, &[vec![stop(&2)], vec![empty()]]);
+        let xml = lazy_graph_to_graphml(&l);
+        assert_eq!(xml.matches("<node ").count(), 5);
+        assert_eq!(xml.matches("<edge ").count(), 4);
+        assert_eq!(xml.matches(">alt<").count(), 2);
+    }
+
+    #[test]
+    fn test_graph_to_mermaid() {
+        let g = forth(&"x", &[back(&"x"), forth(&"\"y\"", &[])]);
+        assert_eq!(
+            graph_to_mermaid(&g),
+            "graph TD\n\
+             \x20   n0[\"x\"]\n\
+             \x20   n1(\"x\"):::back\n\
+             \x20   n0 -.-> n1\n\
+             \x20   n2[\"#quot;y#quot;\"]\n\
+             \x20   n0 --> n2\n\
+             \x20   classDef back stroke-dasharray: 5 5\n"
+        );
+    }
+
+    #[test]
+    fn test_layered_layout() {
+        let g = forth(&1, &[forth(&2, &[back(&1), back(&2)]), back(&3)]);
+        let layout = layered_layout(&g);
+        let cols: Vec<(usize, f64)> =
+            layout.nodes.iter().map(|n| (n.depth, n.column)).collect();
+        assert_eq!(
+            cols,
+            vec![(0, 1.25), (1, 0.5), (2, 0.0), (2, 1.0), (1, 2.0)]
+        );
+        assert_eq!(layout.edges, vec![(1, 2), (1, 3), (0, 1), (0, 4)]);
+        let svg = graph_to_svg(&g);
+        assert!(svg.starts_with("<svg "));
+        assert_eq!(svg.matches("<rect ").count(), 2);
+        assert_eq!(svg.matches("<ellipse ").count(), 3);
+        assert_eq!(svg.matches("<line ").count(), 4);
+        assert!(svg.ends_with("</svg>\n"));
+    }
+}
@@ -0,0 +1,456 @@
+//
+// Recording and replaying supercompilation runs
+//
+// `Recorder` wraps a world and records the decisions taken by it during
+// a run: the results of folding checks and of the whistle, and
+// the configurations produced by developing. The resulting `ReplayLog`
+// can be turned into a `Replayer`, a world that re-executes the run
+// by taking the decisions from the log, without consulting
+// the original world.
+//
+// A log has to be replayed by the same algorithm that has been used
+// for recording it (e.g. `lazy_mrsc`), since the decisions are
+// consumed in the order they have been taken. If the log turns out
+// to be too short, the replayer does not panic: the remaining
+// configurations are pruned, and `check` reports the error.
+//
+// `TimeTravel` is a debugger over a log recorded during a run of
+// `lazy_mrsc`. The run is split into steps (a step per node of the lazy
+// graph, in the order in which they have been produced), and one can
+// move forward and backward through the steps, inspecting, at each
+// point, the configuration, its history and the part of the lazy graph
+// produced so far.
+//
+
+use crate::big_step_sc::{sc_step, ScEvent, ScWorld};
+use crate::graph::*;
+use crate::misc::History;
+
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ReplayLog<C> {
+    // The results of folding checks and of the whistle, in this order.
+    pub decisions: Vec<bool>,
+    // The results of developing.
+    pub developments: Vec<Vec<Vec<C>>>,
+}
+
+pub struct Recorder<S: ScWorld> {
+    inner: S,
+    log: RefCell<ReplayLog<S::C>>,
+}
+
+pub fn record<S: ScWorld>(s: S) -> Recorder<S> {
+    Recorder {
+        inner: s,
+        log: RefCell::new(ReplayLog {
+            decisions: Vec::new(),
+            developments: Vec::new(),
+        }),
+    }
+}
+
+impl<S: ScWorld> Recorder<S> {
+    pub fn log(&self) -> ReplayLog<S::C> {
+        self.log.borrow().clone()
+    }
+
+    fn decide(&self, d: bool) -> bool {
+        self.log.borrow_mut().decisions.push(d);
+        d
+    }
+
+    fn developed(&self, css: Vec<Vec<S::C>>) -> Vec<Vec<S::C>> {
+        self.log.borrow_mut().developments.push(css.clone());
+        css
+    }
+}
+
+impl<S: ScWorld> ScWorld for Recorder<S> {
+    type C = S::C;
+
+    fn is_dangerous(&self, h: &History<S::C>) -> bool {
+        self.decide(self.inner.is_dangerous(h))
+    }
+
+    fn is_foldable_to(&self, c1: &S::C, c2: &S::C) -> bool {
+        self.inner.is_foldable_to(c1, c2)
+    }
+
+    fn develop(&self, c: &S::C) -> Vec<Vec<S::C>> {
+        self.developed(self.inner.develop(c))
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Vec<Vec<S::C>> {
+        self.developed(self.inner.develop_with_history(h, c))
+    }
+
+    fn develop_iter<'a>(
+        &'a self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Box<dyn Iterator<Item = Vec<S::C>> + 'a> {
+        let css = self.inner.develop_iter(h, c).collect();
+        Box::new(self.developed(css).into_iter())
+    }
+
+    fn is_foldable_to_history(&self, c: &S::C, h: &History<S::C>) -> bool {
+        self.decide(self.inner.is_foldable_to_history(c, h))
+    }
+}
+
+// The replayer has asked for more decisions (or developments)
+// than the log contains.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReplayError {
+    DecisionsExhausted,
+    DevelopmentsExhausted,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::DecisionsExhausted => {
+                write!(f, "the decisions of the replay log are exhausted")
+            }
+            ReplayError::DevelopmentsExhausted => {
+                write!(f, "the developments of the replay log are exhausted")
+            }
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+pub struct Replayer<C> {
+    log: ReplayLog<C>,
+    next_decision: Cell<usize>,
+    next_development: Cell<usize>,
+    error: Cell<Option<ReplayError>>,
+}
+
+pub fn replay<C>(log: ReplayLog<C>) -> Replayer<C> {
+    Replayer {
+        log,
+        next_decision: Cell::new(0),
+        next_development: Cell::new(0),
+        error: Cell::new(None),
+    }
+}
+
+impl<C> Replayer<C> {
+    fn decision(&self) -> Result<bool, ReplayError> {
+        let i = self.next_decision.get();
+        self.next_decision.set(i + 1);
+        self.log
+            .decisions
+            .get(i)
+            .copied()
+            .ok_or(ReplayError::DecisionsExhausted)
+    }
+
+    fn development(&self) -> Result<&Vec<Vec<C>>, ReplayError> {
+        let i = self.next_development.get();
+        self.next_development.set(i + 1);
+        self.log
+            .developments
+            .get(i)
+            .ok_or(ReplayError::DevelopmentsExhausted)
+    }
+
+    // Remembers the first error, replacing the result with `default`.
+
+    fn recover<T>(&self, r: Result<T, ReplayError>, default: T) -> T {
+        r.unwrap_or_else(|e| {
+            if self.error.get().is_none() {
+                self.error.set(Some(e));
+            }
+            default
+        })
+    }
+
+    // Whether all the recorded decisions have been replayed.
+
+    pub fn is_finished(&self) -> bool {
+        self.next_decision.get() == self.log.decisions.len()
+            && self.next_development.get() == self.log.developments.len()
+    }
+
+    // The first error encountered so far (if any).
+
+    pub fn check(&self) -> Result<(), ReplayError> {
+        match self.error.get() {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
+    }
+}
+
+impl<C: Clone> ScWorld for Replayer<C> {
+    type C = C;
+
+    // When the log is exhausted, the whistle blows.
+    fn is_dangerous(&self, _h: &History<C>) -> bool {
+        self.recover(self.decision(), true)
+    }
+
+    // Folding checks are only recorded (and replayed) against histories,
+    // so that two configurations are never considered foldable.
+    fn is_foldable_to(&self, _c1: &C, _c2: &C) -> bool {
+        false
+    }
+
+    fn develop(&self, _c: &C) -> Vec<Vec<C>> {
+        let css = self.development().cloned();
+        self.recover(css, vec![])
+    }
+
+    fn is_foldable_to_history(&self, _c: &C, _h: &History<C>) -> bool {
+        self.recover(self.decision(), false)
+    }
+}
+
+//
+// Time travel
+//
+
+// A step of a run of `lazy_mrsc`. `path` leads from the root to the node
+// (each element being the index of an alternative and the index
+// of a configuration in it), `history` is the history of the node, and
+// `alternatives` are the results of developing `conf`
+// (if `event` is `Expanded`).
+
+#[derive(Clone, Debug)]
+pub struct TraceStep<C> {
+    pub path: Vec<(usize, usize)>,
+    pub conf: C,
+    pub history: History<C>,
+    pub event: ScEvent,
+    pub alternatives: Vec<Vec<C>>,
+}
+
+pub struct TimeTravel<C> {
+    steps: Vec<TraceStep<C>>,
+    position: usize,
+}
+
+// Follows the decisions of `lazy_mrsc_loop`: first folding, then
+// the whistle, then developing.
+
+fn trace_loop<C: Clone>(
+    p: &Replayer<C>,
+    path: &mut Vec<(usize, usize)>,
+    h: &History<C>,
+    c: C,
+    steps: &mut Vec<TraceStep<C>>,
+) {
+    let mut step = TraceStep {
+        path: path.clone(),
+        conf: c.clone(),
+        history: h.clone(),
+        event: ScEvent::Expanded,
+        alternatives: Vec::new(),
+    };
+    step.event = sc_step(p, h, &c);
+    if step.event != ScEvent::Expanded {
+        steps.push(step);
+    } else {
+        let css: Vec<_> = p.develop_iter(h, &c).collect();
+        step.alternatives = css.clone();
+        steps.push(step);
+        let h1 = h.cons(c);
+        for (i, cs) in css.into_iter().enumerate() {
+            for (j, c1) in cs.into_iter().enumerate() {
+                path.push((i, j));
+                trace_loop(p, path, &h1, c1, steps);
+                path.pop();
+            }
+        }
+    }
+}
+
+// The lazy graph produced by the first `n` steps, starting from
+// the `k`-th step. The nodes not reached yet are represented by `Empty()`.
+
+fn partial_graph_loop<C: Clone>(
+    steps: &[TraceStep<C>],
+    n: usize,
+    k: &mut usize,
+) -> Rc<LazyGraph<C>> {
+    let i = *k;
+    *k += 1;
+    if i >= n {
+        return empty();
+    }
+    let step = &steps[i];
+    match step.event {
+        ScEvent::Folded => stop(&step.conf),
+        ScEvent::Pruned => empty(),
+        ScEvent::Expanded => {
+            let lss: Vec<Ls<C>> = step
+                .alternatives
+                .iter()
+                .map(|cs| {
+                    cs.iter().map(|_| partial_graph_loop(steps, n, k)).collect()
+                })
+                .collect();
+            build(&step.conf, &lss)
+        }
+    }
+}
+
+impl<C: Clone> TimeTravel<C> {
+    // `log` is supposed to have been recorded by `lazy_mrsc(s, c0)`.
+    // The debugger is positioned before the first step.
+
+    pub fn new(log: ReplayLog<C>, c0: C) -> Result<TimeTravel<C>, ReplayError> {
+        let p = replay(log);
+        let mut steps = Vec::new();
+        trace_loop(&p, &mut Vec::new(), &History::new(), c0, &mut steps);
+        p.check()?;
+        Ok(TimeTravel { steps, position: 0 })
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn steps(&self) -> &[TraceStep<C>] {
+        &self.steps
+    }
+
+    // The number of steps taken.
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    // The last step taken.
+
+    pub fn current(&self) -> Option<&TraceStep<C>> {
+        match self.position {
+            0 => None,
+            k => self.steps.get(k - 1),
+        }
+    }
+
+    pub fn step_forward(&mut self) -> Option<&TraceStep<C>> {
+        if self.position == self.steps.len() {
+            return None;
+        }
+        self.position += 1;
+        self.current()
+    }
+
+    pub fn step_backward(&mut self) -> Option<&TraceStep<C>> {
+        if self.position == 0 {
+            return None;
+        }
+        self.position -= 1;
+        self.current()
+    }
+
+    pub fn seek(&mut self, position: usize) -> Option<&TraceStep<C>> {
+        self.position = position.min(self.steps.len());
+        self.current()
+    }
+
+    // Moves forward to the next step satisfying `p`.
+
+    pub fn seek_forward(
+        &mut self,
+        p: impl Fn(&TraceStep<C>) -> bool,
+    ) -> Option<&TraceStep<C>> {
+        let k = self.steps[self.position..].iter().position(p)?;
+        self.position += k + 1;
+        self.current()
+    }
+
+    // The history of the configuration of the last step taken.
+
+    pub fn history(&self) -> History<C> {
+        self.current()
+            .map_or(History::new(), |step| step.history.clone())
+    }
+
+    pub fn partial_graph(&self) -> Rc<LazyGraph<C>> {
+        partial_graph_loop(&self.steps, self.position, &mut 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::{lazy_mrsc, naive_mrsc};
+    use crate::mock_sc_world::mock_world;
+
+    #[test]
+    fn test_record_and_replay() {
+        let r = record(mock_world());
+        let l = lazy_mrsc(&r, 0);
+        let log = r.log();
+        assert_eq!(log.developments[0], vec![vec![1]]);
+        let p = replay(log.clone());
+        assert_eq!(lazy_mrsc(&p, 0), l);
+        assert!(p.is_finished());
+        assert_eq!(p.check(), Ok(()));
+        let r = record(mock_world());
+        let gs = naive_mrsc(&r, 0);
+        assert_eq!(naive_mrsc(&replay(r.log()), 0), gs);
+    }
+
+    #[test]
+    fn test_replay_exhausted() {
+        let r = record(mock_world());
+        lazy_mrsc(&r, 0);
+        let mut log = r.log();
+        log.developments.truncate(1);
+        let p = replay(log.clone());
+        assert!(!p.is_foldable_to(&0, &0));
+        lazy_mrsc(&p, 0);
+        assert_eq!(p.check(), Err(ReplayError::DevelopmentsExhausted));
+        log.decisions.truncate(1);
+        assert_eq!(
+            TimeTravel::new(log, 0).err(),
+            Some(ReplayError::DecisionsExhausted)
+        );
+    }
+
+    #[test]
+    fn test_time_travel() {
+        let r = record(mock_world());
+        let l = lazy_mrsc(&r, 0);
+        let mut tt = TimeTravel::new(r.log(), 0).unwrap();
+        assert_eq!(tt.partial_graph(), empty());
+        assert_eq!(tt.step_backward().map(|st| st.conf), None);
+        let st = tt.step_forward().unwrap();
+        assert_eq!((st.conf, st.event), (0, ScEvent::Expanded));
+        assert_eq!(tt.partial_graph(), build(&0, &[vec![empty()]]));
+        let st = tt
+            .seek_forward(|st| st.event == ScEvent::Folded && st.conf == 1)
+            .unwrap();
+        assert_eq!(st.conf, 1);
+        assert_eq!(st.path, vec![(0, 0), (0, 0), (0, 1)]);
+        assert_eq!(tt.history().iter().cloned().collect::<Vec<_>>(), [2, 1, 0]);
+        let k = tt.position();
+        tt.step_backward();
+        assert_eq!(tt.position(), k - 1);
+        tt.seek(tt.len());
+        assert_eq!(tt.partial_graph(), l);
+        assert!(tt.step_forward().is_none());
+    }
+}
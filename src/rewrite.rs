@@ -0,0 +1,116 @@
+//
+// Rewriting residual graphs
+//
+// A `RewriteRule<C>` inspects a node of a graph and either leaves it
+// alone (`None`) or returns a graph the node (together with its subtree)
+// is to be replaced with. Closures `Fn(&Graph<C>) -> Option<Rc<Graph<C>>>`
+// are rules.
+//
+// `rewrite(rules, g)` rewrites `g` bottom-up: first the subtrees of
+// a node are rewritten, then the rules are tried at the node in turn,
+// and, if one of them applies, the result is rewritten again. Hence,
+// no rule is applicable to any node of the final graph. (It is up to
+// the rules to ensure that the process terminates.)
+//
+// `CollapseChains(foldable)` is a rule collapsing chains of `Forth` nodes
+// having a single child:
+//     Forth(c, [Forth(c1, gs1)])  ==>  Forth(c, gs1)
+// provided that no back node in the subtree of `c1` is foldable to `c1`
+// (i.e., `c1` is not the target of a fold).
+//
+
+use crate::graph::*;
+
+use std::rc::Rc;
+
+use Graph::{Back, Forth};
+
+pub trait RewriteRule<C> {
+    fn apply(&self, g: &Graph<C>) -> Option<Rc<Graph<C>>>;
+}
+
+impl<C, F: Fn(&Graph<C>) -> Option<Rc<Graph<C>>>> RewriteRule<C> for F {
+    fn apply(&self, g: &Graph<C>) -> Option<Rc<Graph<C>>> {
+        self(g)
+    }
+}
+
+fn apply_rules<C>(
+    rules: &[&dyn RewriteRule<C>],
+    g: &Graph<C>,
+) -> Option<Rc<Graph<C>>> {
+    rules.iter().find_map(|r| r.apply(g))
+}
+
+pub fn rewrite<C: Clone>(
+    rules: &[&dyn RewriteRule<C>],
+    g: &Graph<C>,
+) -> Rc<Graph<C>> {
+    let g1 = match g {
+        Back(c) => back(c),
+        Forth(c, gs) => {
+            let gs1: Gs<C> = gs.iter().map(|g2| rewrite(rules, g2)).collect();
+            forth(c, &gs1)
+        }
+    };
+    match apply_rules(rules, &g1) {
+        None => g1,
+        Some(g2) => rewrite(rules, &g2),
+    }
+}
+
+pub struct CollapseChains<F>(pub F);
+
+fn is_fold_target<C>(
+    foldable: &impl Fn(&C, &C) -> bool,
+    c: &C,
+    g: &Graph<C>,
+) -> bool {
+    g.preorder()
+        .any(|(_, c1, kind)| kind == NodeKind::Back && foldable(c1, c))
+}
+
+impl<C: Clone, F: Fn(&C, &C) -> bool> RewriteRule<C> for CollapseChains<F> {
+    fn apply(&self, g: &Graph<C>) -> Option<Rc<Graph<C>>> {
+        match g {
+            Forth(c, gs) if gs.len() == 1 => match &*gs[0] {
+                Forth(c1, gs1) if !is_fold_target(&self.0, c1, &gs[0]) => {
+                    Some(forth(c, gs1))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collapse_chains() {
+        let eq = |c1: &isize, c2: &isize| c1 == c2;
+        let g = forth(
+            &1,
+            &[forth(&2, &[forth(&3, &[forth(&4, &[back(&2), back(&5)])])])],
+        );
+        assert_eq!(
+            rewrite(&[&CollapseChains(eq)], &g),
+            forth(&1, &[forth(&2, &[back(&2), back(&5)])])
+        );
+        let g = forth(&1, &[forth(&2, &[forth(&3, &[])])]);
+        assert_eq!(rewrite(&[&CollapseChains(eq)], &g), forth(&1, &[]));
+    }
+
+    #[test]
+    fn test_closure_rules() {
+        // Renaming the configurations to a fixed point.
+        let dec = |g: &Graph<isize>| match g {
+            Back(c) if *c > 0 => Some(back(&(c - 1))),
+            _ => None,
+        };
+        let g = forth(&1, &[back(&3), back(&0)]);
+        assert_eq!(rewrite(&[&dec], &g), forth(&1, &[back(&0), back(&0)]));
+    }
+}
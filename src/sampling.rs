@@ -0,0 +1,383 @@
+//
+// Sampling residual graphs
+//
+// Unrolling a lazy graph may be infeasible, since the number of graphs
+// grows exponentially. However, a graph can be sampled from `unroll(l)`
+// without generating the other graphs, which makes possible Monte-Carlo
+// exploration of the set of residual graphs.
+//
+// Weighted sampling
+//
+// Suppose that each node of a graph (including the leaves) is given
+// a weight `w(c) ≥ 0`, depending on its configuration, and the weight
+// of a graph is the product of the weights of its nodes. The total
+// weight of the graphs represented by a lazy graph can be computed
+// without unrolling it (in the same way as `length_unroll` counts
+// the graphs):
+//   Z(Empty()) = 0
+//   Z(Stop(c)) = w(c)
+//   Z(Build(c, lss)) = w(c) * Σ_{ls ∈ lss} Π_{l ∈ ls} Z(l)
+//
+// Then a graph can be sampled top-down, choosing an alternative
+// with probability proportional to its weight. The probability of
+// a graph being produced is its weight divided by `Z(l)`.
+//
+// For example, if all the weights are 1, the graphs are sampled
+// uniformly, and if all the weights are `θ < 1`, the probability of
+// a graph is proportional to `θ^graph_size(g)`, so that small graphs
+// are preferred.
+//
+// Scores that are not multiplicative can be dealt with by rejection
+// sampling (see `sample_by_score`).
+//
+// Determinism
+//
+// No global or thread-local source of randomness is used: all the random
+// choices are made by an explicit generator implementing `Rng`. Given
+// the same lazy graph, the same weights and a generator in the same
+// state, the same graphs are sampled, on any machine. `SplitMix64` is
+// a portable generator, whose state is determined by a `u64` seed.
+// `sample_n(seed, n)` returns the seed of each sample along with
+// the sample, so that a particular sample (say, a counterexample) can
+// be reproduced by `sample_seeded` without repeating the whole run.
+//
+
+use crate::graph::*;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use LazyGraph::*;
+
+//
+// Sources of randomness
+//
+// Any generator can be used by implementing `Rng` (for example,
+// by wrapping a generator from the `rand` crate).
+//
+
+pub trait Rng {
+    fn next_u64(&mut self) -> u64;
+
+    // A number in [0, 1).
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// A small, deterministic pseudo-random number generator (SplitMix64).
+// The same seed produces the same sequence of numbers on all platforms,
+// hence the same samples.
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+}
+
+impl Rng for SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+// Chooses an index with probability proportional to its weight
+// (or `None`, if all the weights are 0).
+
+fn choose(ws: &[f64], rng: &mut impl Rng) -> Option<usize> {
+    let total: f64 = ws.iter().sum();
+    if total.is_nan() || total <= 0.0 {
+        return None;
+    }
+    let mut r = rng.next_f64() * total;
+    let mut last = None;
+    for (i, &w) in ws.iter().enumerate() {
+        if w > 0.0 {
+            if r < w {
+                return Some(i);
+            }
+            r -= w;
+            last = Some(i);
+        }
+    }
+    // Rounding errors.
+    last
+}
+
+// `WeightedSampler::new(l, w)` computes the weights of the subtrees
+// of `l` once (the shared subtrees being visited once), after which
+// any number of graphs can be sampled.
+
+pub struct WeightedSampler<C> {
+    root: Rc<LazyGraph<C>>,
+    weights: HashMap<*const LazyGraph<C>, f64>,
+    w: Box<dyn Fn(&C) -> f64>,
+}
+
+impl<C: Clone> WeightedSampler<C> {
+    pub fn new(
+        l: &Rc<LazyGraph<C>>,
+        w: impl Fn(&C) -> f64 + 'static,
+    ) -> WeightedSampler<C> {
+        let mut weights = HashMap::new();
+        weight_loop(&w, l, &mut weights);
+        WeightedSampler {
+            root: Rc::clone(l),
+            weights,
+            w: Box::new(w),
+        }
+    }
+
+    // The total weight of the graphs represented by `l`.
+    // (If all the weights are 1, this is `length_unroll(l)`.)
+
+    pub fn total_weight(&self) -> f64 {
+        self.weight(&self.root)
+    }
+
+    // The probability of `g` being sampled
+    // (provided that `g` is in `unroll(l)`).
+
+    pub fn probability(&self, g: &Graph<C>) -> f64 {
+        graph_weight(&*self.w, g) / self.total_weight()
+    }
+
+    // `None` if the total weight is 0.
+
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<Rc<Graph<C>>> {
+        self.sample_loop(&self.root, rng)
+    }
+
+    // Samples a graph using a generator seeded with `seed`.
+
+    pub fn sample_seeded(&self, seed: u64) -> Option<Rc<Graph<C>>> {
+        self.sample(&mut SplitMix64::new(seed))
+    }
+
+    // `n` samples, together with their seeds, which are, in turn,
+    // generated from `seed`.
+
+    pub fn sample_n(&self, seed: u64, n: usize) -> Vec<(u64, Rc<Graph<C>>)> {
+        let mut seeds = SplitMix64::new(seed);
+        let mut gs = Vec::with_capacity(n);
+        for _ in 0..n {
+            let seed1 = seeds.next_u64();
+            if let Some(g) = self.sample_seeded(seed1) {
+                gs.push((seed1, g));
+            }
+        }
+        gs
+    }
+
+    fn weight(&self, l: &Rc<LazyGraph<C>>) -> f64 {
+        self.weights[&Rc::as_ptr(l)]
+    }
+
+    fn sample_loop(
+        &self,
+        l: &Rc<LazyGraph<C>>,
+        rng: &mut impl Rng,
+    ) -> Option<Rc<Graph<C>>> {
+        match &**l {
+            Empty() => None,
+            Stop(c) => Some(back(c)),
+            Build(c, lss) => {
+                let ws: Vec<f64> = lss
+                    .iter()
+                    .map(|ls| ls.iter().map(|l1| self.weight(l1)).product())
+                    .collect();
+                let i = choose(&ws, rng)?;
+                let gs: Option<Gs<C>> =
+                    lss[i].iter().map(|l1| self.sample_loop(l1, rng)).collect();
+                gs.map(|gs| forth(c, &gs))
+            }
+        }
+    }
+}
+
+fn weight_loop<C>(
+    w: &impl Fn(&C) -> f64,
+    l: &Rc<LazyGraph<C>>,
+    weights: &mut HashMap<*const LazyGraph<C>, f64>,
+) -> f64 {
+    if let Some(&z) = weights.get(&Rc::as_ptr(l)) {
+        return z;
+    }
+    let z = match &**l {
+        Empty() => 0.0,
+        Stop(c) => w(c),
+        Build(c, lss) => {
+            let mut s = 0.0;
+            for ls in lss {
+                let mut m = 1.0;
+                for l1 in ls {
+                    m *= weight_loop(w, l1, weights);
+                }
+                s += m;
+            }
+            w(c) * s
+        }
+    };
+    weights.insert(Rc::as_ptr(l), z);
+    z
+}
+
+fn graph_weight<C>(w: &dyn Fn(&C) -> f64, g: &Graph<C>) -> f64 {
+    match g {
+        Graph::Back(c) => w(c),
+        Graph::Forth(c, gs) => {
+            w(c) * gs.iter().map(|g1| graph_weight(w, g1)).product::<f64>()
+        }
+    }
+}
+
+// Uniform sampling.
+
+pub fn sample_uniform<C: Clone>(
+    l: &Rc<LazyGraph<C>>,
+    rng: &mut impl Rng,
+) -> Option<Rc<Graph<C>>> {
+    WeightedSampler::new(l, |_| 1.0).sample(rng)
+}
+
+// The probability of a graph is proportional to `theta^graph_size(g)`.
+
+pub fn sample_by_size<C: Clone>(
+    l: &Rc<LazyGraph<C>>,
+    theta: f64,
+    rng: &mut impl Rng,
+) -> Option<Rc<Graph<C>>> {
+    WeightedSampler::new(l, move |_| theta).sample(rng)
+}
+
+// `sample_by_score(l, score, max_tries, rng)` samples graphs
+// with probability proportional to `score(g)`, which is supposed to
+// be in [0, 1]. Graphs are sampled uniformly, and a graph `g` is
+// accepted with probability `score(g)`. `None` is returned if no graph
+// has been accepted after `max_tries` attempts.
+
+pub fn sample_by_score<C: Clone>(
+    l: &Rc<LazyGraph<C>>,
+    score: impl Fn(&Graph<C>) -> f64,
+    max_tries: usize,
+    rng: &mut impl Rng,
+) -> Option<Rc<Graph<C>>> {
+    let sampler = WeightedSampler::new(l, |_| 1.0);
+    for _ in 0..max_tries {
+        let g = sampler.sample(rng)?;
+        if rng.next_f64() < score(&g) {
+            return Some(g);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::lazy_mrsc;
+    use crate::mock_sc_world::mock_world;
+    use crate::statistics::length_unroll;
+
+    #[test]
+    fn test_split_mix64() {
+        let mut r1 = SplitMix64::new(42);
+        let mut r2 = SplitMix64::new(42);
+        let xs: Vec<u64> = (0..5).map(|_| r1.next_u64()).collect();
+        let ys: Vec<u64> = (0..5).map(|_| r2.next_u64()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(xs[0], SplitMix64::new(43).next_u64());
+        let x = r1.next_f64();
+        assert!((0.0..1.0).contains(&x));
+    }
+
+    #[test]
+    fn test_total_weight() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let gs = unroll(&l);
+        let s1 = WeightedSampler::new(&l, |_| 1.0);
+        assert_eq!(s1.total_weight(), length_unroll(&l) as f64);
+        let s2 = WeightedSampler::new(&l, |_| 0.5);
+        let z: f64 = gs.iter().map(|g| 0.5f64.powi(graph_size(g) as i32)).sum();
+        assert!((s2.total_weight() - z).abs() < 1e-12);
+        let p: f64 = gs.iter().map(|g| s2.probability(g)).sum();
+        assert!((p - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_sample() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let gs = unroll(&l);
+        let mut rng = SplitMix64::new(1);
+        let mut counts = vec![0; gs.len()];
+        for _ in 0..1000 {
+            let g = sample_uniform(&l, &mut rng).unwrap();
+            let i = gs.iter().position(|g1| *g1 == g).unwrap();
+            counts[i] += 1;
+        }
+        assert!(counts.iter().all(|&k| k > 0));
+
+        let min = cl_min_size(&l);
+        let g_min = unroll(&min).pop().unwrap();
+        let mut rng = SplitMix64::new(1);
+        let k = (0..100)
+            .filter(|_| sample_by_size(&l, 0.01, &mut rng).unwrap() == g_min)
+            .count();
+        assert!(k > 90);
+
+        let e: Rc<LazyGraph<isize>> = empty();
+        assert_eq!(sample_uniform(&e, &mut rng), None);
+    }
+
+    #[test]
+    fn test_sample_by_score() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let k = graph_size(&unroll(&cl_min_size(&l))[0]);
+        let mut rng = SplitMix64::new(7);
+        for _ in 0..20 {
+            let g = sample_by_score(
+                &l,
+                |g| if graph_size(g) == k { 1.0 } else { 0.0 },
+                1000,
+                &mut rng,
+            )
+            .unwrap();
+            assert_eq!(graph_size(&g), k);
+        }
+        assert_eq!(sample_by_score(&l, |_| 0.0, 10, &mut rng), None);
+    }
+
+    #[test]
+    fn test_sample_deterministic() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let sampler = WeightedSampler::new(&l, |_| 0.7);
+        let mut r1 = SplitMix64::new(99);
+        let mut r2 = SplitMix64::new(99);
+        for _ in 0..10 {
+            assert_eq!(sampler.sample(&mut r1), sampler.sample(&mut r2));
+        }
+    }
+
+    #[test]
+    fn test_sample_n() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let sampler = WeightedSampler::new(&l, |_| 1.0);
+        let gs = sampler.sample_n(2024, 10);
+        assert_eq!(gs.len(), 10);
+        assert_eq!(gs, sampler.sample_n(2024, 10));
+        for (seed, g) in gs {
+            assert_eq!(sampler.sample_seeded(seed), Some(g));
+        }
+    }
+}
@@ -15,7 +15,17 @@
 // lazy graphs such that
 //   length_unroll(l) == length(unroll(l))
 
+use crate::big_step_sc::{lazy_mrsc, lazy_mrsc_with_hook, ScEvent, ScWorld};
+use crate::error::{ResourceLimits, StagedMrscError};
+use crate::fusion::NodeCleaner;
 use crate::graph::*;
+use crate::misc::History;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use LazyGraph::*;
 
@@ -70,16 +80,615 @@ fn size_unroll_ls<C>(ls: &Ls<C>) -> (usize, usize) {
     (k, n)
 }
 
+//
+// Checked counting
+//
+// `length_unroll` and `size_unroll` silently overflow (or panic, in debug
+// builds) if the numbers are too big. `length_unroll_checked` and
+// `size_unroll_checked` return `StagedMrscError::Overflow` instead.
+// They do not use recursion, so that lazy graphs of any depth can be
+// analysed. An alternative having a factor that produces no graphs
+// produces no graphs either, even if the product of the other factors
+// is too big (or their counting overflows).
+//
+// `unroll_checked(l, limits)` refuses to unroll `l` if the number
+// of graphs exceeds `limits.max_graphs` (the other limits being ignored).
+//
+
+pub fn length_unroll_checked<C>(
+    l: &LazyGraph<C>,
+) -> Result<usize, StagedMrscError> {
+    l.fold(
+        || Ok(0),
+        |_| Ok(1),
+        |_, rss| {
+            let mut s: usize = 0;
+            for rs in rss {
+                if rs.contains(&Ok(0)) {
+                    continue;
+                }
+                let mut m: usize = 1;
+                for r in rs {
+                    m = m.checked_mul(r?).ok_or(StagedMrscError::Overflow)?;
+                }
+                s = s.checked_add(m).ok_or(StagedMrscError::Overflow)?;
+            }
+            Ok(s)
+        },
+    )
+}
+
+pub fn size_unroll_checked<C>(
+    l: &LazyGraph<C>,
+) -> Result<(usize, usize), StagedMrscError> {
+    l.fold(
+        || Ok((0, 0)),
+        |_| Ok((1, 1)),
+        |_, rss| {
+            let mut k: usize = 0;
+            let mut n: usize = 0;
+            for rs in rss {
+                let (k1, n1) = size_unroll_ls_checked(rs)?;
+                k = k.checked_add(k1).ok_or(StagedMrscError::Overflow)?;
+                n = n
+                    .checked_add(k1)
+                    .and_then(|n| n.checked_add(n1))
+                    .ok_or(StagedMrscError::Overflow)?;
+            }
+            Ok((k, n))
+        },
+    )
+}
+
+fn size_unroll_ls_checked(
+    rs: Vec<Result<(usize, usize), StagedMrscError>>,
+) -> Result<(usize, usize), StagedMrscError> {
+    if rs.iter().any(|r| matches!(r, Ok((0, _)))) {
+        return Ok((0, 0));
+    }
+    let mut k: usize = 1;
+    let mut n: usize = 0;
+    for r in rs {
+        let (k1, n1) = r?;
+        let n2 = k.checked_mul(n1);
+        let n3 = k1.checked_mul(n);
+        k = k.checked_mul(k1).ok_or(StagedMrscError::Overflow)?;
+        n = n2
+            .zip(n3)
+            .and_then(|(n2, n3)| n2.checked_add(n3))
+            .ok_or(StagedMrscError::Overflow)?;
+    }
+    Ok((k, n))
+}
+
+pub fn unroll_checked<C: Clone>(
+    l: &LazyGraph<C>,
+    limits: &ResourceLimits,
+) -> Result<Gs<C>, StagedMrscError> {
+    let k = length_unroll_checked(l)?;
+    match limits.max_graphs {
+        Some(m) if k > m => Err(StagedMrscError::GraphLimit(m)),
+        _ => Ok(unroll(l)),
+    }
+}
+
+//
+// Statistics before and after cleaning
+//
+// `Measured(n)` is a node cleaner that behaves as `n`, but also computes
+// `size_unroll` of the lazy graph before and after cleaning.
+// In a pipeline of fused cleaners, each stage can be measured,
+// so that the effectiveness of all the cleaners is found in a single
+// traversal of the lazy graph. For example, the root state of
+//     fuse(Measured(n1), Measured(n2))
+// is `((s1, stats1), (s2, stats2))`, where `stats2.before == stats1.after`.
+//
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct CleanStats {
+    pub before: (usize, usize),
+    pub after: (usize, usize),
+}
+
+pub struct Measured<N>(pub N);
+
+fn size_unroll_alts(
+    alts: impl Iterator<Item = Vec<(usize, usize)>>,
+) -> (usize, usize) {
+    let mut k = 0;
+    let mut n = 0;
+    for kns in alts {
+        let mut k1 = 1;
+        let mut n1 = 0;
+        for (k2, n2) in kns {
+            (k1, n1) = (k1 * k2, k1 * n2 + k2 * n1);
+        }
+        (k, n) = (k + k1, n + k1 + n1);
+    }
+    (k, n)
+}
+
+impl<C, N: NodeCleaner<C>> NodeCleaner<C> for Measured<N> {
+    type S = (N::S, CleanStats);
+
+    fn empty(&self) -> Self::S {
+        (self.0.empty(), CleanStats::default())
+    }
+
+    fn stop(&self, c: &C) -> (Self::S, bool) {
+        let (s, keep) = self.0.stop(c);
+        let stats = CleanStats {
+            before: (1, 1),
+            after: if keep { (1, 1) } else { (0, 0) },
+        };
+        ((s, stats), keep)
+    }
+
+    fn build(
+        &self,
+        c: &C,
+        alts: &[Vec<Self::S>],
+    ) -> (Self::S, Option<Vec<usize>>) {
+        let alts1: Vec<Vec<N::S>> = alts
+            .iter()
+            .map(|ss| ss.iter().map(|s| s.0.clone()).collect())
+            .collect();
+        let (s, is) = self.0.build(c, &alts1);
+        let before = size_unroll_alts(
+            alts.iter()
+                .map(|ss| ss.iter().map(|s| s.1.before).collect()),
+        );
+        let after = match &is {
+            None => (0, 0),
+            Some(is) => size_unroll_alts(
+                is.iter()
+                    .map(|&i| alts[i].iter().map(|s| s.1.after).collect()),
+            ),
+        };
+        ((s, CleanStats { before, after }), is)
+    }
+}
+
+//
+// Configuration frequencies
+//
+// For each configuration `c` in a lazy graph `l`, we find the number of
+// graphs in `unroll(l)` containing `c`, without generating the graphs.
+// This number is `length_unroll(l)` minus the number of graphs avoiding
+// `c`, the latter being computed in the same way as `length_unroll`.
+//
+
+fn length_unroll_avoiding<C: PartialEq>(l: &LazyGraph<C>, x: &C) -> usize {
+    match l {
+        Empty() => 0,
+        Stop(c) => (c != x) as usize,
+        Build(c, lss) => {
+            if c == x {
+                return 0;
+            }
+            let mut s = 0;
+            for ls in lss {
+                let mut m = 1;
+                for l1 in ls {
+                    m *= length_unroll_avoiding(l1, x);
+                }
+                s += m;
+            }
+            s
+        }
+    }
+}
+
+fn collect_confs<C: Clone + Eq + Hash>(l: &LazyGraph<C>, cs: &mut HashSet<C>) {
+    match l {
+        Empty() => {}
+        Stop(c) => {
+            cs.insert(c.clone());
+        }
+        Build(c, lss) => {
+            cs.insert(c.clone());
+            for ls in lss {
+                for l1 in ls {
+                    collect_confs(l1, cs);
+                }
+            }
+        }
+    }
+}
+
+pub fn conf_frequencies<C: Clone + Eq + Hash>(
+    l: &LazyGraph<C>,
+) -> HashMap<C, usize> {
+    let mut cs = HashSet::new();
+    collect_confs(l, &mut cs);
+    let n = length_unroll(l);
+    cs.into_iter()
+        .map(|c| {
+            let k = n - length_unroll_avoiding(l, &c);
+            (c, k)
+        })
+        .collect()
+}
+
+//
+// Per-depth expansion report
+//
+// For each depth (the length of the history), how many configurations
+// have been developed, folded and pruned by the whistle while
+// producing a lazy graph.
+//
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct DepthRow {
+    pub expanded: usize,
+    pub folded: usize,
+    pub pruned: usize,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct DepthReport(pub Vec<DepthRow>);
+
+impl DepthReport {
+    pub fn record(&mut self, e: ScEvent, depth: usize) {
+        if self.0.len() <= depth {
+            self.0.resize(depth + 1, DepthRow::default());
+        }
+        let row = &mut self.0[depth];
+        match e {
+            ScEvent::Expanded => row.expanded += 1,
+            ScEvent::Folded => row.folded += 1,
+            ScEvent::Pruned => row.pruned += 1,
+        }
+    }
+}
+
+impl fmt::Display for DepthReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "depth expanded folded pruned")?;
+        for (d, row) in self.0.iter().enumerate() {
+            writeln!(
+                f,
+                "{:5} {:8} {:6} {:6}",
+                d, row.expanded, row.folded, row.pruned
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn lazy_mrsc_depth_report<S: ScWorld>(
+    s: &S,
+    c0: S::C,
+) -> (Rc<LazyGraph<S::C>>, DepthReport) {
+    let mut report = DepthReport::default();
+    let l = lazy_mrsc_with_hook(s, c0, &mut |e, d| report.record(e, d));
+    (l, report)
+}
+
+//
+// Graph metrics
+//
+// A `GraphMetric<C>` is a named numeric characteristic of a graph.
+// `collect_metrics(ms, gs)` computes a number of metrics for each graph
+// in `gs`, which may be the result of `unroll` or any part of it
+// (for example, the first graphs or every k-th graph), and returns
+// a table with a row per graph and a column per metric.
+//
+
+pub trait GraphMetric<C> {
+    fn name(&self) -> String;
+    fn compute(&self, g: &Graph<C>) -> usize;
+}
+
+pub struct SizeMetric;
+
+impl<C> GraphMetric<C> for SizeMetric {
+    fn name(&self) -> String {
+        "size".to_string()
+    }
+
+    fn compute(&self, g: &Graph<C>) -> usize {
+        graph_size(g)
+    }
+}
+
+pub struct BacksMetric;
+
+impl<C> GraphMetric<C> for BacksMetric {
+    fn name(&self) -> String {
+        "backs".to_string()
+    }
+
+    fn compute(&self, g: &Graph<C>) -> usize {
+        graph_backs(g)
+    }
+}
+
+pub struct DepthMetric;
+
+impl<C> GraphMetric<C> for DepthMetric {
+    fn name(&self) -> String {
+        "depth".to_string()
+    }
+
+    fn compute(&self, g: &Graph<C>) -> usize {
+        g.depth()
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct MetricTable {
+    pub names: Vec<String>,
+    pub rows: Vec<Vec<usize>>,
+}
+
+impl MetricTable {
+    pub fn column(&self, name: &str) -> Option<Vec<usize>> {
+        let k = self.names.iter().position(|n| n == name)?;
+        Some(self.rows.iter().map(|row| row[k]).collect())
+    }
+}
+
+impl fmt::Display for MetricTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "graph {}", self.names.join(" "))?;
+        for (i, row) in self.rows.iter().enumerate() {
+            write!(f, "{:5}", i)?;
+            for (name, v) in self.names.iter().zip(row) {
+                write!(f, " {:>w$}", v, w = name.len())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn collect_metrics<'a, C: 'a>(
+    ms: &[&dyn GraphMetric<C>],
+    gs: impl IntoIterator<Item = &'a Rc<Graph<C>>>,
+) -> MetricTable {
+    MetricTable {
+        names: ms.iter().map(|m| m.name()).collect(),
+        rows: gs
+            .into_iter()
+            .map(|g| ms.iter().map(|m| m.compute(g)).collect())
+            .collect(),
+    }
+}
+
+//
+// Comparing whistles
+//
+// `compare_whistles(s, c0, ws, bad)` runs `lazy_mrsc` for the world `s`
+// with its whistle replaced, in turn, by each of the whistles in `ws`.
+// For each whistle, the table contains the size of the lazy graph,
+// the number of proofs (graphs without bad configurations), the size
+// of a minimal proof and the time taken.
+//
+
+pub fn lazy_graph_size<C>(l: &LazyGraph<C>) -> usize {
+    match l {
+        Empty() => 1,
+        Stop(_) => 1,
+        Build(_, lss) => {
+            let mut k = 1;
+            for ls in lss {
+                for l1 in ls {
+                    k += lazy_graph_size(l1);
+                }
+            }
+            k
+        }
+    }
+}
+
+type Whistle<C> = dyn Fn(&History<C>) -> bool;
+
+struct Whistled<'a, S: ScWorld> {
+    inner: &'a S,
+    whistle: &'a Whistle<S::C>,
+}
+
+impl<'a, S: ScWorld> ScWorld for Whistled<'a, S> {
+    type C = S::C;
+
+    fn is_dangerous(&self, h: &History<S::C>) -> bool {
+        (self.whistle)(h)
+    }
+
+    fn is_foldable_to(&self, c1: &S::C, c2: &S::C) -> bool {
+        self.inner.is_foldable_to(c1, c2)
+    }
+
+    fn develop(&self, c: &S::C) -> Vec<Vec<S::C>> {
+        self.inner.develop(c)
+    }
+
+    fn develop_with_history(
+        &self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Vec<Vec<S::C>> {
+        self.inner.develop_with_history(h, c)
+    }
+
+    fn develop_iter<'b>(
+        &'b self,
+        h: &History<S::C>,
+        c: &S::C,
+    ) -> Box<dyn Iterator<Item = Vec<S::C>> + 'b> {
+        self.inner.develop_iter(h, c)
+    }
+
+    fn is_foldable_to_history(&self, c: &S::C, h: &History<S::C>) -> bool {
+        self.inner.is_foldable_to_history(c, h)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct WhistleRow {
+    pub name: String,
+    pub lazy_size: usize,
+    pub proofs: usize,
+    pub min_size: Option<usize>,
+    pub time: Duration,
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct WhistleTable(pub Vec<WhistleRow>);
+
+impl fmt::Display for WhistleTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let w = self.0.iter().map(|r| r.name.len()).max().unwrap_or(0);
+        let w = w.max("whistle".len());
+        writeln!(f, "{:w$} lazy_size proofs min_size time_ms", "whistle")?;
+        for r in &self.0 {
+            writeln!(
+                f,
+                "{:w$} {:9} {:6} {:>8} {:7}",
+                r.name,
+                r.lazy_size,
+                r.proofs,
+                r.min_size.map_or("-".to_string(), |k| k.to_string()),
+                r.time.as_millis()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn compare_whistles<S: ScWorld>(
+    s: &S,
+    c0: S::C,
+    ws: &[(&str, &Whistle<S::C>)],
+    bad: impl Fn(&S::C) -> bool,
+) -> WhistleTable {
+    let mut rows = Vec::with_capacity(ws.len());
+    for &(name, whistle) in ws {
+        let started = Instant::now();
+        let sw = Whistled { inner: s, whistle };
+        let l = lazy_mrsc(&sw, c0.clone());
+        let sl = cl_empty_and_bad(&bad, &l);
+        let proofs = length_unroll(&sl);
+        let min_size = unroll_first(&cl_min_size(&sl)).map(|g| graph_size(&g));
+        rows.push(WhistleRow {
+            name: name.to_string(),
+            lazy_size: lazy_graph_size(&l),
+            proofs,
+            min_size,
+            time: started.elapsed(),
+        });
+    }
+    WhistleTable(rows)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::big_step_sc::*;
+    use crate::mock_sc_world::mock_world;
     use iter_comprehensions::sum;
     use std::rc::Rc;
 
     fn lazy_mrsc_isize(c: isize) -> Rc<LazyGraph<isize>> {
-        lazy_mrsc(&0isize, c)
+        lazy_mrsc(&mock_world(), c)
+    }
+
+    #[test]
+    fn test_measured() {
+        use crate::fusion::*;
+
+        let l = lazy_mrsc_isize(0);
+        let bad = |c: &isize| *c == 0;
+        let n = fuse(Measured(NcBadConf(bad)), Measured(NcEmpty));
+        let (((_, stats1), (_, stats2)), l1) = clean_fused_with_state(&n, &l);
+        assert_eq!(l1, cl_empty_and_bad(bad, &l));
+        assert_eq!(stats1.before, size_unroll(&l));
+        assert_eq!(stats2.before, stats1.after);
+        assert_eq!(stats2.after, size_unroll(&l1));
+        let bad = |c: &isize| *c == 3;
+        let n = fuse(Measured(NcBadConf(bad)), Measured(NcMinSize));
+        let (((_, stats1), (_, stats2)), l2) = clean_fused_with_state(&n, &l);
+        assert_eq!(stats1.before, size_unroll(&l));
+        assert_eq!(stats2.before, stats1.after);
+        assert_eq!(stats2.after, size_unroll(&l2));
+        assert_eq!(stats2.after, (1, 4));
+    }
+
+    #[test]
+    fn test_collect_metrics() {
+        let gs = unroll(&lazy_mrsc_isize(0));
+        let t =
+            collect_metrics(&[&SizeMetric, &BacksMetric, &DepthMetric], &gs);
+        assert_eq!(t.names, ["size", "backs", "depth"]);
+        assert_eq!(
+            t.rows,
+            vec![vec![5, 2, 3], vec![4, 1, 3], vec![6, 2, 4], vec![5, 1, 4]]
+        );
+        assert_eq!(t.column("backs"), Some(vec![2, 1, 2, 1]));
+        assert_eq!(t.column("width"), None);
+        let t1 = collect_metrics(&[&SizeMetric], gs.iter().take(1));
+        assert_eq!(t1.to_string(), "graph size\n    0    5\n");
+    }
+
+    #[test]
+    fn test_conf_frequencies() {
+        let l = lazy_mrsc_isize(0);
+        let gs = unroll(&l);
+        let freqs = conf_frequencies(&l);
+        for (c, k) in &freqs {
+            let k1 =
+                gs.iter().filter(|g| g.preorder().any(|n| n.1 == c)).count();
+            assert_eq!(*k, k1);
+        }
+        assert_eq!(freqs[&0], gs.len());
+        assert_eq!(freqs[&3], 2);
+    }
+
+    #[test]
+    fn test_depth_report() {
+        let (l, report) = lazy_mrsc_depth_report(&mock_world(), 0);
+        assert_eq!(l, lazy_mrsc_isize(0));
+        let row = |e, f, p| DepthRow {
+            expanded: e,
+            folded: f,
+            pruned: p,
+        };
+        assert_eq!(
+            report,
+            DepthReport(vec![
+                row(1, 0, 0),
+                row(1, 0, 0),
+                row(1, 0, 0),
+                row(1, 3, 0),
+                row(0, 3, 1)
+            ])
+        );
+        assert!(report.to_string().starts_with("depth expanded"));
+    }
+
+    #[test]
+    fn test_compare_whistles() {
+        let bad = |c: &isize| *c >= 3;
+        let w3 = |h: &History<isize>| h.length() > 3;
+        let w1 = |h: &History<isize>| h.length() > 1;
+        let t = compare_whistles(
+            &mock_world(),
+            0,
+            &[("length > 3", &w3), ("length > 1", &w1)],
+            bad,
+        );
+        let l = lazy_mrsc_isize(0);
+        let sl = cl_empty_and_bad(bad, &l);
+        assert_eq!(t.0[0].lazy_size, lazy_graph_size(&l));
+        assert_eq!(t.0[0].proofs, length_unroll(&sl));
+        assert_eq!(
+            t.0[0].min_size,
+            Some(graph_size(&unroll(&cl_min_size(&sl))[0]))
+        );
+        assert_eq!((t.0[1].lazy_size, t.0[1].proofs), (3, 0));
+        assert_eq!(t.0[1].min_size, None);
+        assert!(t.to_string().starts_with("whistle    lazy_size"));
     }
 
     #[test]
@@ -93,4 +702,46 @@ mod tests {
             (gs.len(), (sum!(graph_size(&g); g in gs)))
         );
     }
+
+    #[test]
+    fn test_checked_statistics() {
+        let l = lazy_mrsc_isize(0isize);
+        assert_eq!(length_unroll_checked(&l), Ok(length_unroll(&l)));
+        assert_eq!(size_unroll_checked(&l), Ok(size_unroll(&l)));
+        let limits = ResourceLimits {
+            max_graphs: Some(length_unroll(&l)),
+            ..Default::default()
+        };
+        assert_eq!(unroll_checked(&l, &limits), Ok(unroll(&l)));
+        let limits = ResourceLimits {
+            max_graphs: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            unroll_checked(&l, &limits),
+            Err(StagedMrscError::GraphLimit(1))
+        );
+
+        // 2^64 graphs.
+        let l2 = build(&1, &[vec![stop(&1)], vec![stop(&2)]]);
+        let ls = vec![l2; 64];
+        let l64 = build(&0, &[ls]);
+        assert_eq!(length_unroll_checked(&l64), Err(StagedMrscError::Overflow));
+        assert_eq!(size_unroll_checked(&l64), Err(StagedMrscError::Overflow));
+        assert_eq!(
+            unroll_checked(&l64, &ResourceLimits::default()),
+            Err(StagedMrscError::Overflow)
+        );
+
+        // The empty factor comes last, after the product has overflowed.
+        let l2 = build(&1, &[vec![stop(&1)], vec![stop(&2)]]);
+        let mut ls = vec![l2; 64];
+        ls.push(empty());
+        let l0 = build(&0, &[ls, vec![stop(&3)]]);
+        assert_eq!(length_unroll_checked(&l0), Ok(1));
+        assert_eq!(size_unroll_checked(&l0), Ok((1, 2)));
+        let l00 = build(&0, &[vec![l64, empty()]]);
+        assert_eq!(length_unroll_checked(&l00), Ok(0));
+        assert_eq!(size_unroll_checked(&l00), Ok((0, 0)));
+    }
 }
@@ -0,0 +1,274 @@
+//
+// Thread-safe graphs
+//
+// `Graph` and `LazyGraph` are built with `Rc`, hence they cannot be
+// passed to other threads. `ArcGraph` and `ArcLazyGraph` are their
+// counterparts built with `Arc`, which are `Send` and `Sync` (provided
+// that the configurations are).
+//
+// A (lazy) graph can be converted to its `Arc` counterpart and back,
+// the shared subtrees remaining shared, so that
+//     graph_from_arc(&graph_to_arc(g)) == g
+//     lazy_graph_from_arc(&lazy_graph_to_arc(l)) == l
+//
+// Unrolling and the cleaners given by node cleaners (see `fusion`) are
+// defined for `ArcLazyGraph` as well, so that they can be run
+// on worker threads:
+//     graph_from_arc(unroll_arc(lazy_graph_to_arc(l))) == unroll(l)
+//     lazy_graph_from_arc(clean_fused_arc(n, lazy_graph_to_arc(l))) ==
+//         clean_fused(n, l)
+//
+
+use crate::fusion::NodeCleaner;
+use crate::graph::*;
+use crate::misc::cartesian;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ArcGraph<C> {
+    Back(C),
+    Forth(C, ArcGs<C>),
+}
+
+pub type ArcGs<C> = Vec<Arc<ArcGraph<C>>>;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ArcLazyGraph<C> {
+    Empty(),
+    Stop(C),
+    Build(C, Vec<ArcLs<C>>),
+}
+
+pub type ArcLs<C> = Vec<Arc<ArcLazyGraph<C>>>;
+
+//
+// Conversions
+//
+
+fn graph_to_arc_loop<C: Clone>(
+    memo: &mut HashMap<*const Graph<C>, Arc<ArcGraph<C>>>,
+    g: &Rc<Graph<C>>,
+) -> Arc<ArcGraph<C>> {
+    if let Some(g1) = memo.get(&Rc::as_ptr(g)) {
+        return Arc::clone(g1);
+    }
+    let g1 = Arc::new(match &**g {
+        Graph::Back(c) => ArcGraph::Back(c.clone()),
+        Graph::Forth(c, gs) => ArcGraph::Forth(
+            c.clone(),
+            gs.iter().map(|g2| graph_to_arc_loop(memo, g2)).collect(),
+        ),
+    });
+    memo.insert(Rc::as_ptr(g), Arc::clone(&g1));
+    g1
+}
+
+pub fn graph_to_arc<C: Clone>(g: &Rc<Graph<C>>) -> Arc<ArcGraph<C>> {
+    graph_to_arc_loop(&mut HashMap::new(), g)
+}
+
+fn graph_from_arc_loop<C: Clone>(
+    memo: &mut HashMap<*const ArcGraph<C>, Rc<Graph<C>>>,
+    g: &Arc<ArcGraph<C>>,
+) -> Rc<Graph<C>> {
+    if let Some(g1) = memo.get(&Arc::as_ptr(g)) {
+        return Rc::clone(g1);
+    }
+    let g1 = match &**g {
+        ArcGraph::Back(c) => back(c),
+        ArcGraph::Forth(c, gs) => {
+            let gs1: Gs<C> =
+                gs.iter().map(|g2| graph_from_arc_loop(memo, g2)).collect();
+            forth(c, &gs1)
+        }
+    };
+    memo.insert(Arc::as_ptr(g), Rc::clone(&g1));
+    g1
+}
+
+pub fn graph_from_arc<C: Clone>(g: &Arc<ArcGraph<C>>) -> Rc<Graph<C>> {
+    graph_from_arc_loop(&mut HashMap::new(), g)
+}
+
+// Converts a list of graphs, the subtrees shared between the graphs
+// remaining shared.
+
+pub fn graphs_from_arc<C: Clone>(gs: &[Arc<ArcGraph<C>>]) -> Gs<C> {
+    let mut memo = HashMap::new();
+    gs.iter()
+        .map(|g| graph_from_arc_loop(&mut memo, g))
+        .collect()
+}
+
+fn lazy_graph_to_arc_loop<C: Clone>(
+    memo: &mut HashMap<*const LazyGraph<C>, Arc<ArcLazyGraph<C>>>,
+    l: &Rc<LazyGraph<C>>,
+) -> Arc<ArcLazyGraph<C>> {
+    if let Some(l1) = memo.get(&Rc::as_ptr(l)) {
+        return Arc::clone(l1);
+    }
+    let l1 = Arc::new(match &**l {
+        LazyGraph::Empty() => ArcLazyGraph::Empty(),
+        LazyGraph::Stop(c) => ArcLazyGraph::Stop(c.clone()),
+        LazyGraph::Build(c, lss) => ArcLazyGraph::Build(
+            c.clone(),
+            lss.iter()
+                .map(|ls| {
+                    ls.iter()
+                        .map(|l2| lazy_graph_to_arc_loop(memo, l2))
+                        .collect()
+                })
+                .collect(),
+        ),
+    });
+    memo.insert(Rc::as_ptr(l), Arc::clone(&l1));
+    l1
+}
+
+pub fn lazy_graph_to_arc<C: Clone>(
+    l: &Rc<LazyGraph<C>>,
+) -> Arc<ArcLazyGraph<C>> {
+    lazy_graph_to_arc_loop(&mut HashMap::new(), l)
+}
+
+fn lazy_graph_from_arc_loop<C: Clone>(
+    memo: &mut HashMap<*const ArcLazyGraph<C>, Rc<LazyGraph<C>>>,
+    l: &Arc<ArcLazyGraph<C>>,
+) -> Rc<LazyGraph<C>> {
+    if let Some(l1) = memo.get(&Arc::as_ptr(l)) {
+        return Rc::clone(l1);
+    }
+    let l1 = match &**l {
+        ArcLazyGraph::Empty() => empty(),
+        ArcLazyGraph::Stop(c) => stop(c),
+        ArcLazyGraph::Build(c, lss) => {
+            let lss1: Vec<Ls<C>> = lss
+                .iter()
+                .map(|ls| {
+                    ls.iter()
+                        .map(|l2| lazy_graph_from_arc_loop(memo, l2))
+                        .collect()
+                })
+                .collect();
+            build(c, &lss1)
+        }
+    };
+    memo.insert(Arc::as_ptr(l), Rc::clone(&l1));
+    l1
+}
+
+pub fn lazy_graph_from_arc<C: Clone>(
+    l: &Arc<ArcLazyGraph<C>>,
+) -> Rc<LazyGraph<C>> {
+    lazy_graph_from_arc_loop(&mut HashMap::new(), l)
+}
+
+//
+// Unrolling and cleaning
+//
+
+pub fn unroll_arc<C: Clone>(l: &ArcLazyGraph<C>) -> ArcGs<C> {
+    match l {
+        ArcLazyGraph::Empty() => Vec::new(),
+        ArcLazyGraph::Stop(c) => vec![Arc::new(ArcGraph::Back(c.clone()))],
+        ArcLazyGraph::Build(c, lss) => {
+            let mut gs = Vec::new();
+            for ls in lss {
+                let gss: Vec<ArcGs<C>> =
+                    ls.iter().map(|l1| unroll_arc(l1)).collect();
+                for gs1 in cartesian(&gss) {
+                    gs.push(Arc::new(ArcGraph::Forth(c.clone(), gs1)));
+                }
+            }
+            gs
+        }
+    }
+}
+
+fn clean_fused_arc_loop<C: Clone, N: NodeCleaner<C>>(
+    n: &N,
+    l: &ArcLazyGraph<C>,
+) -> (N::S, Arc<ArcLazyGraph<C>>) {
+    let empty = || Arc::new(ArcLazyGraph::Empty());
+    match l {
+        ArcLazyGraph::Empty() => (n.empty(), empty()),
+        ArcLazyGraph::Stop(c) => match n.stop(c) {
+            (s, true) => (s, Arc::new(ArcLazyGraph::Stop(c.clone()))),
+            (s, false) => (s, empty()),
+        },
+        ArcLazyGraph::Build(c, lss) => {
+            let mut alts = Vec::with_capacity(lss.len());
+            let mut lss1 = Vec::with_capacity(lss.len());
+            for ls in lss {
+                let (ss, ls1): (Vec<N::S>, ArcLs<C>) =
+                    ls.iter().map(|l1| clean_fused_arc_loop(n, l1)).unzip();
+                alts.push(ss);
+                lss1.push(ls1);
+            }
+            match n.build(c, &alts) {
+                (s, None) => (s, empty()),
+                (s, Some(is)) => {
+                    let lss2: Vec<ArcLs<C>> =
+                        is.into_iter().map(|i| lss1[i].clone()).collect();
+                    (s, Arc::new(ArcLazyGraph::Build(c.clone(), lss2)))
+                }
+            }
+        }
+    }
+}
+
+pub fn clean_fused_arc<C: Clone, N: NodeCleaner<C>>(
+    n: &N,
+    l: &ArcLazyGraph<C>,
+) -> Arc<ArcLazyGraph<C>> {
+    clean_fused_arc_loop(n, l).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::big_step_sc::lazy_mrsc;
+    use crate::fusion::{clean_fused, fuse, NcBadConf, NcEmpty, NcMinSize};
+    use crate::mock_sc_world::mock_world;
+    use std::thread;
+
+    fn is_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_conversions() {
+        is_send_sync::<ArcGraph<isize>>();
+        is_send_sync::<ArcLazyGraph<isize>>();
+        let l = lazy_mrsc(&mock_world(), 0);
+        assert_eq!(lazy_graph_from_arc(&lazy_graph_to_arc(&l)), l);
+        let gs = unroll(&l);
+        for g in &gs {
+            assert_eq!(&graph_from_arc(&graph_to_arc(g)), g);
+        }
+        let g = forth(&0, &[]);
+        let g2 = forth(&1, &[Rc::clone(&g), Rc::clone(&g)]);
+        match &*graph_from_arc(&graph_to_arc(&g2)) {
+            Graph::Forth(_, gs) => assert!(Rc::ptr_eq(&gs[0], &gs[1])),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_worker_threads() {
+        let l = lazy_mrsc(&mock_world(), 0);
+        let bad = |c: &isize| *c >= 3;
+        let al = lazy_graph_to_arc(&l);
+        let (gs, ml) = thread::spawn(move || {
+            let n = fuse(fuse(NcBadConf(bad), NcEmpty), NcMinSize);
+            (unroll_arc(&al), clean_fused_arc(&n, &al))
+        })
+        .join()
+        .unwrap();
+        assert_eq!(graphs_from_arc(&gs), unroll(&l));
+        let n = fuse(fuse(NcBadConf(bad), NcEmpty), NcMinSize);
+        assert_eq!(lazy_graph_from_arc(&ml), clean_fused(&n, &l));
+    }
+}
@@ -0,0 +1,91 @@
+//
+// Test utilities
+//
+// Fixtures and assertions shared by the crate's tests.
+// Downstream crates get this module by enabling the `test-util` feature.
+//
+
+pub use crate::graph::{back, build, empty, forth, stop};
+pub use crate::mock_sc_world::{mock_world, MockScWorld};
+
+use crate::graph::*;
+
+use std::fmt::Debug;
+use std::rc::Rc;
+
+// Some small graphs over integers.
+
+pub fn sample_graph() -> Rc<Graph<isize>> {
+    forth(&1, &[back(&1), forth(&2, &[back(&1), back(&2)])])
+}
+
+pub fn sample_lazy_graph() -> Rc<LazyGraph<isize>> {
+    build(
+        &1,
+        &[
+            vec![build(&2, &[vec![stop(&1), stop(&2)]])],
+            vec![build(&3, &[vec![stop(&4)]])],
+        ],
+    )
+}
+
+// `gs1` and `gs2` are equivalent if they contain the same graphs
+// with the same multiplicities (the order does not matter).
+
+pub fn graphs_equiv<C: PartialEq>(gs1: &Gs<C>, gs2: &Gs<C>) -> bool {
+    if gs1.len() != gs2.len() {
+        return false;
+    }
+    let mut used = vec![false; gs2.len()];
+    gs1.iter().all(|g1| {
+        match (0..gs2.len()).find(|&i| !used[i] && gs2[i] == *g1) {
+            Some(i) => {
+                used[i] = true;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+pub fn assert_graphs_equiv<C: PartialEq + Debug>(gs1: &Gs<C>, gs2: &Gs<C>) {
+    assert!(
+        graphs_equiv(gs1, gs2),
+        "graph collections are not equivalent:\n{:?}\n{:?}",
+        gs1,
+        gs2
+    );
+}
+
+// Checks that `unroll(l)` contains all graphs in `gs`.
+// Used for checking the soundness of cleaners:
+//     unroll(clean(l)) ⊆ unroll(l)
+
+pub fn assert_subset_of_unroll<C: Clone + PartialEq + Debug>(
+    gs: &Gs<C>,
+    l: &LazyGraph<C>,
+) {
+    let all = unroll(l);
+    for g in gs {
+        assert!(all.contains(g), "{:?} is not in unroll(l)", g);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assert_graphs_equiv() {
+        let gs1 = vec![back(&1), back(&2), back(&1)];
+        let gs2 = vec![back(&1), back(&1), back(&2)];
+        assert_graphs_equiv(&gs1, &gs2);
+        assert!(!graphs_equiv(&gs1, &vec![back(&1), back(&2), back(&2)]));
+    }
+
+    #[test]
+    fn test_assert_subset_of_unroll() {
+        let l = sample_lazy_graph();
+        assert_subset_of_unroll(&unroll(&cl_min_size(&l)), &l);
+    }
+}
@@ -0,0 +1,250 @@
+//
+// Verifying counter systems and reporting the results
+//
+// `verify` runs the standard pipeline on a counter system:
+// lazy multi-result supercompilation, removing graphs containing
+// unsafe configurations and extracting a graph of minimal size.
+// The results can be written as CSV, one row per verified instance.
+//
+// `verify_starts` runs the pipeline from each of the start configurations
+// of a counter system (see `CountersWorld::starts`). The system is proved
+// safe iff a proof is found for each start configuration. The numbers
+// of graphs and nodes, as well as the sizes of the minimal proofs,
+// are summed up.
+//
+
+use crate::big_step_sc::lazy_mrsc;
+use crate::counters::{CountersScWorld, CountersWorld, NWC};
+use crate::graph::*;
+use crate::statistics::size_unroll;
+
+use std::fmt::Debug;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Proved,
+    NoSolution,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct VerificationResult {
+    pub protocol: String,
+    pub max_nw: isize,
+    pub max_depth: usize,
+    // The number of safe graphs and their total size.
+    pub graphs: usize,
+    pub nodes: usize,
+    pub time: Duration,
+    pub outcome: Outcome,
+    pub min_size: Option<usize>,
+}
+
+fn verify_from<CW: CountersWorld>(
+    s: &CountersScWorld<CW>,
+    start: NWC,
+) -> (usize, usize, Option<Rc<Graph<NWC>>>) {
+    let l = lazy_mrsc(s, start);
+    let sl = cl_empty_and_bad(|c: &NWC| s.world().is_unsafe(c), &l);
+    let (graphs, nodes) = size_unroll(&sl);
+    (graphs, nodes, unroll_first(&cl_min_size(&sl)))
+}
+
+pub fn verify<CW: CountersWorld + Debug>(
+    cw: CW,
+    max_nw: isize,
+    max_depth: usize,
+) -> (VerificationResult, Option<Rc<Graph<NWC>>>) {
+    let protocol = format!("{:?}", cw);
+    let started = Instant::now();
+    let s = CountersScWorld::new(cw, max_nw, max_depth);
+    let (graphs, nodes, mg) = verify_from(&s, s.world().start());
+    let time = started.elapsed();
+    let result = VerificationResult {
+        protocol,
+        max_nw,
+        max_depth,
+        graphs,
+        nodes,
+        time,
+        outcome: match mg {
+            Some(_) => Outcome::Proved,
+            None => Outcome::NoSolution,
+        },
+        min_size: mg.as_ref().map(|g| graph_size(g)),
+    };
+    (result, mg)
+}
+
+pub fn verify_starts<CW: CountersWorld + Debug>(
+    cw: CW,
+    max_nw: isize,
+    max_depth: usize,
+) -> (VerificationResult, Vec<Option<Rc<Graph<NWC>>>>) {
+    let protocol = format!("{:?}", cw);
+    let started = Instant::now();
+    let s = CountersScWorld::new(cw, max_nw, max_depth);
+    let mut graphs = 0;
+    let mut nodes = 0;
+    let mut mgs = Vec::new();
+    for start in s.world().starts() {
+        let (k, n, mg) = verify_from(&s, start);
+        graphs += k;
+        nodes += n;
+        mgs.push(mg);
+    }
+    let time = started.elapsed();
+    let min_size: Option<usize> = mgs
+        .iter()
+        .map(|mg| mg.as_ref().map(|g| graph_size(g)))
+        .sum();
+    let result = VerificationResult {
+        protocol,
+        max_nw,
+        max_depth,
+        graphs,
+        nodes,
+        time,
+        outcome: match min_size {
+            Some(_) => Outcome::Proved,
+            None => Outcome::NoSolution,
+        },
+        min_size,
+    };
+    (result, mgs)
+}
+
+//
+// CSV
+//
+
+pub const CSV_HEADER: &str =
+    "protocol,max_nw,max_depth,graphs,nodes,time_ms,outcome,min_size";
+
+pub fn csv_row(r: &VerificationResult) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{}",
+        r.protocol,
+        r.max_nw,
+        r.max_depth,
+        r.graphs,
+        r.nodes,
+        r.time.as_millis(),
+        match r.outcome {
+            Outcome::Proved => "proved",
+            Outcome::NoSolution => "no_solution",
+        },
+        r.min_size.map_or(String::new(), |k| k.to_string())
+    )
+}
+
+pub fn write_csv<W: Write>(
+    w: &mut W,
+    rs: &[VerificationResult],
+) -> io::Result<()> {
+    writeln!(w, "{}", CSV_HEADER)?;
+    for r in rs {
+        writeln!(w, "{}", csv_row(r))?;
+    }
+    Ok(())
+}
+
+// Appends a row to a CSV file, writing the header first if the file
+// does not exist or is empty.
+
+pub fn append_csv<P: AsRef<Path>>(
+    path: P,
+    r: &VerificationResult,
+) -> io::Result<()> {
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    if f.metadata()?.len() == 0 {
+        writeln!(f, "{}", CSV_HEADER)?;
+    }
+    writeln!(f, "{}", csv_row(r))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::counter_system;
+    use crate::counters::*;
+
+    counter_system! {
+        TestCW(i, j);
+        Start(ω, 0);
+        Unsafe(j >= 2);
+        Rules{
+            i >= 1 => i - 1, j + 1;
+            j >= 1 => i + 1, j - 1;
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        let (r, _) = verify(TestCW, 3, 10);
+        assert_eq!(r.outcome, Outcome::NoSolution);
+        assert_eq!(r.min_size, None);
+        let mut buf = Vec::new();
+        write_csv(&mut buf, std::slice::from_ref(&r)).unwrap();
+        let s = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].starts_with("TestCW,3,10,0,0,"));
+        assert!(lines[1].ends_with(",no_solution,"));
+
+        let path = std::env::temp_dir().join("staged_mrsc_test_csv.csv");
+        let _ = std::fs::remove_file(&path);
+        append_csv(&path, &r).unwrap();
+        append_csv(&path, &r).unwrap();
+        let s = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(s.lines().count(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    counter_system! {
+        Exclusive(idle, excl);
+        Start(ω, 0);
+        Starts((ω, 0), (ω, 1));
+        Unsafe(excl >= 2);
+        Rules{
+            idle >= 1 && excl == 0 => idle - 1, excl + 1;
+            excl >= 1 => idle + 1, excl - 1;
+        }
+    }
+
+    counter_system! {
+        Racy(idle, excl);
+        Start(ω, 0);
+        Starts((ω, 0), (ω, 1));
+        Unsafe(excl >= 2);
+        Rules{
+            idle >= 1 => idle - 1, excl + 1;
+        }
+    }
+
+    #[test]
+    fn test_verify_starts() {
+        assert_eq!(TestCW.starts(), vec![TestCW.start()]);
+        assert_eq!(Exclusive.starts().len(), 2);
+        let (r, mgs) = verify_starts(Exclusive, 3, 10);
+        assert_eq!(r.outcome, Outcome::Proved);
+        assert_eq!(mgs.len(), 2);
+        assert_eq!(
+            r.min_size,
+            Some(mgs.iter().map(|g| graph_size(g.as_ref().unwrap())).sum())
+        );
+        assert_eq!(
+            mgs[1].as_ref().unwrap().preorder().next().unwrap().1,
+            &NWC(vec![NW::W(), NW::N(1)])
+        );
+        let (r, mgs) = verify_starts(Racy, 3, 10);
+        assert_eq!(r.outcome, Outcome::NoSolution);
+        assert!(mgs.iter().all(|mg| mg.is_none()));
+        assert_eq!(bmc(Racy, 5).unwrap().rules.len(), 1);
+    }
+}
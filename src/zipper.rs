@@ -0,0 +1,190 @@
+//
+// A zipper for residual graphs
+//
+// A `GraphZipper` points to a node of a graph (the focus), so that
+// the focus can be moved down to a child, up to the parent and
+// replaced with another subtree. Since graphs are immutable, replacing
+// a subtree produces a new graph, which is rebuilt when the focus moves
+// up. The nodes that have not been modified are shared with the original
+// graph:
+//     let mut z = GraphZipper::new(&g);
+//     z.down(1);
+//     z.replace(back(&c));
+//     let g1 = z.to_graph();
+// Here `g1` differs from `g` only in the child 1 of the root, the other
+// children being the same `Rc`s.
+//
+// The path to the focus (as in `subgraph_at`) is returned by `path()`,
+// so that `subgraph_at(&z.to_graph(), &z.path()) == Some(z.focus())`.
+//
+
+use crate::graph::*;
+
+use std::rc::Rc;
+
+#[derive(Clone, Debug)]
+pub struct GraphZipper<C> {
+    focus: Rc<Graph<C>>,
+    // The ancestors of the focus (the root first), together with
+    // the positions of the nodes on the path.
+    parents: Vec<(Rc<Graph<C>>, usize)>,
+}
+
+impl<C: Clone> GraphZipper<C> {
+    pub fn new(g: &Rc<Graph<C>>) -> GraphZipper<C> {
+        GraphZipper {
+            focus: Rc::clone(g),
+            parents: Vec::new(),
+        }
+    }
+
+    pub fn focus(&self) -> &Rc<Graph<C>> {
+        &self.focus
+    }
+
+    pub fn conf(&self) -> &C {
+        self.focus.root_conf()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn path(&self) -> Vec<usize> {
+        self.parents.iter().map(|(_, i)| *i).collect()
+    }
+
+    // Moves the focus to the child `i`. Returns `false` (and leaves
+    // the focus unchanged) if there is no such child.
+
+    pub fn down(&mut self, i: usize) -> bool {
+        let g = match &*self.focus {
+            Graph::Forth(_, gs) if i < gs.len() => Rc::clone(&gs[i]),
+            _ => return false,
+        };
+        let parent = std::mem::replace(&mut self.focus, g);
+        self.parents.push((parent, i));
+        true
+    }
+
+    // Moves the focus to the parent. Returns `false` at the root.
+
+    pub fn up(&mut self) -> bool {
+        let (parent, i) = match self.parents.pop() {
+            None => return false,
+            Some(p) => p,
+        };
+        self.focus = match &*parent {
+            Graph::Forth(c, gs) if !Rc::ptr_eq(&gs[i], &self.focus) => {
+                let mut gs1 = gs.clone();
+                gs1[i] = Rc::clone(&self.focus);
+                Rc::new(Graph::Forth(c.clone(), gs1))
+            }
+            _ => parent,
+        };
+        true
+    }
+
+    // Moves the focus to the sibling `i`. Returns `false` (and leaves
+    // the focus unchanged) at the root or if there is no such sibling.
+
+    pub fn sibling(&mut self, i: usize) -> bool {
+        let n = match self.parents.last() {
+            Some((parent, _)) => match &**parent {
+                Graph::Forth(_, gs) => gs.len(),
+                Graph::Back(_) => 0,
+            },
+            None => return false,
+        };
+        i < n && self.up() && self.down(i)
+    }
+
+    pub fn replace(&mut self, g: Rc<Graph<C>>) {
+        self.focus = g;
+    }
+
+    pub fn modify(&mut self, f: impl FnOnce(&Rc<Graph<C>>) -> Rc<Graph<C>>) {
+        self.focus = f(&self.focus);
+    }
+
+    // Moves the focus to the root.
+
+    pub fn top(&mut self) {
+        while self.up() {}
+    }
+
+    // The whole (modified) graph.
+
+    pub fn to_graph(&self) -> Rc<Graph<C>> {
+        let mut z = self.clone();
+        z.top();
+        z.focus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn g1() -> Rc<Graph<isize>> {
+        forth(&1, &[back(&1), forth(&2, &[back(&1), back(&2)])])
+    }
+
+    #[test]
+    fn test_navigation() {
+        let g = g1();
+        let mut z = GraphZipper::new(&g);
+        assert!(z.is_root());
+        assert!(!z.up());
+        assert!(!z.down(2));
+        assert!(z.down(1));
+        assert_eq!(*z.conf(), 2);
+        assert!(z.down(1));
+        assert_eq!(z.path(), vec![1, 1]);
+        assert_eq!(z.depth(), 2);
+        assert!(!z.down(0));
+        assert!(z.sibling(0));
+        assert_eq!(z.path(), vec![1, 0]);
+        assert!(!z.sibling(2));
+        assert_eq!(subgraph_at(&g, &z.path()).as_ref(), Some(z.focus()));
+        z.top();
+        assert!(z.is_root());
+        assert!(Rc::ptr_eq(&z.to_graph(), &g));
+    }
+
+    #[test]
+    fn test_replace() {
+        let g = g1();
+        let mut z = GraphZipper::new(&g);
+        z.down(1);
+        z.down(0);
+        z.replace(forth(&3, &[back(&1)]));
+        let path = z.path();
+        let g2 = z.to_graph();
+        assert_eq!(
+            g2,
+            forth(
+                &1,
+                &[back(&1), forth(&2, &[forth(&3, &[back(&1)]), back(&2)])]
+            )
+        );
+        assert_eq!(subgraph_at(&g2, &path), Some(forth(&3, &[back(&1)])));
+        // The unmodified subtrees are shared.
+        match (&*g, &*g2) {
+            (Graph::Forth(_, gs), Graph::Forth(_, gs2)) => {
+                assert!(Rc::ptr_eq(&gs[0], &gs2[0]));
+                assert!(!Rc::ptr_eq(&gs[1], &gs2[1]));
+            }
+            _ => panic!(),
+        }
+        // The original graph is unchanged.
+        assert_eq!(g, g1());
+        z.up();
+        z.modify(|g| forth(&4, &[Rc::clone(g)]));
+        assert_eq!(graph_size(&z.to_graph()), 7);
+    }
+}